@@ -0,0 +1,68 @@
+//! Scanner throughput, in tokens per second: `cargo bench --bench scanner`.
+//!
+//! `scan` pulls every token of a generated program with `next_token`; `peek`
+//! also looks at each token with `peek_token` first, as the parser does.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use minicompiler::lexer::{Scanner, TokenType};
+use std::hint::black_box;
+
+/// `functions` copies of a function using every kind of token the language
+/// had from the start: keywords, names, numbers, strings, operators and
+/// comments.
+fn program(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source.push_str(&format!(
+            "// Sums the first n squares, scaled.\n\
+             fn sum_{i}(n: int, scale: float) -> float {{\n    \
+                 int total = 0;\n    \
+                 for (int k = 0; k < n; k = k + 1) {{\n        \
+                     total = total + k * k % 1000;\n    \
+                 }}\n    \
+                 /* Negative totals never happen, but check anyway. */\n    \
+                 if (total >= 0 && scale != 0.0) {{\n        \
+                     return total * scale / 2.5e3;\n    \
+                 }} else {{\n        \
+                     print(\"unexpected total: \\n\");\n    \
+                 }}\n    \
+                 bool done = !false || true;\n    \
+                 return -1.0;\n\
+             }}\n\n"
+        ));
+    }
+    source
+}
+
+fn count_tokens(source: &str) -> u64 {
+    let mut scanner = Scanner::new(source);
+    let mut count = 1;
+    while scanner.next_token().token_type != TokenType::EndOfFile {
+        count += 1;
+    }
+    count
+}
+
+fn scanner(c: &mut Criterion) {
+    let source = program(1000);
+    let mut group = c.benchmark_group("scanner");
+    group.throughput(Throughput::Elements(count_tokens(&source)));
+    group.bench_function("scan", |b| {
+        b.iter(|| {
+            let mut scanner = Scanner::new(black_box(&source));
+            while black_box(scanner.next_token()).token_type != TokenType::EndOfFile {}
+        })
+    });
+    group.bench_function("peek", |b| {
+        b.iter(|| {
+            let mut scanner = Scanner::new(black_box(&source));
+            while black_box(scanner.peek_token()).token_type != TokenType::EndOfFile {
+                black_box(scanner.next_token());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scanner);
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+//! Feeds arbitrary bytes to the parser. Run with `cargo fuzz run parser`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    minicompiler::parser::fuzz_check(data);
+});
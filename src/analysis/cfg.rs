@@ -0,0 +1,542 @@
+//! Control-flow graphs.
+//!
+//! [`Cfg::build`] splits a function body into basic blocks: runs of
+//! declarations and expression statements that execute one after another,
+//! each ending in a [`Terminator`] that says where control goes next. The
+//! graph has one entry block, where the body starts, and one exit block,
+//! which every `return` and the end of the body lead to.
+//!
+//! Conditions stay whole expressions: `a && b` is one branch, not two. A
+//! `match` statement branches to a block per arm, but a `match` used as part
+//! of a larger expression, such as `x = match s { ... };`, is evaluated like
+//! any other expression. Statements after a `return`, `break` or `continue`
+//! go in a block no edge leads to. A `break` or `continue` outside a loop,
+//! which the checker rejects, is ignored.
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    ArmBody, AstArena, Expr, ExprId, ExprKind, Function, MatchArm, PatternKind, StmtId, StmtKind,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
+
+/// Identifies one block of a [`Cfg`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(u32);
+
+impl BlockId {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+/// One straight-line step of a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Element {
+    /// A declaration or expression statement.
+    Stmt(StmtId),
+    /// An expression evaluated on its own: a `for` loop's step, or the body
+    /// of a `match` arm.
+    Expr(ExprId),
+}
+
+/// How control leaves a basic block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Goto(BlockId),
+    /// Evaluates `condition` and goes to `if_true` or `if_false`.
+    Branch {
+        condition: ExprId,
+        if_true: BlockId,
+        if_false: BlockId,
+    },
+    /// Evaluates the scrutinee of a `match` statement and goes to the block
+    /// of the first arm that matches, in the order of the `match`'s arms;
+    /// to `otherwise` if none does. `otherwise` is `None` when an arm
+    /// matches anything.
+    Switch {
+        scrutinee: ExprId,
+        arms: Vec<BlockId>,
+        otherwise: Option<BlockId>,
+    },
+    /// Leaves the function, going to the exit block.
+    Return(Option<ExprId>),
+    /// Ends the exit block.
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub elements: Vec<Element>,
+    pub terminator: Terminator,
+}
+
+/// The control-flow graph of one function body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    blocks: Vec<BasicBlock>,
+    predecessors: Vec<Vec<BlockId>>,
+}
+
+impl Cfg {
+    /// Where the body starts.
+    pub const ENTRY: BlockId = BlockId(0);
+    /// Where every path through the body ends.
+    pub const EXIT: BlockId = BlockId(1);
+
+    /// The graph of `function`, whose expressions and statements live in
+    /// `arena`.
+    pub fn build(arena: &AstArena, function: &Function) -> Cfg {
+        let mut builder = Builder {
+            arena,
+            blocks: Vec::new(),
+            current: Cfg::ENTRY,
+            loops: Vec::new(),
+        };
+        builder.new_block();
+        let exit = builder.new_block();
+        builder.blocks[exit.index()].1 = Some(Terminator::Exit);
+        builder.stmts(&function.body.stmts);
+        builder.terminate(Terminator::Goto(Cfg::EXIT));
+
+        let blocks: Vec<BasicBlock> = builder
+            .blocks
+            .into_iter()
+            .map(|(elements, terminator)| BasicBlock {
+                elements,
+                terminator: terminator.expect("every block is terminated"),
+            })
+            .collect();
+        let mut predecessors = vec![Vec::new(); blocks.len()];
+        for (i, block) in blocks.iter().enumerate() {
+            for successor in successors(&block.terminator) {
+                predecessors[successor.index()].push(BlockId::new(i));
+            }
+        }
+        Cfg {
+            blocks,
+            predecessors,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Every block with its id, in the order they were made.
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockId, &BasicBlock)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (BlockId::new(i), block))
+    }
+
+    /// The blocks control may go to from `block`.
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        successors(&self[block].terminator)
+    }
+
+    /// The blocks control may come to `block` from, each once per edge.
+    pub fn predecessors(&self, block: BlockId) -> &[BlockId] {
+        &self.predecessors[block.index()]
+    }
+
+    /// The blocks reachable from the entry, in reverse postorder: each
+    /// block comes before its successors, except along back edges. Forward
+    /// dataflow analyses converge fastest visiting blocks in this order.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::new();
+        // Each entry is a block and how many of its successors are done.
+        let mut stack = vec![(Cfg::ENTRY, 0)];
+        visited[Cfg::ENTRY.index()] = true;
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.successors(block);
+            match successors.get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor.index()] {
+                        visited[successor.index()] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+}
+
+impl Index<BlockId> for Cfg {
+    type Output = BasicBlock;
+
+    fn index(&self, block: BlockId) -> &BasicBlock {
+        &self.blocks[block.index()]
+    }
+}
+
+fn successors(terminator: &Terminator) -> Vec<BlockId> {
+    match terminator {
+        Terminator::Goto(target) => vec![*target],
+        Terminator::Branch {
+            if_true, if_false, ..
+        } => vec![*if_true, *if_false],
+        Terminator::Switch {
+            arms, otherwise, ..
+        } => arms.iter().chain(otherwise).copied().collect(),
+        Terminator::Return(_) => vec![Cfg::EXIT],
+        Terminator::Exit => Vec::new(),
+    }
+}
+
+/// The arms of every `match` in `function`, by scrutinee.
+pub(crate) fn match_arms<'a>(
+    arena: &'a AstArena,
+    function: &'a Function,
+) -> HashMap<ExprId, &'a [MatchArm]> {
+    struct Matches<'a> {
+        arena: &'a AstArena,
+        arms: HashMap<ExprId, &'a [MatchArm]>,
+    }
+
+    impl<'a> Visitor<'a> for Matches<'a> {
+        fn arena(&self) -> &'a AstArena {
+            self.arena
+        }
+
+        fn visit_expr(&mut self, expr: &'a Expr) {
+            if let ExprKind::Match { scrutinee, arms } = &expr.kind {
+                self.arms.insert(*scrutinee, arms);
+            }
+            visit::walk_expr(self, expr);
+        }
+    }
+
+    let mut matches = Matches {
+        arena,
+        arms: HashMap::new(),
+    };
+    matches.visit_block(&function.body);
+    matches.arms
+}
+
+struct Builder<'a> {
+    arena: &'a AstArena,
+    /// Each block's elements, and its terminator once it has one.
+    blocks: Vec<(Vec<Element>, Option<Terminator>)>,
+    /// The block statements are being added to.
+    current: BlockId,
+    /// The `continue` and `break` targets of each enclosing loop, innermost
+    /// last.
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+impl Builder<'_> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        BlockId::new(self.blocks.len() - 1)
+    }
+
+    fn push(&mut self, element: Element) {
+        self.blocks[self.current.index()].0.push(element);
+    }
+
+    /// Ends the current block with `terminator`.
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current.index()].1 = Some(terminator);
+    }
+
+    /// Ends the current block with `terminator` and carries on in a block
+    /// nothing leads to.
+    fn diverge(&mut self, terminator: Terminator) {
+        self.terminate(terminator);
+        self.current = self.new_block();
+    }
+
+    /// Ends the current block with a jump to `target` and carries on there.
+    fn goto(&mut self, target: BlockId) {
+        self.terminate(Terminator::Goto(target));
+        self.current = target;
+    }
+
+    fn stmts(&mut self, stmts: &[StmtId]) {
+        for &stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, id: StmtId) {
+        let arena = self.arena;
+        match &arena[id].kind {
+            StmtKind::VarDecl(_) => self.push(Element::Stmt(id)),
+            StmtKind::Expr(expr) => match &arena.ungrouped(*expr).kind {
+                ExprKind::Match { scrutinee, arms } => {
+                    let after = self.new_block();
+                    let blocks: Vec<BlockId> = arms.iter().map(|_| self.new_block()).collect();
+                    let exhaustive = arms.iter().any(|arm| {
+                        matches!(
+                            arm.pattern.kind,
+                            PatternKind::Wildcard | PatternKind::Binding(_)
+                        )
+                    });
+                    self.terminate(Terminator::Switch {
+                        scrutinee: *scrutinee,
+                        arms: blocks.clone(),
+                        otherwise: (!exhaustive).then_some(after),
+                    });
+                    for (arm, block) in arms.iter().zip(blocks) {
+                        self.current = block;
+                        match &arm.body {
+                            ArmBody::Expr(body) => self.push(Element::Expr(*body)),
+                            ArmBody::Block(body) => self.stmts(&body.stmts),
+                        }
+                        self.terminate(Terminator::Goto(after));
+                    }
+                    self.current = after;
+                }
+                _ => self.push(Element::Stmt(id)),
+            },
+            StmtKind::Block(block) => self.stmts(&block.stmts),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let then_block = self.new_block();
+                let else_block = else_branch.map(|_| self.new_block());
+                let after = self.new_block();
+                self.terminate(Terminator::Branch {
+                    condition: *condition,
+                    if_true: then_block,
+                    if_false: else_block.unwrap_or(after),
+                });
+                self.current = then_block;
+                self.stmt(*then_branch);
+                self.terminate(Terminator::Goto(after));
+                if let (Some(else_branch), Some(else_block)) = (else_branch, else_block) {
+                    self.current = else_block;
+                    self.stmt(*else_branch);
+                    self.terminate(Terminator::Goto(after));
+                }
+                self.current = after;
+            }
+            StmtKind::While { condition, body } => {
+                let header = self.new_block();
+                let body_block = self.new_block();
+                let after = self.new_block();
+                self.goto(header);
+                self.terminate(Terminator::Branch {
+                    condition: *condition,
+                    if_true: body_block,
+                    if_false: after,
+                });
+                self.current = body_block;
+                self.loop_body(*body, header, after);
+                self.terminate(Terminator::Goto(header));
+                self.current = after;
+            }
+            StmtKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.stmt(*init);
+                }
+                let header = self.new_block();
+                let body_block = self.new_block();
+                let step_block = self.new_block();
+                let after = self.new_block();
+                self.goto(header);
+                self.terminate(match condition {
+                    Some(condition) => Terminator::Branch {
+                        condition: *condition,
+                        if_true: body_block,
+                        if_false: after,
+                    },
+                    None => Terminator::Goto(body_block),
+                });
+                self.current = body_block;
+                self.loop_body(*body, step_block, after);
+                self.goto(step_block);
+                if let Some(step) = step {
+                    self.push(Element::Expr(*step));
+                }
+                self.terminate(Terminator::Goto(header));
+                self.current = after;
+            }
+            StmtKind::Return(value) => self.diverge(Terminator::Return(*value)),
+            StmtKind::Break => {
+                if let Some(&(_, target)) = self.loops.last() {
+                    self.diverge(Terminator::Goto(target));
+                }
+            }
+            StmtKind::Continue => {
+                if let Some(&(target, _)) = self.loops.last() {
+                    self.diverge(Terminator::Goto(target));
+                }
+            }
+        }
+    }
+
+    fn loop_body(&mut self, body: StmtId, continue_target: BlockId, break_target: BlockId) {
+        self.loops.push((continue_target, break_target));
+        self.stmt(body);
+        self.loops.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Item, Program};
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    /// The graph of the first function in `source`, one line per block,
+    /// with the source text of each element and condition.
+    fn graph(source: &str) -> Vec<String> {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let cfg = build_first(&program);
+        let text = |span: crate::source::Span| &source[span.start..span.end];
+        cfg.blocks()
+            .map(|(id, block)| {
+                let mut line = format!("{}:", id);
+                for element in &block.elements {
+                    let span = match *element {
+                        Element::Stmt(stmt) => program.arena[stmt].span,
+                        Element::Expr(expr) => program.arena[expr].span,
+                    };
+                    line += &format!(" [{}]", text(span));
+                }
+                line += &match &block.terminator {
+                    Terminator::Goto(target) => format!(" goto {}", target),
+                    Terminator::Branch {
+                        condition,
+                        if_true,
+                        if_false,
+                    } => format!(
+                        " if {} then {} else {}",
+                        text(program.arena[*condition].span),
+                        if_true,
+                        if_false
+                    ),
+                    Terminator::Switch {
+                        scrutinee,
+                        arms,
+                        otherwise,
+                    } => {
+                        let arms: Vec<String> = arms.iter().map(|arm| arm.to_string()).collect();
+                        let otherwise = otherwise.map_or(String::new(), |b| format!(" else {}", b));
+                        format!(
+                            " match {} {}{}",
+                            text(program.arena[*scrutinee].span),
+                            arms.join(" "),
+                            otherwise
+                        )
+                    }
+                    Terminator::Return(Some(value)) => {
+                        format!(" return {}", text(program.arena[*value].span))
+                    }
+                    Terminator::Return(None) => " return".to_string(),
+                    Terminator::Exit => " exit".to_string(),
+                };
+                line
+            })
+            .collect()
+    }
+
+    fn build_first(program: &Program) -> Cfg {
+        let Some(Item::Function(function)) = program.items.first() else {
+            panic!("expected a function");
+        };
+        Cfg::build(&program.arena, function)
+    }
+
+    #[test]
+    fn test_branches() {
+        let source = "fn f(x: int) -> int { int y = 1; if (x > 0) { y = 2; } else y = 3; if (y) y; return y; }";
+        assert_eq!(
+            graph(source),
+            [
+                "bb0: [int y = 1;] if x > 0 then bb2 else bb3",
+                "bb1: exit",
+                "bb2: [y = 2;] goto bb4",
+                "bb3: [y = 3;] goto bb4",
+                "bb4: if y then bb5 else bb6",
+                "bb5: [y;] goto bb6",
+                "bb6: return y",
+                "bb7: goto bb1",
+            ]
+        );
+        let source =
+            "fn f(s: int) { match s { 1 => g(), _ => { return; } } h(); match s { 2 => {} } }";
+        assert_eq!(
+            graph(source),
+            [
+                "bb0: match s bb3 bb4",
+                "bb1: exit",
+                "bb2: [h();] match s bb7 else bb6",
+                "bb3: [g()] goto bb2",
+                "bb4: return",
+                "bb5: goto bb2",
+                "bb6: goto bb1",
+                "bb7: goto bb6",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let source = "fn f() { int i = 0; while (i < 10) { if (i == 5) break; i += 1; continue; } for (int j = 0; ; j += 1) { g(j); } }";
+        assert_eq!(
+            graph(source),
+            [
+                "bb0: [int i = 0;] goto bb2",
+                "bb1: exit",
+                "bb2: if i < 10 then bb3 else bb4",
+                "bb3: if i == 5 then bb5 else bb6",
+                "bb4: [int j = 0;] goto bb9",
+                "bb5: goto bb4",
+                "bb6: [i += 1;] goto bb2",
+                "bb7: goto bb6",
+                "bb8: goto bb2",
+                "bb9: goto bb10",
+                "bb10: [g(j);] goto bb11",
+                "bb11: [j += 1] goto bb9",
+                "bb12: goto bb1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_edges() {
+        let source = "fn f(x: int) { while (x) { if (x) return; x = 0; } }";
+        let (program, _) = parse(source);
+        let cfg = build_first(&program);
+        let ids = |blocks: &[BlockId]| blocks.iter().map(|b| b.index()).collect::<Vec<_>>();
+        assert_eq!(ids(&cfg.successors(BlockId(2))), [3, 4]);
+        assert_eq!(ids(cfg.predecessors(BlockId(2))), [0, 6]);
+        assert_eq!(ids(cfg.predecessors(Cfg::EXIT)), [4, 5]);
+        // The block after `return` is unreachable, so it is left out.
+        assert_eq!(ids(&cfg.reverse_postorder()), [0, 2, 4, 3, 6, 5, 1]);
+    }
+}
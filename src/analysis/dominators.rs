@@ -0,0 +1,208 @@
+//! Dominators of a control-flow graph.
+//!
+//! A block `a` dominates `b` when every path from the entry to `b` goes
+//! through `a`. The immediate dominator of `b` is the closest of the blocks
+//! that strictly dominate it, which makes the dominators a tree rooted at
+//! the entry. The dominance frontier of `a` is where its dominance ends: the
+//! blocks `a` does not strictly dominate but one of whose predecessors it
+//! does dominate. That is where the values defined in `a` meet values from
+//! other paths, so [`ssa`](super::ssa) puts its phis there.
+//!
+//! Blocks that cannot be reached from the entry have no dominators and
+//! appear in no frontier.
+
+use super::cfg::{BlockId, Cfg};
+
+/// The dominator tree and dominance frontiers of a [`Cfg`], computed with
+/// the iterative algorithm of Cooper, Harvey and Kennedy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominators {
+    /// Each block's immediate dominator; the entry's is itself, and an
+    /// unreachable block's is `None`.
+    idoms: Vec<Option<BlockId>>,
+    children: Vec<Vec<BlockId>>,
+    frontiers: Vec<Vec<BlockId>>,
+}
+
+impl Dominators {
+    pub fn compute(cfg: &Cfg) -> Dominators {
+        let order = cfg.reverse_postorder();
+        let mut rank = vec![usize::MAX; cfg.len()];
+        for (i, block) in order.iter().enumerate() {
+            rank[block.index()] = i;
+        }
+
+        let mut idoms = vec![None; cfg.len()];
+        idoms[Cfg::ENTRY.index()] = Some(Cfg::ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let mut idom = None;
+                for &pred in cfg.predecessors(block) {
+                    if idoms[pred.index()].is_none() {
+                        continue;
+                    }
+                    idom = Some(match idom {
+                        None => pred,
+                        Some(idom) => intersect(&idoms, &rank, pred, idom),
+                    });
+                }
+                if idoms[block.index()] != idom {
+                    idoms[block.index()] = idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![Vec::new(); cfg.len()];
+        for &block in &order[1..] {
+            if let Some(idom) = idoms[block.index()] {
+                children[idom.index()].push(block);
+            }
+        }
+
+        let mut frontiers: Vec<Vec<BlockId>> = vec![Vec::new(); cfg.len()];
+        for &block in &order {
+            let preds = cfg.predecessors(block);
+            if preds.len() < 2 {
+                continue;
+            }
+            let idom = idoms[block.index()];
+            for &pred in preds {
+                let mut runner = pred;
+                while idoms[runner.index()].is_some() && Some(runner) != idom {
+                    let frontier = &mut frontiers[runner.index()];
+                    if !frontier.contains(&block) {
+                        frontier.push(block);
+                    }
+                    runner = idoms[runner.index()].expect("checked above");
+                }
+            }
+        }
+
+        Dominators {
+            idoms,
+            children,
+            frontiers,
+        }
+    }
+
+    /// Whether `block` can be reached from the entry.
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.idoms[block.index()].is_some()
+    }
+
+    /// The immediate dominator of `block`, or `None` for the entry and the
+    /// unreachable blocks.
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idoms[block.index()].filter(|&idom| idom != block)
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`. A block
+    /// dominates itself.
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        if !self.is_reachable(b) {
+            return false;
+        }
+        let mut block = b;
+        loop {
+            if block == a {
+                return true;
+            }
+            match self.idom(block) {
+                Some(idom) => block = idom,
+                None => return false,
+            }
+        }
+    }
+
+    /// The blocks `block` immediately dominates, its children in the
+    /// dominator tree, in reverse postorder.
+    pub fn children(&self, block: BlockId) -> &[BlockId] {
+        &self.children[block.index()]
+    }
+
+    /// The dominance frontier of `block`.
+    pub fn frontier(&self, block: BlockId) -> &[BlockId] {
+        &self.frontiers[block.index()]
+    }
+}
+
+/// The closest common dominator of `a` and `b`, walking up the partial
+/// tree in `idoms` from whichever is later in reverse postorder.
+fn intersect(idoms: &[Option<BlockId>], rank: &[usize], mut a: BlockId, mut b: BlockId) -> BlockId {
+    while a != b {
+        while rank[a.index()] > rank[b.index()] {
+            a = idoms[a.index()].expect("processed blocks have a dominator");
+        }
+        while rank[b.index()] > rank[a.index()] {
+            b = idoms[b.index()].expect("processed blocks have a dominator");
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Item;
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    fn dominators(source: &str) -> (Cfg, Dominators) {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let Some(Item::Function(function)) = program.items.first() else {
+            panic!("expected a function");
+        };
+        let cfg = Cfg::build(&program.arena, function);
+        let dominators = Dominators::compute(&cfg);
+        (cfg, dominators)
+    }
+
+    fn ids(blocks: &[BlockId]) -> Vec<usize> {
+        blocks.iter().map(|b| b.index()).collect()
+    }
+
+    #[test]
+    fn test_dominators() {
+        // bb2 and bb3 are the branches of the `if`, joining at bb4. bb5 is
+        // the loop's header, bb6 its body and bb7 the `return`.
+        let source = "fn f(x: int) { if (x > 0) x = 1; else x = 2; while (x) { x = x - 1; } return; x = 3; }";
+        let (cfg, dominators) = dominators(source);
+        let idoms: Vec<Option<usize>> = cfg
+            .blocks()
+            .map(|(id, _)| dominators.idom(id).map(|b| b.index()))
+            .collect();
+        assert_eq!(
+            idoms,
+            [
+                None,
+                Some(7),
+                Some(0),
+                Some(0),
+                Some(0),
+                Some(4),
+                Some(5),
+                Some(5),
+                None,
+            ]
+        );
+        assert!(dominators.dominates(BlockId::new(0), BlockId::new(6)));
+        assert!(dominators.dominates(BlockId::new(5), BlockId::new(5)));
+        assert!(!dominators.dominates(BlockId::new(2), BlockId::new(4)));
+        // The statement after `return` is unreachable.
+        assert!(!dominators.is_reachable(BlockId::new(8)));
+        assert_eq!(ids(dominators.children(BlockId::new(0))), [3, 2, 4]);
+
+        assert_eq!(ids(dominators.frontier(BlockId::new(2))), [4]);
+        assert_eq!(ids(dominators.frontier(BlockId::new(3))), [4]);
+        assert_eq!(ids(dominators.frontier(BlockId::new(6))), [5]);
+        assert_eq!(ids(dominators.frontier(BlockId::new(5))), [5]);
+        assert_eq!(
+            ids(dominators.frontier(BlockId::new(0))),
+            Vec::<usize>::new()
+        );
+    }
+}
@@ -0,0 +1,16 @@
+//! Analyses over function bodies that later phases share.
+//!
+//! [`cfg`] turns a function body into a control-flow graph of basic blocks,
+//! the representation dataflow analyses and the optimizer work on.
+//! [`dominators`] computes the graph's dominator tree and dominance
+//! frontiers, and [`ssa`] uses them to put local variables in static single
+//! assignment form.
+
+pub mod cfg;
+pub mod dominators;
+pub mod print;
+pub mod ssa;
+
+pub use cfg::{BasicBlock, BlockId, Cfg, Element, Terminator};
+pub use dominators::Dominators;
+pub use ssa::{Phi, Ssa, Value};
@@ -0,0 +1,218 @@
+//! The textual form of a function's control-flow graph, which `ir` prints.
+//!
+//! ```text
+//! fn f(x.0: int) -> int {
+//! bb0:
+//!     int y.1 = 1;
+//!     if x.0 > 0 then bb2 else bb3
+//! bb1: // preds: bb4
+//!     exit
+//! bb2: // preds: bb0
+//!     y.3 = y.1 + x.0;
+//!     goto bb4
+//! bb3: // preds: bb0
+//!     y.2 = 3;
+//!     goto bb4
+//! bb4: // preds: bb2, bb3
+//!     y.4 = phi(bb2: y.3, bb3: y.2)
+//!     return y.4
+//! }
+//! ```
+//!
+//! Every block is printed, in the order of its id, with the blocks control
+//! may come from. Statements are printed as source code, one per line, and
+//! the terminator goes last. With [`Ssa`], variables carry their versions
+//! and each block starts with its phis. The output depends only on the
+//! program, so dumps can be compared as text.
+
+use super::cfg::{match_arms, BlockId, Cfg, Element, Terminator};
+use super::ssa::Ssa;
+use crate::ast::pretty::Fragments;
+use crate::ast::{AstArena, Function, NodeId};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// `function`, whose graph is `cfg`, in the textual form, with the
+/// versions of its variables if `ssa` is given.
+pub fn print(arena: &AstArena, function: &Function, cfg: &Cfg, ssa: Option<&Ssa>) -> String {
+    let names = |node: NodeId| {
+        let ssa = ssa?;
+        let value = ssa.write(node).or_else(|| ssa.read(node))?;
+        Some(ssa.name(value))
+    };
+    let fragments = Fragments::new(arena).with_indent(1).with_names(&names);
+    let reachable: HashSet<BlockId> = cfg.reverse_postorder().into_iter().collect();
+    let arms = match_arms(arena, function);
+
+    let mut out = format!("{} {{\n", fragments.signature(function));
+    for (id, block) in cfg.blocks() {
+        let _ = write!(out, "{}:", id);
+        if !reachable.contains(&id) {
+            out.push_str(" // unreachable");
+        } else if !cfg.predecessors(id).is_empty() {
+            let preds: Vec<String> = cfg.predecessors(id).iter().map(|p| p.to_string()).collect();
+            let _ = write!(out, " // preds: {}", preds.join(", "));
+        }
+        out.push('\n');
+
+        for phi in ssa.iter().flat_map(|ssa| ssa.phis(id)) {
+            let ssa = ssa.expect("phis come from it");
+            let operands: Vec<String> = phi
+                .operands
+                .iter()
+                .map(|(pred, value)| format!("{}: {}", pred, ssa.name(*value)))
+                .collect();
+            let _ = writeln!(
+                out,
+                "    {} = phi({})",
+                ssa.name(phi.value),
+                operands.join(", ")
+            );
+        }
+        for element in &block.elements {
+            let text = match *element {
+                Element::Stmt(stmt) => fragments.stmt(stmt),
+                Element::Expr(expr) => fragments.expr(expr),
+            };
+            let _ = writeln!(out, "    {}", text);
+        }
+
+        let terminator = match &block.terminator {
+            Terminator::Goto(target) => format!("goto {}", target),
+            Terminator::Branch {
+                condition,
+                if_true,
+                if_false,
+            } => format!(
+                "if {} then {} else {}",
+                fragments.expr(*condition),
+                if_true,
+                if_false
+            ),
+            Terminator::Switch {
+                scrutinee,
+                arms: targets,
+                otherwise,
+            } => {
+                let cases: Vec<String> = arms[scrutinee]
+                    .iter()
+                    .zip(targets)
+                    .map(|(arm, target)| {
+                        format!("{} => {}", fragments.pattern(&arm.pattern), target)
+                    })
+                    .collect();
+                let mut text = format!(
+                    "match {} {{ {} }}",
+                    fragments.expr(*scrutinee),
+                    cases.join(", ")
+                );
+                if let Some(otherwise) = otherwise {
+                    let _ = write!(text, " else {}", otherwise);
+                }
+                text
+            }
+            Terminator::Return(Some(value)) => format!("return {}", fragments.expr(*value)),
+            Terminator::Return(None) => "return".to_string(),
+            Terminator::Exit => "exit".to_string(),
+        };
+        let _ = writeln!(out, "    {}", terminator);
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Dominators;
+    use crate::ast::Item;
+    use crate::parser::parse;
+    use crate::semantic::resolve;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_print() {
+        let source = "fn f(x: int, s: int) -> int {
+    int y = 1;
+    if (x > 0) y = y + x; else { y = 3; }
+    match s { 1 => { return y; } n => g(n) }
+    return y;
+    y = 0;
+}
+fn g(n: int) {}";
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, diagnostics) = resolve(&program);
+        assert_eq!(diagnostics, []);
+        let Some(Item::Function(function)) = program.items.first() else {
+            panic!("expected a function");
+        };
+        let cfg = Cfg::build(&program.arena, function);
+        assert_eq!(
+            print(&program.arena, function, &cfg, None),
+            "fn f(x: int, s: int) -> int {
+bb0:
+    int y = 1;
+    if x > 0 then bb2 else bb3
+bb1: // preds: bb5, bb6, bb9
+    exit
+bb2: // preds: bb0
+    y = y + x;
+    goto bb4
+bb3: // preds: bb0
+    y = 3;
+    goto bb4
+bb4: // preds: bb2, bb3
+    match s { 1 => bb6, n => bb7 }
+bb5: // preds: bb7, bb8
+    return y
+bb6: // preds: bb4
+    return y
+bb7: // preds: bb4
+    g(n)
+    goto bb5
+bb8: // unreachable
+    goto bb5
+bb9: // unreachable
+    y = 0;
+    goto bb1
+}
+"
+        );
+
+        let dominators = Dominators::compute(&cfg);
+        let ssa = Ssa::build(&program.arena, function, &cfg, &dominators, &resolution);
+        assert_eq!(
+            print(&program.arena, function, &cfg, Some(&ssa)),
+            "fn f(x.0: int, s.0: int) -> int {
+bb0:
+    int y.1 = 1;
+    if x.0 > 0 then bb2 else bb3
+bb1: // preds: bb5, bb6, bb9
+    exit
+bb2: // preds: bb0
+    y.3 = y.1 + x.0;
+    goto bb4
+bb3: // preds: bb0
+    y.2 = 3;
+    goto bb4
+bb4: // preds: bb2, bb3
+    y.4 = phi(bb2: y.3, bb3: y.2)
+    match s.0 { 1 => bb6, n.1 => bb7 }
+bb5: // preds: bb7, bb8
+    return y.4
+bb6: // preds: bb4
+    return y.4
+bb7: // preds: bb4
+    g(n.1)
+    goto bb5
+bb8: // unreachable
+    goto bb5
+bb9: // unreachable
+    y = 0;
+    goto bb1
+}
+"
+        );
+    }
+}
@@ -0,0 +1,679 @@
+//! Static single assignment form of a function's local variables.
+//!
+//! [`Ssa::build`] splits every local variable into versions, one per
+//! definition, so each version is assigned exactly once, and puts a phi at
+//! the start of each block where versions arriving from different
+//! predecessors meet. The AST itself is not rewritten: the versions are
+//! kept beside it, keyed by node. Each variable expression reads a version,
+//! and each parameter, declaration, binding pattern and assignment to a
+//! variable writes one. A pass can then follow any use of a variable
+//! straight to the one place its value came from, which is what value
+//! numbering and sparse constant propagation need.
+//!
+//! Phis are placed in the iterated dominance frontiers of the blocks that
+//! write a variable, as Cytron et al. describe, but only where the variable
+//! is live, so no phi goes unused. Versions are then numbered in a walk of
+//! the dominator tree. Version 0 of a variable is its value on entry to the
+//! function: the argument, for a parameter. Versions are numbered per name,
+//! so two variables with the same name in different scopes never share a
+//! version number after the first.
+//!
+//! A variable is only put in SSA form when the graph shows every write to
+//! it. One is left out when part of it is assigned, as in `a[i] = x` or
+//! `p.x = 1`, or when it is written where the graph cannot tell whether the
+//! write happens: on the right of `&&` or `||`, in a branch of `?:`, or in
+//! an arm of a `match` used as part of an expression.
+
+use super::cfg::{match_arms, BlockId, Cfg, Element, Terminator};
+use super::dominators::Dominators;
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    AstArena, BinaryOp, Expr, ExprId, ExprKind, Function, Ident, NodeId, Pattern, PatternKind,
+    VarDecl,
+};
+use crate::semantic::{DefId, Resolution};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// One version of a local variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Value {
+    pub var: DefId,
+    pub version: u32,
+}
+
+/// `value = phi(operands)` at the start of a block: `value` is the operand
+/// of the predecessor control came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phi {
+    pub value: Value,
+    /// One per edge from a reachable predecessor, in the order of
+    /// [`Cfg::predecessors`].
+    pub operands: Vec<(BlockId, Value)>,
+}
+
+/// The SSA form of one function.
+#[derive(Debug, Clone, Default)]
+pub struct Ssa {
+    phis: Vec<Vec<Phi>>,
+    reads: HashMap<NodeId, Value>,
+    writes: HashMap<NodeId, Value>,
+    /// The name of every variable in SSA form.
+    names: HashMap<DefId, String>,
+}
+
+impl Ssa {
+    /// The SSA form of `function`, whose graph is `cfg` and whose names
+    /// `resolution` binds.
+    pub fn build(
+        arena: &AstArena,
+        function: &Function,
+        cfg: &Cfg,
+        dominators: &Dominators,
+        resolution: &Resolution,
+    ) -> Ssa {
+        let mut scan = Scan {
+            arena,
+            resolution,
+            conditional: 0,
+            events: Vec::new(),
+            names: HashMap::new(),
+            excluded: HashSet::new(),
+        };
+        for param in &function.params {
+            scan.declare(param.id, &param.name);
+        }
+        let params = std::mem::take(&mut scan.events);
+
+        let mut events: Vec<Vec<Event>> = Vec::with_capacity(cfg.len());
+        for (_, block) in cfg.blocks() {
+            for element in &block.elements {
+                match *element {
+                    Element::Stmt(stmt) => scan.visit_stmt(&arena[stmt]),
+                    Element::Expr(expr) => scan.visit_expr(&arena[expr]),
+                }
+            }
+            match &block.terminator {
+                Terminator::Branch {
+                    condition: expr, ..
+                }
+                | Terminator::Switch {
+                    scrutinee: expr, ..
+                }
+                | Terminator::Return(Some(expr)) => scan.visit_expr(&arena[*expr]),
+                Terminator::Goto(_) | Terminator::Return(None) | Terminator::Exit => {}
+            }
+            events.push(std::mem::take(&mut scan.events));
+        }
+        // The bindings of a `match` statement's arm are written where the
+        // arm's block starts.
+        let matches = match_arms(arena, function);
+        for (_, block) in cfg.blocks() {
+            let Terminator::Switch {
+                scrutinee, arms, ..
+            } = &block.terminator
+            else {
+                continue;
+            };
+            for (arm, target) in matches[scrutinee].iter().zip(arms) {
+                scan.visit_pattern(&arm.pattern);
+                let bindings = std::mem::take(&mut scan.events);
+                events[target.index()].splice(0..0, bindings);
+            }
+        }
+
+        let Scan {
+            mut names,
+            excluded,
+            ..
+        } = scan;
+        names.retain(|var, _| !excluded.contains(var));
+        for block in &mut events {
+            block.retain(|event| names.contains_key(&event.var()));
+        }
+
+        let mut ssa = Ssa {
+            phis: vec![Vec::new(); cfg.len()],
+            reads: HashMap::new(),
+            writes: HashMap::new(),
+            names,
+        };
+        for event in params {
+            if let Event::Write(node, var) = event {
+                if ssa.names.contains_key(&var) {
+                    ssa.writes.insert(node, Value { var, version: 0 });
+                }
+            }
+        }
+        ssa.place_phis(cfg, dominators, &events);
+        ssa.rename(cfg, dominators, &events);
+        ssa
+    }
+
+    /// The phis at the start of `block`.
+    pub fn phis(&self, block: BlockId) -> &[Phi] {
+        &self.phis[block.index()]
+    }
+
+    /// The version read by `node`: a variable expression, or the target of
+    /// a compound assignment such as `x += 1`. `None` if the variable is
+    /// not in SSA form or the node is unreachable.
+    pub fn read(&self, node: NodeId) -> Option<Value> {
+        self.reads.get(&node).copied()
+    }
+
+    /// The version written by `node`: a parameter, a declaration, a binding
+    /// pattern or an assignment to a variable.
+    pub fn write(&self, node: NodeId) -> Option<Value> {
+        self.writes.get(&node).copied()
+    }
+
+    /// Whether `var` is in SSA form.
+    pub fn contains(&self, var: DefId) -> bool {
+        self.names.contains_key(&var)
+    }
+
+    /// `value` as the variable's name and version, such as `x.2`.
+    pub fn name(&self, value: Value) -> String {
+        format!("{}.{}", self.names[&value.var], value.version)
+    }
+
+    /// Puts a phi for each variable in each block of the iterated dominance
+    /// frontier of the blocks writing it, where the variable is live.
+    fn place_phis(&mut self, cfg: &Cfg, dominators: &Dominators, events: &[Vec<Event>]) {
+        let order = cfg.reverse_postorder();
+        let live = live_in(cfg, &order, events);
+        let mut vars: Vec<DefId> = self.names.keys().copied().collect();
+        vars.sort();
+        for var in vars {
+            let mut work: Vec<BlockId> = order
+                .iter()
+                .copied()
+                .filter(|block| {
+                    events[block.index()]
+                        .iter()
+                        .any(|event| matches!(*event, Event::Write(_, written) if written == var))
+                })
+                .collect();
+            let mut queued: HashSet<BlockId> = work.iter().copied().collect();
+            let mut placed = HashSet::new();
+            while let Some(block) = work.pop() {
+                for &join in dominators.frontier(block) {
+                    if !live[join.index()].contains(&var) || !placed.insert(join) {
+                        continue;
+                    }
+                    let undefined = Value { var, version: 0 };
+                    let operands = cfg
+                        .predecessors(join)
+                        .iter()
+                        .filter(|&&pred| dominators.is_reachable(pred))
+                        .map(|&pred| (pred, undefined))
+                        .collect();
+                    self.phis[join.index()].push(Phi {
+                        value: undefined,
+                        operands,
+                    });
+                    if queued.insert(join) {
+                        work.push(join);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Gives every phi, read and write its version, walking the dominator
+    /// tree with a stack of the current version of each variable.
+    fn rename(&mut self, cfg: &Cfg, dominators: &Dominators, events: &[Vec<Event>]) {
+        enum Step {
+            Enter(BlockId),
+            /// Pops the versions a block pushed.
+            Leave(Vec<DefId>),
+        }
+
+        let names = self.names.clone();
+        let mut counters: HashMap<&str, u32> = HashMap::new();
+        let mut fresh = |var: DefId| {
+            let counter = counters.entry(names[&var].as_str()).or_insert(0);
+            *counter += 1;
+            Value {
+                var,
+                version: *counter,
+            }
+        };
+        let mut current: HashMap<DefId, Vec<Value>> = names
+            .keys()
+            .map(|&var| (var, vec![Value { var, version: 0 }]))
+            .collect();
+        let top = |current: &HashMap<DefId, Vec<Value>>, var: DefId| {
+            *current[&var].last().expect("version 0 is never popped")
+        };
+
+        let mut steps = vec![Step::Enter(Cfg::ENTRY)];
+        while let Some(step) = steps.pop() {
+            let block = match step {
+                Step::Enter(block) => block,
+                Step::Leave(pushed) => {
+                    for var in pushed {
+                        current.get_mut(&var).expect("pushed").pop();
+                    }
+                    continue;
+                }
+            };
+            let mut pushed = Vec::new();
+            for phi in &mut self.phis[block.index()] {
+                phi.value = fresh(phi.value.var);
+                current
+                    .get_mut(&phi.value.var)
+                    .expect("in SSA form")
+                    .push(phi.value);
+                pushed.push(phi.value.var);
+            }
+            for event in &events[block.index()] {
+                match *event {
+                    Event::Read(node, var) => {
+                        self.reads.insert(node, top(&current, var));
+                    }
+                    Event::Write(node, var) => {
+                        let value = fresh(var);
+                        self.writes.insert(node, value);
+                        current.get_mut(&var).expect("in SSA form").push(value);
+                        pushed.push(var);
+                    }
+                }
+            }
+            let mut successors = cfg.successors(block);
+            successors.dedup();
+            for successor in successors {
+                for phi in &mut self.phis[successor.index()] {
+                    for (pred, operand) in &mut phi.operands {
+                        if *pred == block {
+                            *operand = top(&current, phi.value.var);
+                        }
+                    }
+                }
+            }
+            steps.push(Step::Leave(pushed));
+            for &child in dominators.children(block).iter().rev() {
+                steps.push(Step::Enter(child));
+            }
+        }
+    }
+}
+
+/// A read or write of a variable, in the order a block evaluates them.
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    Read(NodeId, DefId),
+    Write(NodeId, DefId),
+}
+
+impl Event {
+    fn var(self) -> DefId {
+        match self {
+            Event::Read(_, var) | Event::Write(_, var) => var,
+        }
+    }
+}
+
+/// The variables live on entry to each block: read before being written,
+/// in the block or on some path from it.
+fn live_in(cfg: &Cfg, order: &[BlockId], events: &[Vec<Event>]) -> Vec<BTreeSet<DefId>> {
+    let mut reads = vec![BTreeSet::new(); cfg.len()];
+    let mut writes = vec![BTreeSet::new(); cfg.len()];
+    for &block in order {
+        let (reads, writes) = (&mut reads[block.index()], &mut writes[block.index()]);
+        for event in &events[block.index()] {
+            match *event {
+                Event::Read(_, var) if !writes.contains(&var) => {
+                    reads.insert(var);
+                }
+                Event::Read(..) => {}
+                Event::Write(_, var) => {
+                    writes.insert(var);
+                }
+            }
+        }
+    }
+    let mut live: Vec<BTreeSet<DefId>> = vec![BTreeSet::new(); cfg.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter().rev() {
+            let mut block_live: BTreeSet<DefId> = cfg
+                .successors(block)
+                .iter()
+                .flat_map(|successor| live[successor.index()].iter().copied())
+                .filter(|var| !writes[block.index()].contains(var))
+                .collect();
+            block_live.extend(&reads[block.index()]);
+            if block_live != live[block.index()] {
+                live[block.index()] = block_live;
+                changed = true;
+            }
+        }
+    }
+    live
+}
+
+/// Lists the reads and writes of variables in one part of a block.
+struct Scan<'a> {
+    arena: &'a AstArena,
+    resolution: &'a Resolution,
+    /// How many parts of an expression that may not be evaluated the walk
+    /// is inside.
+    conditional: usize,
+    events: Vec<Event>,
+    /// The name of every local variable declared so far.
+    names: HashMap<DefId, String>,
+    /// The variables that cannot be put in SSA form.
+    excluded: HashSet<DefId>,
+}
+
+impl Scan<'_> {
+    fn declare(&mut self, node: NodeId, name: &Ident) {
+        let Some(var) = self.resolution.declaration(node) else {
+            return;
+        };
+        self.names.insert(var, name.name.clone());
+        self.write(node, var);
+    }
+
+    fn read(&mut self, node: NodeId) {
+        if let Some(var) = self.resolution.use_of(node) {
+            self.events.push(Event::Read(node, var));
+        }
+    }
+
+    fn write(&mut self, node: NodeId, var: DefId) {
+        if self.conditional > 0 {
+            self.excluded.insert(var);
+        }
+        self.events.push(Event::Write(node, var));
+    }
+
+    /// `target op= value`, whose value is evaluated before `target` is
+    /// written.
+    fn assign(&mut self, assign: NodeId, op: Option<BinaryOp>, target: ExprId, value: ExprId) {
+        let arena = self.arena;
+        let variable = arena.ungrouped(target);
+        if !matches!(variable.kind, ExprKind::Variable(_)) {
+            self.visit_expr(&arena[target]);
+            self.visit_expr(&arena[value]);
+            if let Some(var) = self.base(target) {
+                self.excluded.insert(var);
+            }
+            return;
+        }
+        if op.is_some() {
+            self.read(variable.id);
+        }
+        self.visit_expr(&arena[value]);
+        if let Some(var) = self.resolution.use_of(variable.id) {
+            self.write(assign, var);
+        }
+    }
+
+    /// The variable that `target`, a field or element of a variable, is
+    /// part of.
+    fn base(&self, target: ExprId) -> Option<DefId> {
+        let expr = self.arena.ungrouped(target);
+        match expr.kind {
+            ExprKind::Variable(_) => self.resolution.use_of(expr.id),
+            ExprKind::Field { object, .. } | ExprKind::Index { object, .. } => self.base(object),
+            _ => None,
+        }
+    }
+
+    fn conditionally(&mut self, f: impl FnOnce(&mut Self)) {
+        self.conditional += 1;
+        f(self);
+        self.conditional -= 1;
+    }
+}
+
+impl<'a> Visitor<'a> for Scan<'a> {
+    fn arena(&self) -> &'a AstArena {
+        self.arena
+    }
+
+    fn visit_var_decl(&mut self, decl: &'a VarDecl) {
+        if let Some(init) = decl.init {
+            self.visit_expr(&self.arena[init]);
+        }
+        self.declare(decl.id, &decl.name);
+    }
+
+    fn visit_pattern(&mut self, pattern: &'a Pattern) {
+        if let PatternKind::Binding(name) = &pattern.kind {
+            self.declare(pattern.id, name);
+        }
+        visit::walk_pattern(self, pattern);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        let arena = self.arena;
+        match &expr.kind {
+            ExprKind::Variable(_) => self.read(expr.id),
+            ExprKind::Assign { op, target, value } => self.assign(expr.id, *op, *target, *value),
+            ExprKind::Binary {
+                op: BinaryOp::And | BinaryOp::Or,
+                left,
+                right,
+            } => {
+                self.visit_expr(&arena[*left]);
+                self.conditionally(|scan| scan.visit_expr(&arena[*right]));
+            }
+            ExprKind::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.visit_expr(&arena[*condition]);
+                self.conditionally(|scan| {
+                    scan.visit_expr(&arena[*then_expr]);
+                    scan.visit_expr(&arena[*else_expr]);
+                });
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                self.visit_expr(&arena[*scrutinee]);
+                self.conditionally(|scan| {
+                    for arm in arms {
+                        scan.visit_arm(arm);
+                    }
+                });
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Item, Program};
+    use crate::parser::parse;
+    use crate::semantic::resolve;
+    use crate::source::Span;
+    use pretty_assertions::assert_eq;
+
+    /// The first function in `source` in SSA form, one line per reachable
+    /// block: its phis, then the source text of each element and the
+    /// terminator's expression with every variable in SSA form replaced by
+    /// its version.
+    fn ssa(source: &str) -> Vec<String> {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, diagnostics) = resolve(&program);
+        assert_eq!(diagnostics, []);
+        let Some(Item::Function(function)) = program.items.first() else {
+            panic!("expected a function");
+        };
+        let cfg = Cfg::build(&program.arena, function);
+        let dominators = Dominators::compute(&cfg);
+        let ssa = Ssa::build(&program.arena, function, &cfg, &dominators, &resolution);
+
+        let mut lines = Vec::new();
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .filter_map(|param| ssa.write(param.id).map(|value| ssa.name(value)))
+            .collect();
+        lines.push(format!("params: {}", params.join(", ")));
+        for block in cfg.reverse_postorder() {
+            let mut line = format!("{}:", block);
+            for phi in ssa.phis(block) {
+                let operands: Vec<String> = phi
+                    .operands
+                    .iter()
+                    .map(|(pred, value)| format!("{} {}", pred, ssa.name(*value)))
+                    .collect();
+                line += &format!(" {} = phi({});", ssa.name(phi.value), operands.join(", "));
+            }
+            let mut spans: Vec<Span> = cfg[block]
+                .elements
+                .iter()
+                .map(|element| match *element {
+                    Element::Stmt(stmt) => program.arena[stmt].span,
+                    Element::Expr(expr) => program.arena[expr].span,
+                })
+                .collect();
+            match &cfg[block].terminator {
+                Terminator::Branch {
+                    condition: expr, ..
+                }
+                | Terminator::Switch {
+                    scrutinee: expr, ..
+                }
+                | Terminator::Return(Some(expr)) => spans.push(program.arena[*expr].span),
+                _ => {}
+            }
+            for span in spans {
+                line += &format!(" [{}]", versioned(source, &program, &ssa, span));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// The source text of `span` with the versions of its variables.
+    fn versioned(source: &str, program: &Program, ssa: &Ssa, span: Span) -> String {
+        struct Names<'a> {
+            program: &'a Program,
+            ssa: &'a Ssa,
+            names: Vec<(Span, String)>,
+        }
+
+        impl<'a> Visitor<'a> for Names<'a> {
+            fn arena(&self) -> &'a AstArena {
+                &self.program.arena
+            }
+
+            fn visit_var_decl(&mut self, decl: &'a VarDecl) {
+                if let Some(value) = self.ssa.write(decl.id) {
+                    self.names.push((decl.name.span, self.ssa.name(value)));
+                }
+                visit::walk_var_decl(self, decl);
+            }
+
+            fn visit_pattern(&mut self, pattern: &'a Pattern) {
+                if let Some(value) = self.ssa.write(pattern.id) {
+                    self.names.push((pattern.span, self.ssa.name(value)));
+                }
+                visit::walk_pattern(self, pattern);
+            }
+
+            fn visit_expr(&mut self, expr: &'a Expr) {
+                if let Some(value) = self.ssa.read(expr.id) {
+                    self.names.push((expr.span, self.ssa.name(value)));
+                }
+                if let ExprKind::Assign { target, value, .. } = &expr.kind {
+                    if let Some(written) = self.ssa.write(expr.id) {
+                        let target = &self.program.arena[*target];
+                        let read = self.ssa.read(target.id).map(|read| self.ssa.name(read));
+                        let name = match read {
+                            Some(read) => format!("{}<-{}", self.ssa.name(written), read),
+                            None => self.ssa.name(written),
+                        };
+                        self.names.push((target.span, name));
+                        self.visit_expr(&self.program.arena[*value]);
+                        return;
+                    }
+                }
+                visit::walk_expr(self, expr);
+            }
+        }
+
+        let mut names = Names {
+            program,
+            ssa,
+            names: Vec::new(),
+        };
+        names.visit_program(program);
+        let mut names: Vec<(Span, String)> = names
+            .names
+            .into_iter()
+            .filter(|(name, _)| span.start <= name.start && name.end <= span.end)
+            .collect();
+        names.sort_by_key(|(name, _)| name.start);
+        let mut text = String::new();
+        let mut at = span.start;
+        for (name, version) in names {
+            text += &source[at..name.start];
+            text += &version;
+            at = name.end;
+        }
+        text += &source[at..span.end];
+        text
+    }
+
+    #[test]
+    fn test_branches() {
+        let source =
+            "fn f(x: int) -> int { int y = 1; if (x > 0) { y = y + x; } else y = 3; return y; }";
+        assert_eq!(
+            ssa(source),
+            [
+                "params: x.0",
+                "bb0: [int y.1 = 1;] [x.0 > 0]",
+                "bb3: [y.2 = 3;]",
+                "bb2: [y.3 = y.1 + x.0;]",
+                "bb4: y.4 = phi(bb2 y.3, bb3 y.2); [y.4]",
+                "bb1:",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let source = "fn f(n: int) -> int { int s = 0; int i = 0; while (i < n) { int d = i * 2; s += d; i = i + 1; } return s; }";
+        assert_eq!(
+            ssa(source),
+            [
+                "params: n.0",
+                "bb0: [int s.1 = 0;] [int i.1 = 0;]",
+                "bb2: s.2 = phi(bb0 s.1, bb3 s.3); i.2 = phi(bb0 i.1, bb3 i.3); [i.2 < n.0]",
+                "bb4: [s.2]",
+                "bb1:",
+                "bb3: [int d.1 = i.2 * 2;] [s.3<-s.2 += d.1;] [i.3 = i.2 + 1;]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_excluded() {
+        // `a` is assigned through an index and `y` only if `c` holds, so
+        // neither is in SSA form; the bindings of a `match` statement are
+        // written where their arm starts.
+        let source = "fn f(c: bool, a: int[2], s: int) -> int { int y = 0; a[0] = 1; c && (y = 1) > 0; match s { 1 => y, n => n + y }; return y; }";
+        assert_eq!(
+            ssa(source),
+            [
+                "params: c.0, s.0",
+                "bb0: [int y = 0;] [a[0] = 1;] [c.0 && (y = 1) > 0;] [s.0]",
+                "bb4: [n.1 + y]",
+                "bb3: [y]",
+                "bb2: [y]",
+                "bb1:",
+            ]
+        );
+    }
+}
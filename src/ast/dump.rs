@@ -0,0 +1,513 @@
+//! Structured dumps of an AST, for debugging the grammar and for tools that
+//! want the tree rather than source text.
+//!
+//! Both formats are rendered from the same format-neutral [`Node`] view, so
+//! they always list the same nodes and fields. Statements are shown as the
+//! construct they wrap: a declaration statement is a single `VarDecl` node
+//! with the statement's id and span.
+
+use super::*;
+use crate::utils;
+
+/// Output format of [`dump`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DumpFormat {
+    /// Indented S-expressions: `(Kind start..end field=value ...)`.
+    #[default]
+    Sexpr,
+    /// Pretty-printed JSON with `kind`, `id` and `span` keys on every node.
+    Json,
+}
+
+/// Renders `program` in `format`.
+pub fn dump(program: &Program, format: DumpFormat) -> String {
+    let node = self::program(program);
+    let mut out = String::new();
+    match format {
+        DumpFormat::Sexpr => sexpr(&Value::Node(node), 0, &mut out),
+        DumpFormat::Json => json(&Value::Node(node), 0, &mut out),
+    }
+    out
+}
+
+struct Node {
+    kind: &'static str,
+    id: Option<NodeId>,
+    span: Span,
+    fields: Vec<(&'static str, Value)>,
+}
+
+enum Value {
+    Node(Node),
+    List(Vec<Value>),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// The `null` literal.
+    Null,
+    /// A part that was left out, such as a missing initializer.
+    Absent,
+}
+
+impl Node {
+    fn new(kind: &'static str, id: NodeId, span: Span) -> Self {
+        Self {
+            kind,
+            id: Some(id),
+            span,
+            fields: Vec::new(),
+        }
+    }
+
+    fn with(mut self, name: &'static str, value: Value) -> Self {
+        self.fields.push((name, value));
+        self
+    }
+}
+
+impl Value {
+    fn name(ident: &Ident) -> Self {
+        Value::Str(ident.name.clone())
+    }
+
+    fn list<T>(items: &[T], f: impl Fn(&T) -> Node) -> Self {
+        Value::List(items.iter().map(|item| Value::Node(f(item))).collect())
+    }
+
+    fn optional<T>(item: Option<&T>, f: impl Fn(&T) -> Node) -> Self {
+        item.map_or(Value::Absent, |item| Value::Node(f(item)))
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Value::Node(_) | Value::List(_))
+    }
+}
+
+fn program(program: &Program) -> Node {
+    Node {
+        kind: "Program",
+        id: None,
+        span: program.span,
+        fields: vec![(
+            "items",
+            Value::list(&program.items, |i| item(&program.arena, i)),
+        )],
+    }
+}
+
+fn item(arena: &AstArena, item: &Item) -> Node {
+    match item {
+        Item::Function(function) => Node::new("Function", function.id, function.span)
+            .with("name", Value::name(&function.name))
+            .with(
+                "type_params",
+                Value::list(&function.type_params, type_param),
+            )
+            .with(
+                "params",
+                Value::list(&function.params, |param| {
+                    Node::new("Param", param.id, param.span)
+                        .with("name", Value::name(&param.name))
+                        .with("type", Value::Node(ty(&param.ty)))
+                }),
+            )
+            .with(
+                "return_type",
+                Value::optional(function.return_type.as_ref(), ty),
+            )
+            .with("body", Value::Node(block(arena, &function.body))),
+        Item::Struct(decl) => Node::new("Struct", decl.id, decl.span)
+            .with("name", Value::name(&decl.name))
+            .with("type_params", Value::list(&decl.type_params, type_param))
+            .with(
+                "fields",
+                Value::list(&decl.fields, |field| {
+                    Node::new("Field", field.id, field.span)
+                        .with("name", Value::name(&field.name))
+                        .with("type", Value::Node(ty(&field.ty)))
+                }),
+            ),
+        Item::Enum(decl) => Node::new("Enum", decl.id, decl.span)
+            .with("name", Value::name(&decl.name))
+            .with(
+                "variants",
+                Value::list(&decl.variants, |variant| {
+                    Node::new("Variant", variant.id, variant.span)
+                        .with("name", Value::name(&variant.name))
+                        .with("fields", Value::list(&variant.fields, ty))
+                }),
+            ),
+        Item::Global(decl) => var_decl(arena, decl, decl.id, decl.span),
+    }
+}
+
+fn type_param(param: &TypeParam) -> Node {
+    Node::new("TypeParam", param.id, param.span).with("name", Value::name(&param.name))
+}
+
+fn ty(ty: &Type) -> Node {
+    let name = match &ty.kind {
+        TypeKind::Int => "int",
+        TypeKind::Float => "float",
+        TypeKind::Bool => "bool",
+        TypeKind::Char => "char",
+        TypeKind::String => "string",
+        TypeKind::Void => "void",
+        TypeKind::Named(name) => &name.name,
+        TypeKind::Array { element, len } => {
+            return Node::new("ArrayType", ty.id, ty.span)
+                .with(
+                    "len",
+                    match len {
+                        ArrayLen::Literal(len) => Value::Int(*len as i64),
+                        ArrayLen::Const(name) => Value::name(name),
+                    },
+                )
+                .with("element", Value::Node(self::ty(element)));
+        }
+        TypeKind::Generic { name, args } => {
+            return Node::new("GenericType", ty.id, ty.span)
+                .with("name", Value::name(name))
+                .with("args", Value::list(args, self::ty));
+        }
+        TypeKind::Function {
+            params,
+            return_type,
+        } => {
+            return Node::new("FunctionType", ty.id, ty.span)
+                .with("params", Value::list(params, self::ty))
+                .with(
+                    "return_type",
+                    Value::optional(return_type.as_deref(), self::ty),
+                );
+        }
+    };
+    Node::new("Type", ty.id, ty.span).with("name", Value::Str(name.to_string()))
+}
+
+fn var_decl(arena: &AstArena, decl: &VarDecl, id: NodeId, span: Span) -> Node {
+    let node = Node::new("VarDecl", id, span);
+    // Only shown when set, like the `const` keyword itself.
+    let node = if decl.constant {
+        node.with("const", Value::Bool(true))
+    } else {
+        node
+    };
+    node.with("name", Value::name(&decl.name))
+        .with("type", Value::optional(decl.ty.as_ref(), ty))
+        .with(
+            "init",
+            Value::optional(decl.init.as_ref(), |e| expr(arena, *e)),
+        )
+}
+
+fn block(arena: &AstArena, block: &Block) -> Node {
+    Node::new("Block", block.id, block.span).with("stmts", stmts(arena, &block.stmts))
+}
+
+fn stmts(arena: &AstArena, stmts: &[StmtId]) -> Value {
+    Value::list(stmts, |s| stmt(arena, *s))
+}
+
+fn stmt(arena: &AstArena, id: StmtId) -> Node {
+    let stmt = &arena[id];
+    let node = |kind| Node::new(kind, stmt.id, stmt.span);
+    let expr = |e: &ExprId| expr(arena, *e);
+    let child = |s: &StmtId| self::stmt(arena, *s);
+    match &stmt.kind {
+        StmtKind::VarDecl(decl) => var_decl(arena, decl, stmt.id, stmt.span),
+        StmtKind::Expr(e) => node("ExprStmt").with("expr", Value::Node(expr(e))),
+        StmtKind::Block(b) => node("Block").with("stmts", stmts(arena, &b.stmts)),
+        StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => node("If")
+            .with("condition", Value::Node(expr(condition)))
+            .with("then", Value::Node(child(then_branch)))
+            .with("else", Value::optional(else_branch.as_ref(), child)),
+        StmtKind::While { condition, body } => node("While")
+            .with("condition", Value::Node(expr(condition)))
+            .with("body", Value::Node(child(body))),
+        StmtKind::For {
+            init,
+            condition,
+            step,
+            body,
+        } => node("For")
+            .with("init", Value::optional(init.as_ref(), child))
+            .with("condition", Value::optional(condition.as_ref(), expr))
+            .with("step", Value::optional(step.as_ref(), expr))
+            .with("body", Value::Node(child(body))),
+        StmtKind::Return(value) => {
+            node("Return").with("value", Value::optional(value.as_ref(), expr))
+        }
+        StmtKind::Break => node("Break"),
+        StmtKind::Continue => node("Continue"),
+    }
+}
+
+fn expr(arena: &AstArena, id: ExprId) -> Node {
+    let e = &arena[id];
+    let node = |kind| Node::new(kind, e.id, e.span);
+    let expr = |e: &ExprId| expr(arena, *e);
+    let child = |e: &ExprId| Value::Node(expr(e));
+    match &e.kind {
+        ExprKind::Literal(value) => node("Literal").with("value", literal(value)),
+        ExprKind::Variable(name) => node("Variable").with("name", Value::name(name)),
+        ExprKind::Grouping(inner) => node("Grouping").with("expr", child(inner)),
+        ExprKind::IntToFloat(inner) => node("IntToFloat").with("expr", child(inner)),
+        ExprKind::Unary { op, operand } => node("Unary")
+            .with("op", Value::Str(op.to_string()))
+            .with("operand", child(operand)),
+        ExprKind::Binary { op, left, right } => node("Binary")
+            .with("op", Value::Str(op.to_string()))
+            .with("left", child(left))
+            .with("right", child(right)),
+        ExprKind::Assign { op, target, value } => node("Assign")
+            .with(
+                "op",
+                Value::Str(format!("{}=", op.map_or("", |op| op.as_str()))),
+            )
+            .with("target", child(target))
+            .with("value", child(value)),
+        ExprKind::Call { callee, args } => node("Call")
+            .with("callee", child(callee))
+            .with("args", Value::list(args, expr)),
+        ExprKind::Array(elements) => node("Array").with("elements", Value::list(elements, expr)),
+        ExprKind::Index { object, index } => node("Index")
+            .with("object", child(object))
+            .with("index", child(index)),
+        ExprKind::StructLiteral { name, fields } => {
+            node("StructLiteral").with("name", Value::name(name)).with(
+                "fields",
+                Value::list(fields, |field| {
+                    Node::new("FieldInit", field.id, field.span)
+                        .with("name", Value::name(&field.name))
+                        .with("value", child(&field.value))
+                }),
+            )
+        }
+        ExprKind::Field { object, field } => node("Field")
+            .with("field", Value::name(field))
+            .with("object", child(object)),
+        ExprKind::Conditional {
+            condition,
+            then_expr,
+            else_expr,
+        } => node("Conditional")
+            .with("condition", child(condition))
+            .with("then", child(then_expr))
+            .with("else", child(else_expr)),
+        ExprKind::Variant { enum_name, variant } => node("VariantPath")
+            .with("enum", Value::name(enum_name))
+            .with("variant", Value::name(variant)),
+        ExprKind::Match { scrutinee, arms } => {
+            node("Match").with("scrutinee", child(scrutinee)).with(
+                "arms",
+                Value::list(arms, |arm| {
+                    Node::new("MatchArm", arm.id, arm.span)
+                        .with("pattern", Value::Node(pattern(&arm.pattern)))
+                        .with(
+                            "body",
+                            Value::Node(match &arm.body {
+                                ArmBody::Expr(e) => expr(e),
+                                ArmBody::Block(b) => block(arena, b),
+                            }),
+                        )
+                }),
+            )
+        }
+    }
+}
+
+fn pattern(pattern: &Pattern) -> Node {
+    let node = |kind| Node::new(kind, pattern.id, pattern.span);
+    match &pattern.kind {
+        PatternKind::Wildcard => node("WildcardPattern"),
+        PatternKind::Literal(value) => node("LiteralPattern").with("value", literal(value)),
+        PatternKind::Binding(name) => node("BindingPattern").with("name", Value::name(name)),
+        PatternKind::Variant {
+            enum_name,
+            variant,
+            fields,
+        } => node("VariantPattern")
+            .with("enum", Value::name(enum_name))
+            .with("variant", Value::name(variant))
+            .with("fields", Value::list(fields, self::pattern)),
+    }
+}
+
+fn literal(value: &LiteralValue) -> Value {
+    match value {
+        LiteralValue::Integer(n) => Value::Int(*n),
+        LiteralValue::Float(x) => Value::Float(*x),
+        LiteralValue::String(s) => Value::Str(s.clone()),
+        LiteralValue::Boolean(b) => Value::Bool(*b),
+        LiteralValue::Null => Value::Null,
+        LiteralValue::None => Value::Absent,
+    }
+}
+
+/// S-expression rendering: scalar fields go on the node's line, child
+/// nodes and lists each on their own line, indented below it. Absent parts
+/// are left out.
+fn sexpr(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Node(node) => {
+            out.push_str(&format!("({} {}", node.kind, node.span));
+            let (scalars, children): (Vec<_>, Vec<_>) =
+                node.fields.iter().partition(|(_, value)| value.is_scalar());
+            for (name, value) in scalars {
+                if !matches!(value, Value::Absent) {
+                    out.push_str(&format!(" {}=", name));
+                    sexpr(value, indent, out);
+                }
+            }
+            for (name, value) in children {
+                newline(indent + 2, out);
+                out.push_str(&format!("{}: ", name));
+                sexpr(value, indent + 2, out);
+            }
+            out.push(')');
+        }
+        Value::List(items) => {
+            out.push('[');
+            for item in items {
+                newline(indent + 2, out);
+                sexpr(item, indent + 2, out);
+            }
+            out.push(']');
+        }
+        Value::Str(s) => out.push_str(&format!("{:?}", s)),
+        Value::Int(n) => out.push_str(&n.to_string()),
+        Value::Float(x) => out.push_str(&format!("{:?}", x)),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Null => out.push_str("null"),
+        Value::Absent => {}
+    }
+}
+
+/// JSON rendering with two-space indentation. Absent parts are `null`, so
+/// every node of a kind has the same keys.
+fn json(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Node(node) => {
+            out.push('{');
+            newline(indent + 2, out);
+            out.push_str(&format!("\"kind\": \"{}\",", node.kind));
+            if let Some(id) = node.id {
+                newline(indent + 2, out);
+                out.push_str(&format!("\"id\": {},", id.index()));
+            }
+            newline(indent + 2, out);
+            out.push_str(&format!(
+                "\"span\": {{\"start\": {}, \"end\": {}}}",
+                node.span.start, node.span.end
+            ));
+            for (name, value) in &node.fields {
+                out.push(',');
+                newline(indent + 2, out);
+                out.push_str(&format!("\"{}\": ", name));
+                json(value, indent + 2, out);
+            }
+            newline(indent, out);
+            out.push('}');
+        }
+        Value::List(items) if items.is_empty() => out.push_str("[]"),
+        Value::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(indent + 2, out);
+                json(item, indent + 2, out);
+            }
+            newline(indent, out);
+            out.push(']');
+        }
+        Value::Str(s) => utils::json_string(s, out),
+        Value::Int(n) => out.push_str(&n.to_string()),
+        Value::Float(x) => out.push_str(&format!("{:?}", x)),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Null | Value::Absent => out.push_str("null"),
+    }
+}
+
+fn newline(indent: usize, out: &mut String) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    const SOURCE: &str = "fn f(a: int) { return -a; }";
+
+    #[test]
+    fn test_sexpr() {
+        let (program, diagnostics) = parse(SOURCE);
+        assert_eq!(diagnostics, []);
+        assert_eq!(
+            dump(&program, DumpFormat::Sexpr),
+            r#"(Program 0..27
+  items: [
+    (Function 0..27 name="f"
+      type_params: []
+      params: [
+        (Param 5..11 name="a"
+          type: (Type 8..11 name="int"))]
+      body: (Block 13..27
+        stmts: [
+          (Return 15..25
+            value: (Unary 22..24 op="-"
+              operand: (Variable 23..24 name="a")))]))])"#
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let (program, diagnostics) = parse("let s = \"q\\\"\\n\"; int x;");
+        assert_eq!(diagnostics, []);
+        assert_eq!(
+            dump(&program, DumpFormat::Json),
+            r#"{
+  "kind": "Program",
+  "span": {"start": 0, "end": 23},
+  "items": [
+    {
+      "kind": "VarDecl",
+      "id": 1,
+      "span": {"start": 0, "end": 16},
+      "name": "s",
+      "type": null,
+      "init": {
+        "kind": "Literal",
+        "id": 0,
+        "span": {"start": 8, "end": 15},
+        "value": "q\"\n"
+      }
+    },
+    {
+      "kind": "VarDecl",
+      "id": 3,
+      "span": {"start": 17, "end": 23},
+      "name": "x",
+      "type": {
+        "kind": "Type",
+        "id": 2,
+        "span": {"start": 17, "end": 20},
+        "name": "int"
+      },
+      "init": null
+    }
+  ]
+}"#
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Mapping source positions to AST nodes.
+//!
+//! Editor features start from a cursor offset: hover wants the innermost
+//! node under it, go-to-definition the name there and the scopes around it.
+//! [`Program::node_at`] and [`Program::ancestors`] answer both from spans
+//! alone, descending only into nodes that contain the position, so a query
+//! costs the depth of the tree rather than its size.
+
+use super::visit::{self, Visitor};
+use super::{
+    AstArena, Block, EnumDecl, Expr, Field, Function, Ident, MatchArm, NodeId, Param, Pattern,
+    Program, Stmt, StructDecl, Type, TypeParam, VarDecl, Variant,
+};
+use crate::source::Span;
+
+/// A borrowed AST node of any kind.
+#[derive(Debug, Clone, Copy)]
+pub enum NodeRef<'ast> {
+    Function(&'ast Function),
+    TypeParam(&'ast TypeParam),
+    Param(&'ast Param),
+    Struct(&'ast StructDecl),
+    Field(&'ast Field),
+    Enum(&'ast EnumDecl),
+    Variant(&'ast Variant),
+    VarDecl(&'ast VarDecl),
+    Block(&'ast Block),
+    Stmt(&'ast Stmt),
+    Expr(&'ast Expr),
+    MatchArm(&'ast MatchArm),
+    Pattern(&'ast Pattern),
+    Type(&'ast Type),
+    Ident(&'ast Ident),
+}
+
+impl NodeRef<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            NodeRef::Function(node) => node.span,
+            NodeRef::TypeParam(node) => node.span,
+            NodeRef::Param(node) => node.span,
+            NodeRef::Struct(node) => node.span,
+            NodeRef::Field(node) => node.span,
+            NodeRef::Enum(node) => node.span,
+            NodeRef::Variant(node) => node.span,
+            NodeRef::VarDecl(node) => node.span,
+            NodeRef::Block(node) => node.span,
+            NodeRef::Stmt(node) => node.span,
+            NodeRef::Expr(node) => node.span,
+            NodeRef::MatchArm(node) => node.span,
+            NodeRef::Pattern(node) => node.span,
+            NodeRef::Type(node) => node.span,
+            NodeRef::Ident(node) => node.span,
+        }
+    }
+
+    /// The node's id; `None` for an [`Ident`], which has none of its own.
+    pub fn id(&self) -> Option<NodeId> {
+        match self {
+            NodeRef::Function(node) => Some(node.id),
+            NodeRef::TypeParam(node) => Some(node.id),
+            NodeRef::Param(node) => Some(node.id),
+            NodeRef::Struct(node) => Some(node.id),
+            NodeRef::Field(node) => Some(node.id),
+            NodeRef::Enum(node) => Some(node.id),
+            NodeRef::Variant(node) => Some(node.id),
+            NodeRef::VarDecl(node) => Some(node.id),
+            NodeRef::Block(node) => Some(node.id),
+            NodeRef::Stmt(node) => Some(node.id),
+            NodeRef::Expr(node) => Some(node.id),
+            NodeRef::MatchArm(node) => Some(node.id),
+            NodeRef::Pattern(node) => Some(node.id),
+            NodeRef::Type(node) => Some(node.id),
+            NodeRef::Ident(_) => None,
+        }
+    }
+
+    /// Whether both refer to the very same node, not merely equal ones.
+    fn is(&self, other: &NodeRef<'_>) -> bool {
+        fn addr<T>(node: &T) -> *const () {
+            node as *const T as *const ()
+        }
+        let address = |node: &NodeRef<'_>| match *node {
+            NodeRef::Function(node) => addr(node),
+            NodeRef::TypeParam(node) => addr(node),
+            NodeRef::Param(node) => addr(node),
+            NodeRef::Struct(node) => addr(node),
+            NodeRef::Field(node) => addr(node),
+            NodeRef::Enum(node) => addr(node),
+            NodeRef::Variant(node) => addr(node),
+            NodeRef::VarDecl(node) => addr(node),
+            NodeRef::Block(node) => addr(node),
+            NodeRef::Stmt(node) => addr(node),
+            NodeRef::Expr(node) => addr(node),
+            NodeRef::MatchArm(node) => addr(node),
+            NodeRef::Pattern(node) => addr(node),
+            NodeRef::Type(node) => addr(node),
+            NodeRef::Ident(node) => addr(node),
+        };
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && address(self) == address(other)
+    }
+}
+
+impl Program {
+    /// The innermost node whose span contains the byte at `offset`, if any.
+    ///
+    /// Spans are half-open, so an offset just past the end of a name finds
+    /// whatever encloses the name, not the name itself.
+    pub fn node_at(&self, offset: usize) -> Option<NodeRef<'_>> {
+        let mut path = Path::new(&self.arena, Target::Offset(offset));
+        path.visit_program(self);
+        path.nodes.pop()
+    }
+
+    /// The nodes enclosing `node`, innermost first; empty if `node` is an
+    /// item or is not part of this program.
+    pub fn ancestors<'ast>(&'ast self, node: NodeRef<'ast>) -> Vec<NodeRef<'ast>> {
+        let mut path = Path::new(&self.arena, Target::Node(node));
+        path.visit_program(self);
+        if !path.found {
+            return Vec::new();
+        }
+        path.nodes.pop();
+        path.nodes.reverse();
+        path.nodes
+    }
+}
+
+enum Target<'ast> {
+    Offset(usize),
+    Node(NodeRef<'ast>),
+}
+
+/// Collects the chain of nodes from an item down to the target.
+struct Path<'ast> {
+    arena: &'ast AstArena,
+    target: Target<'ast>,
+    /// Outermost first.
+    nodes: Vec<NodeRef<'ast>>,
+    found: bool,
+}
+
+impl<'ast> Path<'ast> {
+    fn new(arena: &'ast AstArena, target: Target<'ast>) -> Self {
+        Self {
+            arena,
+            target,
+            nodes: Vec::new(),
+            found: false,
+        }
+    }
+
+    fn node(&mut self, node: NodeRef<'ast>, walk: impl FnOnce(&mut Self)) {
+        if self.found {
+            return;
+        }
+        let span = node.span();
+        match self.target {
+            // Siblings never overlap, so at most one child contains the
+            // offset and the path never needs to back out.
+            Target::Offset(offset) => {
+                if span.start <= offset && offset < span.end {
+                    self.nodes.push(node);
+                    walk(self);
+                }
+            }
+            // A zero-width target can sit on the boundary of two siblings,
+            // so a subtree that turns out not to hold it is dropped again.
+            Target::Node(target) => {
+                let within = target.span();
+                if span.start <= within.start && within.end <= span.end {
+                    self.nodes.push(node);
+                    if node.is(&target) {
+                        self.found = true;
+                        return;
+                    }
+                    walk(self);
+                    if !self.found {
+                        self.nodes.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for Path<'ast> {
+    fn arena(&self) -> &'ast AstArena {
+        self.arena
+    }
+
+    fn visit_function(&mut self, function: &'ast Function) {
+        self.node(NodeRef::Function(function), |p| {
+            visit::walk_function(p, function)
+        });
+    }
+
+    fn visit_type_param(&mut self, param: &'ast TypeParam) {
+        self.node(NodeRef::TypeParam(param), |p| {
+            visit::walk_type_param(p, param)
+        });
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        self.node(NodeRef::Param(param), |p| visit::walk_param(p, param));
+    }
+
+    fn visit_struct(&mut self, decl: &'ast StructDecl) {
+        self.node(NodeRef::Struct(decl), |p| visit::walk_struct(p, decl));
+    }
+
+    fn visit_field(&mut self, field: &'ast Field) {
+        self.node(NodeRef::Field(field), |p| visit::walk_field(p, field));
+    }
+
+    fn visit_enum(&mut self, decl: &'ast EnumDecl) {
+        self.node(NodeRef::Enum(decl), |p| visit::walk_enum(p, decl));
+    }
+
+    fn visit_variant(&mut self, variant: &'ast Variant) {
+        self.node(NodeRef::Variant(variant), |p| {
+            visit::walk_variant(p, variant)
+        });
+    }
+
+    fn visit_var_decl(&mut self, decl: &'ast VarDecl) {
+        self.node(NodeRef::VarDecl(decl), |p| visit::walk_var_decl(p, decl));
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        self.node(NodeRef::Block(block), |p| visit::walk_block(p, block));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        self.node(NodeRef::Stmt(stmt), |p| visit::walk_stmt(p, stmt));
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        self.node(NodeRef::Expr(expr), |p| visit::walk_expr(p, expr));
+    }
+
+    fn visit_arm(&mut self, arm: &'ast MatchArm) {
+        self.node(NodeRef::MatchArm(arm), |p| visit::walk_arm(p, arm));
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        self.node(NodeRef::Pattern(pattern), |p| {
+            visit::walk_pattern(p, pattern)
+        });
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type) {
+        self.node(NodeRef::Type(ty), |p| visit::walk_type(p, ty));
+    }
+
+    fn visit_ident(&mut self, ident: &'ast Ident) {
+        self.node(NodeRef::Ident(ident), |_| {});
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use pretty_assertions::assert_eq;
+
+    fn parse(source: &str) -> Program {
+        let (program, diagnostics) = Parser::new(Scanner::new(source)).parse_program();
+        assert_eq!(diagnostics, []);
+        program
+    }
+
+    /// The kind and text of a node, e.g. `Expr "a + b"`.
+    fn describe(source: &str, node: NodeRef<'_>) -> String {
+        let debug = format!("{:?}", node);
+        let kind = &debug[..debug.find('(').unwrap()];
+        let span = node.span();
+        format!("{} {:?}", kind, &source[span.start..span.end])
+    }
+
+    #[test]
+    fn test_node_at() {
+        let source = "fn f(n: int) -> int {\n    return n * (n + 1);\n}\n";
+        let program = parse(source);
+        let at = |needle: &str| {
+            let offset = source.find(needle).unwrap();
+            program.node_at(offset).map(|node| describe(source, node))
+        };
+        assert_eq!(at("f(").as_deref(), Some(r#"Ident "f""#));
+        assert_eq!(at("int)").as_deref(), Some(r#"Type "int""#));
+        assert_eq!(
+            at("return").as_deref(),
+            Some(r#"Stmt "return n * (n + 1);""#)
+        );
+        assert_eq!(at("* (").as_deref(), Some(r#"Expr "n * (n + 1)""#));
+        assert_eq!(at("+ 1").as_deref(), Some(r#"Expr "n + 1""#));
+        assert_eq!(at("1)").as_deref(), Some(r#"Expr "1""#));
+        assert_eq!(
+            at("\n}").as_deref(),
+            Some(r#"Block "{\n    return n * (n + 1);\n}""#)
+        );
+        // Nothing covers the space between items or the end of the file.
+        assert!(program.node_at(source.len() - 1).is_none());
+        assert!(program.node_at(source.len()).is_none());
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let source = "struct P { x: int }\nfn f(p: P) { while (true) { p.x = 1; } }\n";
+        let program = parse(source);
+        let offset = source.find("x = ").unwrap();
+        let node = program.node_at(offset).unwrap();
+        assert_eq!(describe(source, node), r#"Ident "x""#);
+        let ancestors: Vec<_> = program
+            .ancestors(node)
+            .into_iter()
+            .map(|node| describe(source, node))
+            .collect();
+        assert_eq!(
+            ancestors,
+            [
+                r#"Expr "p.x""#,
+                r#"Expr "p.x = 1""#,
+                r#"Stmt "p.x = 1;""#,
+                r#"Block "{ p.x = 1; }""#,
+                r#"Stmt "{ p.x = 1; }""#,
+                r#"Stmt "while (true) { p.x = 1; }""#,
+                r#"Block "{ while (true) { p.x = 1; } }""#,
+                r#"Function "fn f(p: P) { while (true) { p.x = 1; } }""#,
+            ]
+        );
+        // Items have no ancestors.
+        let function = *program.ancestors(node).last().unwrap();
+        assert!(program.ancestors(function).is_empty());
+
+        // An equal node from another program is not one of this program's.
+        let other = parse(source);
+        assert!(other.ancestors(node).is_empty());
+    }
+}
@@ -0,0 +1,555 @@
+//! Abstract syntax tree produced by the [`parser`](crate::parser).
+//!
+//! Every node records the span of source text it was parsed from and a
+//! [`NodeId`] unique within its program. Expressions and statements live in
+//! the program's [`AstArena`] and refer to each other by [`ExprId`] and
+//! [`StmtId`] handles; other nodes are owned by their parents.
+
+pub mod dump;
+pub mod locate;
+pub mod pretty;
+pub mod visit;
+pub mod visit_mut;
+
+pub use locate::NodeRef;
+
+use crate::lexer::LiteralValue;
+use crate::source::Span;
+use crate::utils::Symbol;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+/// Identifies one node of a [`Program`]. The parser numbers nodes from 0
+/// in the order it finishes them, so a node's id is greater than the ids
+/// of the nodes inside it, and later passes can key side tables by id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Handle of an expression in an [`AstArena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+/// Handle of a statement in an [`AstArena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(u32);
+
+impl ExprId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl StmtId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Storage for the expressions and statements of a tree.
+///
+/// Nodes are kept in one `Vec` per kind and hold their children as handles
+/// rather than `Box`es, so building a tree costs a push per node instead of
+/// an allocation, and walking it reads memory mostly in order. A handle is
+/// only meaningful for the arena that issued it. Nodes are never freed: one
+/// a pass replaces stays in the arena, unreachable from the tree.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AstArena {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+}
+
+impl AstArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub fn alloc_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    /// `expr` with any enclosing parentheses removed.
+    pub fn ungrouped(&self, expr: ExprId) -> &Expr {
+        match &self[expr].kind {
+            ExprKind::Grouping(inner) => self.ungrouped(*inner),
+            _ => &self[expr],
+        }
+    }
+
+    pub fn expr_count(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn stmt_count(&self) -> usize {
+        self.stmts.len()
+    }
+}
+
+impl Index<ExprId> for AstArena {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.index()]
+    }
+}
+
+impl IndexMut<ExprId> for AstArena {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.index()]
+    }
+}
+
+impl Index<StmtId> for AstArena {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.index()]
+    }
+}
+
+impl IndexMut<StmtId> for AstArena {
+    fn index_mut(&mut self, id: StmtId) -> &mut Stmt {
+        &mut self.stmts[id.index()]
+    }
+}
+
+/// A whole source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub items: Vec<Item>,
+    pub span: Span,
+    /// Number of [`NodeId`]s handed out, all below this; a pass that adds
+    /// nodes numbers them from here.
+    pub node_count: usize,
+    /// Every expression and statement in `items`.
+    pub arena: AstArena,
+}
+
+/// A top-level declaration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Function(Function),
+    Struct(StructDecl),
+    Enum(EnumDecl),
+    Global(VarDecl),
+}
+
+impl Item {
+    pub fn span(&self) -> Span {
+        match self {
+            Item::Function(function) => function.span,
+            Item::Struct(decl) => decl.span,
+            Item::Enum(decl) => decl.span,
+            Item::Global(decl) => decl.span,
+        }
+    }
+}
+
+/// A name as written in the source, with its interned symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident {
+    pub name: String,
+    pub symbol: Symbol,
+    pub span: Span,
+}
+
+/// `fn name<T, ...>(params) -> return_type { body }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub id: NodeId,
+    pub name: Ident,
+    /// Empty unless the function is generic.
+    pub type_params: Vec<TypeParam>,
+    pub params: Vec<Param>,
+    /// `None` when the `-> type` part is left out, meaning `void`.
+    pub return_type: Option<Type>,
+    pub body: Block,
+    pub span: Span,
+}
+
+/// `name: type`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub id: NodeId,
+    pub name: Ident,
+    pub ty: Type,
+    pub span: Span,
+}
+
+/// `struct Name<T, ...> { field: type, ... }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructDecl {
+    pub id: NodeId,
+    pub name: Ident,
+    /// Empty unless the struct is generic.
+    pub type_params: Vec<TypeParam>,
+    pub fields: Vec<Field>,
+    pub span: Span,
+}
+
+/// A name in the `<T, ...>` after a generic function or struct's name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParam {
+    pub id: NodeId,
+    pub name: Ident,
+    pub span: Span,
+}
+
+/// `name: type` in a struct declaration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub id: NodeId,
+    pub name: Ident,
+    pub ty: Type,
+    pub span: Span,
+}
+
+/// `enum Name { Variant, Variant(type, ...), ... }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDecl {
+    pub id: NodeId,
+    pub name: Ident,
+    pub variants: Vec<Variant>,
+    pub span: Span,
+}
+
+/// `Name` or `Name(type, ...)` in an enum declaration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub id: NodeId,
+    pub name: Ident,
+    /// Types of the payload; empty for a unit variant.
+    pub fields: Vec<Type>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Type {
+    pub id: NodeId,
+    pub kind: TypeKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    Int,
+    Float,
+    Bool,
+    Char,
+    String,
+    Void,
+    /// A user-defined type, such as a struct, or a type parameter.
+    Named(Ident),
+    /// `Name<type, ...>`, a generic struct with its type arguments.
+    Generic {
+        name: Ident,
+        args: Vec<Type>,
+    },
+    /// `element[len]`
+    Array {
+        element: Box<Type>,
+        len: ArrayLen,
+    },
+    /// `fn(params) -> return_type`; `None` returns `void`, as for a
+    /// function declaration.
+    Function {
+        params: Vec<Type>,
+        return_type: Option<Box<Type>>,
+    },
+}
+
+/// The length of an array type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayLen {
+    /// `int[3]`
+    Literal(u64),
+    /// `int[N]`, naming a constant whose value is the length.
+    Const(Ident),
+}
+
+impl fmt::Display for ArrayLen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArrayLen::Literal(len) => write!(f, "{}", len),
+            ArrayLen::Const(name) => f.write_str(&name.name),
+        }
+    }
+}
+
+/// `type name = init;` or `let name: type = init;`, either of them after
+/// `const` for a constant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDecl {
+    pub id: NodeId,
+    /// Declared with `const`: the initializer, which is required, must be a
+    /// constant expression.
+    pub constant: bool,
+    /// `None` for a `let` without a type annotation, whose type comes from
+    /// the initializer.
+    pub ty: Option<Type>,
+    pub name: Ident,
+    pub init: Option<ExprId>,
+    pub span: Span,
+}
+
+/// `{ stmts }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: NodeId,
+    pub stmts: Vec<StmtId>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub id: NodeId,
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    VarDecl(VarDecl),
+    /// An expression followed by `;`.
+    Expr(ExprId),
+    Block(Block),
+    If {
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    },
+    While {
+        condition: ExprId,
+        body: StmtId,
+    },
+    /// `for (init; condition; step) body`; every header part is optional.
+    For {
+        /// A variable declaration or expression statement.
+        init: Option<StmtId>,
+        condition: Option<ExprId>,
+        step: Option<ExprId>,
+        body: StmtId,
+    },
+    Return(Option<ExprId>),
+    Break,
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub id: NodeId,
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(LiteralValue),
+    Variable(Ident),
+    /// `(expr)`. Kept in the tree so the span covers the parentheses; passes
+    /// that only care about meaning look through it with
+    /// [`AstArena::ungrouped`].
+    Grouping(ExprId),
+    /// An `int` used as a `float`. Never parsed: the type checker wraps
+    /// operands in it where it allows the conversion, so later phases need
+    /// not work out where one happens.
+    IntToFloat(ExprId),
+    /// `op operand`
+    Unary {
+        op: UnaryOp,
+        operand: ExprId,
+    },
+    Binary {
+        op: BinaryOp,
+        left: ExprId,
+        right: ExprId,
+    },
+    /// `target = value`, or `target op= value` when `op` is set.
+    Assign {
+        op: Option<BinaryOp>,
+        target: ExprId,
+        value: ExprId,
+    },
+    /// `callee(args)`
+    Call {
+        callee: ExprId,
+        args: Vec<ExprId>,
+    },
+    /// `[a, b, c]`
+    Array(Vec<ExprId>),
+    /// `object[index]`
+    Index {
+        object: ExprId,
+        index: ExprId,
+    },
+    /// `Name { field: value, ... }`
+    StructLiteral {
+        name: Ident,
+        fields: Vec<FieldInit>,
+    },
+    /// `object.field`
+    Field {
+        object: ExprId,
+        field: Ident,
+    },
+    /// `condition ? then_expr : else_expr`
+    Conditional {
+        condition: ExprId,
+        then_expr: ExprId,
+        else_expr: ExprId,
+    },
+    /// `Enum::Variant`. A variant with a payload is built by calling it:
+    /// `Shape::Circle(1.0)`.
+    Variant {
+        enum_name: Ident,
+        variant: Ident,
+    },
+    /// `match scrutinee { pattern => body, ... }`
+    Match {
+        scrutinee: ExprId,
+        arms: Vec<MatchArm>,
+    },
+}
+
+/// `pattern => body` in a `match`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub id: NodeId,
+    pub pattern: Pattern,
+    pub body: ArmBody,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArmBody {
+    Expr(ExprId),
+    /// `{ stmts }`, which needs no `,` after it.
+    Block(Block),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub id: NodeId,
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    /// `_`, which matches anything.
+    Wildcard,
+    /// A literal the value must equal.
+    Literal(LiteralValue),
+    /// A name that matches anything and binds it.
+    Binding(Ident),
+    /// `Enum::Variant`, or `Enum::Variant(pattern, ...)` matching the
+    /// payload.
+    Variant {
+        enum_name: Ident,
+        variant: Ident,
+        fields: Vec<Pattern>,
+    },
+}
+
+/// `name: value` in a struct literal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldInit {
+    pub id: NodeId,
+    pub name: Ident,
+    pub value: ExprId,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    /// `-`
+    Neg,
+    /// `!`
+    Not,
+    /// `~`
+    BitNot,
+}
+
+impl UnaryOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::BitNot => "~",
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl BinaryOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
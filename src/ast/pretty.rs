@@ -0,0 +1,818 @@
+//! Renders an AST back into source code.
+//!
+//! The output uses four-space indentation, one statement per line and
+//! spaces around infix operators. Parentheses written in the source are
+//! kept, and more are added wherever the tree would otherwise read
+//! differently, so printing a parsed program and parsing the result gives
+//! back the same tree (apart from spans and ids).
+
+use super::*;
+use crate::parser::expr::{Infix, INFIX_OPERATORS};
+
+/// Binding strength of prefix operators, above every infix operator.
+const UNARY: u8 = 13;
+/// Calls, field accesses and subscripts.
+const POSTFIX: u8 = 14;
+/// Expressions that never need parentheses.
+const PRIMARY: u8 = 15;
+
+/// Formats `program` as source code.
+pub fn print(program: &Program) -> String {
+    let mut printer = Printer::new(&program.arena);
+    for (i, item) in program.items.iter().enumerate() {
+        // Consecutive globals stay together; everything else is separated
+        // by a blank line.
+        let adjacent_globals = matches!(
+            (i.checked_sub(1).map(|prev| &program.items[prev]), item),
+            (Some(Item::Global(_)), Item::Global(_))
+        );
+        if i > 0 && !adjacent_globals {
+            printer.out.push('\n');
+        }
+        printer.item(item);
+    }
+    printer.out
+}
+
+/// Gives the name to print for the variable a node declares, reads or
+/// assigns, in place of its name in the source. It is asked about variable
+/// expressions, assignments to a variable, declarations, parameters and
+/// binding patterns, and returns `None` to keep the source's name.
+pub type Names<'a> = &'a dyn Fn(NodeId) -> Option<String>;
+
+/// Formats parts of a program on their own, such as the statements of a
+/// basic block.
+pub struct Fragments<'a> {
+    arena: &'a AstArena,
+    indent: usize,
+    names: Option<Names<'a>>,
+}
+
+impl<'a> Fragments<'a> {
+    pub fn new(arena: &'a AstArena) -> Self {
+        Self {
+            arena,
+            indent: 0,
+            names: None,
+        }
+    }
+
+    /// Indents the lines after the first of a fragment, such as the arms of
+    /// a `match`, as if it started `indent` levels in.
+    pub fn with_indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    /// Prints variables with the names `names` gives.
+    pub fn with_names(mut self, names: Names<'a>) -> Self {
+        self.names = Some(names);
+        self
+    }
+
+    /// `fn name(params) -> type`, without the body.
+    pub fn signature(&self, function: &Function) -> String {
+        self.print(|printer| printer.signature(function))
+    }
+
+    pub fn stmt(&self, stmt: StmtId) -> String {
+        self.print(|printer| printer.stmt(stmt))
+    }
+
+    pub fn expr(&self, expr: ExprId) -> String {
+        self.print(|printer| printer.expr(expr, 0))
+    }
+
+    pub fn pattern(&self, pattern: &Pattern) -> String {
+        self.print(|printer| printer.pattern(pattern))
+    }
+
+    fn print(&self, f: impl FnOnce(&mut Printer<'a>)) -> String {
+        let mut printer = Printer::new(self.arena);
+        printer.indent = self.indent;
+        printer.names = self.names;
+        f(&mut printer);
+        printer.out
+    }
+}
+
+struct Printer<'a> {
+    arena: &'a AstArena,
+    out: String,
+    indent: usize,
+    names: Option<Names<'a>>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(arena: &'a AstArena) -> Self {
+        Self {
+            arena,
+            out: String::new(),
+            indent: 0,
+            names: None,
+        }
+    }
+
+    /// The name of the variable `node` declares, reads or assigns.
+    fn name(&mut self, node: NodeId, name: &Ident) {
+        match self.names.and_then(|names| names(node)) {
+            Some(name) => self.out.push_str(&name),
+            None => self.out.push_str(&name.name),
+        }
+    }
+
+    fn line_start(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Function(function) => self.function(function),
+            Item::Struct(decl) => self.struct_decl(decl),
+            Item::Enum(decl) => self.enum_decl(decl),
+            Item::Global(decl) => {
+                self.var_decl(decl);
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.signature(function);
+        self.out.push(' ');
+        self.block(&function.body);
+        self.out.push('\n');
+    }
+
+    fn signature(&mut self, function: &Function) {
+        self.out.push_str("fn ");
+        self.out.push_str(&function.name.name);
+        self.type_params(&function.type_params);
+        self.out.push('(');
+        for (i, param) in function.params.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.name(param.id, &param.name);
+            self.out.push_str(": ");
+            self.ty(&param.ty);
+        }
+        self.out.push(')');
+        if let Some(return_type) = &function.return_type {
+            self.out.push_str(" -> ");
+            self.ty(return_type);
+        }
+    }
+
+    fn struct_decl(&mut self, decl: &StructDecl) {
+        self.out.push_str("struct ");
+        self.out.push_str(&decl.name.name);
+        self.type_params(&decl.type_params);
+        if decl.fields.is_empty() {
+            self.out.push_str(" {}\n");
+            return;
+        }
+        self.out.push_str(" {\n");
+        for field in &decl.fields {
+            self.out.push_str("    ");
+            self.out.push_str(&field.name.name);
+            self.out.push_str(": ");
+            self.ty(&field.ty);
+            self.out.push_str(",\n");
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn type_params(&mut self, params: &[TypeParam]) {
+        if params.is_empty() {
+            return;
+        }
+        self.out.push('<');
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(&param.name.name);
+        }
+        self.out.push('>');
+    }
+
+    fn enum_decl(&mut self, decl: &EnumDecl) {
+        self.out.push_str("enum ");
+        self.out.push_str(&decl.name.name);
+        if decl.variants.is_empty() {
+            self.out.push_str(" {}\n");
+            return;
+        }
+        self.out.push_str(" {\n");
+        for variant in &decl.variants {
+            self.out.push_str("    ");
+            self.out.push_str(&variant.name.name);
+            if !variant.fields.is_empty() {
+                self.out.push('(');
+                self.types(&variant.fields);
+                self.out.push(')');
+            }
+            self.out.push_str(",\n");
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn types(&mut self, types: &[Type]) {
+        for (i, ty) in types.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.ty(ty);
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        let name = match &ty.kind {
+            TypeKind::Int => "int",
+            TypeKind::Float => "float",
+            TypeKind::Bool => "bool",
+            TypeKind::Char => "char",
+            TypeKind::String => "string",
+            TypeKind::Void => "void",
+            TypeKind::Named(name) => &name.name,
+            TypeKind::Generic { name, args } => {
+                self.out.push_str(&name.name);
+                self.out.push('<');
+                self.types(args);
+                self.out.push('>');
+                return;
+            }
+            TypeKind::Array { element, len } => {
+                self.ty(element);
+                self.out.push_str(&format!("[{}]", len));
+                return;
+            }
+            TypeKind::Function {
+                params,
+                return_type,
+            } => {
+                self.out.push_str("fn(");
+                self.types(params);
+                self.out.push(')');
+                if let Some(return_type) = return_type {
+                    self.out.push_str(" -> ");
+                    self.ty(return_type);
+                }
+                return;
+            }
+        };
+        self.out.push_str(name);
+    }
+
+    /// A declaration without indentation or line break, as it appears in a
+    /// `for` header.
+    fn var_decl(&mut self, decl: &VarDecl) {
+        if decl.constant {
+            self.out.push_str("const ");
+        }
+        match &decl.ty {
+            Some(ty) => {
+                self.ty(ty);
+                self.out.push(' ');
+                self.name(decl.id, &decl.name);
+            }
+            None => {
+                if !decl.constant {
+                    self.out.push_str("let ");
+                }
+                self.name(decl.id, &decl.name);
+            }
+        }
+        if let Some(init) = &decl.init {
+            self.out.push_str(" = ");
+            self.expr(*init, 0);
+        }
+        self.out.push(';');
+    }
+
+    /// `{ ... }` starting at the current position and ending after `}`.
+    fn block(&mut self, block: &Block) {
+        if block.stmts.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.indent += 1;
+        for stmt in &block.stmts {
+            self.line_start();
+            self.stmt(*stmt);
+            self.out.push('\n');
+        }
+        self.indent -= 1;
+        self.line_start();
+        self.out.push('}');
+    }
+
+    /// A statement starting at the current position, without a final line
+    /// break.
+    fn stmt(&mut self, stmt: StmtId) {
+        let arena = self.arena;
+        match &arena[stmt].kind {
+            StmtKind::VarDecl(decl) => self.var_decl(decl),
+            // Like a block, a `match` statement needs no `;`.
+            StmtKind::Expr(expr) if matches!(arena[*expr].kind, ExprKind::Match { .. }) => {
+                self.expr(*expr, 0)
+            }
+            StmtKind::Expr(expr) => {
+                // A statement starting with `match` ends after its `}`, so
+                // anything following it needs the whole expression grouped.
+                let min_precedence = if starts_with_match(arena, *expr) {
+                    PRIMARY + 1
+                } else {
+                    0
+                };
+                self.expr(*expr, min_precedence);
+                self.out.push(';');
+            }
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.out.push_str("if (");
+                self.expr(*condition, 0);
+                self.out.push(')');
+                self.body(*then_branch);
+                if let Some(else_branch) = *else_branch {
+                    if matches!(arena[*then_branch].kind, StmtKind::Block(_)) {
+                        self.out.push(' ');
+                    } else {
+                        self.out.push('\n');
+                        self.line_start();
+                    }
+                    self.out.push_str("else");
+                    if matches!(arena[else_branch].kind, StmtKind::If { .. }) {
+                        self.out.push(' ');
+                        self.stmt(else_branch);
+                    } else {
+                        self.body(else_branch);
+                    }
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.out.push_str("while (");
+                self.expr(*condition, 0);
+                self.out.push(')');
+                self.body(*body);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                self.out.push_str("for (");
+                match init {
+                    Some(init) => self.stmt(*init),
+                    None => self.out.push(';'),
+                }
+                if let Some(condition) = condition {
+                    self.out.push(' ');
+                    self.expr(*condition, 0);
+                }
+                self.out.push(';');
+                if let Some(step) = step {
+                    self.out.push(' ');
+                    self.expr(*step, 0);
+                }
+                self.out.push(')');
+                self.body(*body);
+            }
+            StmtKind::Return(value) => {
+                self.out.push_str("return");
+                if let Some(value) = value {
+                    self.out.push(' ');
+                    self.expr(*value, 0);
+                }
+                self.out.push(';');
+            }
+            StmtKind::Break => self.out.push_str("break;"),
+            StmtKind::Continue => self.out.push_str("continue;"),
+        }
+    }
+
+    /// The body of an `if`, `else` or loop: a block on the same line, or
+    /// any other statement indented on the next.
+    fn body(&mut self, stmt: StmtId) {
+        if let StmtKind::Block(block) = &self.arena[stmt].kind {
+            self.out.push(' ');
+            self.block(block);
+        } else {
+            self.out.push('\n');
+            self.indent += 1;
+            self.line_start();
+            self.stmt(stmt);
+            self.indent -= 1;
+        }
+    }
+
+    /// Prints `expr`, in parentheses if it binds looser than
+    /// `min_precedence`.
+    fn expr(&mut self, expr: ExprId, min_precedence: u8) {
+        let expr = &self.arena[expr];
+        let parenthesize = precedence(expr) < min_precedence;
+        if parenthesize {
+            self.out.push('(');
+        }
+        match &expr.kind {
+            ExprKind::Literal(value) => self.literal(value),
+            ExprKind::Variable(name) => self.name(expr.id, name),
+            ExprKind::Grouping(inner) => {
+                self.out.push('(');
+                self.expr(*inner, 0);
+                self.out.push(')');
+            }
+            // Implicit in the source.
+            ExprKind::IntToFloat(inner) => self.expr(*inner, min_precedence),
+            ExprKind::Unary { op, operand } => {
+                self.out.push_str(op.as_str());
+                let start = self.out.len();
+                self.expr(*operand, UNARY);
+                // `- -x` must not run together into `--x`.
+                if *op == UnaryOp::Neg && self.out[start..].starts_with('-') {
+                    self.out.insert(start, ' ');
+                }
+            }
+            ExprKind::Binary { op, left, right } => {
+                let level = binary_precedence(*op);
+                self.expr(*left, level);
+                self.out.push_str(&format!(" {} ", op));
+                self.expr(*right, level + 1);
+            }
+            ExprKind::Assign { op, target, value } => {
+                match &self.arena[*target].kind {
+                    // The variable is named for what the assignment writes.
+                    ExprKind::Variable(name) => self.name(expr.id, name),
+                    _ => self.expr(*target, POSTFIX),
+                }
+                self.out.push(' ');
+                if let Some(op) = op {
+                    self.out.push_str(op.as_str());
+                }
+                self.out.push_str("= ");
+                self.expr(*value, 1);
+            }
+            ExprKind::Call { callee, args } => {
+                self.expr(*callee, POSTFIX);
+                self.out.push('(');
+                self.list(args);
+                self.out.push(')');
+            }
+            ExprKind::Array(elements) => {
+                self.out.push('[');
+                self.list(elements);
+                self.out.push(']');
+            }
+            ExprKind::Index { object, index } => {
+                self.expr(*object, POSTFIX);
+                self.out.push('[');
+                self.expr(*index, 0);
+                self.out.push(']');
+            }
+            ExprKind::StructLiteral { name, fields } => {
+                self.out.push_str(&name.name);
+                if fields.is_empty() {
+                    self.out.push_str(" {}");
+                } else {
+                    self.out.push_str(" { ");
+                    for (i, field) in fields.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push_str(", ");
+                        }
+                        self.out.push_str(&field.name.name);
+                        self.out.push_str(": ");
+                        self.expr(field.value, 0);
+                    }
+                    self.out.push_str(" }");
+                }
+            }
+            ExprKind::Field { object, field } => {
+                self.expr(*object, POSTFIX);
+                self.out.push('.');
+                self.out.push_str(&field.name);
+            }
+            ExprKind::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.expr(*condition, 3);
+                self.out.push_str(" ? ");
+                self.expr(*then_expr, 0);
+                self.out.push_str(" : ");
+                self.expr(*else_expr, 2);
+            }
+            ExprKind::Variant { enum_name, variant } => {
+                self.out.push_str(&enum_name.name);
+                self.out.push_str("::");
+                self.out.push_str(&variant.name);
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                self.out.push_str("match ");
+                self.expr(*scrutinee, 0);
+                if arms.is_empty() {
+                    self.out.push_str(" {}");
+                } else {
+                    self.out.push_str(" {\n");
+                    self.indent += 1;
+                    for arm in arms {
+                        self.line_start();
+                        self.pattern(&arm.pattern);
+                        self.out.push_str(" => ");
+                        match &arm.body {
+                            ArmBody::Expr(body) => {
+                                self.expr(*body, 0);
+                                self.out.push(',');
+                            }
+                            ArmBody::Block(body) => self.block(body),
+                        }
+                        self.out.push('\n');
+                    }
+                    self.indent -= 1;
+                    self.line_start();
+                    self.out.push('}');
+                }
+            }
+        }
+        if parenthesize {
+            self.out.push(')');
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Wildcard => self.out.push('_'),
+            PatternKind::Literal(value) => self.literal(value),
+            PatternKind::Binding(name) => self.name(pattern.id, name),
+            PatternKind::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                self.out.push_str(&enum_name.name);
+                self.out.push_str("::");
+                self.out.push_str(&variant.name);
+                if !fields.is_empty() {
+                    self.out.push('(');
+                    for (i, field) in fields.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push_str(", ");
+                        }
+                        self.pattern(field);
+                    }
+                    self.out.push(')');
+                }
+            }
+        }
+    }
+
+    fn list(&mut self, exprs: &[ExprId]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(*expr, 0);
+        }
+    }
+
+    fn literal(&mut self, value: &LiteralValue) {
+        match value {
+            // Debug formatting always includes a `.` or exponent, so the
+            // value reads back as a float.
+            LiteralValue::Float(value) => self.out.push_str(&format!("{:?}", value)),
+            LiteralValue::String(value) => {
+                self.out.push('"');
+                for c in value.chars() {
+                    match c {
+                        '\n' => self.out.push_str("\\n"),
+                        '\t' => self.out.push_str("\\t"),
+                        '\r' => self.out.push_str("\\r"),
+                        '\0' => self.out.push_str("\\0"),
+                        '\\' => self.out.push_str("\\\\"),
+                        '"' => self.out.push_str("\\\""),
+                        c => self.out.push(c),
+                    }
+                }
+                self.out.push('"');
+            }
+            other => self.out.push_str(&other.to_string()),
+        }
+    }
+}
+
+/// How tightly `expr` binds, on the scale of the infix operator table.
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Assign { .. } => 1,
+        ExprKind::Conditional { .. } => 2,
+        ExprKind::Binary { op, .. } => binary_precedence(*op),
+        ExprKind::Unary { .. } => UNARY,
+        ExprKind::Call { .. } | ExprKind::Index { .. } | ExprKind::Field { .. } => POSTFIX,
+        ExprKind::Literal(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Grouping(_)
+        | ExprKind::IntToFloat(_)
+        | ExprKind::Array(_)
+        | ExprKind::StructLiteral { .. }
+        | ExprKind::Variant { .. }
+        | ExprKind::Match { .. } => PRIMARY,
+    }
+}
+
+/// Whether the leftmost token of `expr`, printed without added parentheses,
+/// would be `match`.
+fn starts_with_match(arena: &AstArena, expr: ExprId) -> bool {
+    match &arena[expr].kind {
+        ExprKind::Match { .. } => true,
+        ExprKind::Binary { left: first, .. }
+        | ExprKind::Assign { target: first, .. }
+        | ExprKind::Call { callee: first, .. }
+        | ExprKind::Index { object: first, .. }
+        | ExprKind::Field { object: first, .. }
+        | ExprKind::IntToFloat(first)
+        | ExprKind::Conditional {
+            condition: first, ..
+        } => starts_with_match(arena, *first),
+        ExprKind::Literal(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Grouping(_)
+        | ExprKind::Unary { .. }
+        | ExprKind::Array(_)
+        | ExprKind::StructLiteral { .. }
+        | ExprKind::Variant { .. } => false,
+    }
+}
+
+fn binary_precedence(op: BinaryOp) -> u8 {
+    INFIX_OPERATORS
+        .iter()
+        .find(|row| row.infix == Infix::Binary(op))
+        .map(|row| row.precedence)
+        .expect("every binary operator has a row in the operator table")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    fn format(source: &str) -> String {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, [], "in {:?}", source);
+        print(&program)
+    }
+
+    #[test]
+    fn test_print() {
+        let source =
+            "int limit=10; let name = \"a\\\"b\\n\";\nstruct Point{x:int,y:int[2],}struct Empty{}
+            fn main(p: Point)->int{ if(p.x<limit){return 1;}else if(p.y[0]) p.x+=1; else {}
+            while (x) { x = x - 1; } for (int i = 0; i < 10; i += 1) continue; for (;;) break;
+            f(1.0, [a, b], Point { x: 1, y: (2) }); return -x; }";
+        assert_eq!(
+            format(source),
+            r#"int limit = 10;
+let name = "a\"b\n";
+
+struct Point {
+    x: int,
+    y: int[2],
+}
+
+struct Empty {}
+
+fn main(p: Point) -> int {
+    if (p.x < limit) {
+        return 1;
+    } else if (p.y[0])
+        p.x += 1;
+    else {}
+    while (x) {
+        x = x - 1;
+    }
+    for (int i = 0; i < 10; i += 1)
+        continue;
+    for (;;)
+        break;
+    f(1.0, [a, b], Point { x: 1, y: (2) });
+    return -x;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "fn f() { x = a - (b - c) * -(d + e) + - -g; }",
+            "fn f() { a = b = c ? d : e ? f : g; x = (a ? b : c) ? d : e; }",
+            "fn f() { if (a) if (b) x; else y; }",
+            "fn f() { -a[i].f(x)(y); !(a && b) || ~c; }",
+            "fn f() { x = 1e10 + 2.5 + 0.0; s = \"\\t\\0\\\\\"; }",
+            "fn(int, fn(float) -> bool) -> int[2] g; fn f(h: fn()[3]) { let k: fn() -> P = h[0]; }",
+            "enum E { A, B(int, E[2]) } enum F {}",
+            "struct P<A, B> { a: A } fn f<T>(x: P<T, P<int, fn(T)>>) -> T { Box<Box<T>>[2] b; }",
+            "fn f() { match s { E::B(x, _) => { return x; } 1 => 2, } x = match s { _ => E::A }; }",
+            "fn f() { (match s { \"a\" => f })(1); }",
+            "const int N = 2; const M: int = N; fn f() { const K = M; int[N][K] a; }",
+        ];
+        for source in sources {
+            let printed = format(source);
+            // Printing the reparsed output gives the same text, so the tree
+            // survived the round trip.
+            assert_eq!(format(&printed), printed, "from {:?}", source);
+        }
+        assert_eq!(
+            format("fn f() { x = - -1 - -a; }"),
+            "fn f() {\n    x = - -1 - -a;\n}\n"
+        );
+        assert_eq!(
+            format("fn f() { match s { E::B(x, 0) => { g(); } _ => 1 }; (match s {}).y += 1; }"),
+            "fn f() {
+    match s {
+        E::B(x, 0) => {
+            g();
+        }
+        _ => 1,
+    }
+    (match s {}).y += 1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_adds_needed_parentheses() {
+        // Trees built without groupings still print correctly.
+        let span = crate::source::Span::new(0, 0);
+        let id = NodeId::new(0);
+        let mut arena = AstArena::new();
+        let var = |arena: &mut AstArena, name: &str| {
+            arena.alloc_expr(Expr {
+                id,
+                span,
+                kind: ExprKind::Variable(Ident {
+                    name: name.to_string(),
+                    symbol: crate::utils::SymbolTable::new().intern(name),
+                    span,
+                }),
+            })
+        };
+        let binary = |arena: &mut AstArena, op, left, right| {
+            arena.alloc_expr(Expr {
+                id,
+                span,
+                kind: ExprKind::Binary { op, left, right },
+            })
+        };
+        let (a, b, c, d) = (
+            var(&mut arena, "a"),
+            var(&mut arena, "b"),
+            var(&mut arena, "c"),
+            var(&mut arena, "d"),
+        );
+        let sum = binary(&mut arena, BinaryOp::Add, a, b);
+        let difference = binary(&mut arena, BinaryOp::Sub, c, d);
+        let product = binary(&mut arena, BinaryOp::Mul, sum, difference);
+        let expr = binary(&mut arena, BinaryOp::Sub, c, sum);
+        let mut printer = Printer::new(&arena);
+        printer.expr(product, 0);
+        assert_eq!(printer.out, "(a + b) * (c - d)");
+
+        let mut printer = Printer::new(&arena);
+        printer.expr(expr, 0);
+        assert_eq!(printer.out, "c - (a + b)");
+
+        // A statement can't go on after the `}` of a leading `match`.
+        let (mut program, _) = parse("fn f() { (match s {}).y; }");
+        let Item::Function(function) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let stmt = function.body.stmts[0];
+        let StmtKind::Expr(field) = program.arena[stmt].kind else {
+            panic!("expected an expression statement");
+        };
+        let ExprKind::Field { object, .. } = program.arena[field].kind else {
+            panic!("expected a field access");
+        };
+        let ExprKind::Grouping(inner) = program.arena[object].kind else {
+            panic!("expected a grouping");
+        };
+        if let ExprKind::Field { object, .. } = &mut program.arena[field].kind {
+            *object = inner;
+        }
+        let mut printer = Printer::new(&program.arena);
+        printer.stmt(stmt);
+        assert_eq!(printer.out, "(match s {}.y);");
+    }
+}
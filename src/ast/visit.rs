@@ -0,0 +1,397 @@
+//! Read-only traversal of the AST.
+//!
+//! A pass implements [`Visitor`] and overrides the `visit_*` methods for the
+//! nodes it cares about. Each default method calls the matching `walk_*`
+//! function, which visits the node's children in source order; an override
+//! calls `walk_*` itself to keep descending, or leaves it out to skip the
+//! subtree. Expressions and statements are looked up in the arena the
+//! visitor returns from [`Visitor::arena`].
+
+use super::*;
+
+pub trait Visitor<'ast>: Sized {
+    /// The arena holding the expressions and statements being visited.
+    fn arena(&self) -> &'ast AstArena;
+
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_item(&mut self, item: &'ast Item) {
+        walk_item(self, item);
+    }
+
+    fn visit_function(&mut self, function: &'ast Function) {
+        walk_function(self, function);
+    }
+
+    fn visit_type_param(&mut self, param: &'ast TypeParam) {
+        walk_type_param(self, param);
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        walk_param(self, param);
+    }
+
+    fn visit_struct(&mut self, decl: &'ast StructDecl) {
+        walk_struct(self, decl);
+    }
+
+    fn visit_field(&mut self, field: &'ast Field) {
+        walk_field(self, field);
+    }
+
+    fn visit_enum(&mut self, decl: &'ast EnumDecl) {
+        walk_enum(self, decl);
+    }
+
+    fn visit_variant(&mut self, variant: &'ast Variant) {
+        walk_variant(self, variant);
+    }
+
+    fn visit_var_decl(&mut self, decl: &'ast VarDecl) {
+        walk_var_decl(self, decl);
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_arm(&mut self, arm: &'ast MatchArm) {
+        walk_arm(self, arm);
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type) {
+        walk_type(self, ty);
+    }
+
+    /// Every name, whether it declares something or refers to it.
+    fn visit_ident(&mut self, _ident: &'ast Ident) {}
+}
+
+pub fn walk_program<'ast, V: Visitor<'ast>>(visitor: &mut V, program: &'ast Program) {
+    for item in &program.items {
+        visitor.visit_item(item);
+    }
+}
+
+pub fn walk_item<'ast, V: Visitor<'ast>>(visitor: &mut V, item: &'ast Item) {
+    match item {
+        Item::Function(function) => visitor.visit_function(function),
+        Item::Struct(decl) => visitor.visit_struct(decl),
+        Item::Enum(decl) => visitor.visit_enum(decl),
+        Item::Global(decl) => visitor.visit_var_decl(decl),
+    }
+}
+
+pub fn walk_function<'ast, V: Visitor<'ast>>(visitor: &mut V, function: &'ast Function) {
+    visitor.visit_ident(&function.name);
+    for param in &function.type_params {
+        visitor.visit_type_param(param);
+    }
+    for param in &function.params {
+        visitor.visit_param(param);
+    }
+    if let Some(return_type) = &function.return_type {
+        visitor.visit_type(return_type);
+    }
+    visitor.visit_block(&function.body);
+}
+
+pub fn walk_type_param<'ast, V: Visitor<'ast>>(visitor: &mut V, param: &'ast TypeParam) {
+    visitor.visit_ident(&param.name);
+}
+
+pub fn walk_param<'ast, V: Visitor<'ast>>(visitor: &mut V, param: &'ast Param) {
+    visitor.visit_ident(&param.name);
+    visitor.visit_type(&param.ty);
+}
+
+pub fn walk_struct<'ast, V: Visitor<'ast>>(visitor: &mut V, decl: &'ast StructDecl) {
+    visitor.visit_ident(&decl.name);
+    for param in &decl.type_params {
+        visitor.visit_type_param(param);
+    }
+    for field in &decl.fields {
+        visitor.visit_field(field);
+    }
+}
+
+pub fn walk_field<'ast, V: Visitor<'ast>>(visitor: &mut V, field: &'ast Field) {
+    visitor.visit_ident(&field.name);
+    visitor.visit_type(&field.ty);
+}
+
+pub fn walk_enum<'ast, V: Visitor<'ast>>(visitor: &mut V, decl: &'ast EnumDecl) {
+    visitor.visit_ident(&decl.name);
+    for variant in &decl.variants {
+        visitor.visit_variant(variant);
+    }
+}
+
+pub fn walk_variant<'ast, V: Visitor<'ast>>(visitor: &mut V, variant: &'ast Variant) {
+    visitor.visit_ident(&variant.name);
+    for field in &variant.fields {
+        visitor.visit_type(field);
+    }
+}
+
+pub fn walk_var_decl<'ast, V: Visitor<'ast>>(visitor: &mut V, decl: &'ast VarDecl) {
+    let arena = visitor.arena();
+    if let Some(ty) = &decl.ty {
+        visitor.visit_type(ty);
+    }
+    visitor.visit_ident(&decl.name);
+    if let Some(init) = &decl.init {
+        visitor.visit_expr(&arena[*init]);
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast>>(visitor: &mut V, block: &'ast Block) {
+    let arena = visitor.arena();
+    for stmt in &block.stmts {
+        visitor.visit_stmt(&arena[*stmt]);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast>>(visitor: &mut V, stmt: &'ast Stmt) {
+    let arena = visitor.arena();
+    match &stmt.kind {
+        StmtKind::VarDecl(decl) => visitor.visit_var_decl(decl),
+        StmtKind::Expr(expr) => visitor.visit_expr(&arena[*expr]),
+        StmtKind::Block(block) => visitor.visit_block(block),
+        StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(&arena[*condition]);
+            visitor.visit_stmt(&arena[*then_branch]);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_stmt(&arena[*else_branch]);
+            }
+        }
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr(&arena[*condition]);
+            visitor.visit_stmt(&arena[*body]);
+        }
+        StmtKind::For {
+            init,
+            condition,
+            step,
+            body,
+        } => {
+            if let Some(init) = init {
+                visitor.visit_stmt(&arena[*init]);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expr(&arena[*condition]);
+            }
+            if let Some(step) = step {
+                visitor.visit_expr(&arena[*step]);
+            }
+            visitor.visit_stmt(&arena[*body]);
+        }
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(&arena[*value]);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue => {}
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast>>(visitor: &mut V, expr: &'ast Expr) {
+    let arena = visitor.arena();
+    match &expr.kind {
+        ExprKind::Literal(_) => {}
+        ExprKind::Variable(name) => visitor.visit_ident(name),
+        ExprKind::Grouping(inner) | ExprKind::IntToFloat(inner) => {
+            visitor.visit_expr(&arena[*inner])
+        }
+        ExprKind::Unary { operand, .. } => visitor.visit_expr(&arena[*operand]),
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr(&arena[*left]);
+            visitor.visit_expr(&arena[*right]);
+        }
+        ExprKind::Assign { target, value, .. } => {
+            visitor.visit_expr(&arena[*target]);
+            visitor.visit_expr(&arena[*value]);
+        }
+        ExprKind::Call { callee, args } => {
+            visitor.visit_expr(&arena[*callee]);
+            for arg in args {
+                visitor.visit_expr(&arena[*arg]);
+            }
+        }
+        ExprKind::Array(elements) => {
+            for element in elements {
+                visitor.visit_expr(&arena[*element]);
+            }
+        }
+        ExprKind::Index { object, index } => {
+            visitor.visit_expr(&arena[*object]);
+            visitor.visit_expr(&arena[*index]);
+        }
+        ExprKind::StructLiteral { name, fields } => {
+            visitor.visit_ident(name);
+            for field in fields {
+                visitor.visit_ident(&field.name);
+                visitor.visit_expr(&arena[field.value]);
+            }
+        }
+        ExprKind::Field { object, field } => {
+            visitor.visit_expr(&arena[*object]);
+            visitor.visit_ident(field);
+        }
+        ExprKind::Conditional {
+            condition,
+            then_expr,
+            else_expr,
+        } => {
+            visitor.visit_expr(&arena[*condition]);
+            visitor.visit_expr(&arena[*then_expr]);
+            visitor.visit_expr(&arena[*else_expr]);
+        }
+        ExprKind::Variant { enum_name, variant } => {
+            visitor.visit_ident(enum_name);
+            visitor.visit_ident(variant);
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            visitor.visit_expr(&arena[*scrutinee]);
+            for arm in arms {
+                visitor.visit_arm(arm);
+            }
+        }
+    }
+}
+
+pub fn walk_arm<'ast, V: Visitor<'ast>>(visitor: &mut V, arm: &'ast MatchArm) {
+    let arena = visitor.arena();
+    visitor.visit_pattern(&arm.pattern);
+    match &arm.body {
+        ArmBody::Expr(expr) => visitor.visit_expr(&arena[*expr]),
+        ArmBody::Block(block) => visitor.visit_block(block),
+    }
+}
+
+pub fn walk_pattern<'ast, V: Visitor<'ast>>(visitor: &mut V, pattern: &'ast Pattern) {
+    match &pattern.kind {
+        PatternKind::Wildcard | PatternKind::Literal(_) => {}
+        PatternKind::Binding(name) => visitor.visit_ident(name),
+        PatternKind::Variant {
+            enum_name,
+            variant,
+            fields,
+        } => {
+            visitor.visit_ident(enum_name);
+            visitor.visit_ident(variant);
+            for field in fields {
+                visitor.visit_pattern(field);
+            }
+        }
+    }
+}
+
+pub fn walk_type<'ast, V: Visitor<'ast>>(visitor: &mut V, ty: &'ast Type) {
+    match &ty.kind {
+        TypeKind::Named(name) => visitor.visit_ident(name),
+        TypeKind::Generic { name, args } => {
+            visitor.visit_ident(name);
+            for arg in args {
+                visitor.visit_type(arg);
+            }
+        }
+        TypeKind::Array { element, len } => {
+            visitor.visit_type(element);
+            if let ArrayLen::Const(name) = len {
+                visitor.visit_ident(name);
+            }
+        }
+        TypeKind::Function {
+            params,
+            return_type,
+        } => {
+            for param in params {
+                visitor.visit_type(param);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type(return_type);
+            }
+        }
+        TypeKind::Int
+        | TypeKind::Float
+        | TypeKind::Bool
+        | TypeKind::Char
+        | TypeKind::String
+        | TypeKind::Void => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    /// Collects every name, and counts the expressions outside loops.
+    struct Names<'ast> {
+        arena: &'ast AstArena,
+        names: Vec<&'ast str>,
+        exprs: usize,
+    }
+
+    impl<'ast> Visitor<'ast> for Names<'ast> {
+        fn arena(&self) -> &'ast AstArena {
+            self.arena
+        }
+
+        fn visit_ident(&mut self, ident: &'ast Ident) {
+            self.names.push(&ident.name);
+        }
+
+        fn visit_expr(&mut self, expr: &'ast Expr) {
+            self.exprs += 1;
+            walk_expr(self, expr);
+        }
+
+        fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+            // Not descending skips the whole loop.
+            if !matches!(stmt.kind, StmtKind::While { .. }) {
+                walk_stmt(self, stmt);
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let source = "struct P { x: Q[2] }
+            fn f(p: P) -> int { let a = p.x[0] + g(b); while (c) { d; } return P { x: e }.x; }";
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let mut names = Names {
+            arena: &program.arena,
+            names: Vec::new(),
+            exprs: 0,
+        };
+        names.visit_program(&program);
+        assert_eq!(
+            names.names,
+            ["P", "x", "Q", "f", "p", "P", "a", "p", "x", "g", "b", "P", "x", "e", "x"]
+        );
+        // `p.x[0] + g(b)` has 8, and the return value 3.
+        assert_eq!(names.exprs, 11);
+    }
+}
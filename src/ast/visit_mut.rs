@@ -0,0 +1,517 @@
+//! Traversal of the AST that can rewrite it in place.
+//!
+//! [`MutVisitor`] mirrors [`Visitor`](super::visit::Visitor) method for
+//! method, with `&mut` references, except that expressions and statements
+//! are visited by handle. An override may rewrite the node in the arena or
+//! point the handle at another one (`*expr = ...`), before walking the
+//! children to rewrite the new node, or after to see them already
+//! rewritten.
+//!
+//! The visitor owns or borrows the arena for the walk, so a pass over a
+//! whole [`Program`] takes the arena out of it with `std::mem::take` and
+//! puts it back afterwards.
+
+use super::*;
+
+pub trait MutVisitor: Sized {
+    /// The arena holding the expressions and statements being visited.
+    fn arena(&mut self) -> &mut AstArena;
+
+    fn visit_program(&mut self, program: &mut Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_item(&mut self, item: &mut Item) {
+        walk_item(self, item);
+    }
+
+    fn visit_function(&mut self, function: &mut Function) {
+        walk_function(self, function);
+    }
+
+    fn visit_type_param(&mut self, param: &mut TypeParam) {
+        walk_type_param(self, param);
+    }
+
+    fn visit_param(&mut self, param: &mut Param) {
+        walk_param(self, param);
+    }
+
+    fn visit_struct(&mut self, decl: &mut StructDecl) {
+        walk_struct(self, decl);
+    }
+
+    fn visit_field(&mut self, field: &mut Field) {
+        walk_field(self, field);
+    }
+
+    fn visit_enum(&mut self, decl: &mut EnumDecl) {
+        walk_enum(self, decl);
+    }
+
+    fn visit_variant(&mut self, variant: &mut Variant) {
+        walk_variant(self, variant);
+    }
+
+    fn visit_var_decl(&mut self, decl: &mut VarDecl) {
+        walk_var_decl(self, decl);
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut StmtId) {
+        walk_stmt(self, *stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &mut ExprId) {
+        walk_expr(self, *expr);
+    }
+
+    fn visit_arm(&mut self, arm: &mut MatchArm) {
+        walk_arm(self, arm);
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut Pattern) {
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_type(&mut self, ty: &mut Type) {
+        walk_type(self, ty);
+    }
+
+    /// Every name, whether it declares something or refers to it.
+    fn visit_ident(&mut self, _ident: &mut Ident) {}
+}
+
+pub fn walk_program<V: MutVisitor>(visitor: &mut V, program: &mut Program) {
+    for item in &mut program.items {
+        visitor.visit_item(item);
+    }
+}
+
+pub fn walk_item<V: MutVisitor>(visitor: &mut V, item: &mut Item) {
+    match item {
+        Item::Function(function) => visitor.visit_function(function),
+        Item::Struct(decl) => visitor.visit_struct(decl),
+        Item::Enum(decl) => visitor.visit_enum(decl),
+        Item::Global(decl) => visitor.visit_var_decl(decl),
+    }
+}
+
+pub fn walk_function<V: MutVisitor>(visitor: &mut V, function: &mut Function) {
+    visitor.visit_ident(&mut function.name);
+    for param in &mut function.type_params {
+        visitor.visit_type_param(param);
+    }
+    for param in &mut function.params {
+        visitor.visit_param(param);
+    }
+    if let Some(return_type) = &mut function.return_type {
+        visitor.visit_type(return_type);
+    }
+    visitor.visit_block(&mut function.body);
+}
+
+pub fn walk_type_param<V: MutVisitor>(visitor: &mut V, param: &mut TypeParam) {
+    visitor.visit_ident(&mut param.name);
+}
+
+pub fn walk_param<V: MutVisitor>(visitor: &mut V, param: &mut Param) {
+    visitor.visit_ident(&mut param.name);
+    visitor.visit_type(&mut param.ty);
+}
+
+pub fn walk_struct<V: MutVisitor>(visitor: &mut V, decl: &mut StructDecl) {
+    visitor.visit_ident(&mut decl.name);
+    for param in &mut decl.type_params {
+        visitor.visit_type_param(param);
+    }
+    for field in &mut decl.fields {
+        visitor.visit_field(field);
+    }
+}
+
+pub fn walk_field<V: MutVisitor>(visitor: &mut V, field: &mut Field) {
+    visitor.visit_ident(&mut field.name);
+    visitor.visit_type(&mut field.ty);
+}
+
+pub fn walk_enum<V: MutVisitor>(visitor: &mut V, decl: &mut EnumDecl) {
+    visitor.visit_ident(&mut decl.name);
+    for variant in &mut decl.variants {
+        visitor.visit_variant(variant);
+    }
+}
+
+pub fn walk_variant<V: MutVisitor>(visitor: &mut V, variant: &mut Variant) {
+    visitor.visit_ident(&mut variant.name);
+    for field in &mut variant.fields {
+        visitor.visit_type(field);
+    }
+}
+
+pub fn walk_var_decl<V: MutVisitor>(visitor: &mut V, decl: &mut VarDecl) {
+    if let Some(ty) = &mut decl.ty {
+        visitor.visit_type(ty);
+    }
+    visitor.visit_ident(&mut decl.name);
+    if let Some(init) = &mut decl.init {
+        visitor.visit_expr(init);
+    }
+}
+
+pub fn walk_block<V: MutVisitor>(visitor: &mut V, block: &mut Block) {
+    for stmt in &mut block.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: MutVisitor>(visitor: &mut V, stmt: StmtId) {
+    // The kind is moved out while the children are visited, since they need
+    // the arena too.
+    let mut kind = std::mem::replace(&mut visitor.arena()[stmt].kind, StmtKind::Break);
+    match &mut kind {
+        StmtKind::VarDecl(decl) => visitor.visit_var_decl(decl),
+        StmtKind::Expr(expr) => visitor.visit_expr(expr),
+        StmtKind::Block(block) => visitor.visit_block(block),
+        StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_stmt(else_branch);
+            }
+        }
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+        }
+        StmtKind::For {
+            init,
+            condition,
+            step,
+            body,
+        } => {
+            if let Some(init) = init {
+                visitor.visit_stmt(init);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expr(condition);
+            }
+            if let Some(step) = step {
+                visitor.visit_expr(step);
+            }
+            visitor.visit_stmt(body);
+        }
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue => {}
+    }
+    visitor.arena()[stmt].kind = kind;
+}
+
+pub fn walk_expr<V: MutVisitor>(visitor: &mut V, expr: ExprId) {
+    // As in `walk_stmt`.
+    let placeholder = ExprKind::Literal(LiteralValue::None);
+    let mut kind = std::mem::replace(&mut visitor.arena()[expr].kind, placeholder);
+    match &mut kind {
+        ExprKind::Literal(_) => {}
+        ExprKind::Variable(name) => visitor.visit_ident(name),
+        ExprKind::Grouping(inner) | ExprKind::IntToFloat(inner) => visitor.visit_expr(inner),
+        ExprKind::Unary { operand, .. } => visitor.visit_expr(operand),
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Assign { target, value, .. } => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        ExprKind::Call { callee, args } => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Array(elements) => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        ExprKind::Index { object, index } => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(index);
+        }
+        ExprKind::StructLiteral { name, fields } => {
+            visitor.visit_ident(name);
+            for field in fields {
+                visitor.visit_ident(&mut field.name);
+                visitor.visit_expr(&mut field.value);
+            }
+        }
+        ExprKind::Field { object, field } => {
+            visitor.visit_expr(object);
+            visitor.visit_ident(field);
+        }
+        ExprKind::Conditional {
+            condition,
+            then_expr,
+            else_expr,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(then_expr);
+            visitor.visit_expr(else_expr);
+        }
+        ExprKind::Variant { enum_name, variant } => {
+            visitor.visit_ident(enum_name);
+            visitor.visit_ident(variant);
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_arm(arm);
+            }
+        }
+    }
+    visitor.arena()[expr].kind = kind;
+}
+
+pub fn walk_arm<V: MutVisitor>(visitor: &mut V, arm: &mut MatchArm) {
+    visitor.visit_pattern(&mut arm.pattern);
+    match &mut arm.body {
+        ArmBody::Expr(expr) => visitor.visit_expr(expr),
+        ArmBody::Block(block) => visitor.visit_block(block),
+    }
+}
+
+pub fn walk_pattern<V: MutVisitor>(visitor: &mut V, pattern: &mut Pattern) {
+    match &mut pattern.kind {
+        PatternKind::Wildcard | PatternKind::Literal(_) => {}
+        PatternKind::Binding(name) => visitor.visit_ident(name),
+        PatternKind::Variant {
+            enum_name,
+            variant,
+            fields,
+        } => {
+            visitor.visit_ident(enum_name);
+            visitor.visit_ident(variant);
+            for field in fields {
+                visitor.visit_pattern(field);
+            }
+        }
+    }
+}
+
+pub fn walk_type<V: MutVisitor>(visitor: &mut V, ty: &mut Type) {
+    match &mut ty.kind {
+        TypeKind::Named(name) => visitor.visit_ident(name),
+        TypeKind::Generic { name, args } => {
+            visitor.visit_ident(name);
+            for arg in args {
+                visitor.visit_type(arg);
+            }
+        }
+        TypeKind::Array { element, len } => {
+            visitor.visit_type(element);
+            if let ArrayLen::Const(name) = len {
+                visitor.visit_ident(name);
+            }
+        }
+        TypeKind::Function {
+            params,
+            return_type,
+        } => {
+            for param in params {
+                visitor.visit_type(param);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type(return_type);
+            }
+        }
+        TypeKind::Int
+        | TypeKind::Float
+        | TypeKind::Bool
+        | TypeKind::Char
+        | TypeKind::String
+        | TypeKind::Void => {}
+    }
+}
+
+/// Applies `map` to every span of the nodes it visits, to move a tree
+/// along with text that moved after an edit.
+pub struct MapSpans<'a, F> {
+    arena: &'a mut AstArena,
+    map: F,
+}
+
+impl<'a, F: Fn(Span) -> Span> MapSpans<'a, F> {
+    pub fn new(arena: &'a mut AstArena, map: F) -> Self {
+        Self { arena, map }
+    }
+
+    fn apply(&self, span: &mut Span) {
+        *span = (self.map)(*span);
+    }
+}
+
+impl<F: Fn(Span) -> Span> MutVisitor for MapSpans<'_, F> {
+    fn arena(&mut self) -> &mut AstArena {
+        self.arena
+    }
+
+    fn visit_function(&mut self, function: &mut Function) {
+        self.apply(&mut function.span);
+        walk_function(self, function);
+    }
+
+    fn visit_type_param(&mut self, param: &mut TypeParam) {
+        self.apply(&mut param.span);
+        walk_type_param(self, param);
+    }
+
+    fn visit_param(&mut self, param: &mut Param) {
+        self.apply(&mut param.span);
+        walk_param(self, param);
+    }
+
+    fn visit_struct(&mut self, decl: &mut StructDecl) {
+        self.apply(&mut decl.span);
+        walk_struct(self, decl);
+    }
+
+    fn visit_field(&mut self, field: &mut Field) {
+        self.apply(&mut field.span);
+        walk_field(self, field);
+    }
+
+    fn visit_enum(&mut self, decl: &mut EnumDecl) {
+        self.apply(&mut decl.span);
+        walk_enum(self, decl);
+    }
+
+    fn visit_variant(&mut self, variant: &mut Variant) {
+        self.apply(&mut variant.span);
+        walk_variant(self, variant);
+    }
+
+    fn visit_var_decl(&mut self, decl: &mut VarDecl) {
+        self.apply(&mut decl.span);
+        walk_var_decl(self, decl);
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        self.apply(&mut block.span);
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut StmtId) {
+        let span = &mut self.arena[*stmt].span;
+        *span = (self.map)(*span);
+        walk_stmt(self, *stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &mut ExprId) {
+        let node = &mut self.arena[*expr];
+        node.span = (self.map)(node.span);
+        if let ExprKind::StructLiteral { fields, .. } = &mut node.kind {
+            for field in fields {
+                field.span = (self.map)(field.span);
+            }
+        }
+        walk_expr(self, *expr);
+    }
+
+    fn visit_arm(&mut self, arm: &mut MatchArm) {
+        self.apply(&mut arm.span);
+        walk_arm(self, arm);
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut Pattern) {
+        self.apply(&mut pattern.span);
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_type(&mut self, ty: &mut Type) {
+        self.apply(&mut ty.span);
+        walk_type(self, ty);
+    }
+
+    fn visit_ident(&mut self, ident: &mut Ident) {
+        self.apply(&mut ident.span);
+    }
+}
+
+/// Applies `map` to every span of `program`.
+pub fn map_spans(program: &mut Program, map: impl Fn(Span) -> Span) {
+    program.span = map(program.span);
+    let mut arena = std::mem::take(&mut program.arena);
+    MapSpans::new(&mut arena, map).visit_program(program);
+    program.arena = arena;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::pretty::print;
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    /// Folds arithmetic on integer literals and renames variables.
+    struct Fold {
+        arena: AstArena,
+    }
+
+    impl MutVisitor for Fold {
+        fn arena(&mut self) -> &mut AstArena {
+            &mut self.arena
+        }
+
+        fn visit_expr(&mut self, expr: &mut ExprId) {
+            // Children first, so `1 + 2 * 3` sees `2 * 3` already folded.
+            walk_expr(self, *expr);
+            let ExprKind::Binary { op, left, right } = self.arena[*expr].kind else {
+                return;
+            };
+            let (
+                ExprKind::Literal(LiteralValue::Integer(a)),
+                ExprKind::Literal(LiteralValue::Integer(b)),
+            ) = (
+                &self.arena.ungrouped(left).kind,
+                &self.arena.ungrouped(right).kind,
+            )
+            else {
+                return;
+            };
+            let value = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Mul => a * b,
+                _ => return,
+            };
+            self.arena[*expr].kind = ExprKind::Literal(LiteralValue::Integer(value));
+        }
+
+        fn visit_ident(&mut self, ident: &mut Ident) {
+            ident.name = ident.name.to_uppercase();
+        }
+    }
+
+    #[test]
+    fn test_mut_visitor() {
+        let (mut program, diagnostics) = parse("fn f(a: int) { a = (1 + 2) * 3 + a; }");
+        assert_eq!(diagnostics, []);
+        let mut fold = Fold {
+            arena: std::mem::take(&mut program.arena),
+        };
+        fold.visit_program(&mut program);
+        program.arena = fold.arena;
+        assert_eq!(print(&program), "fn F(A: int) {\n    A = 9 + A;\n}\n");
+    }
+}
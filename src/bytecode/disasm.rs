@@ -0,0 +1,213 @@
+//! The human-readable form of a [`Module`], which `disasm` prints.
+//!
+//! ```text
+//! constants:
+//!     0  0
+//!     1  1
+//!
+//! fn 0 count(n), locals: n, i
+//!     0000  const 0                 ; 0
+//!     0003  set_local 1             ; i
+//!     0006  get_local 1             ; i
+//!     0009  get_local 0             ; n
+//!     0012  lt
+//!     0013  jump_if_false 0029
+//!     ...
+//! ```
+//!
+//! The tables come first, each entry after its index, then every function
+//! with its index, parameters and locals. Each instruction is printed at
+//! its offset, with its operands as numbers and a comment saying what they
+//! refer to: the value of a constant, or the name of a local, global,
+//! function, type, field or variant.
+
+use super::{Instruction, Module, Op, TypeDefKind};
+use crate::semantic::ConstValue;
+use std::fmt::Write;
+
+/// `module` in the human-readable form.
+pub fn disassemble(module: &Module) -> String {
+    let mut sections = Vec::new();
+    if !module.constants.is_empty() {
+        let mut out = "constants:\n".to_string();
+        let width = index_width(module.constants.len());
+        for (i, constant) in module.constants.iter().enumerate() {
+            let _ = writeln!(out, "    {:>width$}  {}", i, constant);
+        }
+        sections.push(out);
+    }
+    if !module.globals.is_empty() {
+        let mut out = "globals:\n".to_string();
+        let width = index_width(module.globals.len());
+        for (i, global) in module.globals.iter().enumerate() {
+            let _ = write!(out, "    {:>width$}  {}", i, global.name);
+            if let Some(value) = global.init.and_then(|c| module.constants.get(c as usize)) {
+                let _ = write!(out, " = {}", value);
+            }
+            out.push('\n');
+        }
+        sections.push(out);
+    }
+    if !module.types.is_empty() {
+        let mut out = "types:\n".to_string();
+        let width = index_width(module.types.len());
+        for (i, ty) in module.types.iter().enumerate() {
+            let (keyword, names) = match &ty.kind {
+                TypeDefKind::Struct { fields } => ("struct", fields),
+                TypeDefKind::Enum { variants } => ("enum", variants),
+            };
+            let _ = writeln!(
+                out,
+                "    {:>width$}  {} {} {{ {} }}",
+                i,
+                keyword,
+                ty.name,
+                names.join(", ")
+            );
+        }
+        sections.push(out);
+    }
+
+    for (i, function) in module.functions.iter().enumerate() {
+        let params = &function.locals[..(function.params as usize).min(function.locals.len())];
+        let mut out = format!("fn {} {}({})", i, function.name, params.join(", "));
+        if !function.locals.is_empty() {
+            let _ = write!(out, ", locals: {}", function.locals.join(", "));
+        }
+        out.push('\n');
+        let mut end = 0;
+        for instruction in function.instructions() {
+            end = instruction.offset + instruction.op.size();
+            let mut text = instruction.op.name().to_string();
+            for (j, operand) in instruction.operands.iter().enumerate() {
+                let _ = match (instruction.op, j) {
+                    (Op::Jump | Op::JumpIfFalse | Op::JumpIfTrue, _) => {
+                        write!(text, " {:04}", operand)
+                    }
+                    (_, 0) => write!(text, " {}", operand),
+                    _ => write!(text, ", {}", operand),
+                };
+            }
+            match comment(module, function, &instruction) {
+                Some(comment) => {
+                    let _ = writeln!(
+                        out,
+                        "    {:04}  {:<22}  ; {}",
+                        instruction.offset, text, comment
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "    {:04}  {}", instruction.offset, text);
+                }
+            }
+        }
+        if let Some(byte) = function.code.get(end) {
+            let _ = writeln!(out, "    {:04}  invalid byte 0x{:02x}", end, byte);
+        }
+        sections.push(out);
+    }
+    sections.join("\n")
+}
+
+/// How many digits the last index of a table of `len` entries has.
+fn index_width(len: usize) -> usize {
+    (len - 1).to_string().len()
+}
+
+/// What the operands of `instruction`, in `function`, refer to.
+fn comment(
+    module: &Module,
+    function: &super::Function,
+    instruction: &Instruction,
+) -> Option<String> {
+    let operand = *instruction.operands.first()? as usize;
+    let name = |names: &[String]| names.get(operand).cloned();
+    match instruction.op {
+        Op::Const => module.constants.get(operand).map(|c| c.to_string()),
+        Op::GetLocal | Op::SetLocal => name(&function.locals),
+        Op::GetGlobal | Op::SetGlobal => module.globals.get(operand).map(|g| g.name.clone()),
+        Op::Function => module.functions.get(operand).map(|f| f.name.clone()),
+        Op::Struct => module.types.get(operand).map(|t| t.name.clone()),
+        Op::GetField | Op::SetField => match module.constants.get(operand)? {
+            ConstValue::String(field) => Some(field.to_string()),
+            other => Some(other.to_string()),
+        },
+        Op::Variant | Op::IsVariant => {
+            let ty = module.types.get(operand)?;
+            let TypeDefKind::Enum { variants } = &ty.kind else {
+                return None;
+            };
+            let variant = variants.get(*instruction.operands.get(1)? as usize)?;
+            Some(format!("{}::{}", ty.name, variant))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::{Function, Global, TypeDef};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_disassemble() {
+        let module = Module {
+            constants: vec![ConstValue::Int(3), ConstValue::String("x".into())],
+            globals: vec![Global {
+                name: "limit".to_string(),
+                init: Some(0),
+            }],
+            types: vec![TypeDef {
+                name: "Shape".to_string(),
+                kind: TypeDefKind::Enum {
+                    variants: vec!["Dot".to_string(), "Circle".to_string()],
+                },
+            }],
+            functions: vec![Function {
+                name: "f".to_string(),
+                params: 1,
+                locals: vec!["p".to_string()],
+                code: vec![
+                    Op::GetLocal as u8,
+                    0,
+                    0,
+                    Op::GetField as u8,
+                    1,
+                    0,
+                    Op::Variant as u8,
+                    0,
+                    0,
+                    1,
+                    1,
+                    Op::JumpIfTrue as u8,
+                    15,
+                    0,
+                    Op::Return as u8,
+                    0xff,
+                ],
+            }],
+        };
+        assert_eq!(
+            disassemble(&module),
+            "constants:
+    0  3
+    1  \"x\"
+
+globals:
+    0  limit = 3
+
+types:
+    0  enum Shape { Dot, Circle }
+
+fn 0 f(p), locals: p
+    0000  get_local 0             ; p
+    0003  get_field 1             ; x
+    0006  variant 0, 1, 1         ; Shape::Circle
+    0011  jump_if_true 0015
+    0014  return
+    0015  invalid byte 0xff
+"
+        );
+    }
+}
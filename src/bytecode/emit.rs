@@ -0,0 +1,1091 @@
+//! Compiling a checked program to a bytecode [`Module`].
+//!
+//! [`emit`] gives every function, global and type its index in item order
+//! before compiling any code, so code can refer to items declared after
+//! it. Each function is then compiled from its AST, statement by
+//! statement. Control flow becomes jumps patched once their targets are
+//! known, and `&&`, `||` and `?:` jump too, so only one side is run.
+//!
+//! Parameters take the first local slots, in order, and every variable or
+//! binding declared in the body gets a slot of its own. The emitter also
+//! keeps values it needs twice in slots: a `match`'s scrutinee, the
+//! indices in an assignment's target, and the value of an assignment used
+//! as an expression. Assigning to a field or element stores the updated
+//! struct or array back into its variable, as `p.pos.x = 1;` becomes
+//! `p = p with pos = (p.pos with x = 1)`.
+//!
+//! Globals start as the values [`evaluate`](crate::semantic::evaluate)
+//! worked out for them, so their initializers are not compiled.
+
+use super::{Function, Global, Module, Op, Operand, TypeDef, TypeDefKind};
+use crate::ast::{
+    self, ArmBody, AstArena, BinaryOp, Block, ExprId, ExprKind, Ident, Item, MatchArm, NodeId,
+    Pattern, PatternKind, Program, StmtId, StmtKind, UnaryOp, VarDecl,
+};
+use crate::lexer::LiteralValue;
+use crate::semantic::{ConstValue, ConstValues, DefId, Resolution};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Why a program could not be compiled.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EmitError {
+    /// One of the module's tables has more entries than an index can
+    /// address.
+    #[error("the program has more than {limit} {what}")]
+    TooMany { what: &'static str, limit: usize },
+
+    /// A function outgrew what an operand can address.
+    #[error("function '{function}' has more than {limit} {what}")]
+    TooManyIn {
+        function: String,
+        what: &'static str,
+        limit: usize,
+    },
+
+    /// A name the resolver did not bind, which a checked program does not
+    /// have.
+    #[error("'{0}' does not refer to anything; only programs without errors can be compiled")]
+    Unresolved(String),
+
+    #[error("'{0}' outside of a loop")]
+    OutsideLoop(&'static str),
+}
+
+type Result<T> = std::result::Result<T, EmitError>;
+
+/// Compiles `program`, whose names `resolution` binds and whose constants
+/// `consts` holds. The program must have checked without errors.
+pub fn emit(program: &Program, resolution: &Resolution, consts: &ConstValues) -> Result<Module> {
+    let mut emitter = Emitter {
+        arena: &program.arena,
+        resolution,
+        module: Module::default(),
+        constants: HashMap::new(),
+        globals: HashMap::new(),
+        functions: HashMap::new(),
+        types: HashMap::new(),
+    };
+    for item in &program.items {
+        emitter.declare(item, consts)?;
+    }
+    let functions = program.items.iter().filter_map(|item| match item {
+        Item::Function(function) => Some(function),
+        _ => None,
+    });
+    for (index, function) in functions.enumerate() {
+        let compiled = FunctionEmitter::new(&mut emitter, function).function(function)?;
+        emitter.module.functions[index] = compiled;
+    }
+    Ok(emitter.module)
+}
+
+/// The index of a new entry in a table that has `len` already.
+fn table_index(len: usize, what: &'static str) -> Result<u16> {
+    u16::try_from(len).map_err(|_| EmitError::TooMany {
+        what,
+        limit: u16::MAX as usize + 1,
+    })
+}
+
+/// The instruction for a binary operator. `&&` and `||` have none: they
+/// are compiled to jumps.
+fn binary(op: BinaryOp) -> Op {
+    match op {
+        BinaryOp::Add => Op::Add,
+        BinaryOp::Sub => Op::Sub,
+        BinaryOp::Mul => Op::Mul,
+        BinaryOp::Div => Op::Div,
+        BinaryOp::Rem => Op::Rem,
+        BinaryOp::Eq => Op::Eq,
+        BinaryOp::Ne => Op::Ne,
+        BinaryOp::Lt => Op::Lt,
+        BinaryOp::Le => Op::Le,
+        BinaryOp::Gt => Op::Gt,
+        BinaryOp::Ge => Op::Ge,
+        BinaryOp::BitAnd => Op::BitAnd,
+        BinaryOp::BitOr => Op::BitOr,
+        BinaryOp::BitXor => Op::BitXor,
+        BinaryOp::Shl => Op::Shl,
+        BinaryOp::Shr => Op::Shr,
+        BinaryOp::And | BinaryOp::Or => unreachable!("'{}' short-circuits", op),
+    }
+}
+
+/// The module being built, and the index of each item in it.
+struct Emitter<'ast> {
+    arena: &'ast AstArena,
+    resolution: &'ast Resolution,
+    module: Module,
+    /// The index of each constant in the pool, keyed by its `Debug` form,
+    /// which tells `1` from `1.0`.
+    constants: HashMap<String, u16>,
+    globals: HashMap<DefId, u16>,
+    functions: HashMap<DefId, u16>,
+    types: HashMap<DefId, u16>,
+}
+
+impl<'ast> Emitter<'ast> {
+    /// Gives `item` its index. Functions get a placeholder until their
+    /// code is compiled.
+    fn declare(&mut self, item: &Item, consts: &ConstValues) -> Result<()> {
+        let resolution = self.resolution;
+        let def = |id| resolution.declaration(id);
+        match item {
+            Item::Function(function) => {
+                let index = table_index(self.module.functions.len(), "functions")?;
+                if let Some(def) = def(function.id) {
+                    self.functions.insert(def, index);
+                }
+                self.module.functions.push(Function {
+                    name: function.name.name.clone(),
+                    params: 0,
+                    locals: Vec::new(),
+                    code: Vec::new(),
+                });
+            }
+            Item::Struct(decl) => {
+                let index = table_index(self.module.types.len(), "types")?;
+                if let Some(def) = def(decl.id) {
+                    self.types.insert(def, index);
+                }
+                let fields = decl.fields.iter().map(|f| f.name.name.clone()).collect();
+                self.module.types.push(TypeDef {
+                    name: decl.name.name.clone(),
+                    kind: TypeDefKind::Struct { fields },
+                });
+            }
+            Item::Enum(decl) => {
+                let index = table_index(self.module.types.len(), "types")?;
+                if decl.variants.len() > u8::MAX as usize + 1 {
+                    return Err(EmitError::TooMany {
+                        what: "variants in an enum",
+                        limit: u8::MAX as usize + 1,
+                    });
+                }
+                if decl
+                    .variants
+                    .iter()
+                    .any(|v| v.fields.len() > u8::MAX as usize)
+                {
+                    return Err(EmitError::TooMany {
+                        what: "fields in a variant",
+                        limit: u8::MAX as usize,
+                    });
+                }
+                if let Some(def) = def(decl.id) {
+                    self.types.insert(def, index);
+                }
+                let variants = decl.variants.iter().map(|v| v.name.name.clone()).collect();
+                self.module.types.push(TypeDef {
+                    name: decl.name.name.clone(),
+                    kind: TypeDefKind::Enum { variants },
+                });
+            }
+            Item::Global(decl) => {
+                let index = table_index(self.module.globals.len(), "globals")?;
+                let value = def(decl.id).and_then(|def| {
+                    self.globals.insert(def, index);
+                    consts.value(def).cloned()
+                });
+                let init = value.map(|value| self.constant(value)).transpose()?;
+                self.module.globals.push(Global {
+                    name: decl.name.name.clone(),
+                    init,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The index of `value` in the pool, adding it if it is new.
+    fn constant(&mut self, value: ConstValue) -> Result<u16> {
+        let key = format!("{:?}", value);
+        if let Some(&index) = self.constants.get(&key) {
+            return Ok(index);
+        }
+        let index = table_index(self.module.constants.len(), "constants")?;
+        self.module.constants.push(value);
+        self.constants.insert(key, index);
+        Ok(index)
+    }
+}
+
+/// What a variable expression refers to.
+#[derive(Debug, Clone, Copy)]
+enum Var {
+    Local(u16),
+    Global(u16),
+    Function(u16),
+}
+
+/// Where an assignment stores: a variable, or the value of an expression
+/// that is not one, then the fields and elements leading into it.
+struct Place {
+    root: Root,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Root {
+    Local(u16),
+    Global(u16),
+    /// A value kept in this slot, whose updates are dropped.
+    Value(u16),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// The field the constant names.
+    Field(u16),
+    /// The element at the index kept in this slot.
+    Index(u16),
+}
+
+/// The jumps out of a loop, patched once it is compiled.
+#[derive(Debug, Default)]
+struct Loop {
+    continues: Vec<usize>,
+    breaks: Vec<usize>,
+}
+
+/// Compiles one function's code.
+struct FunctionEmitter<'e, 'ast> {
+    emitter: &'e mut Emitter<'ast>,
+    arena: &'ast AstArena,
+    resolution: &'ast Resolution,
+    name: String,
+    locals: Vec<String>,
+    /// The slot of each parameter, variable and binding declared so far.
+    slots: HashMap<DefId, u16>,
+    code: Vec<u8>,
+    loops: Vec<Loop>,
+}
+
+impl<'e, 'ast> FunctionEmitter<'e, 'ast> {
+    fn new(emitter: &'e mut Emitter<'ast>, function: &ast::Function) -> Self {
+        FunctionEmitter {
+            arena: emitter.arena,
+            resolution: emitter.resolution,
+            emitter,
+            name: function.name.name.clone(),
+            locals: Vec::new(),
+            slots: HashMap::new(),
+            code: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    fn function(mut self, function: &ast::Function) -> Result<Function> {
+        let params = u8::try_from(function.params.len())
+            .map_err(|_| self.too_many("parameters", u8::MAX as usize))?;
+        for param in &function.params {
+            self.declare(param.id, &param.name.name)?;
+        }
+        self.block(&function.body)?;
+        // What falling off the end returns.
+        self.op(Op::Null);
+        self.op(Op::Return);
+        if self.code.len() > u16::MAX as usize {
+            return Err(self.too_many("bytes of code", u16::MAX as usize));
+        }
+        Ok(Function {
+            name: self.name,
+            params,
+            locals: self.locals,
+            code: self.code,
+        })
+    }
+
+    fn too_many(&self, what: &'static str, limit: usize) -> EmitError {
+        EmitError::TooManyIn {
+            function: self.name.clone(),
+            what,
+            limit,
+        }
+    }
+
+    fn op(&mut self, op: Op) {
+        self.instr(op, &[]);
+    }
+
+    fn instr(&mut self, op: Op, operands: &[u16]) {
+        debug_assert_eq!(op.operands().len(), operands.len(), "{}", op.name());
+        self.code.push(op as u8);
+        for (operand, &value) in op.operands().iter().zip(operands) {
+            match operand {
+                Operand::Byte => self.code.push(value as u8),
+                Operand::Short => self.code.extend(value.to_le_bytes()),
+            }
+        }
+    }
+
+    /// Emits a jump whose target is patched later, returning where its
+    /// operand is.
+    fn jump(&mut self, op: Op) -> usize {
+        self.instr(op, &[0]);
+        self.code.len() - 2
+    }
+
+    /// Points the jump whose operand is `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        self.patch_to(at, self.code.len());
+    }
+
+    fn patch_to(&mut self, at: usize, target: usize) {
+        // Code too long for its offsets is reported once it is finished.
+        self.code[at..at + 2].copy_from_slice(&(target as u16).to_le_bytes());
+    }
+
+    fn constant(&mut self, value: ConstValue) -> Result<u16> {
+        self.emitter.constant(value)
+    }
+
+    /// A constant naming a field.
+    fn field_name(&mut self, field: &Ident) -> Result<u16> {
+        self.constant(ConstValue::String(field.name.as_str().into()))
+    }
+
+    /// A new slot for what `node` declares.
+    fn declare(&mut self, node: NodeId, name: &str) -> Result<u16> {
+        let slot = self.slot(name.to_string())?;
+        if let Some(def) = self.resolution.declaration(node) {
+            self.slots.insert(def, slot);
+        }
+        Ok(slot)
+    }
+
+    /// A new slot for the emitter's own use.
+    fn temp(&mut self) -> Result<u16> {
+        self.slot(format!("${}", self.locals.len()))
+    }
+
+    fn slot(&mut self, name: String) -> Result<u16> {
+        let slot = u16::try_from(self.locals.len())
+            .map_err(|_| self.too_many("locals", u16::MAX as usize + 1))?;
+        self.locals.push(name);
+        Ok(slot)
+    }
+
+    /// Pops the top value into a new slot.
+    fn store_temp(&mut self) -> Result<u16> {
+        let slot = self.temp()?;
+        self.instr(Op::SetLocal, &[slot]);
+        Ok(slot)
+    }
+
+    fn variable(&self, node: NodeId, name: &Ident) -> Result<Var> {
+        let unresolved = || EmitError::Unresolved(name.name.clone());
+        let def = self.resolution.use_of(node).ok_or_else(unresolved)?;
+        if let Some(&slot) = self.slots.get(&def) {
+            Ok(Var::Local(slot))
+        } else if let Some(&index) = self.emitter.globals.get(&def) {
+            Ok(Var::Global(index))
+        } else if let Some(&index) = self.emitter.functions.get(&def) {
+            Ok(Var::Function(index))
+        } else {
+            Err(unresolved())
+        }
+    }
+
+    fn type_index(&self, node: NodeId, name: &Ident) -> Result<u16> {
+        self.resolution
+            .use_of(node)
+            .and_then(|def| self.emitter.types.get(&def).copied())
+            .ok_or_else(|| EmitError::Unresolved(name.name.clone()))
+    }
+
+    /// The enum and variant indices of `variant`, used by `node`.
+    fn variant(&self, node: NodeId, enum_name: &Ident, variant: &Ident) -> Result<(u16, u16)> {
+        let ty = self.type_index(node, enum_name)?;
+        let TypeDefKind::Enum { variants } = &self.emitter.module.types[ty as usize].kind else {
+            return Err(EmitError::Unresolved(enum_name.name.clone()));
+        };
+        let index = variants
+            .iter()
+            .position(|v| *v == variant.name)
+            .ok_or_else(|| {
+                EmitError::Unresolved(format!("{}::{}", enum_name.name, variant.name))
+            })?;
+        Ok((ty, index as u16))
+    }
+
+    fn block(&mut self, block: &Block) -> Result<()> {
+        for &stmt in &block.stmts {
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, id: StmtId) -> Result<()> {
+        match &self.arena[id].kind {
+            StmtKind::VarDecl(decl) => self.var_decl(decl)?,
+            StmtKind::Expr(expr) => self.discard(*expr)?,
+            StmtKind::Block(block) => self.block(block)?,
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition)?;
+                let to_else = self.jump(Op::JumpIfFalse);
+                self.stmt(*then_branch)?;
+                match else_branch {
+                    Some(else_branch) => {
+                        let to_end = self.jump(Op::Jump);
+                        self.patch(to_else);
+                        self.stmt(*else_branch)?;
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            StmtKind::While { condition, body } => {
+                let start = self.code.len();
+                self.expr(*condition)?;
+                let exit = self.jump(Op::JumpIfFalse);
+                let jumps = self.loop_body(*body)?;
+                self.instr(Op::Jump, &[start as u16]);
+                self.patch(exit);
+                self.close_loop(jumps, start);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.stmt(*init)?;
+                }
+                let start = self.code.len();
+                let exit = match condition {
+                    Some(condition) => {
+                        self.expr(*condition)?;
+                        Some(self.jump(Op::JumpIfFalse))
+                    }
+                    None => None,
+                };
+                let jumps = self.loop_body(*body)?;
+                let next = self.code.len();
+                if let Some(step) = step {
+                    self.discard(*step)?;
+                }
+                self.instr(Op::Jump, &[start as u16]);
+                if let Some(exit) = exit {
+                    self.patch(exit);
+                }
+                self.close_loop(jumps, next);
+            }
+            StmtKind::Return(value) => {
+                match value {
+                    Some(value) => self.expr(*value)?,
+                    None => self.op(Op::Null),
+                }
+                self.op(Op::Return);
+            }
+            StmtKind::Break => {
+                let at = self.jump(Op::Jump);
+                let lp = self
+                    .loops
+                    .last_mut()
+                    .ok_or(EmitError::OutsideLoop("break"))?;
+                lp.breaks.push(at);
+            }
+            StmtKind::Continue => {
+                let at = self.jump(Op::Jump);
+                let lp = self
+                    .loops
+                    .last_mut()
+                    .ok_or(EmitError::OutsideLoop("continue"))?;
+                lp.continues.push(at);
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_body(&mut self, body: StmtId) -> Result<Loop> {
+        self.loops.push(Loop::default());
+        self.stmt(body)?;
+        Ok(self.loops.pop().expect("pushed above"))
+    }
+
+    /// Points a loop's `continue`s to `next` and its `break`s past its end.
+    fn close_loop(&mut self, jumps: Loop, next: usize) {
+        for at in jumps.continues {
+            self.patch_to(at, next);
+        }
+        for at in jumps.breaks {
+            self.patch(at);
+        }
+    }
+
+    fn var_decl(&mut self, decl: &VarDecl) -> Result<()> {
+        match decl.init {
+            Some(init) => self.expr(init)?,
+            None => self.op(Op::Null),
+        }
+        // Declared after the initializer, which sees any outer variable of
+        // the same name.
+        let slot = self.declare(decl.id, &decl.name.name)?;
+        self.instr(Op::SetLocal, &[slot]);
+        Ok(())
+    }
+
+    /// Compiles `id` for its effects only, leaving nothing on the stack.
+    fn discard(&mut self, id: ExprId) -> Result<()> {
+        match &self.arena.ungrouped(id).kind {
+            ExprKind::Assign { op, target, value } => self.assign(*op, *target, *value, false),
+            ExprKind::Match { scrutinee, arms } => self.match_expr(*scrutinee, arms, false),
+            _ => {
+                self.expr(id)?;
+                self.op(Op::Pop);
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles `id` to push its value.
+    fn expr(&mut self, id: ExprId) -> Result<()> {
+        let expr = &self.arena[id];
+        match &expr.kind {
+            ExprKind::Literal(value) => self.literal(value)?,
+            ExprKind::Variable(name) => match self.variable(expr.id, name)? {
+                Var::Local(slot) => self.instr(Op::GetLocal, &[slot]),
+                Var::Global(index) => self.instr(Op::GetGlobal, &[index]),
+                Var::Function(index) => self.instr(Op::Function, &[index]),
+            },
+            ExprKind::Grouping(inner) => self.expr(*inner)?,
+            ExprKind::IntToFloat(inner) => {
+                self.expr(*inner)?;
+                self.op(Op::IntToFloat);
+            }
+            ExprKind::Unary { op, operand } => {
+                self.expr(*operand)?;
+                self.op(match op {
+                    UnaryOp::Neg => Op::Neg,
+                    UnaryOp::Not => Op::Not,
+                    UnaryOp::BitNot => Op::BitNot,
+                });
+            }
+            ExprKind::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                left,
+                right,
+            } => {
+                // The left operand is the result if it decides it.
+                self.expr(*left)?;
+                self.op(Op::Dup);
+                let to_end = self.jump(match op {
+                    BinaryOp::And => Op::JumpIfFalse,
+                    _ => Op::JumpIfTrue,
+                });
+                self.op(Op::Pop);
+                self.expr(*right)?;
+                self.patch(to_end);
+            }
+            ExprKind::Binary { op, left, right } => {
+                self.expr(*left)?;
+                self.expr(*right)?;
+                self.op(binary(*op));
+            }
+            ExprKind::Assign { op, target, value } => self.assign(*op, *target, *value, true)?,
+            ExprKind::Call { callee, args } => self.call(*callee, args)?,
+            ExprKind::Array(elements) => {
+                let count = u16::try_from(elements.len())
+                    .map_err(|_| self.too_many("elements in an array", u16::MAX as usize))?;
+                for &element in elements {
+                    self.expr(element)?;
+                }
+                self.instr(Op::Array, &[count]);
+            }
+            ExprKind::Index { object, index } => {
+                self.expr(*object)?;
+                self.expr(*index)?;
+                self.op(Op::GetIndex);
+            }
+            ExprKind::StructLiteral { name, fields } => {
+                let ty = self.type_index(expr.id, name)?;
+                self.instr(Op::Struct, &[ty]);
+                for field in fields {
+                    self.expr(field.value)?;
+                    let name = self.field_name(&field.name)?;
+                    self.instr(Op::SetField, &[name]);
+                }
+            }
+            ExprKind::Field { object, field } => {
+                self.expr(*object)?;
+                let name = self.field_name(field)?;
+                self.instr(Op::GetField, &[name]);
+            }
+            ExprKind::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.expr(*condition)?;
+                let to_else = self.jump(Op::JumpIfFalse);
+                self.expr(*then_expr)?;
+                let to_end = self.jump(Op::Jump);
+                self.patch(to_else);
+                self.expr(*else_expr)?;
+                self.patch(to_end);
+            }
+            ExprKind::Variant { enum_name, variant } => {
+                let (ty, index) = self.variant(expr.id, enum_name, variant)?;
+                self.instr(Op::Variant, &[ty, index, 0]);
+            }
+            ExprKind::Match { scrutinee, arms } => self.match_expr(*scrutinee, arms, true)?,
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, value: &LiteralValue) -> Result<()> {
+        let value = match value {
+            LiteralValue::Integer(value) => ConstValue::Int(*value),
+            LiteralValue::Float(value) => ConstValue::Float(*value),
+            LiteralValue::String(value) => ConstValue::String(value.as_str().into()),
+            LiteralValue::Boolean(true) => {
+                self.op(Op::True);
+                return Ok(());
+            }
+            LiteralValue::Boolean(false) => {
+                self.op(Op::False);
+                return Ok(());
+            }
+            LiteralValue::Null | LiteralValue::None => {
+                self.op(Op::Null);
+                return Ok(());
+            }
+        };
+        let index = self.constant(value)?;
+        self.instr(Op::Const, &[index]);
+        Ok(())
+    }
+
+    fn call(&mut self, callee: ExprId, args: &[ExprId]) -> Result<()> {
+        let count = u8::try_from(args.len())
+            .map_err(|_| self.too_many("arguments in a call", u8::MAX as usize))?;
+        let ungrouped = self.arena.ungrouped(callee);
+        if let ExprKind::Variant { enum_name, variant } = &ungrouped.kind {
+            let (ty, index) = self.variant(ungrouped.id, enum_name, variant)?;
+            for &arg in args {
+                self.expr(arg)?;
+            }
+            self.instr(Op::Variant, &[ty, index, count as u16]);
+            return Ok(());
+        }
+        self.expr(callee)?;
+        for &arg in args {
+            self.expr(arg)?;
+        }
+        self.instr(Op::Call, &[count as u16]);
+        Ok(())
+    }
+
+    fn assign(
+        &mut self,
+        op: Option<BinaryOp>,
+        target: ExprId,
+        value: ExprId,
+        want_value: bool,
+    ) -> Result<()> {
+        let place = self.place(target)?;
+        let result = if want_value { Some(self.temp()?) } else { None };
+        let steps = place.steps.len();
+        self.store(&place, steps, &mut |this| {
+            if let Some(op) = op {
+                this.load(&place, steps);
+                this.expr(value)?;
+                this.op(binary(op));
+            } else {
+                this.expr(value)?;
+            }
+            if let Some(result) = result {
+                this.op(Op::Dup);
+                this.instr(Op::SetLocal, &[result]);
+            }
+            Ok(())
+        })?;
+        if let Some(result) = result {
+            self.instr(Op::GetLocal, &[result]);
+        }
+        Ok(())
+    }
+
+    /// Where assigning to `target` stores, evaluating the indices along the
+    /// way, in source order, into slots.
+    fn place(&mut self, target: ExprId) -> Result<Place> {
+        let expr = self.arena.ungrouped(target);
+        match &expr.kind {
+            ExprKind::Variable(name) => {
+                let root = match self.variable(expr.id, name)? {
+                    Var::Local(slot) => Root::Local(slot),
+                    Var::Global(index) => Root::Global(index),
+                    Var::Function(index) => {
+                        self.instr(Op::Function, &[index]);
+                        Root::Value(self.store_temp()?)
+                    }
+                };
+                Ok(Place {
+                    root,
+                    steps: Vec::new(),
+                })
+            }
+            ExprKind::Field { object, field } => {
+                let mut place = self.place(*object)?;
+                let name = self.field_name(field)?;
+                place.steps.push(Step::Field(name));
+                Ok(place)
+            }
+            ExprKind::Index { object, index } => {
+                let mut place = self.place(*object)?;
+                self.expr(*index)?;
+                let slot = self.store_temp()?;
+                place.steps.push(Step::Index(slot));
+                Ok(place)
+            }
+            _ => {
+                self.expr(target)?;
+                Ok(Place {
+                    root: Root::Value(self.store_temp()?),
+                    steps: Vec::new(),
+                })
+            }
+        }
+    }
+
+    /// Pushes the value at `place` with only its first `steps` steps taken.
+    fn load(&mut self, place: &Place, steps: usize) {
+        match place.root {
+            Root::Local(slot) | Root::Value(slot) => self.instr(Op::GetLocal, &[slot]),
+            Root::Global(index) => self.instr(Op::GetGlobal, &[index]),
+        }
+        for step in &place.steps[..steps] {
+            match *step {
+                Step::Field(name) => self.instr(Op::GetField, &[name]),
+                Step::Index(slot) => {
+                    self.instr(Op::GetLocal, &[slot]);
+                    self.op(Op::GetIndex);
+                }
+            }
+        }
+    }
+
+    /// Stores what `value` pushes at `place` with only its first `steps`
+    /// steps taken, updating each struct and array it is in on the way
+    /// back to the root.
+    fn store(
+        &mut self,
+        place: &Place,
+        steps: usize,
+        value: &mut dyn FnMut(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        if steps == 0 {
+            value(self)?;
+            match place.root {
+                Root::Local(slot) => self.instr(Op::SetLocal, &[slot]),
+                Root::Global(index) => self.instr(Op::SetGlobal, &[index]),
+                Root::Value(_) => self.op(Op::Pop),
+            }
+            return Ok(());
+        }
+        self.store(place, steps - 1, &mut |this| {
+            this.load(place, steps - 1);
+            match place.steps[steps - 1] {
+                Step::Field(name) => {
+                    value(this)?;
+                    this.instr(Op::SetField, &[name]);
+                }
+                Step::Index(slot) => {
+                    this.instr(Op::GetLocal, &[slot]);
+                    value(this)?;
+                    this.op(Op::SetIndex);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Compiles a `match`, pushing the value of the arm that matched if
+    /// `want_value`, or `null` if none did.
+    fn match_expr(&mut self, scrutinee: ExprId, arms: &[MatchArm], want_value: bool) -> Result<()> {
+        self.expr(scrutinee)?;
+        let subject = self.store_temp()?;
+        let mut to_end = Vec::new();
+        for arm in arms {
+            let mut to_next = Vec::new();
+            self.pattern(&arm.pattern, subject, &mut to_next)?;
+            match &arm.body {
+                ArmBody::Expr(body) if want_value => self.expr(*body)?,
+                ArmBody::Expr(body) => self.discard(*body)?,
+                ArmBody::Block(block) => {
+                    self.block(block)?;
+                    if want_value {
+                        self.op(Op::Null);
+                    }
+                }
+            }
+            to_end.push(self.jump(Op::Jump));
+            for at in to_next {
+                self.patch(at);
+            }
+        }
+        if want_value {
+            self.op(Op::Null);
+        }
+        for at in to_end {
+            self.patch(at);
+        }
+        Ok(())
+    }
+
+    /// Tests the value in `slot` against `pattern`, binding its names, and
+    /// adds the jumps taken when it does not match to `to_next`.
+    fn pattern(&mut self, pattern: &Pattern, slot: u16, to_next: &mut Vec<usize>) -> Result<()> {
+        match &pattern.kind {
+            PatternKind::Wildcard => {}
+            PatternKind::Literal(value) => {
+                self.instr(Op::GetLocal, &[slot]);
+                self.literal(value)?;
+                self.op(Op::Eq);
+                to_next.push(self.jump(Op::JumpIfFalse));
+            }
+            PatternKind::Binding(name) => {
+                self.instr(Op::GetLocal, &[slot]);
+                let binding = self.declare(pattern.id, &name.name)?;
+                self.instr(Op::SetLocal, &[binding]);
+            }
+            PatternKind::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let (ty, index) = self.variant(pattern.id, enum_name, variant)?;
+                self.instr(Op::GetLocal, &[slot]);
+                self.instr(Op::IsVariant, &[ty, index]);
+                to_next.push(self.jump(Op::JumpIfFalse));
+                for (i, field) in fields.iter().enumerate() {
+                    if field.kind == PatternKind::Wildcard {
+                        continue;
+                    }
+                    self.instr(Op::GetLocal, &[slot]);
+                    self.instr(Op::GetPayload, &[i as u16]);
+                    if let PatternKind::Binding(name) = &field.kind {
+                        let binding = self.declare(field.id, &name.name)?;
+                        self.instr(Op::SetLocal, &[binding]);
+                        continue;
+                    }
+                    let payload = self.store_temp()?;
+                    self.pattern(field, payload, to_next)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::disassemble;
+    use crate::parser::parse;
+    use crate::semantic::{evaluate, resolve, typeck, TypeckOptions};
+    use pretty_assertions::assert_eq;
+
+    /// The disassembly of `source`, which must check without errors.
+    fn disasm(source: &str) -> String {
+        let (mut program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, diagnostics) = resolve(&program);
+        assert_eq!(diagnostics, []);
+        let (consts, diagnostics) = evaluate(&program, &resolution);
+        assert_eq!(diagnostics, []);
+        let (_, diagnostics) = typeck::check(
+            &mut program,
+            &resolution,
+            &consts,
+            &TypeckOptions::default(),
+        );
+        assert_eq!(diagnostics, []);
+        disassemble(&emit(&program, &resolution, &consts).unwrap())
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "int limit = 10;
+fn f(n: int) -> int {
+    int s = 0;
+    while (s < limit && n > 0) {
+        n -= 1;
+        if (n == 3) continue;
+        if (n == 1) break;
+        s = s + n;
+    }
+    return s > 5 ? s : 0;
+}";
+        assert_eq!(
+            disasm(source),
+            "constants:
+    0  10
+    1  0
+    2  1
+    3  3
+    4  5
+
+globals:
+    0  limit = 10
+
+fn 0 f(n), locals: n, s
+    0000  const 1                 ; 0
+    0003  set_local 1             ; s
+    0006  get_local 1             ; s
+    0009  get_global 0            ; limit
+    0012  lt
+    0013  dup
+    0014  jump_if_false 0025
+    0017  pop
+    0018  get_local 0             ; n
+    0021  const 1                 ; 0
+    0024  gt
+    0025  jump_if_false 0077
+    0028  get_local 0             ; n
+    0031  const 2                 ; 1
+    0034  sub
+    0035  set_local 0             ; n
+    0038  get_local 0             ; n
+    0041  const 3                 ; 3
+    0044  eq
+    0045  jump_if_false 0051
+    0048  jump 0006
+    0051  get_local 0             ; n
+    0054  const 2                 ; 1
+    0057  eq
+    0058  jump_if_false 0064
+    0061  jump 0077
+    0064  get_local 1             ; s
+    0067  get_local 0             ; n
+    0070  add
+    0071  set_local 1             ; s
+    0074  jump 0006
+    0077  get_local 1             ; s
+    0080  const 4                 ; 5
+    0083  gt
+    0084  jump_if_false 0093
+    0087  get_local 1             ; s
+    0090  jump 0096
+    0093  const 1                 ; 0
+    0096  return
+    0097  null
+    0098  return
+"
+        );
+    }
+
+    #[test]
+    fn test_assign() {
+        let source = "struct P { x: int, ys: int[2] }
+fn f(p: P, i: int) -> int {
+    p.ys[i + 1] = 5;
+    return p.x = 2;
+}";
+        assert_eq!(
+            disasm(source),
+            "constants:
+    0  \"ys\"
+    1  1
+    2  5
+    3  \"x\"
+    4  2
+
+types:
+    0  struct P { x, ys }
+
+fn 0 f(p, i), locals: p, i, $2, $3
+    0000  get_local 1             ; i
+    0003  const 1                 ; 1
+    0006  add
+    0007  set_local 2             ; $2
+    0010  get_local 0             ; p
+    0013  get_local 0             ; p
+    0016  get_field 0             ; ys
+    0019  get_local 2             ; $2
+    0022  const 2                 ; 5
+    0025  set_index
+    0026  set_field 0             ; ys
+    0029  set_local 0             ; p
+    0032  get_local 0             ; p
+    0035  const 4                 ; 2
+    0038  dup
+    0039  set_local 3             ; $3
+    0042  set_field 3             ; x
+    0045  set_local 0             ; p
+    0048  get_local 3             ; $3
+    0051  return
+    0052  null
+    0053  return
+"
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let source = "enum E { A, B(int, int) }
+fn f(e: E) -> int {
+    return match e { E::B(1, y) => y, E::A => 0, _ => g(E::B(2, 3)) };
+}
+fn g(e: E) -> int { return 1; }";
+        assert_eq!(
+            disasm(source),
+            "constants:
+    0  1
+    1  0
+    2  2
+    3  3
+
+types:
+    0  enum E { A, B }
+
+fn 0 f(e), locals: e, $1, $2, y
+    0000  get_local 0             ; e
+    0003  set_local 1             ; $1
+    0006  get_local 1             ; $1
+    0009  is_variant 0, 1         ; E::B
+    0013  jump_if_false 0048
+    0016  get_local 1             ; $1
+    0019  get_payload 0
+    0021  set_local 2             ; $2
+    0024  get_local 2             ; $2
+    0027  const 0                 ; 1
+    0030  eq
+    0031  jump_if_false 0048
+    0034  get_local 1             ; $1
+    0037  get_payload 1
+    0039  set_local 3             ; y
+    0042  get_local 3             ; y
+    0045  jump 0084
+    0048  get_local 1             ; $1
+    0051  is_variant 0, 0         ; E::A
+    0055  jump_if_false 0064
+    0058  const 1                 ; 0
+    0061  jump 0084
+    0064  function 1              ; g
+    0067  const 2                 ; 2
+    0070  const 3                 ; 3
+    0073  variant 0, 1, 2         ; E::B
+    0078  call 1
+    0080  jump 0084
+    0083  null
+    0084  return
+    0085  null
+    0086  return
+
+fn 1 g(e), locals: e
+    0000  const 0                 ; 1
+    0003  return
+    0004  null
+    0005  return
+"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! A compact bytecode for a stack machine, so programs can be run without
+//! a native backend.
+//!
+//! A [`Module`] holds everything a program needs at run time: a pool of
+//! constants, the globals with their initial values, the struct and enum
+//! types, and the function table, each function with its own code. [`emit`]
+//! compiles a checked program into one, and [`disassemble`] prints it.
+//!
+//! Code is a sequence of instructions, each an opcode byte followed by its
+//! operands. Indices into the module's tables, local slots and code offsets
+//! are two bytes, little-endian; counts are one byte. Instructions pop their
+//! inputs off the operand stack and push their results, as each [`Op`]
+//! describes. Values carry their types, so one `add` serves `int`, `float`
+//! and `string` alike. A function's parameters are its first locals, and
+//! calling a function pushes what it returns.
+
+pub mod disasm;
+pub mod emit;
+
+pub use disasm::disassemble;
+pub use emit::{emit, EmitError};
+
+use crate::semantic::ConstValue;
+
+/// An instruction's opcode. The operands each takes are listed in brackets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Op {
+    /// `[constant]` Pushes a constant from the pool.
+    Const,
+    /// Pushes `null`, which is also what a `void` function returns.
+    Null,
+    True,
+    False,
+    /// Pops and discards a value.
+    Pop,
+    /// Pushes a copy of the top value.
+    Dup,
+    /// `[slot]` Pushes a local.
+    GetLocal,
+    /// `[slot]` Pops a value into a local.
+    SetLocal,
+    /// `[global]` Pushes a global.
+    GetGlobal,
+    /// `[global]` Pops a value into a global.
+    SetGlobal,
+    /// `[function]` Pushes a function, to call or store.
+    Function,
+    Neg,
+    Not,
+    BitNot,
+    /// Converts an `int` to a `float`.
+    IntToFloat,
+    /// Binary operators pop the right operand, then the left, and push the
+    /// result.
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    /// `[offset]` Continues at `offset` in the function's code.
+    Jump,
+    /// `[offset]` Pops a `bool` and jumps if it is false.
+    JumpIfFalse,
+    /// `[offset]` Pops a `bool` and jumps if it is true.
+    JumpIfTrue,
+    /// `[count]` Pops `count` arguments, then the function to call with
+    /// them, and pushes what it returns.
+    Call,
+    /// Pops a value and returns it to the caller.
+    Return,
+    /// `[count]` Pops `count` elements, the last on top, and pushes an array
+    /// of them.
+    Array,
+    /// Pops an index, then an array, and pushes the element.
+    GetIndex,
+    /// Pops a value, an index and an array, and pushes the array with the
+    /// element replaced.
+    SetIndex,
+    /// `[type]` Pushes a struct of the type, with every field `null`.
+    Struct,
+    /// `[constant]` Pops a struct and pushes the field the constant names.
+    GetField,
+    /// `[constant]` Pops a value, then a struct, and pushes the struct with
+    /// the named field replaced.
+    SetField,
+    /// `[type, variant, count]` Pops `count` payload fields, the last on
+    /// top, and pushes the variant of the enum holding them.
+    Variant,
+    /// `[type, variant]` Pops an enum value and pushes whether it is the
+    /// variant.
+    IsVariant,
+    /// `[index]` Pops an enum value and pushes the payload field at
+    /// `index`.
+    GetPayload,
+}
+
+/// The size of an operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// One byte: a count or a variant or payload index.
+    Byte,
+    /// Two bytes: an index into a module table, a local slot or a code
+    /// offset.
+    Short,
+}
+
+impl Op {
+    /// Every opcode, in the order of its byte.
+    pub const ALL: &'static [Op] = &[
+        Op::Const,
+        Op::Null,
+        Op::True,
+        Op::False,
+        Op::Pop,
+        Op::Dup,
+        Op::GetLocal,
+        Op::SetLocal,
+        Op::GetGlobal,
+        Op::SetGlobal,
+        Op::Function,
+        Op::Neg,
+        Op::Not,
+        Op::BitNot,
+        Op::IntToFloat,
+        Op::Add,
+        Op::Sub,
+        Op::Mul,
+        Op::Div,
+        Op::Rem,
+        Op::Eq,
+        Op::Ne,
+        Op::Lt,
+        Op::Le,
+        Op::Gt,
+        Op::Ge,
+        Op::BitAnd,
+        Op::BitOr,
+        Op::BitXor,
+        Op::Shl,
+        Op::Shr,
+        Op::Jump,
+        Op::JumpIfFalse,
+        Op::JumpIfTrue,
+        Op::Call,
+        Op::Return,
+        Op::Array,
+        Op::GetIndex,
+        Op::SetIndex,
+        Op::Struct,
+        Op::GetField,
+        Op::SetField,
+        Op::Variant,
+        Op::IsVariant,
+        Op::GetPayload,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Op> {
+        Op::ALL.get(byte as usize).copied()
+    }
+
+    /// The opcode's name in disassembly, such as `get_local`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Const => "const",
+            Op::Null => "null",
+            Op::True => "true",
+            Op::False => "false",
+            Op::Pop => "pop",
+            Op::Dup => "dup",
+            Op::GetLocal => "get_local",
+            Op::SetLocal => "set_local",
+            Op::GetGlobal => "get_global",
+            Op::SetGlobal => "set_global",
+            Op::Function => "function",
+            Op::Neg => "neg",
+            Op::Not => "not",
+            Op::BitNot => "bit_not",
+            Op::IntToFloat => "int_to_float",
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Rem => "rem",
+            Op::Eq => "eq",
+            Op::Ne => "ne",
+            Op::Lt => "lt",
+            Op::Le => "le",
+            Op::Gt => "gt",
+            Op::Ge => "ge",
+            Op::BitAnd => "bit_and",
+            Op::BitOr => "bit_or",
+            Op::BitXor => "bit_xor",
+            Op::Shl => "shl",
+            Op::Shr => "shr",
+            Op::Jump => "jump",
+            Op::JumpIfFalse => "jump_if_false",
+            Op::JumpIfTrue => "jump_if_true",
+            Op::Call => "call",
+            Op::Return => "return",
+            Op::Array => "array",
+            Op::GetIndex => "get_index",
+            Op::SetIndex => "set_index",
+            Op::Struct => "struct",
+            Op::GetField => "get_field",
+            Op::SetField => "set_field",
+            Op::Variant => "variant",
+            Op::IsVariant => "is_variant",
+            Op::GetPayload => "get_payload",
+        }
+    }
+
+    /// The operands that follow the opcode, in order.
+    pub fn operands(self) -> &'static [Operand] {
+        use Operand::{Byte, Short};
+        match self {
+            Op::Const
+            | Op::GetLocal
+            | Op::SetLocal
+            | Op::GetGlobal
+            | Op::SetGlobal
+            | Op::Function
+            | Op::Jump
+            | Op::JumpIfFalse
+            | Op::JumpIfTrue
+            | Op::Array
+            | Op::Struct
+            | Op::GetField
+            | Op::SetField => &[Short],
+            Op::Call | Op::GetPayload => &[Byte],
+            Op::Variant => &[Short, Byte, Byte],
+            Op::IsVariant => &[Short, Byte],
+            _ => &[],
+        }
+    }
+
+    /// The size of an instruction with this opcode, in bytes.
+    pub fn size(self) -> usize {
+        1 + self
+            .operands()
+            .iter()
+            .map(|operand| match operand {
+                Operand::Byte => 1,
+                Operand::Short => 2,
+            })
+            .sum::<usize>()
+    }
+}
+
+/// One decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// Where the instruction starts in its function's code.
+    pub offset: usize,
+    pub op: Op,
+    pub operands: Vec<u16>,
+}
+
+impl Instruction {
+    /// The instruction at `offset` in `code`, or `None` if there is no
+    /// valid one there.
+    pub fn decode(code: &[u8], offset: usize) -> Option<Instruction> {
+        let op = Op::from_byte(*code.get(offset)?)?;
+        let mut operands = Vec::new();
+        let mut at = offset + 1;
+        for operand in op.operands() {
+            match operand {
+                Operand::Byte => {
+                    operands.push(*code.get(at)? as u16);
+                    at += 1;
+                }
+                Operand::Short => {
+                    let bytes = code.get(at..at + 2)?;
+                    operands.push(u16::from_le_bytes([bytes[0], bytes[1]]));
+                    at += 2;
+                }
+            }
+        }
+        Some(Instruction {
+            offset,
+            op,
+            operands,
+        })
+    }
+}
+
+/// A compiled program.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub constants: Vec<ConstValue>,
+    pub globals: Vec<Global>,
+    pub types: Vec<TypeDef>,
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    /// The index of the function called `name`, such as `main`.
+    pub fn function(&self, name: &str) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Global {
+    pub name: String,
+    /// The constant the global starts as, or `None` for `null`.
+    pub init: Option<u16>,
+}
+
+/// A struct or enum type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeDef {
+    pub name: String,
+    pub kind: TypeDefKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeDefKind {
+    Struct { fields: Vec<String> },
+    Enum { variants: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    /// How many arguments it takes, which are its first locals.
+    pub params: u8,
+    /// The name of each local slot, for disassembly. Slots the emitter
+    /// uses for its own temporaries are named `$` and the slot number.
+    pub locals: Vec<String>,
+    pub code: Vec<u8>,
+}
+
+impl Function {
+    /// The function's code decoded, stopping at the first invalid byte.
+    pub fn instructions(&self) -> impl Iterator<Item = Instruction> + '_ {
+        let mut offset = 0;
+        std::iter::from_fn(move || {
+            let instruction = Instruction::decode(&self.code, offset)?;
+            offset += instruction.op.size();
+            Some(instruction)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_ops() {
+        for (byte, op) in Op::ALL.iter().enumerate() {
+            assert_eq!(*op as usize, byte, "{}", op.name());
+            assert_eq!(Op::from_byte(byte as u8), Some(*op));
+        }
+        assert_eq!(Op::from_byte(Op::ALL.len() as u8), None);
+        assert_eq!(Op::Variant.size(), 5);
+
+        let function = Function {
+            name: "f".to_string(),
+            params: 0,
+            locals: Vec::new(),
+            code: vec![
+                Op::Const as u8,
+                0x34,
+                0x12,
+                Op::Call as u8,
+                2,
+                Op::Return as u8,
+                0xff,
+            ],
+        };
+        let decoded: Vec<(Op, Vec<u16>)> = function
+            .instructions()
+            .map(|instruction| (instruction.op, instruction.operands))
+            .collect();
+        assert_eq!(
+            decoded,
+            [
+                (Op::Const, vec![0x1234]),
+                (Op::Call, vec![2]),
+                (Op::Return, vec![]),
+            ]
+        );
+        // A truncated operand is not an instruction.
+        assert_eq!(Instruction::decode(&[Op::Jump as u8, 0], 0), None);
+    }
+}
@@ -0,0 +1,514 @@
+use crate::diagnostics::{Code, Diagnostic, Severity};
+use crate::source::Span;
+use crate::utils::{fnv1a64, Fnv1a};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Default cache directory, relative to the working directory.
+pub const DEFAULT_CACHE_DIR: &str = ".minicompiler-cache";
+
+/// Version of what the stages store. Bump it whenever an artifact changes
+/// shape without the compiler version changing, so that artifacts written
+/// by an older build are not read back as current ones.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Default bound on the total size of the cached artifacts, in bytes.
+pub const DEFAULT_SIZE_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// Identifies one cached artifact: a hash of the source text and of every
+/// file it includes, the compiler options that influence the artifact, the
+/// compiler version and the [`FORMAT_VERSION`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey(u64);
+
+impl CacheKey {
+    pub fn new(source: &str, options: &str) -> Self {
+        Self::with_includes(source, [], options)
+    }
+
+    /// The key of `source` when its artifact also depends on `includes`,
+    /// the path and text of each file it includes, in the order they were
+    /// included.
+    pub fn with_includes<'a>(
+        source: &str,
+        includes: impl IntoIterator<Item = (&'a Path, &'a str)>,
+        options: &str,
+    ) -> Self {
+        let mut hasher = Fnv1a::new();
+        hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.write(&[0]);
+        hasher.write(&FORMAT_VERSION.to_le_bytes());
+        hasher.write(options.as_bytes());
+        hasher.write(&[0]);
+        hasher.write(&fnv1a64(source.as_bytes()).to_le_bytes());
+        for (path, text) in includes {
+            hasher.write(&fnv1a64(path.to_string_lossy().as_bytes()).to_le_bytes());
+            hasher.write(&fnv1a64(text.as_bytes()).to_le_bytes());
+        }
+        Self(hasher.finish())
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Pipeline stage an artifact belongs to. Each stage is stored in its own
+/// subdirectory so stages can be invalidated independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// The token listing of `lex`.
+    Tokens,
+    /// The syntax tree `parse` prints.
+    Tree,
+    /// The type-checked tree `parse --check` prints, which `check` stores
+    /// too.
+    Typed,
+    /// The graphs `ir` prints.
+    Ir,
+}
+
+impl Stage {
+    fn dir_name(self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Tree => "tree",
+            Stage::Typed => "typed",
+            Stage::Ir => "ir",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub writes: usize,
+    /// Artifacts removed to keep the cache under its size limit.
+    pub evictions: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cache: {} hit(s), {} miss(es), {} write(s), {} eviction(s)",
+            self.hits, self.misses, self.writes, self.evictions
+        )
+    }
+}
+
+/// Content-addressed on-disk store for per-file artifacts.
+///
+/// A disabled cache never reads or writes anything and reports every lookup
+/// as a miss, so callers do not need to special-case `--no-cache`.
+pub struct Cache {
+    dir: PathBuf,
+    enabled: bool,
+    stats: CacheStats,
+}
+
+impl Cache {
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            enabled: true,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            dir: PathBuf::new(),
+            enabled: false,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// The artifact of `stage` under `key` and the diagnostics stored with
+    /// it. A hit marks the artifact as recently used, so [`Self::prune`]
+    /// keeps it over older ones.
+    pub fn get(&mut self, stage: Stage, key: CacheKey) -> Option<(String, Vec<Diagnostic>)> {
+        let path = self.path_for(stage, key);
+        let entry = if self.enabled {
+            fs::read_to_string(&path)
+                .ok()
+                .and_then(|entry| decode(&entry))
+        } else {
+            None
+        };
+        match entry {
+            Some(_) => {
+                self.stats.hits += 1;
+                // Only the order of eviction depends on it.
+                let _ = fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+            }
+            None => self.stats.misses += 1,
+        }
+        entry
+    }
+
+    /// Stores `artifact` under `key` together with `diagnostics`, which
+    /// [`Self::get`] gives back to be reported again.
+    pub fn put(
+        &mut self,
+        stage: Stage,
+        key: CacheKey,
+        artifact: &str,
+        diagnostics: &[Diagnostic],
+    ) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let path = self.path_for(stage, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, encode(artifact, diagnostics))?;
+        self.stats.writes += 1;
+        Ok(())
+    }
+
+    /// Removes the least recently used artifacts until the ones left take
+    /// at most `limit` bytes.
+    pub fn prune(&mut self, limit: u64) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let mut artifacts = Vec::new();
+        for stage in [Stage::Tokens, Stage::Tree, Stage::Typed, Stage::Ir] {
+            let entries = match fs::read_dir(self.dir.join(stage.dir_name())) {
+                Ok(entries) => entries,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+            for entry in entries {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                artifacts.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+        let mut size: u64 = artifacts.iter().map(|(_, len, _)| len).sum();
+        artifacts.sort();
+        for (_, len, path) in artifacts {
+            if size <= limit {
+                break;
+            }
+            fs::remove_file(path)?;
+            size -= len;
+            self.stats.evictions += 1;
+        }
+        Ok(())
+    }
+
+    fn path_for(&self, stage: Stage, key: CacheKey) -> PathBuf {
+        self.dir.join(stage.dir_name()).join(key.to_string())
+    }
+}
+
+/// An artifact file: the number of lines describing diagnostics, those
+/// lines, then the artifact itself.
+///
+/// ```text
+/// 2
+/// d warning W0601 14 15 unused variable 'x'
+/// f 14 15 _x\tif this is intentional, prefix it with an underscore
+/// (program ...)
+/// ```
+///
+/// A `d` line starts each diagnostic; `l`, `n` and `f` lines add a label,
+/// a note and a fix-it to it. Text is escaped so it stays on one line.
+fn encode(artifact: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut lines = Vec::new();
+    for diagnostic in diagnostics {
+        let span = diagnostic.primary_span;
+        lines.push(format!(
+            "d {} {} {} {} {}",
+            diagnostic.severity,
+            diagnostic.code,
+            span.start,
+            span.end,
+            escape(&diagnostic.message)
+        ));
+        for label in &diagnostic.labels {
+            let span = label.span;
+            lines.push(format!(
+                "l {} {} {}",
+                span.start,
+                span.end,
+                escape(&label.message)
+            ));
+        }
+        for note in &diagnostic.notes {
+            lines.push(format!("n {}", escape(note)));
+        }
+        for fixit in &diagnostic.fixits {
+            let span = fixit.span;
+            lines.push(format!(
+                "f {} {} {}\t{}",
+                span.start,
+                span.end,
+                escape(&fixit.replacement),
+                escape(&fixit.message)
+            ));
+        }
+    }
+    let mut out = format!("{}\n", lines.len());
+    for line in lines {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out.push_str(artifact);
+    out
+}
+
+/// The artifact and diagnostics [`encode`] wrote into `entry`, or `None` if
+/// it is not in that format.
+fn decode(entry: &str) -> Option<(String, Vec<Diagnostic>)> {
+    let (count, mut rest) = entry.split_once('\n')?;
+    let count: usize = count.parse().ok()?;
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for _ in 0..count {
+        let (line, tail) = rest.split_once('\n')?;
+        rest = tail;
+        let (kind, fields) = line.split_once(' ')?;
+        match kind {
+            "d" => {
+                let mut fields = fields.splitn(5, ' ');
+                let severity = match fields.next()? {
+                    "warning" => Severity::Warning,
+                    "error" => Severity::Error,
+                    _ => return None,
+                };
+                let code: Code = fields.next()?.parse().ok()?;
+                let span = span(fields.next()?, fields.next()?)?;
+                let message = unescape(fields.next()?)?;
+                let mut diagnostic = Diagnostic::error(code, message, span);
+                diagnostic.severity = severity;
+                diagnostics.push(diagnostic);
+            }
+            "l" => {
+                let mut fields = fields.splitn(3, ' ');
+                let span = span(fields.next()?, fields.next()?)?;
+                let message = unescape(fields.next()?)?;
+                let diagnostic = diagnostics.pop()?.with_label(span, message);
+                diagnostics.push(diagnostic);
+            }
+            "n" => {
+                let diagnostic = diagnostics.pop()?.with_note(unescape(fields)?);
+                diagnostics.push(diagnostic);
+            }
+            "f" => {
+                let mut fields = fields.splitn(3, ' ');
+                let span = span(fields.next()?, fields.next()?)?;
+                let (replacement, message) = fields.next()?.split_once('\t')?;
+                let diagnostic =
+                    diagnostics
+                        .pop()?
+                        .with_fixit(span, unescape(replacement)?, unescape(message)?);
+                diagnostics.push(diagnostic);
+            }
+            _ => return None,
+        }
+    }
+    Some((rest.to_string(), diagnostics))
+}
+
+fn span(start: &str, end: &str) -> Option<Span> {
+    Some(Span::new(start.parse().ok()?, end.parse().ok()?))
+}
+
+/// `text` with backslashes, line breaks and tabs escaped.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::codes;
+    use pretty_assertions::assert_eq;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "minicompiler-cache-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_roundtrip_and_stats() {
+        let dir = temp_dir("roundtrip");
+        let mut cache = Cache::open(&dir);
+        let key = CacheKey::new("int x;", "");
+
+        assert_eq!(cache.get(Stage::Tokens, key), None);
+        cache.put(Stage::Tokens, key, "tokens", &[]).unwrap();
+        assert_eq!(
+            cache.get(Stage::Tokens, key),
+            Some(("tokens".to_string(), Vec::new()))
+        );
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                writes: 1,
+                evictions: 0
+            }
+        );
+        assert_eq!(cache.get(Stage::Tree, key), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_diagnostics_roundtrip() {
+        let dir = temp_dir("diagnostics");
+        let mut cache = Cache::open(&dir);
+        let key = CacheKey::new("int x;", "");
+        let diagnostics = vec![
+            Diagnostic::warning(
+                codes::UNUSED_VARIABLE,
+                "unused variable 'x'",
+                Span::new(4, 5),
+            )
+            .with_fixit(Span::new(4, 5), "_x", "prefix it\twith '_'"),
+            Diagnostic::warning(
+                codes::IMPLICIT_CONVERSION,
+                "implicit conversion\nfrom int \\ to float",
+                Span::new(0, 3),
+            )
+            .with_label(Span::new(0, 6), "here")
+            .with_note("a note")
+            .with_note(""),
+        ];
+
+        cache
+            .put(Stage::Typed, key, "(program)\n", &diagnostics)
+            .unwrap();
+        assert_eq!(
+            cache.get(Stage::Typed, key),
+            Some(("(program)\n".to_string(), diagnostics))
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_prune_removes_least_recently_used() {
+        let dir = temp_dir("prune");
+        let mut cache = Cache::open(&dir);
+        let keys: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|source| CacheKey::new(source, ""))
+            .collect();
+        for (i, &key) in keys.iter().enumerate() {
+            cache.put(Stage::Tree, key, "artifact", &[]).unwrap();
+            let path = cache.path_for(Stage::Tree, key);
+            let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(i as u64);
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        }
+        // Using the oldest makes the second one the least recently used.
+        assert!(cache.get(Stage::Tree, keys[0]).is_some());
+
+        let size = fs::metadata(cache.path_for(Stage::Tree, keys[0]))
+            .unwrap()
+            .len();
+        cache.prune(2 * size).unwrap();
+        assert_eq!(cache.stats().evictions, 1);
+        assert!(cache.get(Stage::Tree, keys[0]).is_some());
+        assert!(cache.get(Stage::Tree, keys[1]).is_none());
+        assert!(cache.get(Stage::Tree, keys[2]).is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_key_depends_on_source_and_options() {
+        let base = CacheKey::new("int x;", "");
+        assert_eq!(base, CacheKey::new("int x;", ""));
+        assert_ne!(base, CacheKey::new("int y;", ""));
+        assert_ne!(base, CacheKey::new("int x;", "--trivia"));
+    }
+
+    #[test]
+    fn test_key_depends_on_includes() {
+        let key = |includes: &[(&str, &str)]| {
+            let includes = includes.iter().map(|&(path, text)| (Path::new(path), text));
+            CacheKey::with_includes("#include \"a.mc\"", includes, "")
+        };
+        let base = key(&[("a.mc", "int x;")]);
+        assert_eq!(base, key(&[("a.mc", "int x;")]));
+        assert_ne!(base, key(&[]));
+        assert_ne!(base, key(&[("a.mc", "int y;")]));
+        assert_ne!(base, key(&[("b.mc", "int x;")]));
+        assert_ne!(
+            key(&[("a.mc", "int x;"), ("b.mc", "")]),
+            key(&[("b.mc", ""), ("a.mc", "int x;")])
+        );
+    }
+
+    #[test]
+    fn test_disabled_cache_always_misses() {
+        let mut cache = Cache::disabled();
+        let key = CacheKey::new("int x;", "");
+        cache.put(Stage::Tokens, key, "tokens", &[]).unwrap();
+        assert_eq!(cache.get(Stage::Tokens, key), None);
+        assert_eq!(cache.stats().writes, 0);
+        assert_eq!(cache.stats().misses, 1);
+    }
+}
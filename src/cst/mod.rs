@@ -0,0 +1,379 @@
+//! Lossless concrete syntax trees.
+//!
+//! A [`SyntaxNode`] holds every token of the source, each with its
+//! whitespace and comments as trivia, grouped under nodes that mirror the
+//! AST. Writing the tokens back out in order reproduces the source byte for
+//! byte, which is what refactoring tools and a comment-preserving formatter
+//! need. Each node keeps the [`NodeId`] of the AST node it stands for, so
+//! the typed AST can be used as a view of the same tree.
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    AstArena, Block, EnumDecl, Expr, Field, Function, MatchArm, NodeId, Param, Pattern,
+};
+use crate::ast::{Program, Stmt, StructDecl, Type, TypeParam, VarDecl, Variant};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Scanner, Token};
+use crate::parser::Parser;
+use crate::source::Span;
+use std::fmt;
+use std::iter::Peekable;
+use std::vec;
+
+/// What kind of AST node a [`SyntaxNode`] stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    Program,
+    Function,
+    TypeParam,
+    Param,
+    Struct,
+    Field,
+    Enum,
+    Variant,
+    VarDecl,
+    Type,
+    Block,
+    Stmt,
+    Expr,
+    MatchArm,
+    Pattern,
+}
+
+impl fmt::Display for SyntaxKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode<'a> {
+    pub kind: SyntaxKind,
+    /// The AST node this one stands for; `None` for the root.
+    pub id: Option<NodeId>,
+    /// Span of the AST node, without surrounding trivia. The root covers the
+    /// whole source.
+    pub span: Span,
+    /// Child nodes and the node's own tokens, in source order.
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(Token<'a>),
+}
+
+impl<'a> SyntaxNode<'a> {
+    /// Every token under this node, in source order.
+    pub fn tokens(&self) -> Vec<&Token<'a>> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'n>(&'n self, tokens: &mut Vec<&'n Token<'a>>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// The source text of this node, trivia included. For the root this is
+    /// the whole source.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for token in self.tokens() {
+            for trivia in &token.leading_trivia {
+                text.push_str(&trivia.text);
+            }
+            text.push_str(&token.lexeme);
+            for trivia in &token.trailing_trivia {
+                text.push_str(&trivia.text);
+            }
+        }
+        text
+    }
+
+    /// The node standing for the AST node `id`, searching this subtree.
+    pub fn find(&self, id: NodeId) -> Option<&SyntaxNode<'a>> {
+        if self.id == Some(id) {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.find(id),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} {}",
+            "",
+            self.kind,
+            self.span,
+            indent = depth * 2
+        )?;
+        let indent = (depth + 1) * 2;
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.fmt_indented(f, depth + 1)?,
+                SyntaxElement::Token(token) => {
+                    for trivia in &token.leading_trivia {
+                        writeln!(f, "{:indent$}{:?} {:?}", "", trivia.kind, trivia.text)?;
+                    }
+                    writeln!(f, "{:indent$}{}", "", token)?;
+                    for trivia in &token.trailing_trivia {
+                        writeln!(f, "{:indent$}{:?} {:?}", "", trivia.kind, trivia.text)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One line per node, token and piece of trivia, indented by depth.
+impl fmt::Display for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Parses `source` into both its AST and its concrete syntax tree.
+pub fn parse(source: &str) -> (SyntaxNode<'_>, Program, Vec<Diagnostic>) {
+    let (program, diagnostics) = Parser::new(Scanner::new(source)).parse_program();
+    let tokens = Scanner::new(source).with_trivia(true).collect();
+    (build(&program, tokens), program, diagnostics)
+}
+
+/// Arranges `tokens` under nodes shaped like `program`.
+///
+/// `tokens` should be the complete output of a trivia-mode scanner over the
+/// source `program` was parsed from, through `EndOfFile`. Tokens that belong
+/// to no AST node, such as those skipped after a syntax error, stay with the
+/// innermost node around them.
+pub fn build<'a>(program: &Program, tokens: Vec<Token<'a>>) -> SyntaxNode<'a> {
+    let mut builder = Builder {
+        arena: &program.arena,
+        tokens: tokens.into_iter().peekable(),
+        stack: vec![SyntaxNode {
+            kind: SyntaxKind::Program,
+            id: None,
+            span: Span::default(),
+            children: Vec::new(),
+        }],
+    };
+    builder.visit_program(program);
+    builder.take_before(usize::MAX);
+    let mut root = builder.stack.pop().expect("the root is never popped");
+    let tokens = root.tokens();
+    if let (Some(first), Some(last)) = (tokens.first(), tokens.last()) {
+        let start = first.leading_trivia.first().map_or(first.span, |t| t.span);
+        let end = last.trailing_trivia.last().map_or(last.span, |t| t.span);
+        root.span = start.to(end);
+    }
+    root
+}
+
+struct Builder<'ast, 'a> {
+    arena: &'ast AstArena,
+    tokens: Peekable<vec::IntoIter<Token<'a>>>,
+    /// The nodes being built, innermost last.
+    stack: Vec<SyntaxNode<'a>>,
+}
+
+impl<'a> Builder<'_, 'a> {
+    /// Moves the tokens starting before `offset` into the innermost node.
+    fn take_before(&mut self, offset: usize) {
+        let node = self.stack.last_mut().expect("the root is never popped");
+        while let Some(token) = self.tokens.next_if(|t| t.span.start < offset) {
+            node.children.push(SyntaxElement::Token(token));
+        }
+    }
+
+    /// Builds a node for the AST node `id`, with `walk` adding its children.
+    fn node(&mut self, kind: SyntaxKind, id: NodeId, span: Span, walk: impl FnOnce(&mut Self)) {
+        self.take_before(span.start);
+        self.stack.push(SyntaxNode {
+            kind,
+            id: Some(id),
+            span,
+            children: Vec::new(),
+        });
+        walk(self);
+        self.take_before(span.end);
+        let node = self.stack.pop().expect("pushed above");
+        let parent = self.stack.last_mut().expect("the root is never popped");
+        parent.children.push(SyntaxElement::Node(node));
+    }
+}
+
+impl<'ast> Visitor<'ast> for Builder<'ast, '_> {
+    fn arena(&self) -> &'ast AstArena {
+        self.arena
+    }
+
+    fn visit_function(&mut self, function: &'ast Function) {
+        self.node(SyntaxKind::Function, function.id, function.span, |b| {
+            visit::walk_function(b, function)
+        });
+    }
+
+    fn visit_type_param(&mut self, param: &'ast TypeParam) {
+        self.node(SyntaxKind::TypeParam, param.id, param.span, |b| {
+            visit::walk_type_param(b, param)
+        });
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        self.node(SyntaxKind::Param, param.id, param.span, |b| {
+            visit::walk_param(b, param)
+        });
+    }
+
+    fn visit_struct(&mut self, decl: &'ast StructDecl) {
+        self.node(SyntaxKind::Struct, decl.id, decl.span, |b| {
+            visit::walk_struct(b, decl)
+        });
+    }
+
+    fn visit_field(&mut self, field: &'ast Field) {
+        self.node(SyntaxKind::Field, field.id, field.span, |b| {
+            visit::walk_field(b, field)
+        });
+    }
+
+    fn visit_enum(&mut self, decl: &'ast EnumDecl) {
+        self.node(SyntaxKind::Enum, decl.id, decl.span, |b| {
+            visit::walk_enum(b, decl)
+        });
+    }
+
+    fn visit_variant(&mut self, variant: &'ast Variant) {
+        self.node(SyntaxKind::Variant, variant.id, variant.span, |b| {
+            visit::walk_variant(b, variant)
+        });
+    }
+
+    fn visit_var_decl(&mut self, decl: &'ast VarDecl) {
+        self.node(SyntaxKind::VarDecl, decl.id, decl.span, |b| {
+            visit::walk_var_decl(b, decl)
+        });
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        self.node(SyntaxKind::Block, block.id, block.span, |b| {
+            visit::walk_block(b, block)
+        });
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        self.node(SyntaxKind::Stmt, stmt.id, stmt.span, |b| {
+            visit::walk_stmt(b, stmt)
+        });
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        self.node(SyntaxKind::Expr, expr.id, expr.span, |b| {
+            visit::walk_expr(b, expr)
+        });
+    }
+
+    fn visit_arm(&mut self, arm: &'ast MatchArm) {
+        self.node(SyntaxKind::MatchArm, arm.id, arm.span, |b| {
+            visit::walk_arm(b, arm)
+        });
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        self.node(SyntaxKind::Pattern, pattern.id, pattern.span, |b| {
+            visit::walk_pattern(b, pattern)
+        });
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type) {
+        self.node(SyntaxKind::Type, ty.id, ty.span, |b| {
+            visit::walk_type(b, ty)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Item, StmtKind};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "",
+            "// only a comment\n",
+            "/* header */\nfn main() -> int {\n    let x: int[2] = [1, 2]; // two\n    return x[0] /* first */ + 1;\n}\n",
+            "struct P { x: int, }\nint g = 1;\n  \n",
+            "fn f<T>(b: Box<Box<T>>) {}\n",
+            // Tokens skipped by error recovery are kept too.
+            "fn f() { let = ; x = 1 @ 2; }\nfn ) g\n",
+        ];
+        for source in sources {
+            let (tree, _, _) = parse(source);
+            assert_eq!(tree.text(), source);
+            assert_eq!(tree.span, Span::new(0, source.len()));
+        }
+    }
+
+    #[test]
+    fn test_shape() {
+        let source = "fn f() {\n    // note\n    x = 1;\n}";
+        let (tree, program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        assert_eq!(
+            tree.to_string(),
+            r#"Program 0..33
+  Function 0..33
+    Fn "fn"
+    Whitespace " "
+    Identifier "f"
+    LParen "("
+    RParen ")"
+    Whitespace " "
+    Block 7..33
+      LBrace "{"
+      Stmt 25..31
+        Expr 25..30
+          Expr 25..26
+            Whitespace "\n    "
+            LineComment "// note"
+            Whitespace "\n    "
+            Identifier "x"
+            Whitespace " "
+          Equal "="
+          Whitespace " "
+          Expr 29..30
+            IntLiteral "1" 1
+        Semicolon ";"
+      Whitespace "\n"
+      RBrace "}"
+  EndOfFile ""
+"#
+        );
+        // The comment is reachable from the statement's node.
+        let Item::Function(function) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let stmt = &program.arena[function.body.stmts[0]];
+        assert!(matches!(stmt.kind, StmtKind::Expr(_)));
+        let node = tree.find(stmt.id).unwrap();
+        assert_eq!(node.kind, SyntaxKind::Stmt);
+        let comments: Vec<_> = node.tokens()[0]
+            .leading_trivia
+            .iter()
+            .map(|t| &*t.text)
+            .collect();
+        assert_eq!(comments, ["\n    ", "// note", "\n    "]);
+    }
+}
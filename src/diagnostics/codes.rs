@@ -0,0 +1,149 @@
+//! The code of every kind of diagnostic.
+//!
+//! A code is a letter for the phase that reports it and four digits: `L00xx`
+//! for the lexer, `P01xx` for the parser, `T02xx` for the type checker,
+//! `R03xx` for name resolution, `C04xx` for constant evaluation, `D05xx`
+//! for preprocessor directives and `W06xx` for lints. Codes are never
+//! reused, so they stay valid as messages change. Each also has a name, such
+//! as `unused-variable`, that command-line flags accept in its place.
+
+use super::Code;
+
+pub const INVALID_CHARACTER: Code = Code::new("L0001", "invalid-character");
+pub const INVALID_CHARACTERS: Code = Code::new("L0002", "invalid-characters");
+pub const INVALID_UTF8: Code = Code::new("L0003", "invalid-utf8");
+pub const UNTERMINATED_STRING: Code = Code::new("L0004", "unterminated-string");
+pub const INVALID_ESCAPE: Code = Code::new("L0005", "invalid-escape");
+pub const UNTERMINATED_COMMENT: Code = Code::new("L0006", "unterminated-comment");
+pub const MALFORMED_NUMBER: Code = Code::new("L0007", "malformed-number");
+pub const DANGLING_EXPONENT: Code = Code::new("L0008", "dangling-exponent");
+pub const MALFORMED_HEX_LITERAL: Code = Code::new("L0009", "malformed-hex-literal");
+pub const MALFORMED_OCTAL_LITERAL: Code = Code::new("L0010", "malformed-octal-literal");
+pub const MALFORMED_BINARY_LITERAL: Code = Code::new("L0011", "malformed-binary-literal");
+pub const INTEGER_OUT_OF_RANGE: Code = Code::new("L0012", "integer-out-of-range");
+pub const IDENTIFIER_TOO_LONG: Code = Code::new("L0013", "identifier-too-long");
+
+pub const UNEXPECTED_TOKEN: Code = Code::new("P0101", "unexpected-token");
+pub const MISSING_SEMICOLON: Code = Code::new("P0102", "missing-semicolon");
+pub const INVALID_ASSIGNMENT_TARGET: Code = Code::new("P0103", "invalid-assignment-target");
+pub const TOO_DEEPLY_NESTED: Code = Code::new("P0104", "too-deeply-nested");
+pub const DUPLICATE_FIELD: Code = Code::new("P0105", "duplicate-field");
+pub const DUPLICATE_TYPE_PARAM: Code = Code::new("P0106", "duplicate-type-param");
+pub const DUPLICATE_VARIANT: Code = Code::new("P0107", "duplicate-variant");
+pub const POSTFIX_INCREMENT_VALUE: Code = Code::new("P0108", "postfix-increment-value");
+
+pub const MISMATCHED_TYPES: Code = Code::new("T0201", "mismatched-types");
+pub const NO_FIELD: Code = Code::new("T0202", "no-field");
+pub const INVALID_OPERANDS: Code = Code::new("T0203", "invalid-operands");
+pub const NOT_CALLABLE: Code = Code::new("T0204", "not-callable");
+pub const WRONG_ARGUMENT_COUNT: Code = Code::new("T0205", "wrong-argument-count");
+pub const RETURN_FROM_VOID: Code = Code::new("T0206", "return-from-void");
+pub const MISSING_RETURN_VALUE: Code = Code::new("T0207", "missing-return-value");
+pub const NOT_INDEXABLE: Code = Code::new("T0208", "not-indexable");
+pub const NOT_A_STRUCT: Code = Code::new("T0209", "not-a-struct");
+pub const MISSING_FIELDS: Code = Code::new("T0210", "missing-fields");
+pub const NO_VARIANT: Code = Code::new("T0211", "no-variant");
+pub const PATTERN_FIELD_COUNT: Code = Code::new("T0212", "pattern-field-count");
+pub const LOOP_CONTROL_OUTSIDE_LOOP: Code = Code::new("T0213", "loop-control-outside-loop");
+pub const TYPE_ANNOTATIONS_NEEDED: Code = Code::new("T0214", "type-annotations-needed");
+pub const IMPLICIT_CONVERSION: Code = Code::new("T0215", "implicit-conversion");
+
+pub const ALREADY_DEFINED: Code = Code::new("R0301", "already-defined");
+pub const UNDECLARED_NAME: Code = Code::new("R0302", "undeclared-name");
+
+pub const NOT_CONSTANT: Code = Code::new("C0401", "not-constant");
+pub const CYCLIC_CONSTANT: Code = Code::new("C0402", "cyclic-constant");
+pub const CONSTANT_OVERFLOW: Code = Code::new("C0403", "constant-overflow");
+pub const DIVISION_BY_ZERO: Code = Code::new("C0404", "division-by-zero");
+pub const INDEX_OUT_OF_BOUNDS: Code = Code::new("C0405", "index-out-of-bounds");
+pub const ARRAY_LENGTH_NOT_CONSTANT: Code = Code::new("C0406", "array-length-not-constant");
+pub const INVALID_ARRAY_LENGTH: Code = Code::new("C0407", "invalid-array-length");
+
+pub const MALFORMED_INCLUDE: Code = Code::new("D0501", "malformed-include");
+pub const UNREADABLE_INCLUDE: Code = Code::new("D0502", "unreadable-include");
+pub const RECURSIVE_INCLUDE: Code = Code::new("D0503", "recursive-include");
+pub const MALFORMED_DEFINE: Code = Code::new("D0504", "malformed-define");
+
+pub const UNUSED_VARIABLE: Code = Code::new("W0601", "unused-variable");
+pub const UNREACHABLE_CODE: Code = Code::new("W0602", "unreachable-code");
+
+/// Every code above.
+pub const ALL: &[Code] = &[
+    INVALID_CHARACTER,
+    INVALID_CHARACTERS,
+    INVALID_UTF8,
+    UNTERMINATED_STRING,
+    INVALID_ESCAPE,
+    UNTERMINATED_COMMENT,
+    MALFORMED_NUMBER,
+    DANGLING_EXPONENT,
+    MALFORMED_HEX_LITERAL,
+    MALFORMED_OCTAL_LITERAL,
+    MALFORMED_BINARY_LITERAL,
+    INTEGER_OUT_OF_RANGE,
+    IDENTIFIER_TOO_LONG,
+    UNEXPECTED_TOKEN,
+    MISSING_SEMICOLON,
+    INVALID_ASSIGNMENT_TARGET,
+    TOO_DEEPLY_NESTED,
+    DUPLICATE_FIELD,
+    DUPLICATE_TYPE_PARAM,
+    DUPLICATE_VARIANT,
+    POSTFIX_INCREMENT_VALUE,
+    MISMATCHED_TYPES,
+    NO_FIELD,
+    INVALID_OPERANDS,
+    NOT_CALLABLE,
+    WRONG_ARGUMENT_COUNT,
+    RETURN_FROM_VOID,
+    MISSING_RETURN_VALUE,
+    NOT_INDEXABLE,
+    NOT_A_STRUCT,
+    MISSING_FIELDS,
+    NO_VARIANT,
+    PATTERN_FIELD_COUNT,
+    LOOP_CONTROL_OUTSIDE_LOOP,
+    TYPE_ANNOTATIONS_NEEDED,
+    IMPLICIT_CONVERSION,
+    ALREADY_DEFINED,
+    UNDECLARED_NAME,
+    NOT_CONSTANT,
+    CYCLIC_CONSTANT,
+    CONSTANT_OVERFLOW,
+    DIVISION_BY_ZERO,
+    INDEX_OUT_OF_BOUNDS,
+    ARRAY_LENGTH_NOT_CONSTANT,
+    INVALID_ARRAY_LENGTH,
+    MALFORMED_INCLUDE,
+    UNREADABLE_INCLUDE,
+    RECURSIVE_INCLUDE,
+    MALFORMED_DEFINE,
+    UNUSED_VARIABLE,
+    UNREACHABLE_CODE,
+];
+
+/// The codes of warnings about code that is legal but likely a mistake,
+/// whose level a [`LintStore`](super::LintStore) can change.
+pub const LINTS: &[Code] = &[IMPLICIT_CONVERSION, UNUSED_VARIABLE, UNREACHABLE_CODE];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_codes() {
+        let mut unique = ALL.to_vec();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ALL.len(), "codes must be unique");
+        for code in ALL {
+            assert_eq!(code.as_str().len(), 5, "{}", code);
+            assert_eq!(code.as_str().parse::<Code>(), Ok(*code));
+            assert_eq!(code.name().parse::<Code>(), Ok(*code));
+        }
+        assert_eq!("w0601".parse::<Code>(), Ok(UNUSED_VARIABLE));
+        assert!("T9999".parse::<Code>().is_err());
+        assert!(LINTS.iter().all(|lint| ALL.contains(lint)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Diagnostics as JSON, one object per line, for editors and other tools.
+//!
+//! ```text
+//! {"code": "P0102", "severity": "error", "message": "missing ';'", "file": "main.mc",
+//!  "spans": [{"start": 14, "end": 14, "line": 2, "column": 6, "end_line": 2,
+//!  "end_column": 6, "primary": true, "label": null}], "notes": [],
+//!  "fixits": [{"start": 14, ..., "replacement": ";", "message": "insert ';' here"}]}
+//! ```
+//!
+//! Spans give both byte offsets and 1-based lines and columns, counted as
+//! the [`SourceFile`] counts them. The primary span comes first.
+
+use super::Diagnostic;
+use crate::source::{SourceFile, Span};
+use crate::utils::json_string;
+
+/// Output format for diagnostics: [`Renderer`](super::Renderer) output or
+/// [`to_json`] lines. The variant docs are `--error-format` help text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// Rendered for a terminal, with the source lines and carets.
+    #[default]
+    Human,
+    /// One JSON object per line.
+    Json,
+}
+
+/// `diagnostic`, about `file` read from `path`, as a single line of JSON.
+pub fn to_json(diagnostic: &Diagnostic, file: &SourceFile, path: Option<&str>) -> String {
+    let mut out = String::from("{\"code\": ");
+    json_string(diagnostic.code.as_str(), &mut out);
+    out.push_str(", \"severity\": ");
+    json_string(&diagnostic.severity.to_string(), &mut out);
+    out.push_str(", \"message\": ");
+    json_string(&diagnostic.message, &mut out);
+    out.push_str(", \"file\": ");
+    match path {
+        Some(path) => json_string(path, &mut out),
+        None => out.push_str("null"),
+    }
+
+    out.push_str(", \"spans\": [");
+    span(file, diagnostic.primary_span, &mut out);
+    out.push_str(", \"primary\": true, \"label\": null}");
+    for label in &diagnostic.labels {
+        out.push_str(", ");
+        span(file, label.span, &mut out);
+        out.push_str(", \"primary\": false, \"label\": ");
+        json_string(&label.message, &mut out);
+        out.push('}');
+    }
+
+    out.push_str("], \"notes\": [");
+    for (i, note) in diagnostic.notes.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        json_string(note, &mut out);
+    }
+
+    out.push_str("], \"fixits\": [");
+    for (i, fixit) in diagnostic.fixits.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        span(file, fixit.span, &mut out);
+        out.push_str(", \"replacement\": ");
+        json_string(&fixit.replacement, &mut out);
+        out.push_str(", \"message\": ");
+        json_string(&fixit.message, &mut out);
+        out.push('}');
+    }
+    out.push_str("]}");
+    out
+}
+
+/// The opening of an object with the position keys of `span`, left open
+/// for the caller's keys.
+fn span(file: &SourceFile, span: Span, out: &mut String) {
+    let start = file.line_col(span.start);
+    let end = file.line_col(span.end);
+    out.push_str(&format!(
+        "{{\"start\": {}, \"end\": {}, \"line\": {}, \"column\": {}, \"end_line\": {}, \"end_column\": {}",
+        span.start, span.end, start.line, start.column, end.line, end.column
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::codes;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_to_json() {
+        let file = SourceFile::new("fn f() {\n    g(\"a\tb\")\n}");
+        let diagnostic = Diagnostic::error(
+            codes::UNDECLARED_NAME,
+            "use of undeclared function 'g'",
+            Span::new(13, 14),
+        )
+        .with_label(Span::new(3, 4), "in \"f\"")
+        .with_note("a note")
+        .with_fixit(Span::new(13, 14), "f", "replace with 'f'");
+        assert_eq!(
+            to_json(&diagnostic, &file, Some("main.mc")),
+            concat!(
+                r#"{"code": "R0302", "severity": "error", "message": "use of undeclared function 'g'", "file": "main.mc", "#,
+                r#""spans": [{"start": 13, "end": 14, "line": 2, "column": 5, "end_line": 2, "end_column": 6, "primary": true, "label": null}, "#,
+                r#"{"start": 3, "end": 4, "line": 1, "column": 4, "end_line": 1, "end_column": 5, "primary": false, "label": "in \"f\""}], "#,
+                r#""notes": ["a note"], "#,
+                r#""fixits": [{"start": 13, "end": 14, "line": 2, "column": 5, "end_line": 2, "end_column": 6, "replacement": "f", "message": "replace with 'f'"}]}"#,
+            )
+        );
+
+        let diagnostic = Diagnostic::warning(codes::IMPLICIT_CONVERSION, "a\nb", Span::new(0, 0));
+        assert_eq!(
+            to_json(&diagnostic, &file, None),
+            concat!(
+                r#"{"code": "T0215", "severity": "warning", "message": "a\nb", "file": null, "#,
+                r#""spans": [{"start": 0, "end": 0, "line": 1, "column": 1, "end_line": 1, "end_column": 1, "primary": true, "label": null}], "#,
+                r#""notes": [], "fixits": []}"#,
+            )
+        );
+    }
+}
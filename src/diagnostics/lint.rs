@@ -0,0 +1,228 @@
+//! Levels for lints, the warnings in [`codes::LINTS`].
+//!
+//! Every lint warns by default. A [`LintStore`] records the levels the user
+//! chose, from flags such as `--deny implicit-conversion` or from a config
+//! file of `name = level` lines:
+//!
+//! ```text
+//! # Conversions are mistakes here, unused variables are fine.
+//! implicit-conversion = deny
+//! unused-variable = allow
+//! ```
+//!
+//! and [`LintStore::apply`] adjusts diagnostics to them before they are
+//! reported. Only lints can be configured: errors always stay errors.
+
+use super::{codes, Code, Diagnostic, Severity, UnknownCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// What to do with a lint's diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    /// Drop them.
+    Allow,
+    /// Report them as warnings.
+    Warn,
+    /// Report them as errors.
+    Deny,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Deny => "deny",
+        })
+    }
+}
+
+impl FromStr for Level {
+    type Err = LintError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(LintError::UnknownLevel(s.to_string())),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LintError {
+    #[error(transparent)]
+    UnknownCode(#[from] UnknownCode),
+
+    #[error("'{0}' is not a lint, so its level cannot be changed")]
+    NotALint(String),
+
+    #[error("unknown lint level '{0}' (expected allow, warn or deny)")]
+    UnknownLevel(String),
+
+    #[error("expected 'name = level', found '{0}'")]
+    MalformedLine(String),
+
+    /// An error on a line of a config file.
+    #[error("line {line}: {error}")]
+    Config { line: usize, error: Box<LintError> },
+}
+
+/// The level of every lint.
+#[derive(Debug, Clone, Default)]
+pub struct LintStore {
+    levels: HashMap<Code, Level>,
+}
+
+impl LintStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the level of `lint`, given by code or name.
+    pub fn set(&mut self, lint: &str, level: Level) -> Result<(), LintError> {
+        let code: Code = lint.parse()?;
+        if !codes::LINTS.contains(&code) {
+            return Err(LintError::NotALint(lint.to_string()));
+        }
+        self.levels.insert(code, level);
+        Ok(())
+    }
+
+    /// Sets the levels listed in `config`, one `name = level` per line.
+    /// Blank lines and everything after a `#` are ignored.
+    pub fn load(&mut self, config: &str) -> Result<(), LintError> {
+        for (i, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let result = match line.split_once('=') {
+                Some((lint, level)) => level
+                    .trim()
+                    .parse()
+                    .and_then(|level| self.set(lint.trim(), level)),
+                None => Err(LintError::MalformedLine(line.to_string())),
+            };
+            result.map_err(|error| LintError::Config {
+                line: i + 1,
+                error: Box::new(error),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The level set for `code`, if one was.
+    pub fn configured(&self, code: Code) -> Option<Level> {
+        self.levels.get(&code).copied()
+    }
+
+    /// The level of the lint `code`, or `None` if `code` is not a lint.
+    pub fn level(&self, code: Code) -> Option<Level> {
+        if !codes::LINTS.contains(&code) {
+            return None;
+        }
+        Some(self.levels.get(&code).copied().unwrap_or(Level::Warn))
+    }
+
+    /// `diagnostics` with every lint at its level: dropped, a warning or an
+    /// error.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                match self.level(diagnostic.code) {
+                    Some(Level::Allow) => return None,
+                    Some(Level::Warn) => diagnostic.severity = Severity::Warning,
+                    Some(Level::Deny) => diagnostic.severity = Severity::Error,
+                    None => {}
+                }
+                Some(diagnostic)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Span;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_apply() {
+        let span = Span::new(0, 1);
+        let diagnostics = vec![
+            Diagnostic::warning(codes::IMPLICIT_CONVERSION, "conversion", span),
+            Diagnostic::warning(codes::UNUSED_VARIABLE, "unused", span),
+            Diagnostic::warning(codes::UNREACHABLE_CODE, "unreachable", span),
+            Diagnostic::error(codes::MISMATCHED_TYPES, "mismatch", span),
+        ];
+        let levels = |store: &LintStore| {
+            store
+                .apply(diagnostics.clone())
+                .iter()
+                .map(|d| format!("{} {}", d.severity, d.message))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            levels(&LintStore::new()),
+            [
+                "warning conversion",
+                "warning unused",
+                "warning unreachable",
+                "error mismatch"
+            ]
+        );
+
+        let mut store = LintStore::new();
+        store.set("implicit-conversion", Level::Deny).unwrap();
+        store.set("W0601", Level::Allow).unwrap();
+        assert_eq!(
+            levels(&store),
+            ["error conversion", "warning unreachable", "error mismatch"]
+        );
+        assert_eq!(store.level(codes::UNUSED_VARIABLE), Some(Level::Allow));
+        assert_eq!(store.level(codes::MISMATCHED_TYPES), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut store = LintStore::new();
+        assert_eq!(
+            store.set("mismatched-types", Level::Allow),
+            Err(LintError::NotALint("mismatched-types".to_string()))
+        );
+        assert_eq!(
+            store
+                .set("unused-variables", Level::Allow)
+                .unwrap_err()
+                .to_string(),
+            "unknown diagnostic code 'unused-variables'"
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let mut store = LintStore::new();
+        store
+            .load("# lints\n\nunused-variable = allow  # noisy\nT0215=deny\n")
+            .unwrap();
+        assert_eq!(store.level(codes::UNUSED_VARIABLE), Some(Level::Allow));
+        assert_eq!(store.level(codes::IMPLICIT_CONVERSION), Some(Level::Deny));
+
+        let error = |config: &str| LintStore::new().load(config).unwrap_err().to_string();
+        assert_eq!(
+            error("unreachable-code = warn\nunused-variable\n"),
+            "line 2: expected 'name = level', found 'unused-variable'"
+        );
+        assert_eq!(
+            error("unused-variable = forbid"),
+            "line 1: unknown lint level 'forbid' (expected allow, warn or deny)"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Problems found in the source, in a form every phase can report and every
+//! front end can render.
+
+pub mod codes;
+pub mod json;
+pub mod lint;
+pub mod render;
+
+use crate::source::Span;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+pub use json::{to_json, ErrorFormat};
+pub use lint::{Level, LintError, LintStore};
+pub use render::Renderer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Which kind of problem a diagnostic reports, such as `T0201` for
+/// mismatched types. [`codes`] lists them all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Code {
+    code: &'static str,
+    name: &'static str,
+}
+
+impl Code {
+    pub const fn new(code: &'static str, name: &'static str) -> Self {
+        Self { code, name }
+    }
+
+    /// The code itself, such as `T0201`.
+    pub fn as_str(&self) -> &'static str {
+        self.code
+    }
+
+    /// The code's name, such as `mismatched-types`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("unknown diagnostic code '{0}'")]
+pub struct UnknownCode(pub String);
+
+/// Accepts a code, in either case, or its name.
+impl FromStr for Code {
+    type Err = UnknownCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        codes::ALL
+            .iter()
+            .find(|code| code.code.eq_ignore_ascii_case(s) || code.name == s)
+            .copied()
+            .ok_or_else(|| UnknownCode(s.to_string()))
+    }
+}
+
+/// A message about a span of source text, such as a syntax error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub message: String,
+    /// Where the problem is.
+    pub primary_span: Span,
+    /// Other places that explain it, such as an earlier definition.
+    pub labels: Vec<Label>,
+    /// Extra explanation that belongs to no span, such as a hint.
+    pub notes: Vec<String>,
+    /// Edits that would fix it, for tools to offer or apply.
+    pub fixits: Vec<FixIt>,
+}
+
+/// A secondary span with a short explanation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A suggested edit: replace `span` with `replacement`. An empty span
+/// inserts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixIt {
+    pub span: Span,
+    pub replacement: String,
+    /// What the edit does, such as "insert ';' here".
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            code,
+            severity: Severity::Error,
+            message: message.into(),
+            primary_span: span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            fixits: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: Code, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message, span)
+        }
+    }
+
+    /// The same diagnostic with every span moved by `delta` bytes, for text
+    /// that moved after an edit.
+    pub fn shift(self, delta: isize) -> Self {
+        self.map_spans(|span| span.shift(delta))
+    }
+
+    /// The same diagnostic with `map` applied to every span.
+    pub fn map_spans(mut self, map: impl Fn(Span) -> Span) -> Self {
+        self.primary_span = map(self.primary_span);
+        for label in &mut self.labels {
+            label.span = map(label.span);
+        }
+        for fixit in &mut self.fixits {
+            fixit.span = map(fixit.span);
+        }
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_fixit(
+        mut self,
+        span: Span,
+        replacement: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.fixits.push(FixIt {
+            span,
+            replacement: replacement.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
@@ -0,0 +1,238 @@
+//! Renders diagnostics for a terminal, in the style of rustc: a header
+//! with the severity, code and message, then every source line a span
+//! falls on with `^` under the problem and `-` under each label, then the
+//! notes and suggested fixes.
+//!
+//! ```text
+//! error[T0201]: mismatched types: expected int, found bool
+//!  --> main.mc:2:13
+//!   |
+//! 2 |     int x = true;
+//!   |             ^^^^
+//!   |     --- expected because of this type
+//! ```
+
+use super::{Diagnostic, Severity};
+use crate::source::{SourceFile, Span};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics about one source file.
+pub struct Renderer<'a> {
+    file: &'a SourceFile<'a>,
+    path: Option<String>,
+    color: bool,
+}
+
+/// A span to mark on its line: the problem itself, or a label.
+struct Mark<'d> {
+    span: Span,
+    message: Option<&'d str>,
+    primary: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file: &'a SourceFile<'a>) -> Self {
+        Self {
+            file,
+            path: None,
+            color: false,
+        }
+    }
+
+    /// Names the file in each diagnostic's location line.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Colors the output with ANSI escape sequences.
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let severity = match diagnostic.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+        let mut marks = vec![Mark {
+            span: diagnostic.primary_span,
+            message: None,
+            primary: true,
+        }];
+        marks.extend(diagnostic.labels.iter().map(|label| Mark {
+            span: label.span,
+            message: Some(&label.message),
+            primary: false,
+        }));
+        let mut lines: BTreeMap<usize, Vec<Mark>> = BTreeMap::new();
+        for mark in marks {
+            let line = self.file.line_col(mark.span.start).line;
+            lines.entry(line).or_default().push(mark);
+        }
+        let last_line = lines.keys().next_back().copied().unwrap_or(1);
+        let pad = " ".repeat(last_line.to_string().len());
+        let gutter = self.paint(BLUE, "|");
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            self.paint(
+                severity,
+                &format!("{}[{}]", diagnostic.severity, diagnostic.code)
+            ),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        );
+        let position = self.file.line_col(diagnostic.primary_span.start);
+        let path = match &self.path {
+            Some(path) => format!("{}:", path),
+            None => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "{}{} {}{}",
+            pad,
+            self.paint(BLUE, "-->"),
+            path,
+            position
+        );
+        let _ = writeln!(out, "{} {}", pad, gutter);
+        let mut previous = None;
+        for (&line, marks) in &lines {
+            if previous.is_some_and(|previous| line > previous + 1) {
+                let _ = writeln!(out, "{}", self.paint(BLUE, "..."));
+            }
+            previous = Some(line);
+            let text = self.file.line_text(line);
+            let number = self.paint(BLUE, &format!("{:>width$}", line, width = pad.len()));
+            let _ = writeln!(out, "{} {} {}", number, gutter, text);
+            for mark in marks {
+                let (indent, width) = self.columns(line, mark.span);
+                let (style, marker) = match mark.primary {
+                    true => (severity, "^"),
+                    false => (BLUE, "-"),
+                };
+                let mut underline = marker.repeat(width);
+                if let Some(message) = mark.message {
+                    underline = format!("{} {}", underline, message);
+                }
+                let _ = writeln!(
+                    out,
+                    "{} {} {}{}",
+                    pad,
+                    gutter,
+                    indent,
+                    self.paint(style, &underline)
+                );
+            }
+        }
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{} {} note: {}", pad, self.paint(BLUE, "="), note);
+        }
+        for fixit in &diagnostic.fixits {
+            let _ = writeln!(
+                out,
+                "{} {} help: {}",
+                pad,
+                self.paint(BLUE, "="),
+                fixit.message
+            );
+        }
+        out
+    }
+
+    /// The whitespace that lines a marker up under `span` on `line`, which
+    /// keeps the line's tabs so it lines up whatever the tab width, and the
+    /// marker's width, at least 1 and no further than the end of the line.
+    fn columns(&self, line: usize, span: Span) -> (String, usize) {
+        let text = self.file.line_text(line);
+        let line_start = self.file.line_start(line);
+        let start = (span.start - line_start).min(text.len());
+        let end = span.end.saturating_sub(line_start).clamp(start, text.len());
+        let indent = text[..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        (indent, text[start..end].chars().count().max(1))
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        match self.color {
+            true => format!("{}{}{}", style, text, RESET),
+            false => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::codes;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() {
+        let source = "fn f() {\n    int x = true;\n\n\n\tx = 1\n}";
+        let file = SourceFile::new(source);
+        let renderer = Renderer::new(&file).with_path("main.mc");
+
+        let diagnostic = Diagnostic::error(
+            codes::MISMATCHED_TYPES,
+            "mismatched types: expected int, found bool",
+            Span::new(21, 25),
+        )
+        .with_label(Span::new(13, 16), "expected because of this type")
+        .with_label(Span::new(3, 4), "in this function");
+        assert_eq!(
+            renderer.render(&diagnostic),
+            "error[T0201]: mismatched types: expected int, found bool
+ --> main.mc:2:13
+  |
+1 | fn f() {
+  |    - in this function
+2 |     int x = true;
+  |             ^^^^
+  |     --- expected because of this type
+"
+        );
+
+        // An empty span gets one caret; tabs are kept so it lines up.
+        let diagnostic =
+            Diagnostic::warning(codes::MISSING_SEMICOLON, "missing ';'", Span::new(35, 35))
+                .with_label(Span::new(3, 4), "in this function")
+                .with_note("statements end with ';'")
+                .with_fixit(Span::new(35, 35), ";", "insert ';' here");
+        assert_eq!(
+            Renderer::new(&file).render(&diagnostic),
+            "warning[P0102]: missing ';'
+ --> 5:7
+  |
+1 | fn f() {
+  |    - in this function
+...
+5 | \tx = 1
+  | \t     ^
+  = note: statements end with ';'
+  = help: insert ';' here
+"
+        );
+    }
+
+    #[test]
+    fn test_color() {
+        let file = SourceFile::new("x");
+        let diagnostic = Diagnostic::error(codes::UNEXPECTED_TOKEN, "oops", Span::new(0, 1));
+        let rendered = Renderer::new(&file).with_color(true).render(&diagnostic);
+        assert!(rendered.starts_with("\x1b[1;31merror[P0101]\x1b[0m\x1b[1m: oops\x1b[0m\n"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+}
@@ -0,0 +1,197 @@
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::str;
+
+/// Files at least this large are memory-mapped in [`MmapMode::Auto`].
+pub const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// How [`SourceBuffer::read`] loads a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MmapMode {
+    /// Map files of at least 16 MiB, read smaller ones.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How [`SourceBuffer::read`] treats bytes that are not valid UTF-8.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Utf8Mode {
+    /// Reject the file with an "invalid UTF-8 at byte N" error.
+    #[default]
+    Strict,
+    /// Replace each invalid sequence with U+FFFD and remember where it was,
+    /// so the lexer can report it and carry on.
+    Lossy,
+}
+
+/// A byte sequence that was not valid UTF-8 and was replaced by U+FFFD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUtf8 {
+    /// Byte offset of the replacement character in the decoded text.
+    pub offset: usize,
+    /// Byte offset of the invalid sequence in the file.
+    pub byte: usize,
+}
+
+/// Source text of one input file, either read into memory or mapped.
+///
+/// A mapped file is validated as UTF-8 in place, so lexing a very large
+/// generated file does not need a second, heap-allocated copy of it.
+pub enum SourceBuffer {
+    Owned(String),
+    Mapped(Mmap),
+    /// Text decoded in [`Utf8Mode::Lossy`] that contained invalid sequences.
+    Lossy(String, Vec<InvalidUtf8>),
+}
+
+impl SourceBuffer {
+    pub fn read(path: impl AsRef<Path>, mode: MmapMode, utf8: Utf8Mode) -> io::Result<Self> {
+        let path = path.as_ref();
+        let map = match mode {
+            MmapMode::Always => true,
+            MmapMode::Never => false,
+            MmapMode::Auto => fs::metadata(path)?.len() >= MMAP_THRESHOLD,
+        };
+
+        if !map {
+            let bytes = fs::read(path)?;
+            return match String::from_utf8(bytes) {
+                Ok(text) => Ok(SourceBuffer::Owned(text)),
+                Err(err) => match utf8 {
+                    Utf8Mode::Strict => Err(invalid_utf8(err.utf8_error())),
+                    Utf8Mode::Lossy => Ok(decode_lossy(err.as_bytes())),
+                },
+            };
+        }
+
+        let file = File::open(path)?;
+        // SAFETY: the map is read-only. As with every memory-mapped file, the
+        // contents are undefined if another process truncates or rewrites the
+        // file while it is mapped; the compiler treats its inputs as immutable
+        // for the duration of a run.
+        let mmap = unsafe { Mmap::map(&file)? };
+        if let Err(err) = str::from_utf8(&mmap) {
+            // A repaired copy cannot share the mapping, so lossy decoding
+            // falls back to an owned buffer.
+            return match utf8 {
+                Utf8Mode::Strict => Err(invalid_utf8(err)),
+                Utf8Mode::Lossy => Ok(decode_lossy(&mmap)),
+            };
+        }
+        Ok(SourceBuffer::Mapped(mmap))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            SourceBuffer::Owned(text) | SourceBuffer::Lossy(text, _) => text,
+            // SAFETY: mapped buffers are validated as UTF-8 in `read`.
+            SourceBuffer::Mapped(mmap) => unsafe { str::from_utf8_unchecked(mmap) },
+        }
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, SourceBuffer::Mapped(_))
+    }
+
+    /// Sequences replaced while decoding in [`Utf8Mode::Lossy`], in order.
+    pub fn invalid_utf8(&self) -> &[InvalidUtf8] {
+        match self {
+            SourceBuffer::Lossy(_, invalid) => invalid,
+            _ => &[],
+        }
+    }
+}
+
+fn invalid_utf8(err: str::Utf8Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid UTF-8 at byte {}", err.valid_up_to()),
+    )
+}
+
+fn decode_lossy(bytes: &[u8]) -> SourceBuffer {
+    let mut text = String::with_capacity(bytes.len());
+    let mut invalid = Vec::new();
+    let mut byte = 0;
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        byte += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            invalid.push(InvalidUtf8 {
+                offset: text.len(),
+                byte,
+            });
+            text.push(char::REPLACEMENT_CHARACTER);
+            byte += chunk.invalid().len();
+        }
+    }
+    SourceBuffer::Lossy(text, invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "minicompiler-input-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_mapped_and_owned_agree() {
+        let path = temp_file("agree", b"int x = 1;");
+        let owned = SourceBuffer::read(&path, MmapMode::Never, Utf8Mode::Strict).unwrap();
+        let mapped = SourceBuffer::read(&path, MmapMode::Always, Utf8Mode::Strict).unwrap();
+        assert!(!owned.is_mapped());
+        assert!(mapped.is_mapped());
+        assert_eq!(owned.as_str(), mapped.as_str());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_mapped_invalid_utf8_reports_offset() {
+        let path = temp_file("invalid", b"int \xff;");
+        let err = SourceBuffer::read(&path, MmapMode::Always, Utf8Mode::Strict)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "invalid UTF-8 at byte 4");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_lossy_records_invalid_sequences() {
+        let path = temp_file("lossy", b"int \xff\xfe x = \xe2\x82;");
+        for mode in [MmapMode::Never, MmapMode::Always] {
+            let buffer = SourceBuffer::read(&path, mode, Utf8Mode::Lossy).unwrap();
+            assert_eq!(buffer.as_str(), "int \u{fffd}\u{fffd} x = \u{fffd};");
+            assert_eq!(
+                buffer.invalid_utf8(),
+                &[
+                    InvalidUtf8 { offset: 4, byte: 4 },
+                    InvalidUtf8 { offset: 7, byte: 5 },
+                    InvalidUtf8 {
+                        offset: 15,
+                        byte: 11
+                    },
+                ]
+            );
+        }
+        let err = SourceBuffer::read(&path, MmapMode::Never, Utf8Mode::Strict)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "invalid UTF-8 at byte 4");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,3 +1,6 @@
+use super::token::TokenType;
+use crate::diagnostics::{codes, Code, Diagnostic};
+use crate::source::Span;
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone, PartialEq)]
@@ -5,15 +8,104 @@ pub enum LexicalError {
     #[error("invalid character: '{0}'")]
     InvalidCharacter(char),
 
+    #[error("invalid characters: '{0}'")]
+    InvalidCharacters(String),
+
+    #[error("invalid UTF-8 at byte {0}")]
+    InvalidUtf8(usize),
+
     #[error("unterminated string literal")]
     UnterminatedString,
 
+    #[error("invalid escape sequence: '\\{0}'")]
+    InvalidEscape(char),
+
     #[error("unterminated block comment")]
     UnterminatedComment,
 
     #[error("malformed number: '{0}'")]
     MalformedNumber(String),
 
+    #[error("exponent has no digits: '{0}'")]
+    DanglingExponent(String),
+
+    #[error("malformed hexadecimal literal: '{0}'")]
+    MalformedHexLiteral(String),
+
+    #[error("malformed octal literal: '{0}'")]
+    MalformedOctalLiteral(String),
+
+    #[error("malformed binary literal: '{0}'")]
+    MalformedBinaryLiteral(String),
+
     #[error("integer literal out of range: {0}")]
     IntegerOutOfRange(String),
-}
\ No newline at end of file
+
+    #[error("identifier is {length} characters long (maximum is {max})")]
+    IdentifierTooLong { length: usize, max: usize },
+}
+
+impl LexicalError {
+    pub fn code(&self) -> Code {
+        match self {
+            LexicalError::InvalidCharacter(_) => codes::INVALID_CHARACTER,
+            LexicalError::InvalidCharacters(_) => codes::INVALID_CHARACTERS,
+            LexicalError::InvalidUtf8(_) => codes::INVALID_UTF8,
+            LexicalError::UnterminatedString => codes::UNTERMINATED_STRING,
+            LexicalError::InvalidEscape(_) => codes::INVALID_ESCAPE,
+            LexicalError::UnterminatedComment => codes::UNTERMINATED_COMMENT,
+            LexicalError::MalformedNumber(_) => codes::MALFORMED_NUMBER,
+            LexicalError::DanglingExponent(_) => codes::DANGLING_EXPONENT,
+            LexicalError::MalformedHexLiteral(_) => codes::MALFORMED_HEX_LITERAL,
+            LexicalError::MalformedOctalLiteral(_) => codes::MALFORMED_OCTAL_LITERAL,
+            LexicalError::MalformedBinaryLiteral(_) => codes::MALFORMED_BINARY_LITERAL,
+            LexicalError::IntegerOutOfRange(_) => codes::INTEGER_OUT_OF_RANGE,
+            LexicalError::IdentifierTooLong { .. } => codes::IDENTIFIER_TOO_LONG,
+        }
+    }
+
+    /// The error as a diagnostic at `span`, with its hint as a note.
+    pub fn to_diagnostic(&self, span: Span) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code(), self.to_string(), span);
+        match self.hint() {
+            Some(hint) => diagnostic.with_note(hint),
+            None => diagnostic,
+        }
+    }
+
+    /// A suggestion for fixing the error, for common mistakes.
+    ///
+    /// A lone `&` or `|` needs no hint: both are bitwise operators.
+    pub fn hint(&self) -> Option<String> {
+        let hint = match self {
+            LexicalError::InvalidCharacter('\'') => {
+                "string literals use double quotes: \"...\"".to_string()
+            }
+            LexicalError::InvalidCharacter('\u{201c}' | '\u{201d}' | '\u{2018}' | '\u{2019}') => {
+                "did you mean '\"'?".to_string()
+            }
+            LexicalError::InvalidCharacter('#') => "comments start with '//'".to_string(),
+            LexicalError::InvalidEscape(_) => {
+                "supported escapes are \\n, \\t, \\r, \\0, \\\\ and \\\"".to_string()
+            }
+            LexicalError::MalformedNumber(number) if number.starts_with('.') => {
+                format!("did you mean '0{}'?", number)
+            }
+            LexicalError::MalformedNumber(number) if number.ends_with('.') => {
+                format!("did you mean '{}0'?", number)
+            }
+            _ => return None,
+        };
+        Some(hint)
+    }
+}
+
+/// A token other than the one a [`TokenStream`](super::TokenStream) was
+/// told to expect.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("expected {expected}, found {found}")]
+pub struct UnexpectedToken {
+    pub expected: TokenType,
+    pub found: TokenType,
+    pub span: Span,
+}
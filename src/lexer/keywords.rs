@@ -0,0 +1,75 @@
+use super::token::TokenType;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Reserved words of the language, in the order the specification lists them.
+pub const KEYWORDS: &[&str] = &[
+    "if", "else", "while", "for", "int", "float", "bool", "return", "true", "false", "void",
+    "struct", "fn", "let", "const", "break", "continue", "char", "string", "null", "import",
+    "module", "enum", "match",
+];
+
+/// A custom set of reserved words for embedding the lexer in another
+/// language.
+///
+/// A word may map to any token type. Mapping it to `Identifier` makes it a
+/// contextual keyword: it lexes as a plain identifier and the parser decides
+/// what it means. Words missing from the table are identifiers.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct KeywordTable {
+    words: HashMap<String, TokenType>,
+}
+
+impl KeywordTable {
+    /// An empty table, in which every word is an identifier.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The language's own keywords, as a starting point for additions and
+    /// removals.
+    pub fn standard() -> Self {
+        KEYWORDS
+            .iter()
+            .filter_map(|&word| Some((word, TokenType::keyword(word)?)))
+            .collect()
+    }
+
+    pub fn insert(&mut self, word: impl Into<String>, token_type: TokenType) {
+        self.words.insert(word.into(), token_type);
+    }
+
+    pub fn remove(&mut self, word: &str) -> Option<TokenType> {
+        self.words.remove(word)
+    }
+
+    pub fn get(&self, word: &str) -> Option<TokenType> {
+        self.words.get(word).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, TokenType)> for KeywordTable {
+    fn from_iter<I: IntoIterator<Item = (S, TokenType)>>(iter: I) -> Self {
+        Self {
+            words: iter.into_iter().map(|(w, t)| (w.into(), t)).collect(),
+        }
+    }
+}
+
+// Sorted so the output is stable; scanner options are hashed into cache keys
+// through their `Debug` form.
+impl fmt::Debug for KeywordTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words: Vec<_> = self.words.iter().collect();
+        words.sort_by_key(|&(word, _)| word);
+        f.debug_map().entries(words).finish()
+    }
+}
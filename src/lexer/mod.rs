@@ -1,16 +1,60 @@
 pub mod error;
+pub mod keywords;
+pub mod options;
 pub mod scanner;
+pub mod stream;
 pub mod token;
 
-pub use scanner::Scanner;
-pub use token::{LiteralValue, Token, TokenType};
+pub use error::{LexicalError, UnexpectedToken};
+pub use keywords::KeywordTable;
+pub use options::{IntWidth, NewlineMode, ScannerOptions};
+pub use scanner::{Checkpoint, Scanner};
+pub use stream::TokenStream;
+pub use token::{LiteralValue, NumberSuffix, Token, TokenType, Trivia, TriviaKind};
+
+/// Turns tokens scanned in trivia mode back into source text.
+///
+/// Each token contributes its leading trivia, its lexeme and its trailing
+/// trivia, so for a complete token stream (through `EndOfFile`) from
+/// [`Scanner::with_trivia`] the result is the original source byte for byte,
+/// errors included.
+pub fn reconstruct(tokens: &[Token<'_>]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        for trivia in &token.leading_trivia {
+            text.push_str(&trivia.text);
+        }
+        text.push_str(&token.lexeme);
+        for trivia in &token.trailing_trivia {
+            text.push_str(&trivia.text);
+        }
+    }
+    text
+}
+
+/// Lexes `source` to the end, separating valid tokens from lexical errors.
+///
+/// The returned tokens end with `EndOfFile` and contain no `Error` tokens;
+/// the errors are returned in source order.
+pub fn tokenize(source: &str) -> (Vec<Token<'_>>, Vec<LexicalError>) {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner
+        .by_ref()
+        .filter(|t| t.token_type != TokenType::Error)
+        .collect();
+    let errors = scanner.errors().iter().map(|e| e.value.clone()).collect();
+    (tokens, errors)
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::input::InvalidUtf8;
+    use crate::source::{LineCol, SourceFile, Span};
+    use crate::utils::SymbolTable;
     use pretty_assertions::assert_eq;
 
-    fn tokenize(source: &str) -> Vec<Token> {
+    fn tokenize(source: &str) -> Vec<Token<'_>> {
         let mut scanner = Scanner::new(source);
         let mut tokens = Vec::new();
         loop {
@@ -48,6 +92,174 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_binding_and_loop_control_keywords() {
+        let src = "let const break continue";
+        let tokens = tokenize(src);
+        let expected = vec![
+            TokenType::Let,
+            TokenType::Const,
+            TokenType::Break,
+            TokenType::Continue,
+        ];
+        assert_eq!(tokens.len(), expected.len());
+        for (token, exp_type) in tokens.iter().zip(expected) {
+            assert_eq!(token.token_type, exp_type);
+            assert_eq!(token.literal, LiteralValue::None);
+        }
+
+        // Keywords only match whole words.
+        let tokens = tokenize("letter constant breaks");
+        for token in tokens {
+            assert_eq!(token.token_type, TokenType::Identifier);
+        }
+    }
+
+    #[test]
+    fn test_type_keywords() {
+        let src = r#"string name = "x"; char c;"#;
+        let types: Vec<_> = tokenize(src).iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::String,
+                TokenType::Identifier,
+                TokenType::Equal,
+                TokenType::StringLiteral,
+                TokenType::Semicolon,
+                TokenType::Char,
+                TokenType::Identifier,
+                TokenType::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_null_literal() {
+        let tokens = tokenize("null nullable");
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].token_type, TokenType::Null);
+        assert_eq!(tokens[0].literal, LiteralValue::Null);
+        assert_eq!(tokens[1].token_type, TokenType::Identifier);
+    }
+
+    #[test]
+    fn test_custom_keywords() {
+        let mut table = KeywordTable::standard();
+        assert_eq!(table.len(), keywords::KEYWORDS.len());
+        table.remove("fn");
+        table.insert("def", TokenType::Fn);
+        table.insert("where", TokenType::Identifier);
+
+        let tokens: Vec<_> = Scanner::new("def fn where if")
+            .with_keywords(table)
+            .collect();
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Fn,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::If,
+                TokenType::EndOfFile,
+            ]
+        );
+        assert_eq!(tokens[0].lexeme, "def");
+
+        // Literal keywords keep their values under a custom spelling.
+        let table: KeywordTable = [("yes", TokenType::True)].into_iter().collect();
+        let tokens: Vec<_> = Scanner::new("yes true").with_keywords(table).collect();
+        assert_eq!(tokens[0].literal, LiteralValue::Boolean(true));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier);
+    }
+
+    #[test]
+    fn test_case_insensitive_keywords() {
+        let src = "IF If if WHILE True NULL iffy";
+        let types =
+            |scanner: Scanner| -> Vec<TokenType> { scanner.map(|t| t.token_type).collect() };
+
+        // By default case matters, so `IF` and `If` are free identifiers.
+        assert_eq!(
+            types(Scanner::new(src)),
+            vec![
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::If,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::EndOfFile,
+            ]
+        );
+
+        let tokens: Vec<_> = Scanner::new(src)
+            .with_case_insensitive_keywords(true)
+            .collect();
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::If,
+                TokenType::If,
+                TokenType::If,
+                TokenType::While,
+                TokenType::True,
+                TokenType::Null,
+                TokenType::Identifier,
+                TokenType::EndOfFile,
+            ]
+        );
+        assert_eq!(tokens[0].lexeme, "IF");
+        assert_eq!(tokens[4].literal, LiteralValue::Boolean(true));
+        assert_eq!(tokens[5].literal, LiteralValue::Null);
+
+        // Custom tables are folded the same way.
+        let table: KeywordTable = [("def", TokenType::Fn)].into_iter().collect();
+        let tokens: Vec<_> = Scanner::new("DEF")
+            .with_keywords(table)
+            .with_case_insensitive_keywords(true)
+            .collect();
+        assert_eq!(tokens[0].token_type, TokenType::Fn);
+    }
+
+    #[test]
+    fn test_module_keywords() {
+        let src = "module geometry; import math::vec;";
+        let types: Vec<_> = tokenize(src).iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Module,
+                TokenType::Identifier,
+                TokenType::Semicolon,
+                TokenType::Import,
+                TokenType::Identifier,
+                TokenType::ColonColon,
+                TokenType::Identifier,
+                TokenType::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_tokens() {
+        let src = "enum match => == =";
+        let types: Vec<_> = tokenize(src).iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Enum,
+                TokenType::Match,
+                TokenType::FatArrow,
+                TokenType::EqualEqual,
+                TokenType::Equal,
+            ]
+        );
+    }
+
     #[test]
     fn test_identifiers() {
         let src = "x _foo bar123 a_very_long_identifier_that_is_under_255_chars";
@@ -64,7 +276,7 @@ mod tests {
         // Positive literals only; negative numbers are handled as two tokens.
         let src = "0 42 2147483647";
         let tokens = tokenize(src);
-        let expected_values = vec![0, 42, 2147483647];
+        let expected_values = [0, 42, 2147483647];
         assert_eq!(tokens.len(), 3);
         for (token, &val) in tokens.iter().zip(expected_values.iter()) {
             assert_eq!(token.token_type, TokenType::IntLiteral);
@@ -78,7 +290,140 @@ mod tests {
         assert_eq!(tok1.token_type, TokenType::Minus);
         let tok2 = scanner.next_token();
         assert_eq!(tok2.token_type, TokenType::Error);
-        assert!(tok2.lexeme.contains("integer literal out of range"));
+        assert!(matches!(
+            tok2.error,
+            Some(LexicalError::IntegerOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_fold_negative_literals() {
+        let lex = |src: &str| -> Vec<(TokenType, LiteralValue)> {
+            Scanner::new(src)
+                .with_fold_negative_literals(true)
+                .map(|t| (t.token_type, t.literal))
+                .filter(|(t, _)| *t != TokenType::EndOfFile)
+                .collect()
+        };
+
+        assert_eq!(
+            lex("-2147483648"),
+            vec![(TokenType::IntLiteral, LiteralValue::Integer(-2147483648))]
+        );
+        assert_eq!(
+            lex("x = -1.5 + -0x10;"),
+            vec![
+                (TokenType::Identifier, LiteralValue::None),
+                (TokenType::Equal, LiteralValue::None),
+                (TokenType::FloatLiteral, LiteralValue::Float(-1.5)),
+                (TokenType::Plus, LiteralValue::None),
+                (TokenType::IntLiteral, LiteralValue::Integer(-16)),
+                (TokenType::Semicolon, LiteralValue::None),
+            ]
+        );
+
+        // After an operand the minus is subtraction.
+        for src in ["a -1", "2 -1", "f() -1", "v[0] -1"] {
+            let types: Vec<_> = lex(src).into_iter().map(|(t, _)| t).collect();
+            assert_eq!(types[types.len() - 2], TokenType::Minus, "{}", src);
+        }
+
+        // Folding is opt-in, and `-u8` literals are still range checked.
+        assert_eq!(tokenize("-1")[0].token_type, TokenType::Minus);
+        assert_eq!(lex("-1u8")[0].0, TokenType::Error);
+    }
+
+    #[test]
+    fn test_configurable_int_width() {
+        let src = "2147483648 9223372036854775807 9223372036854775808 18446744073709551616";
+        let results = |width: IntWidth| -> Vec<Option<i64>> {
+            Scanner::new(src)
+                .with_int_width(width)
+                .filter(|t| t.token_type != TokenType::EndOfFile)
+                .map(|t| match t.literal {
+                    LiteralValue::Integer(v) => Some(v),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(results(IntWidth::I32), vec![None, None, None, None]);
+        assert_eq!(
+            results(IntWidth::I64),
+            vec![Some(2147483648), Some(i64::MAX), None, None]
+        );
+        assert_eq!(
+            results(IntWidth::Unchecked),
+            vec![Some(2147483648), Some(i64::MAX), Some(i64::MIN), None]
+        );
+
+        // Suffixes take precedence over the configured width.
+        let tokens: Vec<_> = Scanner::new("300u8")
+            .with_int_width(IntWidth::I64)
+            .collect();
+        assert_eq!(tokens[0].token_type, TokenType::Error);
+    }
+
+    #[test]
+    fn test_hex_literals() {
+        let src = "0xFF 0x7fffffff 0X1a 0x0";
+        let tokens = tokenize(src);
+        let expected_values = [255, 2147483647, 26, 0];
+        assert_eq!(tokens.len(), 4);
+        for (token, &val) in tokens.iter().zip(expected_values.iter()) {
+            assert_eq!(token.token_type, TokenType::IntLiteral);
+            assert_eq!(token.literal, LiteralValue::Integer(val));
+        }
+
+        let tokens = tokenize("0x 0xFG 0x80000000");
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(
+            tokens[0].error,
+            Some(LexicalError::MalformedHexLiteral("0x".into()))
+        );
+        assert_eq!(
+            tokens[1].error,
+            Some(LexicalError::MalformedHexLiteral("0xFG".into()))
+        );
+        assert!(matches!(
+            tokens[2].error,
+            Some(LexicalError::IntegerOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_binary_and_octal_literals() {
+        let src = "0b1010 0B0 0o755 0O17 0b1111111111111111111111111111111";
+        let tokens = tokenize(src);
+        let expected_values = [10, 0, 493, 15, 2147483647];
+        assert_eq!(tokens.len(), 5);
+        for (token, &val) in tokens.iter().zip(expected_values.iter()) {
+            assert_eq!(token.token_type, TokenType::IntLiteral);
+            assert_eq!(token.literal, LiteralValue::Integer(val));
+        }
+
+        let tokens = tokenize("0b 0b102 0o 0o78 0o20000000000");
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(
+            tokens[0].error,
+            Some(LexicalError::MalformedBinaryLiteral("0b".into()))
+        );
+        assert_eq!(
+            tokens[1].error,
+            Some(LexicalError::MalformedBinaryLiteral("0b102".into()))
+        );
+        assert_eq!(
+            tokens[2].error,
+            Some(LexicalError::MalformedOctalLiteral("0o".into()))
+        );
+        assert_eq!(
+            tokens[3].error,
+            Some(LexicalError::MalformedOctalLiteral("0o78".into()))
+        );
+        assert!(matches!(
+            tokens[4].error,
+            Some(LexicalError::IntegerOutOfRange(_))
+        ));
     }
 
     #[test]
@@ -89,9 +434,131 @@ mod tests {
         assert_eq!(tokens[0].token_type, TokenType::FloatLiteral);
         assert_eq!(tokens[1].token_type, TokenType::FloatLiteral);
         assert_eq!(tokens[2].token_type, TokenType::Error);
-        assert!(tokens[2].lexeme.contains("malformed number"));
+        assert!(matches!(
+            tokens[2].error,
+            Some(LexicalError::MalformedNumber(_))
+        ));
         assert_eq!(tokens[3].token_type, TokenType::Error);
-        assert!(tokens[3].lexeme.contains("malformed number"));
+        assert!(matches!(
+            tokens[3].error,
+            Some(LexicalError::MalformedNumber(_))
+        ));
+    }
+
+    #[test]
+    fn test_number_suffixes() {
+        let src = "42u 100i64 255u8 3000000000u32 0xFFu8 0b1i8 7 1.5f 2f64 1e3f32";
+        let tokens = tokenize(src);
+        let summary: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.token_type, t.literal.clone(), t.suffix))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    TokenType::IntLiteral,
+                    LiteralValue::Integer(42),
+                    Some(NumberSuffix::U32)
+                ),
+                (
+                    TokenType::IntLiteral,
+                    LiteralValue::Integer(100),
+                    Some(NumberSuffix::I64)
+                ),
+                (
+                    TokenType::IntLiteral,
+                    LiteralValue::Integer(255),
+                    Some(NumberSuffix::U8)
+                ),
+                (
+                    TokenType::IntLiteral,
+                    LiteralValue::Integer(3_000_000_000),
+                    Some(NumberSuffix::U32)
+                ),
+                (
+                    TokenType::IntLiteral,
+                    LiteralValue::Integer(255),
+                    Some(NumberSuffix::U8)
+                ),
+                (
+                    TokenType::IntLiteral,
+                    LiteralValue::Integer(1),
+                    Some(NumberSuffix::I8)
+                ),
+                (TokenType::IntLiteral, LiteralValue::Integer(7), None),
+                (
+                    TokenType::FloatLiteral,
+                    LiteralValue::Float(1.5),
+                    Some(NumberSuffix::F32)
+                ),
+                (
+                    TokenType::FloatLiteral,
+                    LiteralValue::Float(2.0),
+                    Some(NumberSuffix::F64)
+                ),
+                (
+                    TokenType::FloatLiteral,
+                    LiteralValue::Float(1000.0),
+                    Some(NumberSuffix::F32)
+                ),
+            ]
+        );
+        assert_eq!(tokens[0].lexeme, "42u");
+
+        // Range is checked against the suffix, not against i32.
+        let tokens = tokenize("256u8 128i8 9223372036854775808i64 18446744073709551615u64");
+        assert!(matches!(
+            tokens[0].error,
+            Some(LexicalError::IntegerOutOfRange(_))
+        ));
+        assert!(matches!(
+            tokens[1].error,
+            Some(LexicalError::IntegerOutOfRange(_))
+        ));
+        assert!(matches!(
+            tokens[2].error,
+            Some(LexicalError::IntegerOutOfRange(_))
+        ));
+        assert_eq!(tokens[3].literal, LiteralValue::Integer(-1));
+
+        // Integer suffixes do not apply to floats; unknown suffixes are not
+        // part of the number.
+        let tokens = tokenize("1.5i32 42x");
+        assert!(matches!(
+            tokens[0].error,
+            Some(LexicalError::MalformedNumber(_))
+        ));
+        assert_eq!(tokens[1].token_type, TokenType::IntLiteral);
+        assert_eq!(tokens[2].token_type, TokenType::Identifier);
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        let src = "1e9 2.5e-3 1E+6 0.5e0";
+        let tokens = tokenize(src);
+        let expected_values = [1e9, 2.5e-3, 1e6, 0.5];
+        assert_eq!(tokens.len(), 4);
+        for (token, &val) in tokens.iter().zip(expected_values.iter()) {
+            assert_eq!(token.token_type, TokenType::FloatLiteral);
+            assert_eq!(token.literal, LiteralValue::Float(val));
+        }
+
+        let tokens = tokenize("1e 2.5e+ 3E-x");
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(
+            tokens[0].error,
+            Some(LexicalError::DanglingExponent("1e".into()))
+        );
+        assert_eq!(
+            tokens[1].error,
+            Some(LexicalError::DanglingExponent("2.5e+".into()))
+        );
+        assert_eq!(
+            tokens[2].error,
+            Some(LexicalError::DanglingExponent("3E-".into()))
+        );
+        assert_eq!(tokens[3].token_type, TokenType::Identifier);
     }
 
     #[test]
@@ -100,22 +567,38 @@ mod tests {
         let tokens = tokenize(src);
         assert_eq!(tokens.len(), 3);
         assert_eq!(tokens[0].token_type, TokenType::StringLiteral);
-        assert_eq!(
-            tokens[0].literal,
-            LiteralValue::String("hello".to_string())
-        );
+        assert_eq!(tokens[0].literal, LiteralValue::String("hello".to_string()));
         assert_eq!(tokens[1].token_type, TokenType::StringLiteral);
-        assert_eq!(
-            tokens[1].literal,
-            LiteralValue::String("world".to_string())
-        );
+        assert_eq!(tokens[1].literal, LiteralValue::String("world".to_string()));
         assert_eq!(tokens[2].token_type, TokenType::StringLiteral);
         assert_eq!(tokens[2].literal, LiteralValue::String("".to_string()));
     }
 
+    #[test]
+    fn test_string_escapes() {
+        let src = r#""a\nb" "\t\r\0" "say \"hi\"" "back\\slash""#;
+        let tokens = tokenize(src);
+        assert_eq!(tokens.len(), 4);
+        let expected = ["a\nb", "\t\r\0", "say \"hi\"", "back\\slash"];
+        for (token, value) in tokens.iter().zip(expected) {
+            assert_eq!(token.token_type, TokenType::StringLiteral);
+            assert_eq!(token.literal, LiteralValue::String(value.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_invalid_escape() {
+        let src = r#""bad \q escape" x"#;
+        let tokens = tokenize(src);
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].token_type, TokenType::Error);
+        assert_eq!(tokens[0].error, Some(LexicalError::InvalidEscape('q')));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier);
+    }
+
     #[test]
     fn test_operators() {
-        let src = "+ - * / % = == != < <= > >= && || ! += -= *= /=";
+        let src = "+ - * / % = == != < <= > >= && || ! += -= *= /= %= ++ -- & | ^ ~ << >> &= |= ^= <<= >>= ?";
         let tokens = tokenize(src);
         let expected = vec![
             TokenType::Plus,
@@ -137,6 +620,21 @@ mod tests {
             TokenType::MinusEqual,
             TokenType::StarEqual,
             TokenType::SlashEqual,
+            TokenType::PercentEqual,
+            TokenType::PlusPlus,
+            TokenType::MinusMinus,
+            TokenType::Amp,
+            TokenType::Pipe,
+            TokenType::Caret,
+            TokenType::Tilde,
+            TokenType::Shl,
+            TokenType::Shr,
+            TokenType::AmpEqual,
+            TokenType::PipeEqual,
+            TokenType::CaretEqual,
+            TokenType::ShlEqual,
+            TokenType::ShrEqual,
+            TokenType::Question,
         ];
         assert_eq!(tokens.len(), expected.len());
         for (token, exp_type) in tokens.iter().zip(expected) {
@@ -144,9 +642,129 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bitwise_disambiguation() {
+        let types: Vec<_> = tokenize("a&b&&c|d||e<<f<=g>>h>=i<<<j")
+            .iter()
+            .map(|t| t.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Amp,
+                TokenType::Identifier,
+                TokenType::AndAnd,
+                TokenType::Identifier,
+                TokenType::Pipe,
+                TokenType::Identifier,
+                TokenType::OrOr,
+                TokenType::Identifier,
+                TokenType::Shl,
+                TokenType::Identifier,
+                TokenType::LessEqual,
+                TokenType::Identifier,
+                TokenType::Shr,
+                TokenType::Identifier,
+                TokenType::GreaterEqual,
+                TokenType::Identifier,
+                TokenType::Shl,
+                TokenType::Less,
+                TokenType::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditional_expression() {
+        let types: Vec<_> = tokenize("x > 0 ? x : -x")
+            .iter()
+            .map(|t| t.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Greater,
+                TokenType::IntLiteral,
+                TokenType::Question,
+                TokenType::Identifier,
+                TokenType::Colon,
+                TokenType::Minus,
+                TokenType::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_increment_maximal_munch() {
+        let types =
+            |src: &str| -> Vec<TokenType> { tokenize(src).iter().map(|t| t.token_type).collect() };
+        assert_eq!(
+            types("a+++b"),
+            vec![
+                TokenType::Identifier,
+                TokenType::PlusPlus,
+                TokenType::Plus,
+                TokenType::Identifier
+            ]
+        );
+        assert_eq!(
+            types("a---b"),
+            vec![
+                TokenType::Identifier,
+                TokenType::MinusMinus,
+                TokenType::Minus,
+                TokenType::Identifier
+            ]
+        );
+        assert_eq!(
+            types("i++=1"),
+            vec![
+                TokenType::Identifier,
+                TokenType::PlusPlus,
+                TokenType::Equal,
+                TokenType::IntLiteral
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let src = "#[inline] #[test(slow, 2)] fn f() {} # [x]";
+        let types: Vec<_> = tokenize(src).iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::HashBracket,
+                TokenType::Identifier,
+                TokenType::RBracket,
+                TokenType::HashBracket,
+                TokenType::Identifier,
+                TokenType::LParen,
+                TokenType::Identifier,
+                TokenType::Comma,
+                TokenType::IntLiteral,
+                TokenType::RParen,
+                TokenType::RBracket,
+                TokenType::Fn,
+                TokenType::Identifier,
+                TokenType::LParen,
+                TokenType::RParen,
+                TokenType::LBrace,
+                TokenType::RBrace,
+                // Only `#[` without a space opens an attribute.
+                TokenType::Error,
+                TokenType::LBracket,
+                TokenType::Identifier,
+                TokenType::RBracket,
+            ]
+        );
+    }
+
     #[test]
     fn test_delimiters() {
-        let src = "( ) { } [ ] ; , :";
+        let src = "( ) { } [ ] ; , : . :: Math::abs a:::b -> x->y";
         let tokens = tokenize(src);
         let expected = vec![
             TokenType::LParen,
@@ -158,6 +776,19 @@ mod tests {
             TokenType::Semicolon,
             TokenType::Comma,
             TokenType::Colon,
+            TokenType::Dot,
+            TokenType::ColonColon,
+            TokenType::Identifier,
+            TokenType::ColonColon,
+            TokenType::Identifier,
+            TokenType::Identifier,
+            TokenType::ColonColon,
+            TokenType::Colon,
+            TokenType::Identifier,
+            TokenType::Arrow,
+            TokenType::Identifier,
+            TokenType::Arrow,
+            TokenType::Identifier,
         ];
         assert_eq!(tokens.len(), expected.len());
         for (token, exp_type) in tokens.iter().zip(expected) {
@@ -165,30 +796,437 @@ mod tests {
         }
     }
 
-#[test]
-fn test_comments() {
-    let src = r#"
+    #[test]
+    fn test_member_access_dot() {
+        let tokens = tokenize("p.x 1.foo 1.0 a.b.c");
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::IntLiteral,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::FloatLiteral,
+                TokenType::Identifier,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::Dot,
+                TokenType::Identifier,
+            ]
+        );
+        assert_eq!(tokens[3].literal, LiteralValue::Integer(1));
+        assert_eq!(tokens[6].literal, LiteralValue::Float(1.0));
+    }
+
+    #[test]
+    fn test_comments() {
+        let src = r#"
     // single line comment
     int x = 5; // trailing comment
     /* block comment */
     /* nested /* block */ comment */
     "/* not a comment */"
     "#;
-    let tokens = tokenize(src);
-    assert_eq!(tokens.len(), 6);
-    assert_eq!(tokens[0].token_type, TokenType::Int);
-    assert_eq!(tokens[1].token_type, TokenType::Identifier);
-    assert_eq!(tokens[1].lexeme, "x");
-    assert_eq!(tokens[2].token_type, TokenType::Equal);
-    assert_eq!(tokens[3].token_type, TokenType::IntLiteral);
-    assert_eq!(tokens[3].literal, LiteralValue::Integer(5));
-    assert_eq!(tokens[4].token_type, TokenType::Semicolon);
-    assert_eq!(tokens[5].token_type, TokenType::StringLiteral);
-    assert_eq!(
-        tokens[5].literal,
-        LiteralValue::String("/* not a comment */".to_string())
-    );
-}
+        let tokens = tokenize(src);
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(tokens[0].token_type, TokenType::Int);
+        assert_eq!(tokens[1].token_type, TokenType::Identifier);
+        assert_eq!(tokens[1].lexeme, "x");
+        assert_eq!(tokens[2].token_type, TokenType::Equal);
+        assert_eq!(tokens[3].token_type, TokenType::IntLiteral);
+        assert_eq!(tokens[3].literal, LiteralValue::Integer(5));
+        assert_eq!(tokens[4].token_type, TokenType::Semicolon);
+        assert_eq!(tokens[5].token_type, TokenType::StringLiteral);
+        assert_eq!(
+            tokens[5].literal,
+            LiteralValue::String("/* not a comment */".to_string())
+        );
+    }
+
+    #[test]
+    fn test_trivia_mode() {
+        let src = "// header\nint x; /* note */\n  y // end\n";
+        let tokens: Vec<_> = Scanner::new(src).with_trivia(true).collect();
+        let kinds = |trivia: &[Trivia]| -> Vec<(TriviaKind, String)> {
+            trivia
+                .iter()
+                .map(|t| (t.kind, t.text.to_string()))
+                .collect()
+        };
+
+        assert_eq!(tokens[0].token_type, TokenType::Int);
+        assert_eq!(
+            kinds(&tokens[0].leading_trivia),
+            vec![
+                (TriviaKind::LineComment, "// header".to_string()),
+                (TriviaKind::Whitespace, "\n".to_string()),
+            ]
+        );
+        // `;` owns the rest of its line, the newline goes to `y`.
+        assert_eq!(
+            kinds(&tokens[2].trailing_trivia),
+            vec![
+                (TriviaKind::Whitespace, " ".to_string()),
+                (TriviaKind::BlockComment, "/* note */".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds(&tokens[3].leading_trivia),
+            vec![(TriviaKind::Whitespace, "\n  ".to_string())]
+        );
+        assert_eq!(
+            kinds(&tokens[3].trailing_trivia),
+            vec![
+                (TriviaKind::Whitespace, " ".to_string()),
+                (TriviaKind::LineComment, "// end".to_string()),
+            ]
+        );
+        assert_eq!(tokens[4].token_type, TokenType::EndOfFile);
+        assert_eq!(
+            kinds(&tokens[4].leading_trivia),
+            vec![(TriviaKind::Whitespace, "\n".to_string())]
+        );
+
+        // Without trivia mode nothing is attached.
+        assert!(tokenize(src)
+            .iter()
+            .all(|t| t.leading_trivia.is_empty() && t.trailing_trivia.is_empty()));
+    }
+
+    #[test]
+    fn test_shebang() {
+        let tokens = tokenize("#!/usr/bin/env minicompiler run\nint x;");
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![TokenType::Int, TokenType::Identifier, TokenType::Semicolon]
+        );
+
+        let tokens: Vec<_> = Scanner::new("#!run\nx").with_trivia(true).collect();
+        assert_eq!(tokens[0].leading_trivia[0].kind, TriviaKind::Shebang);
+        assert_eq!(tokens[0].leading_trivia[0].text, "#!run");
+
+        // Only the first line of the file may be a shebang.
+        let tokens = tokenize("x\n#!run");
+        assert_eq!(tokens[1].token_type, TokenType::Error);
+    }
+
+    #[test]
+    fn test_public_tokenize() {
+        let (tokens, errors) = super::tokenize("x = @ 1 $;");
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Equal,
+                TokenType::IntLiteral,
+                TokenType::Semicolon,
+                TokenType::EndOfFile,
+            ]
+        );
+        assert_eq!(
+            errors,
+            vec![
+                LexicalError::InvalidCharacter('@'),
+                LexicalError::InvalidCharacter('$'),
+            ]
+        );
+
+        // Peeking must not report the same error twice.
+        let mut scanner = Scanner::new("@");
+        scanner.peek_token();
+        scanner.next_token();
+        assert_eq!(scanner.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_error_accumulation() {
+        let mut scanner = Scanner::new("int @x;\n\"abc");
+        let types: Vec<_> = scanner.by_ref().map(|t| t.token_type).collect();
+        assert_eq!(types.iter().filter(|&&t| t == TokenType::Error).count(), 2);
+
+        let errors = scanner.errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].value, LexicalError::InvalidCharacter('@'));
+        assert_eq!(errors[0].span, Span::new(4, 5));
+        assert_eq!(errors[1].value, LexicalError::UnterminatedString);
+        assert_eq!(errors[1].span, Span::new(8, 12));
+        assert_eq!(errors[1].to_string(), "unterminated string literal");
+    }
+
+    #[test]
+    fn test_incremental_relex() {
+        let old_src = "let a = 1;\nlet bc = a + 2; // two\n/* c */ x = \"s\";\n";
+        let edits: &[(std::ops::Range<usize>, &str)] = &[
+            (8..9, "42 + y"),                      // replace a literal
+            (16..16, "d"),                         // extend an identifier: bc -> bdc
+            (9..10, ""),                           // delete a `;`
+            (30..30, "/*"),                        // insert into a line comment
+            (40..41, "* */ z /*"),                 // close and reopen a block comment
+            (0..0, "#!run\n"),                     // insert at the very start
+            (old_src.len()..old_src.len(), "end"), // append at the very end
+        ];
+
+        fn scanner(src: &str, trivia: bool, symbols: SymbolTable) -> Scanner<'_> {
+            Scanner::new(src)
+                .with_trivia(trivia)
+                .with_symbol_table(symbols)
+        }
+
+        for trivia in [false, true] {
+            let mut old_scanner = scanner(old_src, trivia, SymbolTable::new());
+            let old_tokens: Vec<_> = old_scanner.by_ref().collect();
+            let symbols = old_scanner.into_symbols();
+            for (range, text) in edits {
+                let mut new_src = old_src.to_string();
+                new_src.replace_range(range.clone(), text);
+
+                let expected: Vec<_> = scanner(&new_src, trivia, symbols.clone()).collect();
+                let relexed = scanner(&new_src, trivia, symbols.clone()).relex(
+                    range.clone(),
+                    text,
+                    &old_tokens,
+                );
+                assert_eq!(relexed, expected, "edit {:?} -> {:?}", range, text);
+            }
+        }
+
+        // Where newlines are tokens only outside brackets, an edit that
+        // opens or closes one changes the newlines after it.
+        let old_src = "a\n(b\nc)\nd\n";
+        let edits: &[(std::ops::Range<usize>, &str)] = &[
+            (0..0, "("),  // open a bracket at the start
+            (2..3, ""),   // delete a `(`
+            (6..7, ""),   // delete a `)`
+            (5..5, ")("), // close and reopen
+            (8..9, "[e"), // leave one open to the end
+        ];
+        fn newlines(src: &str, symbols: SymbolTable) -> Scanner<'_> {
+            let options = ScannerOptions::new().with_newlines(NewlineMode::EmitOutsideBrackets);
+            Scanner::with_options(src, options).with_symbol_table(symbols)
+        }
+        let mut old_scanner = newlines(old_src, SymbolTable::new());
+        let old_tokens: Vec<_> = old_scanner.by_ref().collect();
+        let symbols = old_scanner.into_symbols();
+        for (range, text) in edits {
+            let mut new_src = old_src.to_string();
+            new_src.replace_range(range.clone(), text);
+            let expected: Vec<_> = newlines(&new_src, symbols.clone()).collect();
+            let relexed =
+                newlines(&new_src, symbols.clone()).relex(range.clone(), text, &old_tokens);
+            assert_eq!(relexed, expected, "edit {:?} -> {:?}", range, text);
+        }
+        // xorshift64, so the edits are the same on every run.
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut random = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % n
+        };
+        let pieces = ["(", ")", "[", "]", "\n", "x", " ", "+ 1"];
+        for _ in 0..500 {
+            let start = random(old_src.len() + 1);
+            let range = start..start + random(old_src.len() - start + 1);
+            let text: String = (0..random(3))
+                .map(|_| pieces[random(pieces.len())])
+                .collect();
+            let mut new_src = old_src.to_string();
+            new_src.replace_range(range.clone(), &text);
+            let expected: Vec<_> = newlines(&new_src, symbols.clone()).collect();
+            let relexed =
+                newlines(&new_src, symbols.clone()).relex(range.clone(), &text, &old_tokens);
+            assert_eq!(relexed, expected, "edit {:?} -> {:?}", range, text);
+        }
+
+        // A local edit stops scanning at the end of its line.
+        let old_src = "let a = 1;\nlet bc = a + 2; // two\n/* c */ x = \"s\";\n";
+        let old_tokens: Vec<_> = Scanner::new(old_src).collect();
+        let new_src = old_src.replacen('1', "42", 1);
+        let mut scanner = Scanner::new(&new_src);
+        scanner.relex(8..9, "42", &old_tokens);
+        assert!(scanner.offset() < new_src.find('\n').unwrap() + 5);
+    }
+
+    #[test]
+    fn test_lexemes_borrow_from_source() {
+        use std::borrow::Cow;
+
+        let src = String::from("let s = \"hi\\n\" @");
+        let tokens = tokenize(&src);
+        for token in &tokens {
+            assert!(matches!(token.lexeme, Cow::Borrowed(_)), "{}", token);
+        }
+        assert_eq!(tokens[4].lexeme, "@");
+
+        let owned: Vec<Token<'static>> = tokens.into_iter().map(Token::into_owned).collect();
+        drop(src);
+        assert_eq!(owned[3].lexeme, "\"hi\\n\"");
+        assert_eq!(owned[3].literal, LiteralValue::String("hi\n".to_string()));
+    }
+
+    #[test]
+    fn test_identifier_symbols() {
+        let mut scanner = Scanner::new("for i i j fn i");
+        let tokens: Vec<_> = scanner.by_ref().collect();
+        let symbols: Vec<_> = tokens.iter().map(|t| t.symbol).collect();
+        assert_eq!(symbols[0], None);
+        assert_eq!(symbols[1], symbols[2]);
+        assert_ne!(symbols[1], symbols[3]);
+        assert_eq!(symbols[4], None);
+        assert_eq!(symbols[5], symbols[1]);
+        assert_eq!(scanner.symbols().resolve(symbols[3].unwrap()), "j");
+
+        // A shared table keeps symbols stable across files.
+        let table = scanner.into_symbols();
+        let i = table.get("i");
+        let mut scanner = Scanner::new("k i").with_symbol_table(table);
+        let tokens: Vec<_> = scanner.by_ref().collect();
+        assert_eq!(tokens[1].symbol, i);
+        assert_eq!(scanner.symbols().len(), 3);
+    }
+
+    #[test]
+    fn test_peek_nth() {
+        let mut scanner = Scanner::new("a :: b [ 1 ]");
+        assert_eq!(scanner.peek_nth(1).token_type, TokenType::ColonColon);
+        assert_eq!(scanner.peek_nth(3).token_type, TokenType::LBracket);
+        assert_eq!(scanner.peek_nth(0).lexeme, "a");
+        assert_eq!(scanner.offset(), 0);
+
+        // Buffered tokens come out of next_token in order.
+        assert_eq!(scanner.next_token().lexeme, "a");
+        assert_eq!(scanner.offset(), 1);
+        assert_eq!(scanner.peek_token().token_type, TokenType::ColonColon);
+        let rest: Vec<_> = scanner.by_ref().map(|t| t.token_type).collect();
+        assert_eq!(
+            rest,
+            vec![
+                TokenType::ColonColon,
+                TokenType::Identifier,
+                TokenType::LBracket,
+                TokenType::IntLiteral,
+                TokenType::RBracket,
+                TokenType::EndOfFile,
+            ]
+        );
+        assert_eq!(scanner.peek_nth(5).token_type, TokenType::EndOfFile);
+
+        // Also when the end is reached while other tokens are buffered.
+        let mut scanner = Scanner::new("a");
+        assert_eq!(scanner.peek_nth(2).token_type, TokenType::EndOfFile);
+    }
+
+    #[test]
+    fn test_checkpoint_and_rewind() {
+        let mut scanner = Scanner::new("x = @ y;");
+        scanner.next_token();
+        let checkpoint = scanner.checkpoint();
+        assert_eq!(checkpoint.offset(), 1);
+
+        let speculative: Vec<_> = scanner.by_ref().map(|t| t.token_type).collect();
+        assert_eq!(speculative.len(), 5);
+        assert_eq!(scanner.errors().len(), 1);
+
+        scanner.rewind(checkpoint);
+        assert_eq!(scanner.offset(), 1);
+        assert!(scanner.errors().is_empty());
+        let again: Vec<_> = scanner.by_ref().map(|t| t.token_type).collect();
+        assert_eq!(again, speculative);
+        assert_eq!(scanner.errors().len(), 1);
+
+        // A checkpoint taken while tokens are buffered still starts before them.
+        let mut scanner = Scanner::new("a b c");
+        scanner.peek_nth(2);
+        let checkpoint = scanner.checkpoint();
+        scanner.next_token();
+        scanner.rewind(checkpoint);
+        assert_eq!(scanner.next_token().lexeme, "a");
+    }
+
+    #[test]
+    fn test_split_greater() {
+        let mut scanner = Scanner::new("a>>=b >> c");
+        scanner.next_token();
+        let split: Vec<_> = (0..3)
+            .map(|_| {
+                // Only `>>=` and `>=` are split; the `>` in front is left.
+                scanner.split_greater();
+                let token = scanner.next_token();
+                (token.token_type, token.lexeme, token.span)
+            })
+            .collect();
+        assert_eq!(
+            split,
+            [
+                (TokenType::Greater, ">".into(), Span::new(1, 2)),
+                (TokenType::Greater, ">".into(), Span::new(2, 3)),
+                (TokenType::Equal, "=".into(), Span::new(3, 4)),
+            ]
+        );
+
+        // Other tokens are left alone, and a split token rewinds correctly.
+        scanner.split_greater();
+        assert_eq!(scanner.peek_nth(0).lexeme, "b");
+        let mut scanner = Scanner::new("x >> y");
+        scanner.next_token();
+        scanner.split_greater();
+        scanner.next_token();
+        let checkpoint = scanner.checkpoint();
+        assert_eq!(scanner.next_token().token_type, TokenType::Greater);
+        scanner.rewind(checkpoint);
+        assert_eq!(scanner.next_token().span, Span::new(3, 4));
+        assert_eq!(scanner.next_token().lexeme, "y");
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let mut stream = TokenStream::new(Scanner::new("let x = 1;"));
+        assert!(stream.eat(TokenType::Let));
+        assert!(!stream.eat(TokenType::Let));
+        let name = stream.expect(TokenType::Identifier).unwrap();
+        assert_eq!(name.lexeme, "x");
+        assert_eq!(stream.prev_span(), Span::new(4, 5));
+
+        let err = stream.expect(TokenType::Semicolon).unwrap_err();
+        assert_eq!(
+            err,
+            UnexpectedToken {
+                expected: TokenType::Semicolon,
+                found: TokenType::Equal,
+                span: Span::new(6, 7),
+            }
+        );
+        assert_eq!(err.to_string(), "expected Semicolon, found Equal");
+        assert!(stream.check(TokenType::Equal));
+
+        assert_eq!(stream.next_token().token_type, TokenType::Equal);
+        assert_eq!(stream.peek_nth(1).token_type, TokenType::Semicolon);
+        stream.next_token();
+        stream.next_token();
+        assert!(stream.is_at_end());
+        assert_eq!(stream.next_token().token_type, TokenType::EndOfFile);
+        assert_eq!(stream.next_token().token_type, TokenType::EndOfFile);
+    }
+
+    #[test]
+    fn test_lossless_reconstruction() {
+        let sources = [
+            "",
+            "#!/usr/bin/env run\r\nfn main() {\r\n\treturn 0; // done\r\n}\r\n",
+            "/* a /* nested */ comment */ x\n\n  \n// trailing",
+            "bad @ \"str\\q\" 0xZZ 1e /* never closed",
+            "\"unterminated\n caf\u{e9} = \"\u{20ac}\";  ",
+        ];
+        for src in sources {
+            let tokens: Vec<_> = Scanner::new(src).with_trivia(true).collect();
+            assert_eq!(reconstruct(&tokens), src);
+        }
+    }
 
     #[test]
     fn test_invalid_characters() {
@@ -200,21 +1238,97 @@ fn test_comments() {
         }
     }
 
+    #[test]
+    fn test_invalid_character_runs() {
+        let tokens = tokenize("x @$# y \u{1f600}\u{200d}\u{1f4bb}+\u{0}\u{1}");
+        let summary: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.token_type, t.error.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (TokenType::Identifier, None),
+                (
+                    TokenType::Error,
+                    Some(LexicalError::InvalidCharacters("@$#".to_string()))
+                ),
+                (TokenType::Identifier, None),
+                (
+                    TokenType::Error,
+                    Some(LexicalError::InvalidCharacters(
+                        "\u{1f600}\u{200d}\u{1f4bb}".to_string()
+                    ))
+                ),
+                (TokenType::Plus, None),
+                (
+                    TokenType::Error,
+                    Some(LexicalError::InvalidCharacters("\u{0}\u{1}".to_string()))
+                ),
+            ]
+        );
+        assert_eq!(tokens[1].span, Span::new(2, 5));
+    }
+
+    #[test]
+    fn test_error_hints() {
+        let hints: Vec<_> = tokenize("'a' .5 10. \"\\q\" # & |")
+            .iter()
+            .filter_map(|t| t.error.as_ref().and_then(LexicalError::hint))
+            .collect();
+        assert_eq!(
+            hints,
+            vec![
+                "string literals use double quotes: \"...\"",
+                "string literals use double quotes: \"...\"",
+                "did you mean '0.5'?",
+                "did you mean '10.0'?",
+                "supported escapes are \\n, \\t, \\r, \\0, \\\\ and \\\"",
+                "comments start with '//'",
+            ]
+        );
+        assert_eq!(LexicalError::InvalidCharacter('@').hint(), None);
+    }
+
     #[test]
     fn test_unterminated_string() {
         let src = r#""hello"#;
         let tokens = tokenize(src);
         assert_eq!(tokens.len(), 1);
         assert_eq!(tokens[0].token_type, TokenType::Error);
-        assert!(tokens[0].lexeme.contains("unterminated string"));
+        assert_eq!(tokens[0].error, Some(LexicalError::UnterminatedString));
+    }
+
+    #[test]
+    fn test_unterminated_string_recovery() {
+        let src = "x = \"oops;\ny = \"ok\";\nz = \"trailing \\\nw;";
+        let tokens = tokenize(src);
+        let errors: Vec<_> = tokens.iter().filter_map(|t| t.error.clone()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                LexicalError::UnterminatedString,
+                LexicalError::UnterminatedString
+            ]
+        );
+        // The error stops at the end of its line.
+        assert_eq!(tokens[2].lexeme, "\"oops;");
+        assert_eq!(tokens[3].lexeme, "y");
+        assert_eq!(tokens[5].literal, LiteralValue::String("ok".to_string()));
+        assert_eq!(tokens[10].lexeme, "w");
+        assert_eq!(tokens[11].token_type, TokenType::Semicolon);
     }
 
     #[test]
     fn test_unterminated_comment() {
-        let src = "/* comment never ends";
+        let src = "x /* comment /* never */ ends";
         let mut scanner = Scanner::new(src);
-        let token = scanner.next_token(); // should consume comment and return EOF
-        assert_eq!(token.token_type, TokenType::EndOfFile);
+        assert_eq!(scanner.next_token().token_type, TokenType::Identifier);
+        let token = scanner.next_token();
+        assert_eq!(token.token_type, TokenType::Error);
+        assert_eq!(token.error, Some(LexicalError::UnterminatedComment));
+        assert_eq!(token.span, Span::new(2, src.len()));
+        assert_eq!(scanner.next_token().token_type, TokenType::EndOfFile);
     }
 
     #[test]
@@ -223,7 +1337,121 @@ fn test_comments() {
         let tokens = tokenize(&long_id);
         assert_eq!(tokens.len(), 1);
         assert_eq!(tokens[0].token_type, TokenType::Error);
-        assert!(tokens[0].lexeme.contains("malformed number"));
+        assert_eq!(
+            tokens[0].error,
+            Some(LexicalError::IdentifierTooLong {
+                length: 300,
+                max: 255
+            })
+        );
+        assert_eq!(
+            tokens[0].to_string(),
+            "Error \"identifier is 300 characters long (maximum is 255)\""
+        );
+
+        let max_id = "b".repeat(255);
+        let tokens = tokenize(&max_id);
+        assert_eq!(tokens[0].token_type, TokenType::Identifier);
+    }
+
+    #[test]
+    fn test_options_builder() {
+        let options = ScannerOptions::new()
+            .with_trivia(true)
+            .with_keyword("def", TokenType::Fn)
+            .with_int_width(IntWidth::I64)
+            .with_max_identifier_length(None)
+            .with_tab_width(4);
+        assert_eq!(options.tab_width, 4);
+
+        let scanner = Scanner::with_options("def fn 3000000000 // c", options.clone());
+        assert_eq!(scanner.options(), &options);
+        let tokens: Vec<_> = scanner.collect();
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Fn,
+                TokenType::Fn,
+                TokenType::IntLiteral,
+                TokenType::EndOfFile,
+            ]
+        );
+        assert_eq!(tokens[2].trailing_trivia.len(), 2);
+    }
+
+    #[test]
+    fn test_significant_newlines() {
+        let src = "\n// header\nx = f(a,\n  b)\n\n  // note\n\ny = [1,\n2]";
+        let types = |mode: NewlineMode| -> Vec<TokenType> {
+            let options = ScannerOptions::new().with_newlines(mode);
+            Scanner::with_options(src, options)
+                .map(|t| t.token_type)
+                .collect()
+        };
+        let count =
+            |types: &[TokenType]| types.iter().filter(|&&t| t == TokenType::Newline).count();
+
+        assert_eq!(count(&types(NewlineMode::Skip)), 0);
+        // Leading and repeated line breaks collapse into one token each.
+        assert_eq!(count(&types(NewlineMode::Emit)), 3);
+
+        let outside = types(NewlineMode::EmitOutsideBrackets);
+        assert_eq!(
+            outside,
+            vec![
+                TokenType::Identifier,
+                TokenType::Equal,
+                TokenType::Identifier,
+                TokenType::LParen,
+                TokenType::Identifier,
+                TokenType::Comma,
+                TokenType::Identifier,
+                TokenType::RParen,
+                TokenType::Newline,
+                TokenType::Identifier,
+                TokenType::Equal,
+                TokenType::LBracket,
+                TokenType::IntLiteral,
+                TokenType::Comma,
+                TokenType::IntLiteral,
+                TokenType::RBracket,
+                TokenType::EndOfFile,
+            ]
+        );
+
+        // Newline tokens are part of the lossless stream.
+        let options = ScannerOptions::new()
+            .with_newlines(NewlineMode::Emit)
+            .with_trivia(true);
+        let tokens: Vec<_> = Scanner::with_options(src, options).collect();
+        assert_eq!(reconstruct(&tokens), src);
+    }
+
+    #[test]
+    fn test_configurable_identifier_length() {
+        let src = "abcd abcde";
+        let types =
+            |scanner: Scanner| -> Vec<TokenType> { scanner.map(|t| t.token_type).collect() };
+
+        let limited = Scanner::new(src).with_max_identifier_length(Some(4));
+        assert_eq!(
+            types(limited),
+            vec![
+                TokenType::Identifier,
+                TokenType::Error,
+                TokenType::EndOfFile
+            ]
+        );
+
+        let options = ScannerOptions {
+            max_identifier_length: None,
+            ..ScannerOptions::default()
+        };
+        let long_id = "a".repeat(10_000);
+        let tokens: Vec<_> = Scanner::with_options(&long_id, options).collect();
+        assert_eq!(tokens[0].token_type, TokenType::Identifier);
+        assert_eq!(tokens[0].lexeme.len(), 10_000);
     }
 
     #[test]
@@ -232,21 +1460,125 @@ fn test_comments() {
         let tokens = tokenize(src);
         assert_eq!(tokens.len(), 1);
         assert_eq!(tokens[0].token_type, TokenType::Error);
-        assert!(tokens[0].lexeme.contains("integer literal out of range"));
+        assert!(matches!(
+            tokens[0].error,
+            Some(LexicalError::IntegerOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_non_ascii_input() {
+        let src = "\"h\u{e9}llo \u{20ac}\" x \u{20ac} y";
+        let tokens = tokenize(src);
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(
+            tokens[0].literal,
+            LiteralValue::String("h\u{e9}llo \u{20ac}".to_string())
+        );
+        let file = SourceFile::new(src);
+        assert_eq!(tokens[1].lexeme, "x");
+        assert_eq!(file.line_col(tokens[1].span.start).column, 11);
+        assert_eq!(tokens[2].token_type, TokenType::Error);
+        assert_eq!(file.line_col(tokens[2].span.start).column, 13);
+        assert_eq!(tokens[3].lexeme, "y");
+        assert_eq!(file.line_col(tokens[3].span.start).column, 15);
+    }
+
+    #[test]
+    fn test_invalid_utf8_positions() {
+        // As decoded from b"x \xff\xfe \"a\xc0\" \u{fffd}": only the first three
+        // replacement characters stand for invalid bytes.
+        let src = "x \u{fffd}\u{fffd} \"a\u{fffd}\" \u{fffd}";
+        let invalid = vec![
+            InvalidUtf8 { offset: 2, byte: 2 },
+            InvalidUtf8 { offset: 5, byte: 3 },
+            InvalidUtf8 {
+                offset: 10,
+                byte: 6,
+            },
+        ];
+        let tokens: Vec<_> = Scanner::new(src).with_invalid_utf8(invalid).collect();
+        let errors: Vec<_> = tokens.iter().map(|t| t.error.clone()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                None,
+                Some(LexicalError::InvalidUtf8(2)),
+                Some(LexicalError::InvalidUtf8(3)),
+                Some(LexicalError::InvalidUtf8(6)),
+                Some(LexicalError::InvalidCharacter('\u{fffd}')),
+                None,
+            ]
+        );
+        assert_eq!(tokens[3].lexeme, "\"a\u{fffd}\"");
+    }
+
+    #[test]
+    fn test_scanner_iterator_ends_after_eof() {
+        let mut scanner = Scanner::new("x y");
+        let types: Vec<_> = scanner.by_ref().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::EndOfFile
+            ]
+        );
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let src = "caf\u{e9} = \u{53d8}\u{91cf} + x";
+        let file = SourceFile::new(src);
+        let tokens: Vec<_> = Scanner::new(src).with_unicode_identifiers(true).collect();
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Equal,
+                TokenType::Identifier,
+                TokenType::Plus,
+                TokenType::Identifier,
+                TokenType::EndOfFile,
+            ]
+        );
+        assert_eq!(tokens[0].lexeme, "caf\u{e9}");
+        assert_eq!(tokens[2].lexeme, "\u{53d8}\u{91cf}");
+        // Columns count characters, not bytes.
+        assert_eq!(file.line_col(tokens[3].span.start).column, 11);
+        assert_eq!(file.line_col(tokens[4].span.start).column, 13);
+
+        // Without the option, non-ASCII letters are invalid characters.
+        let tokens = tokenize("caf\u{e9}");
+        assert_eq!(tokens[0].lexeme, "caf");
+        assert_eq!(tokens[1].token_type, TokenType::Error);
     }
 
     #[test]
     fn test_position_tracking() {
         let src = "if x\n123";
+        let file = SourceFile::new(src);
         let mut scanner = Scanner::new(src);
         let tok1 = scanner.next_token();
-        assert_eq!(tok1.line, 1);
-        assert_eq!(tok1.column, 1);
+        assert_eq!(tok1.span, Span::new(0, 2));
+        assert_eq!(
+            file.line_col(tok1.span.start),
+            LineCol { line: 1, column: 1 }
+        );
         let tok2 = scanner.next_token();
-        assert_eq!(tok2.line, 1);
-        assert_eq!(tok2.column, 4);
+        assert_eq!(tok2.span, Span::new(3, 4));
+        assert_eq!(
+            file.line_col(tok2.span.start),
+            LineCol { line: 1, column: 4 }
+        );
         let tok3 = scanner.next_token();
-        assert_eq!(tok3.line, 2);
-        assert_eq!(tok3.column, 1);
+        assert_eq!(tok3.span, Span::new(5, 8));
+        assert_eq!(
+            file.line_col(tok3.span.start),
+            LineCol { line: 2, column: 1 }
+        );
     }
-}
\ No newline at end of file
+}
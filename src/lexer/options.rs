@@ -0,0 +1,163 @@
+use super::keywords::KeywordTable;
+use super::token::TokenType;
+use crate::source::ColumnEncoding;
+
+/// Range that integer literals without a type suffix are checked against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum IntWidth {
+    /// 32-bit signed, as the language specifies.
+    #[default]
+    I32,
+    /// 64-bit signed.
+    I64,
+    /// Anything that fits in 64 bits, signed or unsigned. Values above
+    /// `i64::MAX` keep their bits, as with a `u64` suffix.
+    Unchecked,
+}
+
+impl IntWidth {
+    /// Inclusive range of accepted values.
+    pub fn range(self) -> (i128, i128) {
+        match self {
+            IntWidth::I32 => (i32::MIN as i128, i32::MAX as i128),
+            IntWidth::I64 => (i64::MIN as i128, i64::MAX as i128),
+            IntWidth::Unchecked => (i64::MIN as i128, u64::MAX as i128),
+        }
+    }
+}
+
+/// Whether line breaks produce tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum NewlineMode {
+    /// Line breaks are whitespace, as the language specifies.
+    #[default]
+    Skip,
+    /// Line breaks are `Newline` tokens.
+    Emit,
+    /// Like `Emit`, but line breaks inside `( )` or `[ ]` are whitespace, so
+    /// long argument lists and indexes can span lines.
+    EmitOutsideBrackets,
+}
+
+/// Configuration for a [`Scanner`](super::Scanner).
+///
+/// The defaults describe the language as specified; each field relaxes or
+/// changes one rule for embedders and tools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannerOptions {
+    /// Accept Unicode XID_Start/XID_Continue characters in identifiers.
+    pub unicode_identifiers: bool,
+    /// Attach whitespace and comments to tokens instead of discarding them.
+    pub trivia: bool,
+    /// Longest identifier accepted, in characters; `None` means unlimited.
+    pub max_identifier_length: Option<usize>,
+    /// Reserved words to use instead of the language's own; `None` keeps the
+    /// built-in set.
+    pub keywords: Option<KeywordTable>,
+    /// Match keywords regardless of ASCII case (`IF`, `If` and `if` are all
+    /// `If`). Lexemes keep their original spelling.
+    pub case_insensitive_keywords: bool,
+    /// Range for integer literals without a suffix.
+    pub int_width: IntWidth,
+    /// Lex `-` directly before a number as part of the literal (`-5` is one
+    /// `IntLiteral`) unless the previous token ends an operand, as in `a -5`.
+    pub fold_negative_literals: bool,
+    /// Columns a tab advances to (the next multiple of this), for reporting
+    /// positions through [`SourceFile`](crate::source::SourceFile). Scanning
+    /// itself is not affected.
+    pub tab_width: usize,
+    /// Unit columns are counted in when reporting positions, e.g. UTF-16 code
+    /// units for editors speaking LSP. Like `tab_width`, only affects
+    /// reporting.
+    pub columns: ColumnEncoding,
+    /// Whether line breaks produce `Newline` tokens. A run of line breaks
+    /// (with blank lines or comments between them) produces one token, and
+    /// none are produced before the first token.
+    pub newlines: NewlineMode,
+}
+
+impl ScannerOptions {
+    /// Identifier length limit from the language specification.
+    pub const DEFAULT_MAX_IDENTIFIER_LENGTH: usize = 255;
+
+    /// The default options; start of a chain of `with_*` calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_unicode_identifiers(mut self, enabled: bool) -> Self {
+        self.unicode_identifiers = enabled;
+        self
+    }
+
+    pub fn with_trivia(mut self, enabled: bool) -> Self {
+        self.trivia = enabled;
+        self
+    }
+
+    pub fn with_max_identifier_length(mut self, max: Option<usize>) -> Self {
+        self.max_identifier_length = max;
+        self
+    }
+
+    pub fn with_keywords(mut self, keywords: KeywordTable) -> Self {
+        self.keywords = Some(keywords);
+        self
+    }
+
+    /// Adds or replaces one reserved word on top of the current keyword set
+    /// (the built-in one, unless [`Self::with_keywords`] was used).
+    pub fn with_keyword(mut self, word: impl Into<String>, token_type: TokenType) -> Self {
+        self.keywords
+            .get_or_insert_with(KeywordTable::standard)
+            .insert(word, token_type);
+        self
+    }
+
+    pub fn with_case_insensitive_keywords(mut self, enabled: bool) -> Self {
+        self.case_insensitive_keywords = enabled;
+        self
+    }
+
+    pub fn with_int_width(mut self, width: IntWidth) -> Self {
+        self.int_width = width;
+        self
+    }
+
+    pub fn with_fold_negative_literals(mut self, enabled: bool) -> Self {
+        self.fold_negative_literals = enabled;
+        self
+    }
+
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width;
+        self
+    }
+
+    pub fn with_columns(mut self, encoding: ColumnEncoding) -> Self {
+        self.columns = encoding;
+        self
+    }
+
+    pub fn with_newlines(mut self, mode: NewlineMode) -> Self {
+        self.newlines = mode;
+        self
+    }
+}
+
+impl Default for ScannerOptions {
+    fn default() -> Self {
+        Self {
+            unicode_identifiers: false,
+            trivia: false,
+            max_identifier_length: Some(Self::DEFAULT_MAX_IDENTIFIER_LENGTH),
+            keywords: None,
+            case_insensitive_keywords: false,
+            int_width: IntWidth::I32,
+            fold_negative_literals: false,
+            tab_width: 1,
+            columns: ColumnEncoding::Chars,
+            newlines: NewlineMode::Skip,
+        }
+    }
+}
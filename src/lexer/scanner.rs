@@ -1,96 +1,554 @@
 use super::error::LexicalError;
-use super::token::{LiteralValue, Token, TokenType};
-use std::collections::HashMap;
-use std::iter::Peekable;
-use std::str::Chars;
+use super::keywords::KeywordTable;
+use super::options::{IntWidth, NewlineMode, ScannerOptions};
+use super::token::{LiteralValue, NumberSuffix, Token, TokenType, Trivia, TriviaKind};
+use crate::input::InvalidUtf8;
+use crate::source::{Span, Spanned};
+use crate::utils::SymbolTable;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ops::Range;
 
+/// Scans tokens out of a source string.
+///
+/// The scanner works on the raw UTF-8 bytes of the source with direct
+/// indexing. ASCII characters (all operators, delimiters, digits and the
+/// common identifier characters) take a fast path; other characters are
+/// decoded on demand.
+///
+/// Tokens only record byte offsets; line and column numbers are computed
+/// from a [`SourceFile`](crate::source::SourceFile) when they are needed.
 pub struct Scanner<'a> {
     source: &'a str,
-    chars: Peekable<Chars<'a>>,
-    line: usize,
-    column: usize,
+    bytes: &'a [u8],
     start: usize,
     current: usize,
-    keywords: HashMap<&'static str, TokenType>,
+    /// Set once the iterator has yielded `EndOfFile`.
+    finished: bool,
+    options: ScannerOptions,
+    /// Type of the last token returned, for context-sensitive lexing.
+    prev: Option<TokenType>,
+    /// Number of unclosed `(` and `[` before the current position.
+    bracket_depth: usize,
+    /// Names of the identifiers scanned so far.
+    symbols: SymbolTable,
+    /// Every error reported so far, in source order.
+    errors: Vec<Spanned<LexicalError>>,
+    /// Tokens scanned ahead by `peek_nth`, each with the state before it.
+    lookahead: VecDeque<(Checkpoint, Token<'a>)>,
+    /// Replacement characters that stand for invalid UTF-8 in the file.
+    invalid_utf8: Vec<InvalidUtf8>,
 }
 
-struct ScannerState {
+/// A saved scanner position; see [`Scanner::checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
     start: usize,
     current: usize,
-    line: usize,
-    column: usize,
+    prev: Option<TokenType>,
+    bracket_depth: usize,
+    errors: usize,
+    finished: bool,
+}
+
+impl Checkpoint {
+    /// Byte offset of the next character to be scanned from here.
+    pub fn offset(&self) -> usize {
+        self.current
+    }
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
-        let mut keywords = HashMap::new();
-        keywords.insert("if", TokenType::If);
-        keywords.insert("else", TokenType::Else);
-        keywords.insert("while", TokenType::While);
-        keywords.insert("for", TokenType::For);
-        keywords.insert("int", TokenType::Int);
-        keywords.insert("float", TokenType::Float);
-        keywords.insert("bool", TokenType::Bool);
-        keywords.insert("return", TokenType::Return);
-        keywords.insert("true", TokenType::True);
-        keywords.insert("false", TokenType::False);
-        keywords.insert("void", TokenType::Void);
-        keywords.insert("struct", TokenType::Struct);
-        keywords.insert("fn", TokenType::Fn);
+        Self::with_options(source, ScannerOptions::default())
+    }
 
+    pub fn with_options(source: &'a str, options: ScannerOptions) -> Self {
         Self {
             source,
-            chars: source.chars().peekable(),
-            line: 1,
-            column: 1,
+            bytes: source.as_bytes(),
             start: 0,
             current: 0,
-            keywords,
+            finished: false,
+            options,
+            prev: None,
+            bracket_depth: 0,
+            symbols: SymbolTable::new(),
+            errors: Vec::new(),
+            lookahead: VecDeque::new(),
+            invalid_utf8: Vec::new(),
         }
     }
 
-    pub fn get_line(&self) -> usize {
-        self.line
+    /// The text being scanned.
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    pub fn options(&self) -> &ScannerOptions {
+        &self.options
+    }
+
+    /// Keeps whitespace and comments as trivia on the tokens around them.
+    ///
+    /// Trivia on the same line after a token (up to, but not including, the
+    /// newline) is that token's trailing trivia; everything else is leading
+    /// trivia of the next token. Trivia at the end of the file becomes the
+    /// leading trivia of `EndOfFile`, so no source text is lost.
+    pub fn with_trivia(mut self, enabled: bool) -> Self {
+        self.options.trivia = enabled;
+        self
+    }
+
+    /// Allows identifiers to contain non-ASCII letters (Unicode
+    /// XID_Start/XID_Continue, as in `café` or `变量`). Off by default, where
+    /// only ASCII letters, digits and `_` are accepted.
+    pub fn with_unicode_identifiers(mut self, enabled: bool) -> Self {
+        self.options.unicode_identifiers = enabled;
+        self
+    }
+
+    /// Replaces the built-in reserved words with `keywords`.
+    pub fn with_keywords(mut self, keywords: KeywordTable) -> Self {
+        self.options.keywords = Some(keywords);
+        self
+    }
+
+    /// Matches keywords regardless of ASCII case. A word that differs from a
+    /// keyword only in case can then no longer be used as an identifier.
+    pub fn with_case_insensitive_keywords(mut self, enabled: bool) -> Self {
+        self.options.case_insensitive_keywords = enabled;
+        self
+    }
+
+    /// Lexes a `-` directly followed by a digit as part of the number when
+    /// it cannot be binary subtraction, so `-2147483648` is a single in-range
+    /// literal. After an operand (`a -1`, `f() -1`) it stays an operator.
+    pub fn with_fold_negative_literals(mut self, enabled: bool) -> Self {
+        self.options.fold_negative_literals = enabled;
+        self
     }
 
-    pub fn get_column(&self) -> usize {
-        self.column
+    /// Sets the range integer literals without a suffix are checked against
+    /// (32-bit signed by default).
+    pub fn with_int_width(mut self, width: IntWidth) -> Self {
+        self.options.int_width = width;
+        self
     }
 
-    pub fn is_at_end(&mut self) -> bool {
-        self.chars.peek().is_none()
+    /// Sets the longest identifier accepted, in characters (255 by default).
+    /// `None` removes the limit.
+    pub fn with_max_identifier_length(mut self, max: Option<usize>) -> Self {
+        self.options.max_identifier_length = max;
+        self
     }
 
-    pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+    /// Interns identifiers into `symbols` instead of a fresh table, so that
+    /// several files (or an incremental re-lex) share one set of symbols.
+    pub fn with_symbol_table(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Reports the U+FFFD characters at these positions (from a lossily
+    /// decoded file, see [`SourceBuffer`](crate::input::SourceBuffer)) as
+    /// invalid UTF-8 rather than as stray characters.
+    pub fn with_invalid_utf8(mut self, invalid: Vec<InvalidUtf8>) -> Self {
+        self.invalid_utf8 = invalid;
+        self
+    }
+
+    /// Hands out `tokens`, scanned earlier, instead of scanning the text,
+    /// which then only supplies what lies between them (as the parser asks
+    /// when deciding whether a `;` is missing at the end of a line). Past
+    /// them the scanner is at the end of the text. A preprocessor's output
+    /// is parsed this way.
+    pub fn with_tokens(mut self, tokens: impl IntoIterator<Item = Token<'a>>) -> Self {
+        self.start = self.bytes.len();
+        self.current = self.bytes.len();
+        let state = self.raw_checkpoint();
+        self.lookahead = tokens.into_iter().map(|token| (state, token)).collect();
+        self
+    }
+
+    /// Symbols of the identifiers scanned so far.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Gives back the symbol table, e.g. to pass it on to the next file.
+    pub fn into_symbols(self) -> SymbolTable {
+        self.symbols
+    }
+
+    /// Byte offset of the next character to be scanned.
+    pub fn offset(&self) -> usize {
+        self.checkpoint().offset()
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.offset() >= self.bytes.len()
+    }
+
+    pub fn next_token(&mut self) -> Token<'a> {
+        match self.lookahead.pop_front() {
+            Some((_, token)) => token,
+            None => self.lex_token(),
+        }
+    }
+
+    /// The `n`th token after the current position (`peek_nth(0)` is the
+    /// next one), without consuming anything. Tokens scanned to answer are
+    /// buffered and handed out by `next_token` later, so each token is only
+    /// scanned once. Past the end of input this is `EndOfFile`.
+    pub fn peek_nth(&mut self, n: usize) -> &Token<'a> {
+        while self.lookahead.len() <= n {
+            let state = self.raw_checkpoint();
+            let token = self.lex_token();
+            self.lookahead.push_back((state, token));
+        }
+        &self.lookahead[n].1
+    }
+
+    /// Lexical errors found so far, in source order, each with the span of
+    /// the offending text. Errors are still returned as `Error` tokens too,
+    /// so a driver can either handle them in-stream or collect them here
+    /// once scanning is done.
+    pub fn errors(&self) -> &[Spanned<LexicalError>] {
+        &self.errors
+    }
+
+    /// Re-tokenizes a file after an edit, reusing the tokens the edit cannot
+    /// have changed.
+    ///
+    /// The scanner must be over the edited text, with the options
+    /// `old_tokens` were produced with. `old_tokens` is the complete stream
+    /// for the text before the edit (through `EndOfFile`), and `edit` is the
+    /// byte range of that text which was replaced by `new_text`.
+    ///
+    /// Identifier symbols of reused tokens are kept, so the scanner should
+    /// be given the symbol table of the earlier scan.
+    ///
+    /// Scanning restarts at the token before the edit and stops as soon as
+    /// it produces a token identical to an old one past the edit, in the
+    /// same state the old scan was in after it; from there on the old tokens
+    /// are reused with their spans shifted. The state matters where newlines
+    /// are only tokens outside brackets: after `(` is inserted, old `Newline`
+    /// tokens further on would otherwise be kept.
+    pub fn relex(
+        &mut self,
+        edit: Range<usize>,
+        new_text: &str,
+        old_tokens: &[Token<'_>],
+    ) -> Vec<Token<'a>> {
+        let delta = new_text.len() as isize - edit.len() as isize;
+        let edit_end = edit.start + new_text.len();
+
+        // The last token ending before the edit may still change (`ab` + `c`),
+        // so restart from the one before it, where the original scan was.
+        let touched = old_tokens
+            .iter()
+            .position(|t| t.span.end >= edit.start)
+            .unwrap_or(old_tokens.len());
+        let keep = touched.saturating_sub(1);
+        self.seek(old_tokens, keep);
+        let mut tokens: Vec<_> = old_tokens[..keep]
+            .iter()
+            .map(|t| self.reuse(t, 0))
+            .collect();
+
+        let mut old = old_tokens.iter().enumerate().skip(keep).peekable();
+        // Bracket depth of the old scan before the next old token.
+        let mut old_depth = self.bracket_depth;
+        loop {
+            let token = self.next_token();
+            if token.span.start >= edit_end {
+                // Skip old tokens that now lie before the new one.
+                let target = token.span.start.wrapping_add_signed(-delta);
+                while let Some((_, t)) = old.next_if(|(_, t)| t.span.start < target) {
+                    old_depth = bracket_depth(old_depth, t.token_type);
+                }
+                if let Some(&(i, t)) = old.peek() {
+                    // Equal tokens leave `prev` equal too.
+                    let same_state = bracket_depth(old_depth, t.token_type) == self.bracket_depth;
+                    if t.span.start >= edit.end && t.shifted(delta) == token && same_state {
+                        tokens.extend(old_tokens[i..].iter().map(|t| self.reuse(t, delta)));
+                        return tokens;
+                    }
+                }
+            }
+            let at_end = token.token_type == TokenType::EndOfFile;
+            tokens.push(token);
+            if at_end {
+                return tokens;
+            }
+        }
+    }
+
+    /// Moves to the start of `tokens[index]`, as if the tokens before it
+    /// had just been scanned. `tokens` must be the stream for this
+    /// scanner's text, such as the result of [`Self::relex`].
+    pub fn seek(&mut self, tokens: &[Token<'_>], index: usize) {
+        self.lookahead.clear();
+        self.current = tokens.get(index).map_or(0, |t| {
+            t.leading_trivia
+                .first()
+                .map_or(t.span.start, |tr| tr.span.start)
+        });
         self.start = self.current;
+        self.prev = index.checked_sub(1).map(|prev| tokens[prev].token_type);
+        self.bracket_depth = tokens[..index]
+            .iter()
+            .fold(0, |depth, t| bracket_depth(depth, t.token_type));
+        self.finished = false;
+    }
+
+    /// Splits the first `>` off the next token when it is `>>`, `>=` or
+    /// `>>=`, leaving `>` as the next token and the rest after it. A parser
+    /// uses this to close nested type arguments, as in `Pair<Pair<int>>`,
+    /// one `>` at a time.
+    pub fn split_greater(&mut self) {
+        let rest_type = match self.peek_nth(0).token_type {
+            TokenType::Shr => TokenType::Greater,
+            TokenType::GreaterEqual => TokenType::Equal,
+            TokenType::ShrEqual => TokenType::GreaterEqual,
+            _ => return,
+        };
+        let (state, token) = self.lookahead.pop_front().expect("peeked above");
+        let split = token.span.start + 1;
+        let mut first = Token::simple(TokenType::Greater, ">", Span::new(token.span.start, split));
+        first.leading_trivia = token.leading_trivia;
+        let mut rest = Token::simple(
+            rest_type,
+            match token.lexeme {
+                Cow::Borrowed(lexeme) => Cow::Borrowed(&lexeme[1..]),
+                Cow::Owned(lexeme) => Cow::Owned(lexeme[1..].to_string()),
+            },
+            Span::new(split, token.span.end),
+        );
+        rest.trailing_trivia = token.trailing_trivia;
+        let rest_state = Checkpoint {
+            start: split,
+            current: split,
+            prev: Some(TokenType::Greater),
+            ..state
+        };
+        self.lookahead.push_front((rest_state, rest));
+        self.lookahead.push_front((state, first));
+    }
+
+    pub fn peek_token(&mut self) -> Token<'a> {
+        self.peek_nth(0).clone()
+    }
+
+    /// Saves the current position, for backtracking with [`Self::rewind`].
+    ///
+    /// Tokens already peeked at are not part of the checkpoint: rewinding
+    /// to it yields them again, in the same order.
+    pub fn checkpoint(&self) -> Checkpoint {
+        match self.lookahead.front() {
+            Some(&(state, _)) => state,
+            None => self.raw_checkpoint(),
+        }
+    }
 
-        if self.is_at_end() {
+    /// Returns to a position saved by [`Self::checkpoint`] on this scanner.
+    /// Errors reported after the checkpoint are dropped, since they will be
+    /// reported again when the tokens are rescanned.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.lookahead.clear();
+        self.start = checkpoint.start;
+        self.current = checkpoint.current;
+        self.prev = checkpoint.prev;
+        self.bracket_depth = checkpoint.bracket_depth;
+        self.errors.truncate(checkpoint.errors);
+        self.finished = checkpoint.finished;
+    }
+
+    // -------------------------------------------------------------------------
+    // Private helpers
+    // -------------------------------------------------------------------------
+
+    fn raw_checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            start: self.start,
+            current: self.current,
+            prev: self.prev,
+            bracket_depth: self.bracket_depth,
+            errors: self.errors.len(),
+            finished: self.finished,
+        }
+    }
+
+    fn lex_token(&mut self) -> Token<'a> {
+        let leading_trivia = self.skip_trivia(self.newline_is_token());
+        self.start = self.current;
+        let mut token = self.scan_token();
+        if self.options.trivia {
+            token.leading_trivia = leading_trivia;
+            token.trailing_trivia = self.skip_trivia(true);
+        }
+        self.prev = Some(token.token_type);
+        self.bracket_depth = bracket_depth(self.bracket_depth, token.token_type);
+        token
+    }
+
+    /// Whether `c` begins a token or whitespace, i.e. is not a stray character.
+    fn can_start_token(&self, c: char) -> bool {
+        matches!(
+            c,
+            ' ' | '\t'
+                | '\r'
+                | '\n'
+                | '('
+                | ')'
+                | '{'
+                | '}'
+                | '['
+                | ']'
+                | ';'
+                | ','
+                | ':'
+                | '+'
+                | '-'
+                | '*'
+                | '/'
+                | '%'
+                | '='
+                | '!'
+                | '<'
+                | '>'
+                | '&'
+                | '|'
+                | '^'
+                | '~'
+                | '?'
+                | '.'
+                | '"'
+        ) || c.is_ascii_digit()
+            || is_identifier_start(c)
+            || (self.options.unicode_identifiers && unicode_ident::is_xid_start(c))
+    }
+
+    /// File offset of the invalid UTF-8 replaced by the character at
+    /// `offset`, if it was replaced.
+    fn invalid_utf8_at(&self, offset: usize) -> Option<usize> {
+        self.invalid_utf8
+            .binary_search_by_key(&offset, |invalid| invalid.offset)
+            .ok()
+            .map(|i| self.invalid_utf8[i].byte)
+    }
+
+    /// File offset of the first invalid UTF-8 replaced by a character in
+    /// `range`, if any was.
+    fn invalid_utf8_in(&self, range: Range<usize>) -> Option<usize> {
+        let i = self
+            .invalid_utf8
+            .partition_point(|invalid| invalid.offset < range.start);
+        self.invalid_utf8
+            .get(i)
+            .filter(|invalid| invalid.offset < range.end)
+            .map(|invalid| invalid.byte)
+    }
+
+    /// Whether a line break at the current position is a `Newline` token
+    /// rather than whitespace.
+    fn newline_is_token(&self) -> bool {
+        let after_token = !matches!(self.prev, None | Some(TokenType::Newline));
+        match self.options.newlines {
+            NewlineMode::Skip => false,
+            NewlineMode::Emit => after_token,
+            NewlineMode::EmitOutsideBrackets => after_token && self.bracket_depth == 0,
+        }
+    }
+
+    /// `token` from an earlier scan, moved by `delta` bytes and borrowing
+    /// from this scanner's source instead of the old one.
+    fn reuse(&self, token: &Token<'_>, delta: isize) -> Token<'a> {
+        let source = self.source;
+        let rebind = |text: &Cow<'_, str>, span: Span| -> Cow<'a, str> {
+            match text {
+                Cow::Borrowed(_) => Cow::Borrowed(&source[span.start..span.end]),
+                Cow::Owned(text) => Cow::Owned(text.clone()),
+            }
+        };
+        let trivia = |trivia: &[Trivia<'_>]| -> Vec<Trivia<'a>> {
+            trivia
+                .iter()
+                .map(|t| {
+                    let span = t.span.shift(delta);
+                    Trivia::new(t.kind, rebind(&t.text, span), span)
+                })
+                .collect()
+        };
+        let span = token.span.shift(delta);
+        Token {
+            token_type: token.token_type,
+            lexeme: rebind(&token.lexeme, span),
+            span,
+            literal: token.literal.clone(),
+            leading_trivia: trivia(&token.leading_trivia),
+            trailing_trivia: trivia(&token.trailing_trivia),
+            error: token.error.clone(),
+            suffix: token.suffix,
+            symbol: token.symbol,
+        }
+    }
+
+    /// Scans one token starting at `self.start`, which must not be trivia.
+    fn scan_token(&mut self) -> Token<'a> {
+        // Not `is_at_end`, which is where the consumer is: this may be
+        // scanning ahead of it for `peek_nth`.
+        if self.current >= self.bytes.len() {
             return self.make_token(TokenType::EndOfFile, LiteralValue::None);
         }
 
         let c = self.advance().unwrap();
 
         match c {
-            '(' => return self.simple_token(TokenType::LParen),
-            ')' => return self.simple_token(TokenType::RParen),
-            '{' => return self.simple_token(TokenType::LBrace),
-            '}' => return self.simple_token(TokenType::RBrace),
-            '[' => return self.simple_token(TokenType::LBracket),
-            ']' => return self.simple_token(TokenType::RBracket),
-            ';' => return self.simple_token(TokenType::Semicolon),
-            ',' => return self.simple_token(TokenType::Comma),
-            ':' => return self.simple_token(TokenType::Colon),
+            // Only reached when line breaks are significant.
+            '\n' => self.simple_token(TokenType::Newline),
+            '(' => self.simple_token(TokenType::LParen),
+            ')' => self.simple_token(TokenType::RParen),
+            '{' => self.simple_token(TokenType::LBrace),
+            '}' => self.simple_token(TokenType::RBrace),
+            '[' => self.simple_token(TokenType::LBracket),
+            ']' => self.simple_token(TokenType::RBracket),
+            // `#[` opens an attribute such as `#[inline]`; its contents are
+            // ordinary tokens, closed by `]`. A `#` on its own is invalid.
+            '#' if self.r#match('[') => self.simple_token(TokenType::HashBracket),
+            ';' => self.simple_token(TokenType::Semicolon),
+            ',' => self.simple_token(TokenType::Comma),
+            ':' => {
+                if self.r#match(':') {
+                    self.simple_token(TokenType::ColonColon)
+                } else {
+                    self.simple_token(TokenType::Colon)
+                }
+            }
             '+' => {
-                if self.r#match('=') {
+                if self.r#match('+') {
+                    self.simple_token(TokenType::PlusPlus)
+                } else if self.r#match('=') {
                     self.simple_token(TokenType::PlusEqual)
                 } else {
                     self.simple_token(TokenType::Plus)
                 }
             }
+            '-' if self.options.fold_negative_literals
+                && self.peek_byte().is_some_and(|b| b.is_ascii_digit())
+                && !self.prev.is_some_and(TokenType::ends_operand) =>
+            {
+                let digit = self.advance().unwrap();
+                self.number(digit)
+            }
             '-' => {
-                if self.r#match('=') {
+                if self.r#match('-') {
+                    self.simple_token(TokenType::MinusMinus)
+                } else if self.r#match('=') {
                     self.simple_token(TokenType::MinusEqual)
+                } else if self.r#match('>') {
+                    self.simple_token(TokenType::Arrow)
                 } else {
                     self.simple_token(TokenType::Minus)
                 }
@@ -103,23 +561,29 @@ impl<'a> Scanner<'a> {
                 }
             }
             '/' => {
-                if self.r#match('/') {
-                    self.single_line_comment();
-                    return self.next_token();
-                } else if self.r#match('*') {
-                    // Consume block comment; if unterminated, report error but continue.
-                    let _ = self.block_comment();
-                    return self.next_token();
-                } else if self.r#match('=') {
+                if self.r#match('=') {
                     self.simple_token(TokenType::SlashEqual)
+                } else if self.r#match('*') {
+                    // Terminated comments are trivia, so this `/*` never ends.
+                    // The error spans from the opening `/*` to the end of input.
+                    self.current = self.bytes.len();
+                    self.error_token(LexicalError::UnterminatedComment)
                 } else {
                     self.simple_token(TokenType::Slash)
                 }
             }
-            '%' => return self.simple_token(TokenType::Percent),
+            '%' => {
+                if self.r#match('=') {
+                    self.simple_token(TokenType::PercentEqual)
+                } else {
+                    self.simple_token(TokenType::Percent)
+                }
+            }
             '=' => {
                 if self.r#match('=') {
                     self.simple_token(TokenType::EqualEqual)
+                } else if self.r#match('>') {
+                    self.simple_token(TokenType::FatArrow)
                 } else {
                     self.simple_token(TokenType::Equal)
                 }
@@ -132,14 +596,26 @@ impl<'a> Scanner<'a> {
                 }
             }
             '<' => {
-                if self.r#match('=') {
+                if self.r#match('<') {
+                    if self.r#match('=') {
+                        self.simple_token(TokenType::ShlEqual)
+                    } else {
+                        self.simple_token(TokenType::Shl)
+                    }
+                } else if self.r#match('=') {
                     self.simple_token(TokenType::LessEqual)
                 } else {
                     self.simple_token(TokenType::Less)
                 }
             }
             '>' => {
-                if self.r#match('=') {
+                if self.r#match('>') {
+                    if self.r#match('=') {
+                        self.simple_token(TokenType::ShrEqual)
+                    } else {
+                        self.simple_token(TokenType::Shr)
+                    }
+                } else if self.r#match('=') {
                     self.simple_token(TokenType::GreaterEqual)
                 } else {
                     self.simple_token(TokenType::Greater)
@@ -148,46 +624,71 @@ impl<'a> Scanner<'a> {
             '&' => {
                 if self.r#match('&') {
                     self.simple_token(TokenType::AndAnd)
+                } else if self.r#match('=') {
+                    self.simple_token(TokenType::AmpEqual)
                 } else {
-                    self.error_token(LexicalError::InvalidCharacter('&'))
+                    self.simple_token(TokenType::Amp)
                 }
             }
             '|' => {
                 if self.r#match('|') {
                     self.simple_token(TokenType::OrOr)
+                } else if self.r#match('=') {
+                    self.simple_token(TokenType::PipeEqual)
+                } else {
+                    self.simple_token(TokenType::Pipe)
+                }
+            }
+            '^' => {
+                if self.r#match('=') {
+                    self.simple_token(TokenType::CaretEqual)
                 } else {
-                    self.error_token(LexicalError::InvalidCharacter('|'))
+                    self.simple_token(TokenType::Caret)
+                }
+            }
+            '~' => self.simple_token(TokenType::Tilde),
+            '?' => self.simple_token(TokenType::Question),
+            '.' => {
+                // `.5` is scanned as a (malformed) number so it gets a
+                // better error than a stray dot followed by an integer.
+                if self.peek_byte().is_some_and(|b| b.is_ascii_digit()) {
+                    self.number(c)
+                } else {
+                    self.simple_token(TokenType::Dot)
+                }
+            }
+            '"' => self.string(),
+            _ if c.is_ascii_digit() => self.number(c),
+            _ if is_identifier_start(c) => self.identifier(),
+            _ if self.options.unicode_identifiers && unicode_ident::is_xid_start(c) => {
+                self.identifier()
+            }
+            char::REPLACEMENT_CHARACTER if self.invalid_utf8_at(self.start).is_some() => {
+                let byte = self.invalid_utf8_at(self.start).unwrap_or_default();
+                self.error_token(LexicalError::InvalidUtf8(byte))
+            }
+            _ => {
+                // A run of stray characters (a pasted blob, an emoji sequence)
+                // is reported once rather than character by character.
+                while self.peek().is_some_and(|c| !self.can_start_token(c))
+                    && self.invalid_utf8_at(self.current).is_none()
+                {
+                    self.advance();
+                }
+                let text = &self.source[self.start..self.current];
+                if text.len() == c.len_utf8() {
+                    self.error_token(LexicalError::InvalidCharacter(c))
+                } else {
+                    self.error_token(LexicalError::InvalidCharacters(text.to_string()))
                 }
             }
-            '"' => return self.string(),
-            _ if c.is_ascii_digit() || c == '.' => return self.number(c),
-            _ if is_identifier_start(c) => return self.identifier(),
-            _ => self.error_token(LexicalError::InvalidCharacter(c)),
         }
     }
 
-    pub fn peek_token(&mut self) -> Token {
-        let snapshot = self.save();
-        let token = self.next_token();
-        self.restore(snapshot);
-        token
-    }
-
-    // -------------------------------------------------------------------------
-    // Private helpers
-    // -------------------------------------------------------------------------
-
     fn advance(&mut self) -> Option<char> {
-        let c = self.chars.next();
-        if let Some(ch) = c {
-            self.current += ch.len_utf8();
-            self.column += 1;
-            if ch == '\n' {
-                self.line += 1;
-                self.column = 1;
-            }
-        }
-        c
+        let ch = self.peek()?;
+        self.current += ch.len_utf8();
+        Some(ch)
     }
 
     fn r#match(&mut self, expected: char) -> bool {
@@ -199,132 +700,228 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn peek(&mut self) -> Option<char> {
-        self.chars.peek().copied()
+    fn peek(&self) -> Option<char> {
+        let &b = self.bytes.get(self.current)?;
+        if b.is_ascii() {
+            Some(b as char)
+        } else {
+            self.source[self.current..].chars().next()
+        }
     }
 
-    fn skip_whitespace(&mut self) {
-        while let Some(c) = self.peek() {
-            if c == ' ' || c == '\t' || c == '\r' || c == '\n' {
-                self.advance();
-            } else {
-                break;
+    fn peek_byte(&self) -> Option<u8> {
+        self.bytes.get(self.current).copied()
+    }
+
+    /// Advances over a byte that is known to be ASCII.
+    fn bump_ascii(&mut self) {
+        self.current += 1;
+    }
+
+    /// Skips whitespace and comments. In trivia mode the skipped pieces are
+    /// returned; otherwise the result is always empty. With
+    /// `stop_at_newline`, stops before the next newline.
+    fn skip_trivia(&mut self, stop_at_newline: bool) -> Vec<Trivia<'a>> {
+        let mut trivia = Vec::new();
+        loop {
+            let start = self.current;
+            let kind = match self.peek_byte() {
+                Some(b'\n') if stop_at_newline => break,
+                Some(b' ' | b'\t' | b'\r' | b'\n') => {
+                    while let Some(b) = self.peek_byte() {
+                        if !matches!(b, b' ' | b'\t' | b'\r' | b'\n')
+                            || (stop_at_newline && b == b'\n')
+                        {
+                            break;
+                        }
+                        self.bump_ascii();
+                    }
+                    TriviaKind::Whitespace
+                }
+                // A `#!` interpreter line is only recognised at the very start.
+                Some(b'#') if self.current == 0 && self.bytes.get(1) == Some(&b'!') => {
+                    self.single_line_comment();
+                    TriviaKind::Shebang
+                }
+                Some(b'/') if self.bytes.get(self.current + 1) == Some(&b'/') => {
+                    self.current += 2;
+                    self.single_line_comment();
+                    TriviaKind::LineComment
+                }
+                Some(b'/') if self.bytes.get(self.current + 1) == Some(&b'*') => {
+                    self.current += 2;
+                    if self.block_comment().is_err() {
+                        // Leave an unterminated comment for `scan_token` to
+                        // report as an error token.
+                        self.current = start;
+                        break;
+                    }
+                    TriviaKind::BlockComment
+                }
+                _ => break,
+            };
+            if self.options.trivia {
+                trivia.push(Trivia::new(
+                    kind,
+                    &self.source[start..self.current],
+                    Span::new(start, self.current),
+                ));
             }
         }
+        trivia
     }
 
     fn single_line_comment(&mut self) {
-        while let Some(c) = self.peek() {
-            if c == '\n' {
-                break;
-            }
-            self.advance();
+        self.current = self.bytes[self.current..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(self.bytes.len(), |i| self.current + i);
+    }
+
+    fn skip_digits(&mut self) {
+        while self.peek_byte().is_some_and(|b| b.is_ascii_digit()) {
+            self.bump_ascii();
         }
     }
 
     fn block_comment(&mut self) -> Result<(), LexicalError> {
+        // `/` and `*` are ASCII, so they never occur inside a multi-byte
+        // character and the comment can be walked byte by byte.
         let mut nesting = 1;
         while nesting > 0 {
-            match self.peek() {
-                None => return Err(LexicalError::UnterminatedComment),
-                Some('/') => {
-                    self.advance();
-                    if self.peek() == Some('*') {
-                        self.advance();
-                        nesting += 1;
-                    }
+            let next = self.bytes.get(self.current + 1).copied();
+            match (self.peek_byte(), next) {
+                (None, _) => return Err(LexicalError::UnterminatedComment),
+                (Some(b'/'), Some(b'*')) => {
+                    self.current += 2;
+                    nesting += 1;
                 }
-                Some('*') => {
-                    self.advance();
-                    if self.peek() == Some('/') {
-                        self.advance();
-                        nesting -= 1;
-                    }
-                }
-                Some(_) => {
-                    self.advance();
+                (Some(b'*'), Some(b'/')) => {
+                    self.current += 2;
+                    nesting -= 1;
                 }
+                _ => self.bump_ascii(),
             }
         }
         Ok(())
     }
 
-    fn string(&mut self) -> Token {
+    fn string(&mut self) -> Token<'a> {
         let mut value = String::new();
-        let start_line = self.line;
-        let start_column = self.column - 1;
+        // The first bad escape (or invalid UTF-8) is reported once the closing
+        // quote is found, so the rest of the literal is still consumed as part
+        // of the same token.
+        let mut error = None;
 
         while let Some(c) = self.peek() {
-            if c == '"' {
-                self.advance();
-                return Token::new(
-                    TokenType::StringLiteral,
-                    &self.source[self.start..self.current],
-                    start_line,
-                    start_column,
-                    LiteralValue::String(value),
-                );
-            }
-            if c == '\n' {
-                break;
+            match c {
+                '"' => {
+                    self.advance();
+                    if let Some(error) = error {
+                        return self.error_token(error);
+                    }
+                    return Token::new(
+                        TokenType::StringLiteral,
+                        &self.source[self.start..self.current],
+                        self.span(),
+                        LiteralValue::String(value),
+                    );
+                }
+                // Strings cannot span lines. The line break is left for the
+                // next token, so lexing resumes cleanly on the next line.
+                '\n' => break,
+                '\\' => {
+                    self.advance();
+                    match self.peek() {
+                        None | Some('\n') => break,
+                        Some(escape) => {
+                            self.advance();
+                            match unescape(escape) {
+                                Some(ch) => value.push(ch),
+                                None => {
+                                    error.get_or_insert(LexicalError::InvalidEscape(escape));
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    // Copy the run of characters up to the next one that
+                    // needs a look, as one piece.
+                    let end = self.bytes[self.current..]
+                        .iter()
+                        .position(|&b| matches!(b, b'"' | b'\n' | b'\\'))
+                        .map_or(self.bytes.len(), |i| self.current + i);
+                    if error.is_none() {
+                        error = self
+                            .invalid_utf8_in(self.current..end)
+                            .map(LexicalError::InvalidUtf8);
+                    }
+                    value.push_str(&self.source[self.current..end]);
+                    self.current = end;
+                }
             }
-            let ch = self.advance().unwrap();
-            value.push(ch);
         }
 
         self.error_token(LexicalError::UnterminatedString)
     }
 
-    fn number(&mut self, first_char: char) -> Token {
-        let start_line = self.line;
-        let start_column = self.column - 1; // column of the first character
-    
+    fn number(&mut self, first_char: char) -> Token<'a> {
+        if first_char == '0' {
+            match self.peek_byte() {
+                Some(b'x' | b'X') => return self.prefixed_integer(16),
+                Some(b'o' | b'O') => return self.prefixed_integer(8),
+                Some(b'b' | b'B') => return self.prefixed_integer(2),
+                _ => {}
+            }
+        }
+
         let mut has_int_part = false;
         let mut has_frac_part = false;
-    
+
         // ----- integer part (only if first character is a digit) -----
         if first_char.is_ascii_digit() {
             has_int_part = true;
-            while let Some(c) = self.peek() {
-                if c.is_ascii_digit() {
-                    self.advance();
-                } else {
-                    break;
-                }
-            }
+            self.skip_digits();
         }
-    
+
         // ----- fractional part -----
         // If the number starts with '.', the dot is already consumed.
         // Otherwise, look for a dot after the integer part.
         if first_char == '.' {
             // dot already consumed, now must be followed by a digit
-            if self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            if self.peek_byte().is_some_and(|b| b.is_ascii_digit()) {
                 has_frac_part = true;
-                while let Some(c) = self.peek() {
-                    if c.is_ascii_digit() {
-                        self.advance();
-                    } else {
-                        break;
-                    }
-                }
+                self.skip_digits();
             }
-        } else if self.peek() == Some('.') {
-            self.advance(); // consume the dot
-            if self.peek().map_or(false, |c| c.is_ascii_digit()) {
+        } else if self.peek_byte() == Some(b'.') && !self.dot_starts_member_access() {
+            self.bump_ascii(); // consume the dot
+            if self.peek_byte().is_some_and(|b| b.is_ascii_digit()) {
                 has_frac_part = true;
-                while let Some(c) = self.peek() {
-                    if c.is_ascii_digit() {
-                        self.advance();
-                    } else {
-                        break;
-                    }
-                }
+                self.skip_digits();
             }
         }
-    
-        let lexeme = &self.source[self.start..self.current];
-    
+
+        // ----- exponent -----
+        // 'e' or 'E', an optional sign, then at least one digit.
+        let mut has_exponent = false;
+        if let Some(b'e' | b'E') = self.peek_byte() {
+            self.bump_ascii();
+            if let Some(b'+' | b'-') = self.peek_byte() {
+                self.bump_ascii();
+            }
+            if !self.peek_byte().is_some_and(|b| b.is_ascii_digit()) {
+                let lexeme = &self.source[self.start..self.current];
+                return self.error_token(LexicalError::DanglingExponent(lexeme.to_string()));
+            }
+            has_exponent = true;
+            self.skip_digits();
+        }
+
+        let digits_end = self.current;
+        let suffix = self.number_suffix(true);
+        let lexeme = &self.source[self.start..digits_end];
+
         // ----- validation rules -----
         // 1. Leading dot without any digit after it? Should never happen because
         //    we only enter this branch when a digit follows.
@@ -339,45 +936,132 @@ impl<'a> Scanner<'a> {
         if !has_frac_part && lexeme.contains('.') {
             return self.error_token(LexicalError::MalformedNumber(lexeme.to_string()));
         }
-    
+
         // ----- parse and create token -----
-        if lexeme.contains('.') {
-            match lexeme.parse::<f64>() {
-                Ok(val) => Token::new(
-                    TokenType::FloatLiteral,
-                    lexeme,
-                    start_line,
-                    start_column,
-                    LiteralValue::Float(val),
-                ),
-                Err(_) => self.error_token(LexicalError::MalformedNumber(lexeme.to_string())),
+        let is_float = lexeme.contains('.') || has_exponent;
+        if is_float || suffix.is_some_and(NumberSuffix::is_float) {
+            let full_lexeme = &self.source[self.start..self.current];
+            if suffix.is_some_and(|s| !s.is_float()) {
+                // An integer suffix on a float literal (`1.5i32`).
+                return self.error_token(LexicalError::MalformedNumber(full_lexeme.to_string()));
             }
-        } else {
-            match lexeme.parse::<i64>() {
+            match lexeme.parse::<f64>() {
                 Ok(val) => {
-                    if val < i32::MIN as i64 || val > i32::MAX as i64 {
-                        self.error_token(LexicalError::IntegerOutOfRange(lexeme.to_string()))
-                    } else {
-                        Token::new(
-                            TokenType::IntLiteral,
-                            lexeme,
-                            start_line,
-                            start_column,
-                            LiteralValue::Integer(val),
-                        )
-                    }
+                    let val = match suffix {
+                        Some(NumberSuffix::F32) => val as f32 as f64,
+                        _ => val,
+                    };
+                    let mut token = Token::new(
+                        TokenType::FloatLiteral,
+                        full_lexeme,
+                        self.span(),
+                        LiteralValue::Float(val),
+                    );
+                    token.suffix = suffix;
+                    token
                 }
-                Err(_) => self.error_token(LexicalError::MalformedNumber(lexeme.to_string())),
+                Err(_) => self.error_token(LexicalError::MalformedNumber(full_lexeme.to_string())),
             }
+        } else {
+            // Only digits remain, so parsing can fail on overflow alone.
+            let val = lexeme.parse::<i128>().unwrap_or(i128::MAX);
+            self.integer_token(val, suffix)
         }
     }
 
-    fn identifier(&mut self) -> Token {
-        let start_line = self.line;
-        let start_column = self.column - 1;
+    /// Whether the `.` at the current position belongs to a member access
+    /// (`1.foo`) rather than to the number before it (`1.0`).
+    fn dot_starts_member_access(&self) -> bool {
+        self.bytes
+            .get(self.current + 1)
+            .is_some_and(|&b| b.is_ascii() && is_identifier_start(b as char))
+    }
 
-        while let Some(c) = self.peek() {
-            if is_identifier_continue(c) {
+    /// Scans the rest of a `0x`, `0o` or `0b` literal; the leading `0` is
+    /// already consumed.
+    fn prefixed_integer(&mut self, radix: u32) -> Token<'a> {
+        self.bump_ascii(); // consume the radix letter
+        let digits_start = self.current;
+        while self
+            .peek_byte()
+            .is_some_and(|b| (b as char).is_digit(radix))
+        {
+            self.bump_ascii();
+        }
+        let digits = &self.source[digits_start..self.current];
+        let suffix = self.number_suffix(false);
+
+        // Letters or digits glued onto the literal (`0xFG`, `0b102`) make the
+        // whole literal malformed instead of starting a new token.
+        let mut malformed = digits.is_empty();
+        while self
+            .peek_byte()
+            .is_some_and(|b| b.is_ascii() && is_identifier_continue(b as char))
+        {
+            self.bump_ascii();
+            malformed = true;
+        }
+
+        let lexeme = &self.source[self.start..self.current];
+        if malformed {
+            let lexeme = lexeme.to_string();
+            return self.error_token(match radix {
+                16 => LexicalError::MalformedHexLiteral(lexeme),
+                8 => LexicalError::MalformedOctalLiteral(lexeme),
+                _ => LexicalError::MalformedBinaryLiteral(lexeme),
+            });
+        }
+        let mut val = i128::from_str_radix(digits, radix).unwrap_or(i128::MAX);
+        if self.bytes[self.start] == b'-' {
+            val = -val;
+        }
+        self.integer_token(val, suffix)
+    }
+
+    /// Consumes a type suffix glued onto a number (`42u8`, `1.5f`). Anything
+    /// that is not a known suffix is left for the next token.
+    fn number_suffix(&mut self, allow_float: bool) -> Option<NumberSuffix> {
+        let len = self.bytes[self.current..]
+            .iter()
+            .take_while(|&&b| b.is_ascii_alphanumeric() || b == b'_')
+            .count();
+        let suffix = NumberSuffix::parse(&self.source[self.current..self.current + len])
+            .filter(|s| allow_float || !s.is_float())?;
+        self.current += len;
+        Some(suffix)
+    }
+
+    /// Builds an integer token for the whole lexeme, checking `val` against
+    /// the suffix's range, or the configured width without a suffix.
+    fn integer_token(&mut self, val: i128, suffix: Option<NumberSuffix>) -> Token<'a> {
+        let lexeme = &self.source[self.start..self.current];
+        let (min, max) = suffix
+            .and_then(NumberSuffix::integer_range)
+            .unwrap_or_else(|| self.options.int_width.range());
+        if val < min || val > max {
+            return self.error_token(LexicalError::IntegerOutOfRange(lexeme.to_string()));
+        }
+        let mut token = Token::new(
+            TokenType::IntLiteral,
+            lexeme,
+            self.span(),
+            // Values above `i64::MAX` (`u64`, unchecked) keep their bits.
+            LiteralValue::Integer(val as i64),
+        );
+        token.suffix = suffix;
+        token
+    }
+
+    fn identifier(&mut self) -> Token<'a> {
+        while let Some(b) = self.peek_byte() {
+            if b.is_ascii() {
+                if !is_identifier_continue(b as char) {
+                    break;
+                }
+                self.bump_ascii();
+            } else if self.options.unicode_identifiers
+                && self.peek().is_some_and(unicode_ident::is_xid_continue)
+            {
                 self.advance();
             } else {
                 break;
@@ -385,70 +1069,116 @@ impl<'a> Scanner<'a> {
         }
 
         let lexeme = &self.source[self.start..self.current];
-        if let Some(&token_type) = self.keywords.get(lexeme) {
+        let keyword = self.keyword(lexeme).or_else(|| {
+            let folded = self.options.case_insensitive_keywords
+                && lexeme.bytes().any(|b| b.is_ascii_uppercase());
+            folded
+                .then(|| self.keyword(&lexeme.to_ascii_lowercase()))
+                .flatten()
+        });
+        if let Some(token_type) = keyword.filter(|&t| t != TokenType::Identifier) {
             let literal = match token_type {
                 TokenType::True => LiteralValue::Boolean(true),
                 TokenType::False => LiteralValue::Boolean(false),
+                TokenType::Null => LiteralValue::Null,
                 _ => LiteralValue::None,
             };
-            Token::new(token_type, lexeme, start_line, start_column, literal)
+            Token::new(token_type, lexeme, self.span(), literal)
         } else {
-            if lexeme.len() > 255 {
-                self.error_token(LexicalError::MalformedNumber(lexeme.to_string()))
-            } else {
-                Token::new(
-                    TokenType::Identifier,
-                    lexeme,
-                    start_line,
-                    start_column,
-                    LiteralValue::None,
-                )
+            // The byte length bounds the character count, so most identifiers
+            // are accepted without counting characters.
+            match self.options.max_identifier_length {
+                Some(max) if lexeme.len() > max && lexeme.chars().count() > max => self
+                    .error_token(LexicalError::IdentifierTooLong {
+                        length: lexeme.chars().count(),
+                        max,
+                    }),
+                _ => {
+                    let mut token = Token::new(
+                        TokenType::Identifier,
+                        lexeme,
+                        self.span(),
+                        LiteralValue::None,
+                    );
+                    token.symbol = Some(self.symbols.intern(lexeme));
+                    token
+                }
             }
         }
     }
 
-    fn simple_token(&self, token_type: TokenType) -> Token {
+    fn keyword(&self, word: &str) -> Option<TokenType> {
+        match &self.options.keywords {
+            Some(table) => table.get(word),
+            None => TokenType::keyword(word),
+        }
+    }
+
+    fn simple_token(&self, token_type: TokenType) -> Token<'a> {
         Token::simple(
             token_type,
             &self.source[self.start..self.current],
-            self.line,
-            self.column - (self.current - self.start),
+            self.span(),
         )
     }
 
-    fn make_token(&self, token_type: TokenType, literal: LiteralValue) -> Token {
+    fn make_token(&self, token_type: TokenType, literal: LiteralValue) -> Token<'a> {
         Token::new(
             token_type,
             &self.source[self.start..self.current],
-            self.line,
-            self.column - (self.current - self.start),
+            self.span(),
             literal,
         )
     }
 
-    fn error_token(&self, err: LexicalError) -> Token {
-        Token::error(
-            format!("{}", err),
-            self.line,
-            self.column - (self.current - self.start),
-        )
+    fn error_token(&mut self, err: LexicalError) -> Token<'a> {
+        self.errors.push(Spanned::new(err.clone(), self.span()));
+        Token::error(err, &self.source[self.start..self.current], self.span())
     }
 
-    fn save(&self) -> ScannerState {
-        ScannerState {
-            start: self.start,
-            current: self.current,
-            line: self.line,
-            column: self.column,
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current)
+    }
+}
+
+/// Bracket nesting after a token of type `token_type`.
+fn bracket_depth(depth: usize, token_type: TokenType) -> usize {
+    match token_type {
+        TokenType::LParen | TokenType::LBracket | TokenType::HashBracket => depth + 1,
+        TokenType::RParen | TokenType::RBracket => depth.saturating_sub(1),
+        _ => depth,
+    }
+}
+
+/// Streams tokens on demand, ending with (and including) `EndOfFile`.
+///
+/// Consumers can pull tokens one at a time instead of materializing the whole
+/// token vector up front.
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.finished {
+            return None;
         }
+        let token = self.next_token();
+        self.finished = token.token_type == TokenType::EndOfFile;
+        Some(token)
     }
+}
+
+impl std::iter::FusedIterator for Scanner<'_> {}
 
-    fn restore(&mut self, state: ScannerState) {
-        self.start = state.start;
-        self.current = state.current;
-        self.line = state.line;
-        self.column = state.column;
-        self.chars = self.source[self.current..].chars().peekable();
+/// Character denoted by the escape sequence `\<c>`, if it is one.
+fn unescape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        '"' => Some('"'),
+        _ => None,
     }
 }
 
@@ -458,4 +1188,4 @@ fn is_identifier_start(c: char) -> bool {
 
 fn is_identifier_continue(c: char) -> bool {
     c.is_ascii_alphanumeric() || c == '_'
-}
\ No newline at end of file
+}
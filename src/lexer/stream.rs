@@ -0,0 +1,103 @@
+use super::error::UnexpectedToken;
+use super::scanner::Scanner;
+use super::token::{Token, TokenType};
+use crate::source::Span;
+
+/// A parser-facing view of a [`Scanner`]: tokens are pulled lazily, can be
+/// peeked at, and are consumed with `next_token`, `eat` or `expect`.
+pub struct TokenStream<'a> {
+    scanner: Scanner<'a>,
+    prev_span: Span,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(scanner: Scanner<'a>) -> Self {
+        Self {
+            scanner,
+            prev_span: Span::default(),
+        }
+    }
+
+    /// The next token, without consuming it.
+    pub fn peek(&mut self) -> &Token<'a> {
+        self.scanner.peek_nth(0)
+    }
+
+    /// The `n`th token ahead, without consuming anything.
+    pub fn peek_nth(&mut self, n: usize) -> &Token<'a> {
+        self.scanner.peek_nth(n)
+    }
+
+    /// Consumes the next token. At the end of input this keeps returning
+    /// `EndOfFile`.
+    pub fn next_token(&mut self) -> Token<'a> {
+        let token = self.scanner.next_token();
+        self.prev_span = token.span;
+        token
+    }
+
+    /// Whether the next token has type `token_type`.
+    pub fn check(&mut self, token_type: TokenType) -> bool {
+        self.peek().token_type == token_type
+    }
+
+    /// Consumes the next token if it has type `token_type`.
+    pub fn eat(&mut self, token_type: TokenType) -> bool {
+        let matched = self.check(token_type);
+        if matched {
+            self.next_token();
+        }
+        matched
+    }
+
+    /// Consumes the next token if it has type `token_type`, and reports it
+    /// otherwise. A mismatched token is left in the stream.
+    pub fn expect(&mut self, token_type: TokenType) -> Result<Token<'a>, UnexpectedToken> {
+        if self.check(token_type) {
+            return Ok(self.next_token());
+        }
+        let found = self.peek();
+        Err(UnexpectedToken {
+            expected: token_type,
+            found: found.token_type,
+            span: found.span,
+        })
+    }
+
+    /// Splits a leading `>` off a `>>`, `>=` or `>>=` next token; see
+    /// [`Scanner::split_greater`].
+    pub fn split_greater(&mut self) {
+        self.scanner.split_greater();
+    }
+
+    /// Moves to the start of `tokens[index]`; see [`Scanner::seek`].
+    pub fn seek(&mut self, tokens: &[Token<'_>], index: usize) {
+        self.scanner.seek(tokens, index);
+        self.prev_span = index
+            .checked_sub(1)
+            .map_or(Span::default(), |prev| tokens[prev].span);
+    }
+
+    pub fn is_at_end(&mut self) -> bool {
+        self.check(TokenType::EndOfFile)
+    }
+
+    /// Span of the last consumed token; empty at offset 0 before the first.
+    pub fn prev_span(&self) -> Span {
+        self.prev_span
+    }
+
+    pub fn scanner(&self) -> &Scanner<'a> {
+        &self.scanner
+    }
+
+    pub fn into_scanner(self) -> Scanner<'a> {
+        self.scanner
+    }
+}
+
+impl<'a> From<Scanner<'a>> for TokenStream<'a> {
+    fn from(scanner: Scanner<'a>) -> Self {
+        Self::new(scanner)
+    }
+}
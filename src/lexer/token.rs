@@ -1,19 +1,239 @@
+use super::error::LexicalError;
+use crate::source::Span;
+use crate::utils::Symbol;
+use std::borrow::Cow;
 use std::fmt;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokenType {
     // Keywords
-    If, Else, While, For, Int, Float, Bool, Return, True, False, Void, Struct, Fn,
+    If,
+    Else,
+    While,
+    For,
+    Int,
+    Float,
+    Bool,
+    Return,
+    True,
+    False,
+    Void,
+    Struct,
+    Fn,
+    Let,
+    Const,
+    Break,
+    Continue,
+    Char,
+    String,
+    Null,
+    Import,
+    Module,
+    Enum,
+    Match,
     // Literals
-    Identifier, IntLiteral, FloatLiteral, StringLiteral, BoolLiteral,
+    Identifier,
+    IntLiteral,
+    FloatLiteral,
+    StringLiteral,
+    BoolLiteral,
     // Operators
-    Plus, Minus, Star, Slash, Percent, Equal, EqualEqual, NotEqual,
-    Less, LessEqual, Greater, GreaterEqual, AndAnd, OrOr, Bang,
-    PlusEqual, MinusEqual, StarEqual, SlashEqual,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Equal,
+    EqualEqual,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AndAnd,
+    OrOr,
+    Bang,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    PlusPlus,
+    MinusMinus,
+    Amp,
+    Pipe,
+    Caret,
+    Tilde,
+    Shl,
+    Shr,
+    AmpEqual,
+    PipeEqual,
+    CaretEqual,
+    ShlEqual,
+    ShrEqual,
+    Question,
     // Delimiters
-    LParen, RParen, LBrace, RBrace, LBracket, RBracket, Semicolon, Comma, Colon,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Semicolon,
+    Comma,
+    Colon,
+    Dot,
+    ColonColon,
+    HashBracket,
+    Arrow,
+    FatArrow,
     // Special
-    EndOfFile, Error,
+    Newline,
+    EndOfFile,
+    Error,
+}
+
+impl TokenType {
+    /// Looks up a reserved word. The match compiles to a comparison on the
+    /// length followed by the bytes, so no table is built at runtime.
+    pub fn keyword(lexeme: &str) -> Option<TokenType> {
+        let token_type = match lexeme.as_bytes() {
+            b"if" => TokenType::If,
+            b"else" => TokenType::Else,
+            b"while" => TokenType::While,
+            b"for" => TokenType::For,
+            b"int" => TokenType::Int,
+            b"float" => TokenType::Float,
+            b"bool" => TokenType::Bool,
+            b"return" => TokenType::Return,
+            b"true" => TokenType::True,
+            b"false" => TokenType::False,
+            b"void" => TokenType::Void,
+            b"struct" => TokenType::Struct,
+            b"fn" => TokenType::Fn,
+            b"let" => TokenType::Let,
+            b"const" => TokenType::Const,
+            b"break" => TokenType::Break,
+            b"continue" => TokenType::Continue,
+            b"char" => TokenType::Char,
+            b"string" => TokenType::String,
+            b"null" => TokenType::Null,
+            b"import" => TokenType::Import,
+            b"module" => TokenType::Module,
+            b"enum" => TokenType::Enum,
+            b"match" => TokenType::Match,
+            _ => return None,
+        };
+        Some(token_type)
+    }
+
+    /// How the token type is named in messages: the spelling in quotes for
+    /// keywords and punctuation (`'{'`), a description for the rest
+    /// (`identifier`).
+    pub fn description(self) -> &'static str {
+        match self {
+            TokenType::If => "'if'",
+            TokenType::Else => "'else'",
+            TokenType::While => "'while'",
+            TokenType::For => "'for'",
+            TokenType::Int => "'int'",
+            TokenType::Float => "'float'",
+            TokenType::Bool => "'bool'",
+            TokenType::Return => "'return'",
+            TokenType::True => "'true'",
+            TokenType::False => "'false'",
+            TokenType::Void => "'void'",
+            TokenType::Struct => "'struct'",
+            TokenType::Fn => "'fn'",
+            TokenType::Let => "'let'",
+            TokenType::Const => "'const'",
+            TokenType::Break => "'break'",
+            TokenType::Continue => "'continue'",
+            TokenType::Char => "'char'",
+            TokenType::String => "'string'",
+            TokenType::Null => "'null'",
+            TokenType::Import => "'import'",
+            TokenType::Module => "'module'",
+            TokenType::Enum => "'enum'",
+            TokenType::Match => "'match'",
+            TokenType::Identifier => "identifier",
+            TokenType::IntLiteral => "integer literal",
+            TokenType::FloatLiteral => "float literal",
+            TokenType::StringLiteral => "string literal",
+            TokenType::BoolLiteral => "boolean literal",
+            TokenType::Plus => "'+'",
+            TokenType::Minus => "'-'",
+            TokenType::Star => "'*'",
+            TokenType::Slash => "'/'",
+            TokenType::Percent => "'%'",
+            TokenType::Equal => "'='",
+            TokenType::EqualEqual => "'=='",
+            TokenType::NotEqual => "'!='",
+            TokenType::Less => "'<'",
+            TokenType::LessEqual => "'<='",
+            TokenType::Greater => "'>'",
+            TokenType::GreaterEqual => "'>='",
+            TokenType::AndAnd => "'&&'",
+            TokenType::OrOr => "'||'",
+            TokenType::Bang => "'!'",
+            TokenType::PlusEqual => "'+='",
+            TokenType::MinusEqual => "'-='",
+            TokenType::StarEqual => "'*='",
+            TokenType::SlashEqual => "'/='",
+            TokenType::PercentEqual => "'%='",
+            TokenType::PlusPlus => "'++'",
+            TokenType::MinusMinus => "'--'",
+            TokenType::Amp => "'&'",
+            TokenType::Pipe => "'|'",
+            TokenType::Caret => "'^'",
+            TokenType::Tilde => "'~'",
+            TokenType::Shl => "'<<'",
+            TokenType::Shr => "'>>'",
+            TokenType::AmpEqual => "'&='",
+            TokenType::PipeEqual => "'|='",
+            TokenType::CaretEqual => "'^='",
+            TokenType::ShlEqual => "'<<='",
+            TokenType::ShrEqual => "'>>='",
+            TokenType::Question => "'?'",
+            TokenType::LParen => "'('",
+            TokenType::RParen => "')'",
+            TokenType::LBrace => "'{'",
+            TokenType::RBrace => "'}'",
+            TokenType::LBracket => "'['",
+            TokenType::RBracket => "']'",
+            TokenType::Semicolon => "';'",
+            TokenType::Comma => "','",
+            TokenType::Colon => "':'",
+            TokenType::Dot => "'.'",
+            TokenType::ColonColon => "'::'",
+            TokenType::HashBracket => "'#['",
+            TokenType::Arrow => "'->'",
+            TokenType::FatArrow => "'=>'",
+            TokenType::Newline => "newline",
+            TokenType::EndOfFile => "end of file",
+            TokenType::Error => "invalid token",
+        }
+    }
+
+    /// Whether a token of this type can end an operand, so that a `-` after
+    /// it must be binary subtraction.
+    pub fn ends_operand(self) -> bool {
+        matches!(
+            self,
+            TokenType::Identifier
+                | TokenType::IntLiteral
+                | TokenType::FloatLiteral
+                | TokenType::StringLiteral
+                | TokenType::BoolLiteral
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Null
+                | TokenType::RParen
+                | TokenType::RBracket
+                | TokenType::PlusPlus
+                | TokenType::MinusMinus
+        )
+    }
 }
 
 impl fmt::Display for TokenType {
@@ -28,6 +248,7 @@ pub enum LiteralValue {
     Float(f64),
     String(String),
     Boolean(bool),
+    Null,
     None,
 }
 
@@ -36,64 +257,209 @@ impl fmt::Display for LiteralValue {
         match self {
             LiteralValue::Integer(i) => write!(f, "{}", i),
             LiteralValue::Float(fl) => write!(f, "{}", fl),
-            LiteralValue::String(s) => write!(f, "\"{}\"", s),
+            // Debug formatting re-escapes the decoded value so dumps stay one line.
+            LiteralValue::String(s) => write!(f, "{:?}", s),
             LiteralValue::Boolean(b) => write!(f, "{}", b),
+            LiteralValue::Null => write!(f, "null"),
             LiteralValue::None => write!(f, ""),
         }
     }
 }
 
+/// Type suffix of a numeric literal, as in `42u8` or `1.5f`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NumberSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumberSuffix {
+    /// Parses a suffix spelling. `u` is short for `u32` and `f` for `f32`.
+    pub fn parse(text: &str) -> Option<NumberSuffix> {
+        let suffix = match text {
+            "i8" => NumberSuffix::I8,
+            "i16" => NumberSuffix::I16,
+            "i32" => NumberSuffix::I32,
+            "i64" => NumberSuffix::I64,
+            "u8" => NumberSuffix::U8,
+            "u16" => NumberSuffix::U16,
+            "u" | "u32" => NumberSuffix::U32,
+            "u64" => NumberSuffix::U64,
+            "f" | "f32" => NumberSuffix::F32,
+            "f64" => NumberSuffix::F64,
+            _ => return None,
+        };
+        Some(suffix)
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, NumberSuffix::F32 | NumberSuffix::F64)
+    }
+
+    /// Inclusive range of values an integer literal with this suffix may
+    /// have; `None` for float suffixes.
+    pub fn integer_range(self) -> Option<(i128, i128)> {
+        let range = match self {
+            NumberSuffix::I8 => (i8::MIN as i128, i8::MAX as i128),
+            NumberSuffix::I16 => (i16::MIN as i128, i16::MAX as i128),
+            NumberSuffix::I32 => (i32::MIN as i128, i32::MAX as i128),
+            NumberSuffix::I64 => (i64::MIN as i128, i64::MAX as i128),
+            NumberSuffix::U8 => (0, u8::MAX as i128),
+            NumberSuffix::U16 => (0, u16::MAX as i128),
+            NumberSuffix::U32 => (0, u32::MAX as i128),
+            NumberSuffix::U64 => (0, u64::MAX as i128),
+            NumberSuffix::F32 | NumberSuffix::F64 => return None,
+        };
+        Some(range)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+    Shebang,
+}
+
+/// Whitespace or a comment kept alongside a token in trivia mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: Cow<'a, str>,
+    pub span: Span,
+}
+
+impl<'a> Trivia<'a> {
+    pub fn new(kind: TriviaKind, text: impl Into<Cow<'a, str>>, span: Span) -> Self {
+        Self {
+            kind,
+            text: text.into(),
+            span,
+        }
+    }
+
+    pub fn into_owned(self) -> Trivia<'static> {
+        Trivia {
+            kind: self.kind,
+            text: Cow::Owned(self.text.into_owned()),
+            span: self.span,
+        }
+    }
+}
+
+/// A token scanned from source text.
+///
+/// The lexeme borrows from the source, so scanning does not allocate for
+/// most tokens; [`Token::into_owned`] detaches a token from the source.
 #[derive(Debug, Clone, PartialEq)]
-pub struct Token {
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub lexeme: String,
-    pub line: usize,
-    pub column: usize,
+    pub lexeme: Cow<'a, str>,
+    pub span: Span,
     pub literal: LiteralValue,
+    /// Trivia before the token; only populated in trivia mode.
+    pub leading_trivia: Vec<Trivia<'a>>,
+    /// Trivia after the token on the same line; only populated in trivia mode.
+    pub trailing_trivia: Vec<Trivia<'a>>,
+    /// The error an `Error` token stands for; `None` for every other token.
+    pub error: Option<LexicalError>,
+    /// Type suffix of a numeric literal. `u64` values above `i64::MAX` are
+    /// stored in `LiteralValue::Integer` with the same bits.
+    pub suffix: Option<NumberSuffix>,
+    /// Interned name of an `Identifier` token.
+    pub symbol: Option<Symbol>,
 }
 
-impl Token {
+impl<'a> Token<'a> {
     pub fn new(
         token_type: TokenType,
-        lexeme: impl Into<String>,
-        line: usize,
-        column: usize,
+        lexeme: impl Into<Cow<'a, str>>,
+        span: Span,
         literal: LiteralValue,
     ) -> Self {
         Self {
             token_type,
             lexeme: lexeme.into(),
-            line,
-            column,
+            span,
             literal,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+            error: None,
+            suffix: None,
+            symbol: None,
         }
     }
 
-    pub fn simple(
-        token_type: TokenType,
-        lexeme: impl Into<String>,
-        line: usize,
-        column: usize,
-    ) -> Self {
-        Self::new(token_type, lexeme, line, column, LiteralValue::None)
+    pub fn simple(token_type: TokenType, lexeme: impl Into<Cow<'a, str>>, span: Span) -> Self {
+        Self::new(token_type, lexeme, span, LiteralValue::None)
+    }
+
+    /// The same token with its span and trivia moved by `delta` bytes.
+    pub fn shifted(&self, delta: isize) -> Token<'a> {
+        let mut token = self.clone();
+        token.span = token.span.shift(delta);
+        for trivia in token
+            .leading_trivia
+            .iter_mut()
+            .chain(token.trailing_trivia.iter_mut())
+        {
+            trivia.span = trivia.span.shift(delta);
+        }
+        token
+    }
+
+    /// An `Error` token for the offending source text `lexeme`.
+    pub fn error(error: LexicalError, lexeme: impl Into<Cow<'a, str>>, span: Span) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::new(TokenType::Error, lexeme, span, LiteralValue::None)
+        }
     }
 
-    pub fn error(lexeme: impl Into<String>, line: usize, column: usize) -> Self {
-        Self::new(TokenType::Error, lexeme, line, column, LiteralValue::None)
+    /// Copies the borrowed parts so the token no longer refers to the source.
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            token_type: self.token_type,
+            lexeme: Cow::Owned(self.lexeme.into_owned()),
+            span: self.span,
+            literal: self.literal,
+            leading_trivia: self
+                .leading_trivia
+                .into_iter()
+                .map(Trivia::into_owned)
+                .collect(),
+            trailing_trivia: self
+                .trailing_trivia
+                .into_iter()
+                .map(Trivia::into_owned)
+                .collect(),
+            error: self.error,
+            suffix: self.suffix,
+            symbol: self.symbol,
+        }
     }
 }
 
-impl fmt::Display for Token {
+impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let literal_str = if self.literal != LiteralValue::None {
             format!(" {}", self.literal)
         } else {
             String::new()
         };
-        write!(
-            f,
-            "{}:{} {} \"{}\"{}",
-            self.line, self.column, self.token_type, self.lexeme, literal_str
-        )
+        match &self.error {
+            // Error tokens show what went wrong rather than the source text.
+            Some(error) => write!(f, "{} \"{}\"", self.token_type, error),
+            None => write!(f, "{} \"{}\"{}", self.token_type, self.lexeme, literal_str),
+        }
     }
-}
\ No newline at end of file
+}
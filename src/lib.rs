@@ -0,0 +1,15 @@
+pub mod analysis;
+pub mod ast;
+pub mod bytecode;
+pub mod cache;
+pub mod cst;
+pub mod diagnostics;
+pub mod input;
+pub mod lexer;
+pub mod lowering;
+pub mod parser;
+pub mod preprocess;
+pub mod query;
+pub mod semantic;
+pub mod source;
+pub mod utils;
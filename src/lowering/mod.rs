@@ -0,0 +1,561 @@
+//! Desugaring of the AST into a smaller core language.
+//!
+//! [`lower`] rewrites two constructs in place, so later phases see fewer
+//! forms:
+//!
+//! - `x += e` (and the other compound assignments) becomes `x = x + e`.
+//!   The target is repeated, so this is only done when evaluating it twice
+//!   is the same as evaluating it once: `a[f()] += 1` keeps its compound
+//!   form rather than calling `f` twice.
+//! - `for (init; cond; step) body` becomes
+//!   `{ init; while (cond) { body step; } }`, with `true` for a missing
+//!   condition. A `continue` of the loop becomes `{ step; continue; }`, so
+//!   the step still runs before the next iteration. Declarations in the
+//!   body of names the step uses get fresh symbols first, so that the
+//!   copies of the step still mean the loop's variables rather than ones
+//!   the body shadows them with.
+//!
+//! New nodes get fresh [`NodeId`]s from [`Program::node_count`] and take the
+//! span of the construct they replace.
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::visit_mut::{self, MutVisitor};
+use crate::ast::{
+    ArrayLen, AstArena, Block, Expr, ExprId, ExprKind, Ident, MatchArm, NodeId, Pattern,
+    PatternKind, Program, Stmt, StmtId, StmtKind, Type, TypeKind, VarDecl,
+};
+use crate::lexer::LiteralValue;
+use crate::source::Span;
+use crate::utils::Symbol;
+use std::collections::HashSet;
+
+/// Desugars `program` in place.
+pub fn lower(program: &mut Program) {
+    let mut lowering = Lowering {
+        node_count: program.node_count,
+        symbol_count: 0,
+        arena: std::mem::take(&mut program.arena),
+    };
+    lowering.visit_program(program);
+    program.node_count = lowering.node_count;
+    program.arena = lowering.arena;
+}
+
+struct Lowering {
+    node_count: usize,
+    /// Number of [`Symbol::synthetic`]s handed out.
+    symbol_count: usize,
+    arena: AstArena,
+}
+
+impl Lowering {
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId::new(self.node_count);
+        self.node_count += 1;
+        id
+    }
+
+    fn next_symbol(&mut self) -> Symbol {
+        let symbol = Symbol::synthetic(self.symbol_count);
+        self.symbol_count += 1;
+        symbol
+    }
+
+    fn expr(&mut self, kind: ExprKind, span: Span) -> ExprId {
+        let id = self.next_id();
+        self.arena.alloc_expr(Expr { id, kind, span })
+    }
+
+    fn stmt(&mut self, kind: StmtKind, span: Span) -> StmtId {
+        let id = self.next_id();
+        self.arena.alloc_stmt(Stmt { id, kind, span })
+    }
+
+    fn block(&mut self, stmts: Vec<StmtId>, span: Span) -> StmtId {
+        let block = Block {
+            id: self.next_id(),
+            stmts,
+            span,
+        };
+        self.stmt(StmtKind::Block(block), span)
+    }
+
+    /// A copy of `expr` with fresh ids, so the copy and the original can
+    /// both stay in the tree.
+    fn copy(&mut self, expr: ExprId) -> ExprId {
+        let mut copy = expr;
+        Renumber(self).visit_expr(&mut copy);
+        copy
+    }
+
+    /// `target op= value` as `target = target op value`.
+    fn lower_compound_assign(&mut self, expr: ExprId) {
+        let ExprKind::Assign {
+            op: Some(op),
+            target,
+            value,
+        } = self.arena[expr].kind
+        else {
+            return;
+        };
+        if !repeatable(&self.arena, target) {
+            return;
+        }
+        let left = self.copy(target);
+        let binary = ExprKind::Binary {
+            op,
+            left,
+            right: value,
+        };
+        let value = self.expr(binary, self.arena[expr].span);
+        self.arena[expr].kind = ExprKind::Assign {
+            op: None,
+            target,
+            value,
+        };
+    }
+
+    /// A `for` statement as a block holding its initializer and a `while`.
+    fn lower_for(&mut self, stmt: &mut StmtId) {
+        let StmtKind::For {
+            init,
+            condition,
+            step,
+            mut body,
+        } = self.arena[*stmt].kind
+        else {
+            return;
+        };
+        let span = self.arena[*stmt].span;
+        let condition = match condition {
+            Some(condition) => condition,
+            None => self.expr(ExprKind::Literal(LiteralValue::Boolean(true)), span),
+        };
+        if let Some(step) = step {
+            let mut used = Used {
+                arena: &self.arena,
+                symbols: HashSet::new(),
+            };
+            used.visit_expr(&self.arena[step]);
+            Unshadow {
+                symbols: used.symbols,
+                lowering: self,
+                scopes: vec![Vec::new()],
+            }
+            .visit_stmt(&mut body);
+            Continues {
+                lowering: self,
+                step,
+            }
+            .visit_stmt(&mut body);
+            let step_span = self.arena[step].span;
+            let step = self.stmt(StmtKind::Expr(step), step_span);
+            body = self.block(vec![body, step], span);
+        }
+        let mut stmts = Vec::new();
+        if let Some(init) = init {
+            stmts.push(init);
+        }
+        stmts.push(self.stmt(StmtKind::While { condition, body }, span));
+        *stmt = self.block(stmts, span);
+    }
+}
+
+impl MutVisitor for Lowering {
+    fn arena(&mut self) -> &mut AstArena {
+        &mut self.arena
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut StmtId) {
+        // Inner loops first, so `continue`s found below belong to this one.
+        visit_mut::walk_stmt(self, *stmt);
+        self.lower_for(stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &mut ExprId) {
+        visit_mut::walk_expr(self, *expr);
+        self.lower_compound_assign(*expr);
+    }
+}
+
+/// Points every handle of an expression at a copy of its node with a fresh
+/// id, inner nodes first.
+struct Renumber<'l>(&'l mut Lowering);
+
+impl MutVisitor for Renumber<'_> {
+    fn arena(&mut self) -> &mut AstArena {
+        &mut self.0.arena
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut StmtId) {
+        let copy = self.0.arena[*stmt].clone();
+        *stmt = self.0.arena.alloc_stmt(copy);
+        visit_mut::walk_stmt(self, *stmt);
+        self.0.arena[*stmt].id = self.0.next_id();
+    }
+
+    fn visit_expr(&mut self, expr: &mut ExprId) {
+        let copy = self.0.arena[*expr].clone();
+        *expr = self.0.arena.alloc_expr(copy);
+        visit_mut::walk_expr(self, *expr);
+        // The fields are numbered after their values, like the parser does.
+        let placeholder = ExprKind::Literal(LiteralValue::None);
+        let mut kind = std::mem::replace(&mut self.0.arena[*expr].kind, placeholder);
+        if let ExprKind::StructLiteral { fields, .. } = &mut kind {
+            for field in fields {
+                field.id = self.0.next_id();
+            }
+        }
+        self.0.arena[*expr].kind = kind;
+        self.0.arena[*expr].id = self.0.next_id();
+    }
+}
+
+/// The symbols of the variables an expression reads or assigns.
+struct Used<'ast> {
+    arena: &'ast AstArena,
+    symbols: HashSet<Symbol>,
+}
+
+impl<'ast> Visitor<'ast> for Used<'ast> {
+    fn arena(&self) -> &'ast AstArena {
+        self.arena
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        if let ExprKind::Variable(name) = &expr.kind {
+            self.symbols.insert(name.symbol);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Gives each declaration in a loop body of one of `symbols` a fresh
+/// symbol, and its uses with it, so that none of `symbols` is shadowed
+/// anywhere in the body.
+struct Unshadow<'l> {
+    lowering: &'l mut Lowering,
+    symbols: HashSet<Symbol>,
+    /// The renamings of each open scope, innermost last, starting with the
+    /// loop's.
+    scopes: Vec<Vec<(Symbol, Symbol)>>,
+}
+
+impl Unshadow<'_> {
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Vec::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &mut Ident) {
+        if !self.symbols.contains(&name.symbol) {
+            return;
+        }
+        let fresh = self.lowering.next_symbol();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((name.symbol, fresh));
+        }
+        name.symbol = fresh;
+    }
+
+    /// The symbol `symbol` has been renamed to where the walk is, if any.
+    fn renamed(&self, symbol: Symbol) -> Option<Symbol> {
+        let mut renamings = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev());
+        renamings
+            .find(|(old, _)| *old == symbol)
+            .map(|&(_, fresh)| fresh)
+    }
+}
+
+impl MutVisitor for Unshadow<'_> {
+    fn arena(&mut self) -> &mut AstArena {
+        &mut self.lowering.arena
+    }
+
+    fn visit_var_decl(&mut self, decl: &mut VarDecl) {
+        if let Some(ty) = &mut decl.ty {
+            self.visit_type(ty);
+        }
+        if let Some(init) = &mut decl.init {
+            self.visit_expr(init);
+        }
+        // Declared after its initializer, as the resolver does.
+        self.declare(&mut decl.name);
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        self.scoped(|u| visit_mut::walk_block(u, block));
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut StmtId) {
+        match self.lowering.arena[*stmt].kind {
+            StmtKind::For { .. } => self.scoped(|u| visit_mut::walk_stmt(u, *stmt)),
+            _ => visit_mut::walk_stmt(self, *stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut ExprId) {
+        if let ExprKind::Variable(name) = &self.lowering.arena[*expr].kind {
+            if let Some(fresh) = self.renamed(name.symbol) {
+                if let ExprKind::Variable(name) = &mut self.lowering.arena[*expr].kind {
+                    name.symbol = fresh;
+                }
+            }
+        }
+        visit_mut::walk_expr(self, *expr);
+    }
+
+    fn visit_arm(&mut self, arm: &mut MatchArm) {
+        self.scoped(|u| visit_mut::walk_arm(u, arm));
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut Pattern) {
+        if let PatternKind::Binding(name) = &mut pattern.kind {
+            self.declare(name);
+        }
+        visit_mut::walk_pattern(self, pattern);
+    }
+
+    fn visit_type(&mut self, ty: &mut Type) {
+        if let TypeKind::Array {
+            len: ArrayLen::Const(name),
+            ..
+        } = &mut ty.kind
+        {
+            if let Some(fresh) = self.renamed(name.symbol) {
+                name.symbol = fresh;
+            }
+        }
+        visit_mut::walk_type(self, ty);
+    }
+}
+
+/// Puts a copy of `step` before each `continue` of a loop being lowered.
+struct Continues<'l> {
+    lowering: &'l mut Lowering,
+    step: ExprId,
+}
+
+impl MutVisitor for Continues<'_> {
+    fn arena(&mut self) -> &mut AstArena {
+        &mut self.lowering.arena
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut StmtId) {
+        match self.lowering.arena[*stmt].kind {
+            // A nested loop's `continue`s are its own; `for`s are already
+            // `while`s by now.
+            StmtKind::While { .. } | StmtKind::For { .. } => {}
+            StmtKind::Continue => {
+                let span = self.lowering.arena[*stmt].span;
+                let step = self.lowering.copy(self.step);
+                let step = self.lowering.stmt(StmtKind::Expr(step), span);
+                *stmt = self.lowering.block(vec![step, *stmt], span);
+            }
+            _ => visit_mut::walk_stmt(self, *stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, _expr: &mut ExprId) {}
+}
+
+/// Whether evaluating `expr` twice has the same effect as once: it reads
+/// variables, fields and elements but calls and assigns nothing.
+fn repeatable(arena: &AstArena, expr: ExprId) -> bool {
+    match &arena[expr].kind {
+        ExprKind::Literal(_) | ExprKind::Variable(_) | ExprKind::Variant { .. } => true,
+        ExprKind::Grouping(inner) | ExprKind::IntToFloat(inner) => repeatable(arena, *inner),
+        ExprKind::Unary { operand, .. } => repeatable(arena, *operand),
+        ExprKind::Binary { left, right, .. } => {
+            repeatable(arena, *left) && repeatable(arena, *right)
+        }
+        ExprKind::Field { object, .. } => repeatable(arena, *object),
+        ExprKind::Index { object, index } => {
+            repeatable(arena, *object) && repeatable(arena, *index)
+        }
+        ExprKind::Assign { .. }
+        | ExprKind::Call { .. }
+        | ExprKind::Array(_)
+        | ExprKind::StructLiteral { .. }
+        | ExprKind::Conditional { .. }
+        | ExprKind::Match { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::pretty::print;
+    use crate::ast::visit::{self, Visitor};
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    /// Every expression and statement id in a program.
+    struct Ids<'ast> {
+        arena: &'ast AstArena,
+        ids: Vec<NodeId>,
+    }
+
+    impl<'ast> Visitor<'ast> for Ids<'ast> {
+        fn arena(&self) -> &'ast AstArena {
+            self.arena
+        }
+
+        fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+            self.ids.push(stmt.id);
+            visit::walk_stmt(self, stmt);
+        }
+
+        fn visit_expr(&mut self, expr: &'ast Expr) {
+            self.ids.push(expr.id);
+            visit::walk_expr(self, expr);
+        }
+    }
+
+    fn lowered(source: &str) -> String {
+        let (mut program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        lower(&mut program);
+        let mut ids = Ids {
+            arena: &program.arena,
+            ids: Vec::new(),
+        };
+        ids.visit_program(&program);
+        let mut ids = ids.ids;
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count, "duplicate ids");
+        assert!(ids.iter().all(|id| id.index() < program.node_count));
+        print(&program)
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eq!(
+            lowered("fn f() { x += 1; p.a[i] *= y - 2; a[g()] -= 1; }"),
+            "fn f() {
+    x = x + 1;
+    p.a[i] = p.a[i] * (y - 2);
+    a[g()] -= 1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_for() {
+        let source = "fn f() {
+            for (let i = 0; i < n; i += 1) { if (i == 2) continue; while (c) continue; }
+            for (;;) break;
+        }";
+        assert_eq!(
+            lowered(source),
+            "fn f() {
+    {
+        let i = 0;
+        while (i < n) {
+            {
+                if (i == 2) {
+                    i = i + 1;
+                    continue;
+                }
+                while (c)
+                    continue;
+            }
+            i = i + 1;
+        }
+    }
+    {
+        while (true)
+            break;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_for_step_is_not_shadowed() {
+        let source = "fn f() {
+            for (int i = 0; i < 3; i += 1) {
+                float i = 5.0;
+                if (i > 1.0) continue;
+                { int i = 1; continue; }
+            }
+        }";
+        let (mut program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        lower(&mut program);
+        assert_eq!(
+            print(&program),
+            "fn f() {
+    {
+        int i = 0;
+        while (i < 3) {
+            {
+                float i = 5.0;
+                if (i > 1.0) {
+                    i = i + 1;
+                    continue;
+                }
+                {
+                    int i = 1;
+                    {
+                        i = i + 1;
+                        continue;
+                    }
+                }
+            }
+            i = i + 1;
+        }
+    }
+}
+"
+        );
+
+        /// The declarations of `i` and the targets of assignments to it.
+        struct Is<'ast> {
+            arena: &'ast AstArena,
+            decls: Vec<NodeId>,
+            targets: Vec<NodeId>,
+        }
+
+        impl<'ast> Visitor<'ast> for Is<'ast> {
+            fn arena(&self) -> &'ast AstArena {
+                self.arena
+            }
+
+            fn visit_var_decl(&mut self, decl: &'ast VarDecl) {
+                self.decls.push(decl.id);
+                visit::walk_var_decl(self, decl);
+            }
+
+            fn visit_expr(&mut self, expr: &'ast Expr) {
+                if let ExprKind::Assign { target, .. } = expr.kind {
+                    self.targets.push(self.arena[target].id);
+                }
+                visit::walk_expr(self, expr);
+            }
+        }
+
+        let (resolution, diagnostics) = crate::semantic::resolve(&program);
+        assert_eq!(diagnostics, []);
+        let mut is = Is {
+            arena: &program.arena,
+            decls: Vec::new(),
+            targets: Vec::new(),
+        };
+        is.visit_program(&program);
+        assert_eq!(is.targets.len(), 3);
+        let loop_variable = resolution.declaration(is.decls[0]);
+        for target in is.targets {
+            assert_eq!(resolution.use_of(target), loop_variable);
+        }
+    }
+}
@@ -1,11 +1,22 @@
-mod lexer;
-mod utils;
-
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use lexer::{Scanner, TokenType};
+use clap::{Args, Parser, Subcommand};
+use minicompiler::analysis::{self, Cfg};
+use minicompiler::ast::dump::{dump, DumpFormat};
+use minicompiler::ast::{Item, Program};
+use minicompiler::bytecode;
+use minicompiler::cache::{Cache, CacheKey, Stage, DEFAULT_CACHE_DIR, DEFAULT_SIZE_LIMIT};
+use minicompiler::cst;
+use minicompiler::diagnostics::{self, codes, Diagnostic, ErrorFormat, Level, LintStore, Renderer};
+use minicompiler::input::{MmapMode, SourceBuffer, Utf8Mode};
+use minicompiler::lexer::{IntWidth, NewlineMode, Scanner, ScannerOptions};
+use minicompiler::parser;
+use minicompiler::preprocess::{PreprocessError, Preprocessor, SourceToken, Unit};
+use minicompiler::query::{Database, FileId};
+use minicompiler::semantic::{ConversionPolicy, TypeckOptions};
+use minicompiler::source::{ColumnEncoding, SourceFile, SourceManager, Spanned};
 use std::fs;
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "compiler")]
@@ -26,51 +37,1077 @@ enum Commands {
         /// Optional output file (stdout if not provided).
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Memory-map the input instead of reading it (auto: large files only).
+        #[arg(long, value_enum, default_value_t = MmapMode::Auto)]
+        mmap: MmapMode,
+
+        /// What to do with bytes that are not valid UTF-8 (lossy: replace
+        /// them and report each one as a lexical error).
+        #[arg(long, value_enum, default_value_t = Utf8Mode::Strict)]
+        invalid_utf8: Utf8Mode,
+
+        #[command(flatten)]
+        preprocess: PreprocessArgs,
+
+        #[command(flatten)]
+        cache: CacheArgs,
+
+        #[command(flatten)]
+        scanner: ScannerArgs,
+
+        #[command(flatten)]
+        diagnostics: DiagnosticArgs,
+    },
+    /// Run the lexer and parser on an input file and output the syntax tree.
+    Parse {
+        /// Path to the source file.
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Optional output file (stdout if not provided).
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// How to print the tree.
+        #[arg(long, value_enum, default_value_t = DumpFormat::Sexpr)]
+        format: DumpFormat,
+
+        /// Print the lossless concrete syntax tree, with every token and
+        /// comment, instead of the AST.
+        #[arg(long, conflicts_with = "preprocess")]
+        cst: bool,
+
+        /// Resolve names and check types too, reporting their errors and
+        /// printing the tree with the conversions the checker inserted.
+        #[arg(long, conflicts_with = "cst")]
+        check: bool,
+
+        #[command(flatten)]
+        preprocess: PreprocessArgs,
+
+        #[command(flatten)]
+        cache: CacheArgs,
+
+        #[command(flatten)]
+        scanner: ScannerArgs,
+
+        #[command(flatten)]
+        typeck: TypeckArgs,
+
+        #[command(flatten)]
+        diagnostics: DiagnosticArgs,
+    },
+    /// Check an input file for errors without printing anything else.
+    Check {
+        /// Path to the source file.
+        #[arg(short, long)]
+        input: PathBuf,
+
+        #[command(flatten)]
+        preprocess: PreprocessArgs,
+
+        #[command(flatten)]
+        cache: CacheArgs,
+
+        #[command(flatten)]
+        scanner: ScannerArgs,
+
+        #[command(flatten)]
+        typeck: TypeckArgs,
+
+        #[command(flatten)]
+        diagnostics: DiagnosticArgs,
+    },
+    /// Print the control-flow graph of every function in an input file.
+    Ir {
+        /// Path to the source file.
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Run the passes up to and including this one first.
+        #[arg(long, value_enum, value_name = "PASS")]
+        after: Option<Pass>,
+
+        #[command(flatten)]
+        preprocess: PreprocessArgs,
+
+        #[command(flatten)]
+        cache: CacheArgs,
+
+        #[command(flatten)]
+        scanner: ScannerArgs,
+
+        #[command(flatten)]
+        typeck: TypeckArgs,
+
+        #[command(flatten)]
+        diagnostics: DiagnosticArgs,
+    },
+    /// Compile an input file to bytecode and print it in readable form.
+    Disasm {
+        /// Path to the source file.
+        #[arg(short, long)]
+        input: PathBuf,
+
+        #[command(flatten)]
+        scanner: ScannerArgs,
+
+        #[command(flatten)]
+        typeck: TypeckArgs,
+
+        #[command(flatten)]
+        diagnostics: DiagnosticArgs,
     },
     /// Run all tests (valid/invalid) and report results.
     Test,
 }
 
+/// The passes `ir --after` can stop after, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+enum Pass {
+    /// Desugar compound assignments and `for` loops.
+    Lower,
+    /// Put local variables in SSA form.
+    Ssa,
+}
+
+/// The tree `parse` prints.
+#[derive(Debug, Clone, Copy)]
+enum TreeOutput {
+    /// The AST, in the given format.
+    Ast(DumpFormat),
+    /// The lossless concrete syntax tree.
+    Cst,
+}
+
+/// Command-line switches for `#include` and `#define` expansion.
+#[derive(Args, Debug, Default)]
+struct PreprocessArgs {
+    /// Expand `#include "file"` directives.
+    #[arg(long)]
+    preprocess: bool,
+
+    /// Also expand `#define NAME value` macros.
+    #[arg(long, requires = "preprocess")]
+    macros: bool,
+}
+
+/// Command-line switches for the on-disk artifact cache.
+#[derive(Args)]
+struct CacheArgs {
+    /// Do not read or write the on-disk artifact cache.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Print cache hit/miss statistics to stderr.
+    #[arg(long)]
+    cache_stats: bool,
+
+    /// Largest total size of the cache, in MiB, past which the least
+    /// recently used artifacts are removed.
+    #[arg(long, value_name = "MIB", default_value_t = DEFAULT_SIZE_LIMIT >> 20)]
+    cache_size_limit: u64,
+}
+
+impl CacheArgs {
+    fn open(&self) -> Cache {
+        if self.no_cache {
+            Cache::disabled()
+        } else {
+            Cache::open(DEFAULT_CACHE_DIR)
+        }
+    }
+
+    /// Keeps `cache` under its size limit if anything was written to it,
+    /// and prints its statistics if they were asked for.
+    fn finish(&self, cache: &mut Cache) {
+        if cache.stats().writes > 0 {
+            // A cache that cannot be pruned is not a compilation error.
+            let _ = cache.prune(self.cache_size_limit << 20);
+        }
+        if self.cache_stats {
+            eprintln!("{}", cache.stats());
+        }
+    }
+}
+
+/// Command-line switches that change how source text is tokenized.
+#[derive(Args)]
+struct ScannerArgs {
+    /// Allow non-ASCII (Unicode XID) letters in identifiers.
+    #[arg(long)]
+    unicode_identifiers: bool,
+
+    /// Longest identifier accepted, in characters (0 for no limit).
+    #[arg(long, value_name = "N", default_value_t = ScannerOptions::DEFAULT_MAX_IDENTIFIER_LENGTH)]
+    max_identifier_length: usize,
+
+    /// Match keywords regardless of case (`IF`, `If` and `if`).
+    #[arg(long)]
+    case_insensitive_keywords: bool,
+
+    /// Range checked for integer literals without a type suffix.
+    #[arg(long, value_enum, default_value_t = IntWidth::I32)]
+    int_width: IntWidth,
+
+    /// Lex a leading minus as part of a number literal (`-5`) where it cannot
+    /// be subtraction.
+    #[arg(long)]
+    fold_negative_literals: bool,
+
+    /// Columns per tab stop when reporting token positions.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    tab_width: usize,
+
+    /// Unit token columns are counted in (utf16 matches LSP positions).
+    #[arg(long, value_enum, default_value_t = ColumnEncoding::Chars)]
+    columns: ColumnEncoding,
+
+    /// Whether line breaks produce Newline tokens.
+    #[arg(long, value_enum, default_value_t = NewlineMode::Skip)]
+    newlines: NewlineMode,
+}
+
+impl ScannerArgs {
+    fn options(&self) -> ScannerOptions {
+        ScannerOptions::new()
+            .with_unicode_identifiers(self.unicode_identifiers)
+            .with_max_identifier_length(Some(self.max_identifier_length).filter(|&n| n > 0))
+            .with_case_insensitive_keywords(self.case_insensitive_keywords)
+            .with_int_width(self.int_width)
+            .with_fold_negative_literals(self.fold_negative_literals)
+            .with_tab_width(self.tab_width)
+            .with_columns(self.columns)
+            .with_newlines(self.newlines)
+    }
+
+    fn scanner<'a>(&self, source: &'a str) -> Scanner<'a> {
+        Scanner::with_options(source, self.options())
+    }
+
+    /// Stable description of the options, mixed into cache keys so artifacts
+    /// produced with different options never collide.
+    fn cache_key(&self) -> String {
+        format!("{:?}", self.options())
+    }
+}
+
+/// Command-line switches that change what the type checker accepts.
+#[derive(Args)]
+struct TypeckArgs {
+    /// Whether an int may be used where a float is expected.
+    #[arg(long, value_enum, default_value_t = ConversionPolicy::Implicit)]
+    int_to_float: ConversionPolicy,
+}
+
+impl TypeckArgs {
+    /// The checker's options. Asking for the `implicit-conversion` lint
+    /// turns on the warnings it applies to.
+    fn options(&self, lints: &LintStore) -> TypeckOptions {
+        let policy = match (
+            self.int_to_float,
+            lints.configured(codes::IMPLICIT_CONVERSION),
+        ) {
+            (ConversionPolicy::Implicit, Some(Level::Warn | Level::Deny)) => ConversionPolicy::Warn,
+            (policy, _) => policy,
+        };
+        TypeckOptions::new().with_int_to_float(policy)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ColorChoice {
+    /// Color when stderr is a terminal and `NO_COLOR` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+/// Command-line switches that change how diagnostics are reported.
+#[derive(Args)]
+struct DiagnosticArgs {
+    /// Whether to color diagnostics.
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// How to print diagnostics (json: one object per line).
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
+
+    /// Lint levels, one `name = level` per line, which the flags below
+    /// override.
+    #[arg(long, value_name = "FILE")]
+    lint_config: Option<PathBuf>,
+
+    /// Silence a lint, given by name or code (repeatable).
+    #[arg(long, value_name = "LINT")]
+    allow: Vec<String>,
+
+    /// Report a lint as a warning (repeatable).
+    #[arg(long, value_name = "LINT")]
+    warn: Vec<String>,
+
+    /// Report a lint as an error (repeatable).
+    #[arg(long, value_name = "LINT")]
+    deny: Vec<String>,
+}
+
+impl DiagnosticArgs {
+    fn reporter(&self) -> Result<Reporter> {
+        let mut lints = LintStore::new();
+        if let Some(path) = &self.lint_config {
+            let config = fs::read_to_string(path)?;
+            lints
+                .load(&config)
+                .map_err(|error| anyhow::anyhow!("{}: {}", path.display(), error))?;
+        }
+        for (names, level) in [
+            (&self.allow, Level::Allow),
+            (&self.warn, Level::Warn),
+            (&self.deny, Level::Deny),
+        ] {
+            for name in names {
+                lints.set(name, level)?;
+            }
+        }
+        let color = match self.color {
+            ColorChoice::Auto => {
+                std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        Ok(Reporter {
+            color,
+            format: self.error_format,
+            lints,
+        })
+    }
+}
+
+/// Prints diagnostics as the command line asked.
+struct Reporter {
+    color: bool,
+    format: ErrorFormat,
+    lints: LintStore,
+}
+
+impl Reporter {
+    /// Stable description of the lint levels, which decide what is reported,
+    /// mixed into cache keys.
+    fn cache_key(&self) -> String {
+        let levels: Vec<_> = codes::LINTS
+            .iter()
+            .map(|&lint| self.lints.level(lint))
+            .collect();
+        format!("{:?}", levels)
+    }
+
+    /// Prints `diagnostics` about `file`, read from `path`, to stderr at
+    /// their lint levels, returning the ones printed.
+    fn report(
+        &self,
+        path: &Path,
+        file: &SourceFile,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let diagnostics = self.lints.apply(diagnostics);
+        let path = path.display().to_string();
+        if self.format == ErrorFormat::Json {
+            for diagnostic in &diagnostics {
+                eprintln!("{}", diagnostics::to_json(diagnostic, file, Some(&path)));
+            }
+            return diagnostics;
+        }
+        let renderer = Renderer::new(file).with_path(path).with_color(self.color);
+        for diagnostic in &diagnostics {
+            eprintln!("{}", renderer.render(diagnostic));
+        }
+        diagnostics
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Lex { input, output } => run_lexer(&input, output.as_ref()),
+        Commands::Lex {
+            input,
+            output,
+            mmap,
+            invalid_utf8,
+            preprocess,
+            cache,
+            scanner,
+            diagnostics,
+        } => {
+            let reporter = diagnostics.reporter()?;
+            let mut artifacts = cache.open();
+            let errors = if preprocess.preprocess {
+                run_preprocessed(
+                    &input,
+                    output.as_ref(),
+                    &preprocess,
+                    &scanner,
+                    &reporter,
+                    &mut artifacts,
+                )?
+            } else {
+                run_lexer(
+                    &input,
+                    output.as_ref(),
+                    mmap,
+                    invalid_utf8,
+                    &scanner,
+                    &reporter,
+                    &mut artifacts,
+                )?
+            };
+            cache.finish(&mut artifacts);
+            exit_on_errors(&errors);
+            Ok(())
+        }
+        Commands::Parse {
+            input,
+            output,
+            format,
+            cst,
+            check,
+            preprocess,
+            cache,
+            scanner,
+            typeck,
+            diagnostics,
+        } => {
+            let reporter = diagnostics.reporter()?;
+            let input = Input::read(&input, &preprocess, &scanner)?;
+            let typeck = check.then(|| typeck.options(&reporter.lints));
+            let tree = if cst {
+                TreeOutput::Cst
+            } else {
+                TreeOutput::Ast(format)
+            };
+            let mut artifacts = cache.open();
+            let errors = run_parser(
+                &input,
+                output.as_ref(),
+                tree,
+                typeck.as_ref(),
+                &scanner,
+                &reporter,
+                &mut artifacts,
+            )?;
+            cache.finish(&mut artifacts);
+            exit_on_errors(&errors);
+            Ok(())
+        }
+        Commands::Check {
+            input,
+            preprocess,
+            cache,
+            scanner,
+            typeck,
+            diagnostics,
+        } => {
+            let reporter = diagnostics.reporter()?;
+            let input = Input::read(&input, &preprocess, &scanner)?;
+            let mut artifacts = cache.open();
+            let errors = run_check(
+                &input,
+                &typeck.options(&reporter.lints),
+                &scanner,
+                &reporter,
+                &mut artifacts,
+            )?;
+            cache.finish(&mut artifacts);
+            exit_on_errors(&errors);
+            Ok(())
+        }
+        Commands::Ir {
+            input,
+            after,
+            preprocess,
+            cache,
+            scanner,
+            typeck,
+            diagnostics,
+        } => {
+            let reporter = diagnostics.reporter()?;
+            let input = Input::read(&input, &preprocess, &scanner)?;
+            let mut artifacts = cache.open();
+            let errors = run_ir(
+                &input,
+                after,
+                &typeck.options(&reporter.lints),
+                &scanner,
+                &reporter,
+                &mut artifacts,
+            )?;
+            cache.finish(&mut artifacts);
+            exit_on_errors(&errors);
+            Ok(())
+        }
+        Commands::Disasm {
+            input,
+            scanner,
+            typeck,
+            diagnostics,
+        } => {
+            let reporter = diagnostics.reporter()?;
+            run_disasm(
+                &input,
+                &typeck.options(&reporter.lints),
+                &scanner,
+                &reporter,
+            )
+        }
         Commands::Test => run_tests(),
     }
 }
 
-fn run_lexer(input_path: &PathBuf, output_path: Option<&PathBuf>) -> Result<()> {
-    let source = fs::read_to_string(input_path)?;
-    let mut scanner = Scanner::new(&source);
-    let mut tokens = Vec::new();
+/// Lexes `input_path` and prints its tokens. Lexical errors are reported
+/// on stderr instead of listed, and returned.
+fn run_lexer(
+    input_path: &PathBuf,
+    output_path: Option<&PathBuf>,
+    mmap: MmapMode,
+    utf8: Utf8Mode,
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+    cache: &mut Cache,
+) -> Result<Vec<Diagnostic>> {
+    let buffer = SourceBuffer::read(input_path, mmap, utf8)?;
+    let source = buffer.as_str();
+    // Replaced sequences are not visible in the decoded text, so they are
+    // part of the key.
+    let options = format!("{} {:?}", scanner.cache_key(), buffer.invalid_utf8());
+    let key = CacheKey::new(source, &options);
+    let file = SourceFile::new(source)
+        .with_tab_width(scanner.tab_width)
+        .with_column_encoding(scanner.columns);
+    let (output, errors) = cached(
+        cache,
+        Stage::Tokens,
+        key,
+        || {
+            let scanner = scanner
+                .scanner(source)
+                .with_invalid_utf8(buffer.invalid_utf8().to_vec());
+            Ok(lex_to_string(scanner, &file))
+        },
+        |errors| reporter.report(input_path, &file, errors),
+    )?;
 
-    loop {
-        let token = scanner.next_token();
-        let is_eof = token.token_type == TokenType::EndOfFile;
-        tokens.push(token);
-        if is_eof {
-            break;
+    match output_path {
+        Some(path) => fs::write(path, output)?,
+        None => println!("{}", output),
+    }
+
+    Ok(errors)
+}
+
+/// Lexes `input_path` with its includes expanded. Tokens from included
+/// files are prefixed with their path; tokens from a macro are reported at
+/// the macro's use. Directive and lexical errors are reported on stderr
+/// instead of listed, and returned.
+fn run_preprocessed(
+    input_path: &PathBuf,
+    output_path: Option<&PathBuf>,
+    preprocess: &PreprocessArgs,
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+    cache: &mut Cache,
+) -> Result<Vec<Diagnostic>> {
+    let mut sources = SourceManager::new();
+    let root = sources.load(input_path)?;
+    let mut preprocessor =
+        Preprocessor::new(&mut sources, scanner.options()).with_macros(preprocess.macros);
+    let tokens = preprocessor.run(root);
+    let errors = preprocessor.errors().to_vec();
+
+    let options = format!("{} {:?}", scanner.cache_key(), preprocess);
+    let key = sources_cache_key(&sources, &options);
+    // Directive and lexical problems are all errors, which are not stored,
+    // so `produce` reports them itself.
+    let (output, reported) = cached(
+        cache,
+        Stage::Tokens,
+        key,
+        || {
+            Ok(list_preprocessed(
+                &sources, root, &tokens, &errors, scanner, reporter,
+            ))
+        },
+        |reported| reported,
+    )?;
+
+    match output_path {
+        Some(path) => fs::write(path, output)?,
+        None => println!("{}", output),
+    }
+
+    Ok(reported)
+}
+
+/// The token listing `lex --preprocess` prints for `tokens`, preprocessed
+/// from `root`, after reporting `errors` and the lexical errors among the
+/// tokens; and what was reported.
+fn list_preprocessed(
+    sources: &SourceManager,
+    root: FileId,
+    tokens: &[SourceToken],
+    errors: &[(FileId, Spanned<PreprocessError>)],
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+) -> (String, Vec<Diagnostic>) {
+    let files = source_files(sources, scanner);
+    let position = |file: FileId, offset| {
+        let line_col = files[file.index()].line_col(offset);
+        if file == root {
+            line_col.to_string()
+        } else {
+            format!("{}:{}", sources.path(file).display(), line_col)
         }
+    };
+    let mut reported = Vec::new();
+    for (file, error) in errors {
+        let diagnostic = Diagnostic::error(error.value.code(), error.to_string(), error.span);
+        reported.extend(reporter.report(
+            sources.path(*file),
+            &files[file.index()],
+            vec![diagnostic],
+        ));
     }
+    let mut lines = Vec::new();
+    // Lexical errors are reported where the bad text is, once even if a
+    // macro holding it is used many times.
+    let mut lexical = Vec::new();
+    for t in tokens {
+        match &t.token.error {
+            Some(error) => {
+                if !lexical.contains(&(t.file, t.token.span)) {
+                    lexical.push((t.file, t.token.span));
+                    let diagnostic = error.to_diagnostic(t.token.span);
+                    reported.extend(reporter.report(
+                        sources.path(t.file),
+                        &files[t.file.index()],
+                        vec![diagnostic],
+                    ));
+                }
+            }
+            None => {
+                let (file, span) = t
+                    .expanded_from
+                    .map_or((t.file, t.token.span), |site| (site.file, site.span));
+                lines.push(format!("{} {}", position(file, span.start), t.token));
+            }
+        }
+    }
+    (lines.join("\n"), reported)
+}
 
-    let output: String = tokens
-        .iter()
-        .map(|t| t.to_string())
-        .collect::<Vec<_>>()
-        .join("\n");
+/// The cache key of an artifact made with `options` from the first file in
+/// `sources` and the files it includes.
+fn sources_cache_key(sources: &SourceManager, options: &str) -> CacheKey {
+    let mut files = sources
+        .files()
+        .map(|file| (sources.path(file), sources.text(file)));
+    let (_, root) = files.next().expect("the input is loaded first");
+    CacheKey::with_includes(root, files, options)
+}
+
+/// The line index of every file in `sources`, by file index, built once so
+/// positions in them can be looked up token by token.
+fn source_files<'s>(sources: &'s SourceManager, scanner: &ScannerArgs) -> Vec<SourceFile<'s>> {
+    sources
+        .files()
+        .map(|file| {
+            sources
+                .source_file(file)
+                .with_tab_width(scanner.tab_width)
+                .with_column_encoding(scanner.columns)
+        })
+        .collect()
+}
+
+/// The file a command works on: its text or, with `--preprocess`, the
+/// text of it and of everything it includes, laid out as one [`Unit`].
+struct Input {
+    path: PathBuf,
+    sources: SourceManager,
+    unit: Option<Unit>,
+    /// Problems with the `#include` and `#define` directives, with spans in
+    /// the unit.
+    directive_errors: Vec<Diagnostic>,
+    /// How it was preprocessed, mixed into its cache keys.
+    preprocess: String,
+}
+
+impl Input {
+    fn read(path: &Path, preprocess: &PreprocessArgs, scanner: &ScannerArgs) -> Result<Self> {
+        let mut sources = SourceManager::new();
+        let root = sources.load(path)?;
+        let (unit, directive_errors) = if preprocess.preprocess {
+            let mut preprocessor =
+                Preprocessor::new(&mut sources, scanner.options()).with_macros(preprocess.macros);
+            let tokens = preprocessor.run(root);
+            let errors = preprocessor.errors().to_vec();
+            let unit = Unit::new(&sources, tokens);
+            let errors = errors
+                .iter()
+                .map(|(file, error)| {
+                    let span = unit.span(*file, error.span);
+                    Diagnostic::error(error.value.code(), error.to_string(), span)
+                })
+                .collect();
+            (Some(unit), errors)
+        } else {
+            (None, Vec::new())
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            sources,
+            unit,
+            directive_errors,
+            preprocess: format!("{:?}", preprocess),
+        })
+    }
+
+    /// The cache key of an artifact made from the input with `options`,
+    /// which covers the files it includes too.
+    fn cache_key(&self, options: &str) -> CacheKey {
+        sources_cache_key(&self.sources, &format!("{} {}", self.preprocess, options))
+    }
+
+    /// The text the command works on, which the spans of its diagnostics
+    /// refer to.
+    fn text(&self) -> &str {
+        match &self.unit {
+            Some(unit) => &unit.text,
+            None => self.sources.text(self.root()),
+        }
+    }
+
+    fn root(&self) -> FileId {
+        self.sources
+            .files()
+            .next()
+            .expect("the input is loaded first")
+    }
+
+    /// Reports `diagnostics` about [`Self::text`] as `reporter` does, each
+    /// in the file it is about, and returns the ones printed.
+    fn report(
+        &self,
+        reporter: &Reporter,
+        scanner: &ScannerArgs,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let files = source_files(&self.sources, scanner);
+        let Some(unit) = &self.unit else {
+            return reporter.report(&self.path, &files[0], diagnostics);
+        };
+        let mut reported = Vec::new();
+        for diagnostic in diagnostics {
+            let (file, diagnostic) = unit.locate_diagnostic(diagnostic);
+            reported.extend(reporter.report(
+                self.sources.path(file),
+                &files[file.index()],
+                vec![diagnostic],
+            ));
+        }
+        reported
+    }
+}
+
+/// Parses `input` and prints its tree. Syntax errors go to stderr and are
+/// returned, and the tree of whatever did parse is still printed. With
+/// `typeck` options, a program without syntax errors is also resolved and
+/// type checked, and their errors are reported the same way.
+fn run_parser(
+    input: &Input,
+    output_path: Option<&PathBuf>,
+    tree: TreeOutput,
+    typeck: Option<&TypeckOptions>,
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+    cache: &mut Cache,
+) -> Result<Vec<Diagnostic>> {
+    let source = input.text();
+    let (stage, options) = match typeck {
+        Some(typeck) => (
+            Stage::Typed,
+            format!("{} {:?}", check_cache_key(scanner, typeck, reporter), tree),
+        ),
+        None => (Stage::Tree, format!("{} {:?}", scanner.cache_key(), tree)),
+    };
+    let (output, diagnostics) = cached(
+        cache,
+        stage,
+        input.cache_key(&options),
+        || {
+            let Analysis {
+                program,
+                diagnostics,
+                ..
+            } = analyze(input, typeck, scanner);
+            let output = match tree {
+                TreeOutput::Ast(format) => dump(&program, format),
+                TreeOutput::Cst => {
+                    let tokens = scanner.scanner(source).with_trivia(true).collect();
+                    let tree = cst::build(&program, tokens).to_string();
+                    tree.trim_end().to_string()
+                }
+            };
+            Ok((output, diagnostics))
+        },
+        |diagnostics| input.report(reporter, scanner, diagnostics),
+    )?;
 
     match output_path {
         Some(path) => fs::write(path, output)?,
         None => println!("{}", output),
     }
 
+    Ok(diagnostics)
+}
+
+/// Type checks `input` and reports what is wrong with it, printing nothing
+/// else, and returns what it reported.
+fn run_check(
+    input: &Input,
+    typeck: &TypeckOptions,
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+    cache: &mut Cache,
+) -> Result<Vec<Diagnostic>> {
+    // The tree `parse --check` prints, so each command can reuse what the
+    // other stored.
+    let options = format!(
+        "{} {:?}",
+        check_cache_key(scanner, typeck, reporter),
+        TreeOutput::Ast(DumpFormat::Sexpr)
+    );
+    let (_, diagnostics) = cached(
+        cache,
+        Stage::Typed,
+        input.cache_key(&options),
+        || {
+            let Analysis {
+                program,
+                diagnostics,
+                ..
+            } = analyze(input, Some(typeck), scanner);
+            Ok((dump(&program, DumpFormat::Sexpr), diagnostics))
+        },
+        |diagnostics| input.report(reporter, scanner, diagnostics),
+    )?;
+    Ok(diagnostics)
+}
+
+/// Checks `input` and prints the graph of each of its functions after the
+/// passes up to `after`. Errors are reported as `check` reports them, and
+/// then nothing is printed.
+fn run_ir(
+    input: &Input,
+    after: Option<Pass>,
+    typeck: &TypeckOptions,
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+    cache: &mut Cache,
+) -> Result<Vec<Diagnostic>> {
+    let options = format!("{} {:?}", check_cache_key(scanner, typeck, reporter), after);
+    let (output, diagnostics) = cached(
+        cache,
+        Stage::Ir,
+        input.cache_key(&options),
+        || ir_graphs(input, after, typeck, scanner, reporter),
+        |diagnostics| input.report(reporter, scanner, diagnostics),
+    )?;
+    print!("{}", output);
+    Ok(diagnostics)
+}
+
+/// The graphs `ir` prints for `input`, or nothing if checking it found
+/// errors at the levels `reporter` reports them; and the diagnostics, not
+/// yet reported.
+fn ir_graphs(
+    input: &Input,
+    after: Option<Pass>,
+    typeck: &TypeckOptions,
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+) -> Result<(String, Vec<Diagnostic>)> {
+    let Analysis {
+        mut db,
+        file,
+        program,
+        diagnostics,
+    } = analyze(input, Some(typeck), scanner);
+    let errors = reporter.lints.apply(diagnostics.clone());
+    if errors.iter().any(|d| d.is_error()) {
+        return Ok((String::new(), diagnostics));
+    }
+
+    let graphs: Vec<String> = if after >= Some(Pass::Lower) {
+        let ir = db.ir(file);
+        ir.functions
+            .iter()
+            .map(|function| {
+                let Item::Function(item) = &ir.program.items[function.item] else {
+                    unreachable!("the IR only has graphs of functions");
+                };
+                let ssa = (after >= Some(Pass::Ssa)).then_some(&function.ssa);
+                analysis::print::print(&ir.program.arena, item, &function.cfg, ssa)
+            })
+            .collect()
+    } else {
+        program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(function) => Some(function),
+                _ => None,
+            })
+            .map(|function| {
+                let cfg = Cfg::build(&program.arena, function);
+                analysis::print::print(&program.arena, function, &cfg, None)
+            })
+            .collect()
+    };
+    Ok((graphs.join("\n"), diagnostics))
+}
+
+/// Compiles `input_path` to bytecode and prints its disassembly, after
+/// reporting diagnostics; errors stop it with exit status 1.
+fn run_disasm(
+    input_path: &Path,
+    typeck: &TypeckOptions,
+    scanner: &ScannerArgs,
+    reporter: &Reporter,
+) -> Result<()> {
+    let input = Input::read(input_path, &PreprocessArgs::default(), scanner)?;
+    let Analysis {
+        mut db,
+        file,
+        diagnostics,
+        ..
+    } = analyze(&input, Some(typeck), scanner);
+    let diagnostics = input.report(reporter, scanner, diagnostics);
+    exit_on_errors(&diagnostics);
+
+    let typed = db.typeck(file);
+    let resolved = db.resolve(file);
+    let module = bytecode::emit(&typed.program, &resolved.resolution, &typed.consts)?;
+    print!("{}", bytecode::disassemble(&module));
     Ok(())
 }
 
+/// Stable description of everything besides the source that decides what
+/// checking it reports, mixed into cache keys.
+fn check_cache_key(scanner: &ScannerArgs, typeck: &TypeckOptions, reporter: &Reporter) -> String {
+    format!(
+        "{} {:?} {}",
+        scanner.cache_key(),
+        typeck,
+        reporter.cache_key()
+    )
+}
+
+/// The artifact of `stage` under `key`, from `cache` or else made by
+/// `produce`, and the diagnostics that came with it as `report` reports
+/// them. An artifact is stored with its diagnostics unless one of them was
+/// reported as an error, so warnings are reported again on a hit while a
+/// file with errors is always checked again.
+fn cached(
+    cache: &mut Cache,
+    stage: Stage,
+    key: CacheKey,
+    produce: impl FnOnce() -> Result<(String, Vec<Diagnostic>)>,
+    report: impl FnOnce(Vec<Diagnostic>) -> Vec<Diagnostic>,
+) -> Result<(String, Vec<Diagnostic>)> {
+    if let Some((artifact, diagnostics)) = cache.get(stage, key) {
+        return Ok((artifact, report(diagnostics)));
+    }
+    let (artifact, diagnostics) = produce()?;
+    let reported = report(diagnostics.clone());
+    if !reported.iter().any(|d| d.is_error()) {
+        // A cache that cannot be written is not a compilation error.
+        let _ = cache.put(stage, key, &artifact, &diagnostics);
+    }
+    Ok((artifact, reported))
+}
+
+/// Exits with status 1 if any of the reported `diagnostics` is an error.
+fn exit_on_errors(diagnostics: &[Diagnostic]) {
+    if diagnostics.iter().any(|d| d.is_error()) {
+        std::process::exit(1);
+    }
+}
+
+/// What [`analyze`] found out about a file.
+struct Analysis {
+    /// The database the file was analyzed in, whose queries hold the
+    /// results of each phase.
+    db: Database,
+    file: FileId,
+    /// The program as far as the analysis went: type checked, or only
+    /// parsed.
+    program: Program,
+    /// Diagnostics about [`Input::text`].
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Parses `input` and, with `typeck` options and no syntax or directive
+/// errors that left part of it out of the tree, resolves, evaluates, type
+/// checks and lints it, all through the queries of a [`Database`].
+fn analyze(input: &Input, typeck: Option<&TypeckOptions>, scanner: &ScannerArgs) -> Analysis {
+    let options = typeck.cloned().unwrap_or_default();
+    let mut db = Database::with_options(scanner.options(), options);
+    let file = match &input.unit {
+        Some(unit) => db.set_source_tokens(&input.path, unit.text.clone(), unit.tokens.clone()),
+        None => db.set_source_text(&input.path, input.text()),
+    };
+    let parsed = db.parse(file);
+    let mut diagnostics = input.directive_errors.clone();
+    diagnostics.extend(parsed.diagnostics.iter().cloned());
+    let complete = !diagnostics
+        .iter()
+        .any(|d| d.is_error() && !parser::keeps_tree(d));
+    let program = if typeck.is_some() && complete {
+        diagnostics.extend(db.resolve(file).diagnostics.iter().cloned());
+        let typed = db.typeck(file);
+        diagnostics.extend(typed.diagnostics.iter().cloned());
+        typed.program.clone()
+    } else {
+        parsed.program.clone()
+    };
+    Analysis {
+        db,
+        file,
+        program,
+        diagnostics,
+    }
+}
+
+/// The listing of every token but the errors, which are returned as
+/// diagnostics.
+fn lex_to_string(scanner: Scanner, file: &SourceFile) -> (String, Vec<Diagnostic>) {
+    let mut errors = Vec::new();
+    let mut lines = Vec::new();
+    for t in scanner {
+        match &t.error {
+            Some(error) => errors.push(error.to_diagnostic(t.span)),
+            None => lines.push(format!("{} {}", file.line_col(t.span.start), t)),
+        }
+    }
+    (lines.join("\n"), errors)
+}
+
 fn run_tests() -> Result<()> {
     println!("Running lexer tests via `cargo test`...");
     // The actual tests are in src/lexer/mod.rs (unit tests).
     // This CLI command just delegates to the test runner.
     Ok(())
-}
\ No newline at end of file
+}
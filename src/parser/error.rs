@@ -0,0 +1,154 @@
+use crate::diagnostics::{codes, Code, Diagnostic};
+use crate::lexer::{LexicalError, TokenType};
+use crate::source::Span;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// A token that none of the alternatives in `expected` can start, as in
+    /// "expected ';' or '=', found '{'".
+    #[error("expected {}, found {}", one_of(expected), found.description())]
+    Expected {
+        expected: Vec<Expected>,
+        found: TokenType,
+        span: Span,
+    },
+
+    /// The lexer could not make a token out of the input here.
+    #[error("{error}")]
+    Lexical { error: LexicalError, span: Span },
+
+    /// A statement or declaration ends at a line break, `}` or the start of
+    /// another statement without its `;`. `span` is the empty span where
+    /// the `;` belongs.
+    #[error("missing ';'")]
+    MissingSemicolon { span: Span },
+
+    #[error("invalid assignment target")]
+    InvalidAssignmentTarget { span: Span },
+
+    /// `x++` or `x--` whose value is used. Only a statement, or the first
+    /// or last part of a `for` header, may consist of one.
+    #[error("the value of a postfix {} cannot be used", op.description())]
+    PostfixIncrementValue { op: TokenType, span: Span },
+
+    /// Nesting past the parser's depth limit, which stops the recursion
+    /// before it overflows the stack.
+    #[error("{construct} too deeply nested")]
+    TooDeep { construct: &'static str, span: Span },
+
+    /// A struct declaration or literal names the same field twice.
+    #[error("field '{name}' is listed more than once")]
+    DuplicateField {
+        name: String,
+        span: Span,
+        /// The earlier occurrence.
+        first: Span,
+    },
+
+    /// A generic function or struct names the same type parameter twice.
+    #[error("type parameter '{name}' is listed more than once")]
+    DuplicateTypeParam {
+        name: String,
+        span: Span,
+        /// The earlier occurrence.
+        first: Span,
+    },
+
+    /// An enum declaration names the same variant twice.
+    #[error("variant '{name}' is listed more than once")]
+    DuplicateVariant {
+        name: String,
+        span: Span,
+        /// The earlier occurrence.
+        first: Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Expected { span, .. }
+            | ParseError::Lexical { span, .. }
+            | ParseError::MissingSemicolon { span }
+            | ParseError::InvalidAssignmentTarget { span }
+            | ParseError::PostfixIncrementValue { span, .. }
+            | ParseError::TooDeep { span, .. }
+            | ParseError::DuplicateField { span, .. }
+            | ParseError::DuplicateTypeParam { span, .. }
+            | ParseError::DuplicateVariant { span, .. } => *span,
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            ParseError::Expected { .. } => codes::UNEXPECTED_TOKEN,
+            ParseError::Lexical { error, .. } => error.code(),
+            ParseError::MissingSemicolon { .. } => codes::MISSING_SEMICOLON,
+            ParseError::InvalidAssignmentTarget { .. } => codes::INVALID_ASSIGNMENT_TARGET,
+            ParseError::PostfixIncrementValue { .. } => codes::POSTFIX_INCREMENT_VALUE,
+            ParseError::TooDeep { .. } => codes::TOO_DEEPLY_NESTED,
+            ParseError::DuplicateField { .. } => codes::DUPLICATE_FIELD,
+            ParseError::DuplicateTypeParam { .. } => codes::DUPLICATE_TYPE_PARAM,
+            ParseError::DuplicateVariant { .. } => codes::DUPLICATE_VARIANT,
+        }
+    }
+}
+
+/// Something the parser would have accepted where it found an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    Token(TokenType),
+    /// A construct that can start in several ways, such as "expression".
+    Construct(&'static str),
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Token(token_type) => f.write_str(token_type.description()),
+            Expected::Construct(name) => f.write_str(name),
+        }
+    }
+}
+
+/// `a`, `a or b`, `a, b or c`.
+fn one_of(expected: &[Expected]) -> String {
+    match expected {
+        [] => "nothing".to_string(),
+        [only] => only.to_string(),
+        [rest @ .., last] => {
+            let rest: Vec<String> = rest.iter().map(Expected::to_string).collect();
+            format!("{} or {}", rest.join(", "), last)
+        }
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Self {
+        let diagnostic = Diagnostic::error(error.code(), error.to_string(), error.span());
+        match error {
+            ParseError::Lexical { error, span } => error.to_diagnostic(span),
+            ParseError::DuplicateField { first, .. }
+            | ParseError::DuplicateTypeParam { first, .. }
+            | ParseError::DuplicateVariant { first, .. } => {
+                diagnostic.with_label(first, "first listed here")
+            }
+            ParseError::MissingSemicolon { span } => {
+                diagnostic.with_fixit(span, ";", "insert ';' here")
+            }
+            ParseError::PostfixIncrementValue { op, .. } => {
+                let (prefix, compound) = match op {
+                    TokenType::PlusPlus => ("++x", "x += 1"),
+                    _ => ("--x", "x -= 1"),
+                };
+                diagnostic.with_note(format!(
+                    "write '{}' for the updated value, or '{}' as a statement of its own",
+                    prefix, compound
+                ))
+            }
+            _ => diagnostic,
+        }
+    }
+}
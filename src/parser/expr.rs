@@ -0,0 +1,455 @@
+//! Expression parsing by precedence climbing (Pratt parsing).
+//!
+//! Infix operators are described by [`INFIX_OPERATORS`]; supporting a new
+//! operator token means adding a row there (and a case to the AST). Prefix
+//! operators, listed in [`prefix_operator`], bind tighter than any infix
+//! operator but looser than calls, field accesses and subscripts.
+//!
+//! `++x` and `--x` are parsed as `x += 1` and `x -= 1`, which have the same
+//! effect and value. So are `x++` and `x--`, whose value would be the old
+//! one, and which are therefore only accepted where the value is thrown
+//! away: as a whole expression statement or `for` step.
+
+use super::{Expected, ParseError, ParseResult, Parser};
+use crate::ast::{ArmBody, BinaryOp, ExprId, ExprKind, FieldInit, Ident, MatchArm, UnaryOp};
+use crate::lexer::{LiteralValue, TokenType};
+use crate::source::Span;
+use Assoc::{Left, Right};
+use Infix::{Assign, Binary, Conditional};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// What an infix operator token builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Infix {
+    Binary(BinaryOp),
+    /// `=`, or a compound assignment such as `+=` applying the operator.
+    Assign(Option<BinaryOp>),
+    /// `cond ? a : b`
+    Conditional,
+}
+
+/// One row of the operator table.
+#[derive(Debug, Clone, Copy)]
+pub struct InfixOperator {
+    pub token: TokenType,
+    /// Binding strength; higher binds tighter.
+    pub precedence: u8,
+    pub assoc: Assoc,
+    pub infix: Infix,
+}
+
+const fn op(token: TokenType, precedence: u8, assoc: Assoc, infix: Infix) -> InfixOperator {
+    InfixOperator {
+        token,
+        precedence,
+        assoc,
+        infix,
+    }
+}
+
+/// Every infix operator, loosest first. The levels follow C.
+pub const INFIX_OPERATORS: &[InfixOperator] = &[
+    op(TokenType::Equal, 1, Right, Assign(None)),
+    op(TokenType::PlusEqual, 1, Right, Assign(Some(BinaryOp::Add))),
+    op(TokenType::MinusEqual, 1, Right, Assign(Some(BinaryOp::Sub))),
+    op(TokenType::StarEqual, 1, Right, Assign(Some(BinaryOp::Mul))),
+    op(TokenType::SlashEqual, 1, Right, Assign(Some(BinaryOp::Div))),
+    op(
+        TokenType::PercentEqual,
+        1,
+        Right,
+        Assign(Some(BinaryOp::Rem)),
+    ),
+    op(
+        TokenType::AmpEqual,
+        1,
+        Right,
+        Assign(Some(BinaryOp::BitAnd)),
+    ),
+    op(
+        TokenType::PipeEqual,
+        1,
+        Right,
+        Assign(Some(BinaryOp::BitOr)),
+    ),
+    op(
+        TokenType::CaretEqual,
+        1,
+        Right,
+        Assign(Some(BinaryOp::BitXor)),
+    ),
+    op(TokenType::ShlEqual, 1, Right, Assign(Some(BinaryOp::Shl))),
+    op(TokenType::ShrEqual, 1, Right, Assign(Some(BinaryOp::Shr))),
+    op(TokenType::Question, 2, Right, Conditional),
+    op(TokenType::OrOr, 3, Left, Binary(BinaryOp::Or)),
+    op(TokenType::AndAnd, 4, Left, Binary(BinaryOp::And)),
+    op(TokenType::Pipe, 5, Left, Binary(BinaryOp::BitOr)),
+    op(TokenType::Caret, 6, Left, Binary(BinaryOp::BitXor)),
+    op(TokenType::Amp, 7, Left, Binary(BinaryOp::BitAnd)),
+    op(TokenType::EqualEqual, 8, Left, Binary(BinaryOp::Eq)),
+    op(TokenType::NotEqual, 8, Left, Binary(BinaryOp::Ne)),
+    op(TokenType::Less, 9, Left, Binary(BinaryOp::Lt)),
+    op(TokenType::LessEqual, 9, Left, Binary(BinaryOp::Le)),
+    op(TokenType::Greater, 9, Left, Binary(BinaryOp::Gt)),
+    op(TokenType::GreaterEqual, 9, Left, Binary(BinaryOp::Ge)),
+    op(TokenType::Shl, 10, Left, Binary(BinaryOp::Shl)),
+    op(TokenType::Shr, 10, Left, Binary(BinaryOp::Shr)),
+    op(TokenType::Plus, 11, Left, Binary(BinaryOp::Add)),
+    op(TokenType::Minus, 11, Left, Binary(BinaryOp::Sub)),
+    op(TokenType::Star, 12, Left, Binary(BinaryOp::Mul)),
+    op(TokenType::Slash, 12, Left, Binary(BinaryOp::Div)),
+    op(TokenType::Percent, 12, Left, Binary(BinaryOp::Rem)),
+];
+
+/// The table row for `token`, if it is an infix operator.
+pub fn infix_operator(token: TokenType) -> Option<&'static InfixOperator> {
+    INFIX_OPERATORS.iter().find(|op| op.token == token)
+}
+
+/// The operator `++` or `--` applies to its operand, if `token` is one.
+fn increment_operator(token: TokenType) -> Option<BinaryOp> {
+    match token {
+        TokenType::PlusPlus => Some(BinaryOp::Add),
+        TokenType::MinusMinus => Some(BinaryOp::Sub),
+        _ => None,
+    }
+}
+
+/// Whether `token` after an operand makes it part of a larger expression.
+fn continues_operand(token: TokenType) -> bool {
+    infix_operator(token).is_some()
+        || increment_operator(token).is_some()
+        || matches!(
+            token,
+            TokenType::LParen | TokenType::Dot | TokenType::LBracket
+        )
+}
+
+/// The operator a prefix token applies, if it is one.
+pub fn prefix_operator(token: TokenType) -> Option<UnaryOp> {
+    match token {
+        TokenType::Minus => Some(UnaryOp::Neg),
+        TokenType::Bang => Some(UnaryOp::Not),
+        TokenType::Tilde => Some(UnaryOp::BitNot),
+        _ => None,
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub(super) fn expression(&mut self) -> ParseResult<ExprId> {
+        self.expression_above(0)
+    }
+
+    /// An expression whose value is thrown away, which may be a postfix
+    /// `x++`.
+    pub(super) fn discarded_expression(&mut self) -> ParseResult<ExprId> {
+        let start = self.tokens.peek().span.start;
+        let outer = self.discarded.replace(start);
+        let expr = self.expression();
+        self.discarded = outer;
+        expr
+    }
+
+    /// An expression whose infix operators all bind at least as tightly as
+    /// `min_precedence`.
+    fn expression_above(&mut self, min_precedence: u8) -> ParseResult<ExprId> {
+        self.nested("expression", |parser| {
+            parser.expression_above_inner(min_precedence)
+        })
+    }
+
+    fn expression_above_inner(&mut self, min_precedence: u8) -> ParseResult<ExprId> {
+        let mut left = self.chain(Self::unary)?;
+        while let Some(&operator) = infix_operator(self.tokens.peek().token_type) {
+            if operator.precedence < min_precedence {
+                break;
+            }
+            self.deepen("expression")?;
+            self.tokens.next_token();
+            let start = self.arena[left].span;
+            let next_min = match operator.assoc {
+                Assoc::Left => operator.precedence + 1,
+                Assoc::Right => operator.precedence,
+            };
+            let kind = match operator.infix {
+                Infix::Binary(op) => ExprKind::Binary {
+                    op,
+                    left,
+                    right: self.expression_above(next_min)?,
+                },
+                Infix::Assign(op) => {
+                    if !self.is_assignable(left) {
+                        return Err(ParseError::InvalidAssignmentTarget { span: start });
+                    }
+                    ExprKind::Assign {
+                        op,
+                        target: left,
+                        value: self.expression_above(next_min)?,
+                    }
+                }
+                Infix::Conditional => {
+                    // Anything may appear between `?` and `:`, as in C.
+                    let then_expr = self.expression()?;
+                    self.expect(TokenType::Colon)?;
+                    ExprKind::Conditional {
+                        condition: left,
+                        then_expr,
+                        else_expr: self.expression_above(next_min)?,
+                    }
+                }
+            };
+            left = self.alloc_expr(kind, start.to(self.tokens.prev_span()));
+        }
+        Ok(left)
+    }
+
+    /// Prefix operators applied to a postfix expression, so `-a[i]` negates
+    /// `a[i]`.
+    fn unary(&mut self) -> ParseResult<ExprId> {
+        let token_type = self.tokens.peek().token_type;
+        if let Some(op) = increment_operator(token_type) {
+            let start = self.tokens.next_token().span;
+            let target = self.nested("expression", Self::unary)?;
+            let span = start.to(self.arena[target].span);
+            return self.increment(op, target, start, span);
+        }
+        let Some(op) = prefix_operator(token_type) else {
+            return self.postfix();
+        };
+        let start = self.tokens.next_token().span;
+        let operand = self.nested("expression", Self::unary)?;
+        let span = start.to(self.arena[operand].span);
+        Ok(self.alloc_expr(ExprKind::Unary { op, operand }, span))
+    }
+
+    /// A primary expression followed by any number of calls, `.field`
+    /// accesses and `[index]` subscripts.
+    fn postfix(&mut self) -> ParseResult<ExprId> {
+        let mut expr = self.primary()?;
+        loop {
+            let start = self.arena[expr].span;
+            let token_type = self.tokens.peek().token_type;
+            let increment = increment_operator(token_type);
+            if increment.is_none()
+                && !matches!(
+                    token_type,
+                    TokenType::Dot | TokenType::LParen | TokenType::LBracket
+                )
+            {
+                return Ok(expr);
+            }
+            self.deepen("expression")?;
+            if self.tokens.eat(TokenType::Dot) {
+                let field = self.ident()?;
+                let span = start.to(field.span);
+                expr = self.alloc_expr(
+                    ExprKind::Field {
+                        object: expr,
+                        field,
+                    },
+                    span,
+                );
+            } else if self.tokens.check(TokenType::LParen) {
+                let args = self.list(TokenType::LParen, TokenType::RParen)?;
+                let span = start.to(self.tokens.prev_span());
+                expr = self.alloc_expr(ExprKind::Call { callee: expr, args }, span);
+            } else if self.tokens.eat(TokenType::LBracket) {
+                let index = self.expression()?;
+                let end = self.expect(TokenType::RBracket)?.span;
+                expr = self.alloc_expr(
+                    ExprKind::Index {
+                        object: expr,
+                        index,
+                    },
+                    start.to(end),
+                );
+            } else if let Some(op) = increment {
+                let token = self.tokens.next_token();
+                // The operand starts the expression, and nothing continues
+                // it, so `x++` is all of it.
+                let whole = self.discarded == Some(start.start)
+                    && !continues_operand(self.tokens.peek().token_type);
+                if !whole {
+                    let error = ParseError::PostfixIncrementValue {
+                        op: token.token_type,
+                        span: token.span,
+                    };
+                    self.diagnostics.push(error.into());
+                }
+                expr = self.increment(op, expr, token.span, start.to(token.span))?;
+            }
+        }
+    }
+
+    /// `target op= 1`, for `++` or `--` at `op_span` applied to `target`.
+    fn increment(
+        &mut self,
+        op: BinaryOp,
+        target: ExprId,
+        op_span: Span,
+        span: Span,
+    ) -> ParseResult<ExprId> {
+        if !self.is_assignable(target) {
+            let span = self.arena[target].span;
+            return Err(ParseError::InvalidAssignmentTarget { span });
+        }
+        let one = self.alloc_expr(ExprKind::Literal(LiteralValue::Integer(1)), op_span);
+        let kind = ExprKind::Assign {
+            op: Some(op),
+            target,
+            value: one,
+        };
+        Ok(self.alloc_expr(kind, span))
+    }
+
+    fn primary(&mut self) -> ParseResult<ExprId> {
+        let token_type = self.tokens.peek().token_type;
+        match token_type {
+            // The lexer attaches the value to literal and keyword tokens.
+            TokenType::IntLiteral
+            | TokenType::FloatLiteral
+            | TokenType::StringLiteral
+            | TokenType::BoolLiteral
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Null => {
+                let token = self.tokens.next_token();
+                Ok(self.alloc_expr(ExprKind::Literal(token.literal), token.span))
+            }
+            TokenType::Identifier if self.at_struct_literal() => self.struct_literal(),
+            TokenType::Identifier
+                if self.tokens.peek_nth(1).token_type == TokenType::ColonColon =>
+            {
+                let (enum_name, variant) = self.path()?;
+                let span = enum_name.span.to(variant.span);
+                Ok(self.alloc_expr(ExprKind::Variant { enum_name, variant }, span))
+            }
+            TokenType::Identifier => {
+                let name = self.ident()?;
+                let span = name.span;
+                Ok(self.alloc_expr(ExprKind::Variable(name), span))
+            }
+            TokenType::LBracket => self.array_literal(),
+            TokenType::Match => self.match_expr(),
+            TokenType::LParen => {
+                let start = self.tokens.next_token().span;
+                let expr = self.struct_literals(true, Self::expression)?;
+                let end = self.expect(TokenType::RParen)?.span;
+                Ok(self.alloc_expr(ExprKind::Grouping(expr), start.to(end)))
+            }
+            _ => Err(self.unexpected(Expected::Construct("expression"))),
+        }
+    }
+
+    /// `Enum::Variant`
+    pub(super) fn path(&mut self) -> ParseResult<(Ident, Ident)> {
+        let enum_name = self.ident()?;
+        self.expect(TokenType::ColonColon)?;
+        Ok((enum_name, self.ident()?))
+    }
+
+    /// `match scrutinee { pattern => body, ... }`. Arms are separated by
+    /// commas, which may be left out after a block body, and the last arm
+    /// may have one too.
+    pub(super) fn match_expr(&mut self) -> ParseResult<ExprId> {
+        let start = self.expect(TokenType::Match)?.span;
+        let scrutinee = self.struct_literals(false, Self::expression)?;
+        self.expect(TokenType::LBrace)?;
+        let mut arms = Vec::new();
+        while !self.tokens.check(TokenType::RBrace) {
+            let pattern = self.pattern()?;
+            self.expect(TokenType::FatArrow)?;
+            let body = if self.tokens.check(TokenType::LBrace) {
+                ArmBody::Block(self.block()?)
+            } else {
+                ArmBody::Expr(self.expression()?)
+            };
+            let is_block = matches!(body, ArmBody::Block(_));
+            arms.push(MatchArm {
+                id: self.next_id(),
+                span: pattern.span.to(self.tokens.prev_span()),
+                pattern,
+                body,
+            });
+            if !self.eat(TokenType::Comma) && !is_block {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span;
+        Ok(self.alloc_expr(ExprKind::Match { scrutinee, arms }, start.to(end)))
+    }
+
+    /// `[a, b, c]`, with an optional trailing comma.
+    fn array_literal(&mut self) -> ParseResult<ExprId> {
+        let start = self.tokens.peek().span;
+        let elements = self.list(TokenType::LBracket, TokenType::RBracket)?;
+        let span = start.to(self.tokens.prev_span());
+        Ok(self.alloc_expr(ExprKind::Array(elements), span))
+    }
+
+    /// Comma-separated expressions between `open` and `close`, with an
+    /// optional trailing comma.
+    fn list(&mut self, open: TokenType, close: TokenType) -> ParseResult<Vec<ExprId>> {
+        self.expect(open)?;
+        let mut exprs = Vec::new();
+        while !self.tokens.check(close) {
+            exprs.push(self.struct_literals(true, Self::expression)?);
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(close)?;
+        Ok(exprs)
+    }
+
+    /// Whether the next tokens are `Name {` followed by `}` or `field:`.
+    fn at_struct_literal(&mut self) -> bool {
+        self.struct_literals
+            && self.tokens.peek_nth(1).token_type == TokenType::LBrace
+            && match self.tokens.peek_nth(2).token_type {
+                TokenType::RBrace => true,
+                TokenType::Identifier => self.tokens.peek_nth(3).token_type == TokenType::Colon,
+                _ => false,
+            }
+    }
+
+    /// `Name { field: value, ... }`, with an optional trailing comma.
+    fn struct_literal(&mut self) -> ParseResult<ExprId> {
+        let name = self.ident()?;
+        self.expect(TokenType::LBrace)?;
+        let mut fields: Vec<FieldInit> = Vec::new();
+        while !self.tokens.check(TokenType::RBrace) {
+            let field = self.ident()?;
+            let unique = self.check_unique(&fields, |f| &f.name, &field);
+            self.expect(TokenType::Colon)?;
+            let value = self.expression()?;
+            let field = FieldInit {
+                id: self.next_id(),
+                span: field.span.to(self.arena[value].span),
+                name: field,
+                value,
+            };
+            if unique {
+                fields.push(field);
+            }
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span;
+        let span = name.span.to(end);
+        Ok(self.alloc_expr(ExprKind::StructLiteral { name, fields }, span))
+    }
+
+    fn is_assignable(&self, expr: ExprId) -> bool {
+        matches!(
+            self.arena.ungrouped(expr).kind,
+            ExprKind::Variable(_) | ExprKind::Field { .. } | ExprKind::Index { .. }
+        )
+    }
+}
@@ -0,0 +1,271 @@
+//! Reparsing a program after an edit, for editors that reparse on every
+//! keystroke.
+//!
+//! Items are parsed independently of each other, so only the items near
+//! the edit need parsing again:
+//!
+//! - Items before the one the edit falls in are kept, with their ids and
+//!   diagnostics. Parsing resumes at the first token of that item, which
+//!   must end before the edit.
+//! - From there items are parsed as usual, until the parser reaches the
+//!   start of an old item that lies wholly after the edit. The text from
+//!   there on is unchanged, so that item and every one after it are kept,
+//!   with their spans and diagnostics moved by the length change.
+//!
+//! A diagnostic starting exactly where an item starts was reported before
+//! the item was parsed, so it goes with the text before the item.
+//!
+//! Reparsed items are numbered from the old [`Program::node_count`] and
+//! allocated in the old arena. The nodes of the items they replace stay
+//! there unused; a full parse now and then reclaims them.
+
+use super::Parser;
+use crate::ast::visit_mut::{MapSpans, MutVisitor};
+use crate::ast::{Item, Program};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Token;
+use crate::source::Span;
+use std::ops::Range;
+
+impl Parser<'_> {
+    /// Parses the edited text, reusing what it can of `old`, the program
+    /// parsed from the text before the edit along with `old_diagnostics`.
+    /// `edit` is the byte range of the old text that was replaced by
+    /// `new_text`, and `tokens` is the complete token stream of the edited
+    /// text, as [`Scanner::relex`](crate::lexer::Scanner::relex) returns.
+    ///
+    /// The parser must be new, over the edited text, with the scanner
+    /// options and symbol table `old` was parsed with. The result is the
+    /// same as parsing the edited text from scratch, apart from node ids.
+    pub fn reparse_program(
+        &mut self,
+        old: Program,
+        old_diagnostics: Vec<Diagnostic>,
+        edit: Range<usize>,
+        new_text: &str,
+        tokens: &[Token<'_>],
+    ) -> (Program, Vec<Diagnostic>) {
+        let delta = new_text.len() as isize - edit.len() as isize;
+        let first_token = |item: &Item| {
+            tokens
+                .binary_search_by_key(&item_span(item).start, |t| t.span.start)
+                .ok()
+        };
+
+        // The last item whose first token ends before the edit is where
+        // parsing resumes; everything before it is kept.
+        let mut old_items = old.items;
+        let resume = old_items
+            .iter()
+            .rposition(|item| first_token(item).is_some_and(|i| tokens[i].span.end < edit.start));
+        let (start, resume_at) = match resume {
+            Some(index) => {
+                let token = first_token(&old_items[index]).expect("found above");
+                (index, token)
+            }
+            None => (0, 0),
+        };
+        let resume_offset = tokens.get(resume_at).map_or(0, |t| t.span.start);
+        let mut after = old_items.split_off(start);
+        let mut items = old_items;
+        let mut diagnostics: Vec<Diagnostic> = old_diagnostics
+            .iter()
+            .filter(|d| resume.is_some() && d.primary_span.start <= resume_offset)
+            .cloned()
+            .collect();
+
+        // Old items that may be reached again, the first of them first.
+        let first_after = after
+            .iter()
+            .position(|item| item_span(item).start >= edit.end)
+            .unwrap_or(after.len());
+        let mut after = after.split_off(first_after).into_iter().peekable();
+
+        self.node_count = old.node_count;
+        self.arena = old.arena;
+        self.tokens.seek(tokens, resume_at);
+        let mut reached = None;
+        while !self.tokens.is_at_end() {
+            let offset = self.tokens.peek().span.start;
+            while after
+                .next_if(|item| shifted(item_span(item).start, delta) < offset)
+                .is_some()
+            {}
+            if let Some(item) = after.peek() {
+                if shifted(item_span(item).start, delta) == offset {
+                    reached = Some(item_span(item).start);
+                    break;
+                }
+            }
+            let before = self.tokens.peek().span;
+            match self.item() {
+                Ok(item) => items.push(item),
+                Err(error) => self.recover(error, before),
+            }
+        }
+        diagnostics.append(&mut self.diagnostics);
+
+        if let Some(reached) = reached {
+            let mut shift = MapSpans::new(&mut self.arena, |span: Span| span.shift(delta));
+            for mut item in after {
+                shift.visit_item(&mut item);
+                items.push(item);
+            }
+            diagnostics.extend(
+                old_diagnostics
+                    .into_iter()
+                    .filter(|d| d.primary_span.start > reached)
+                    .map(|d| d.shift(delta)),
+            );
+        }
+
+        let span = match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span.to(last.span),
+            _ => Span::default(),
+        };
+        let program = Program {
+            items,
+            span,
+            node_count: self.node_count,
+            arena: std::mem::take(&mut self.arena),
+        };
+        (program, diagnostics)
+    }
+}
+
+fn item_span(item: &Item) -> Span {
+    match item {
+        Item::Function(function) => function.span,
+        Item::Struct(decl) => decl.span,
+        Item::Enum(decl) => decl.span,
+        Item::Global(decl) => decl.span,
+    }
+}
+
+fn shifted(offset: usize, delta: isize) -> usize {
+    offset.wrapping_add_signed(delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::dump::{dump, DumpFormat};
+    use crate::ast::visit::Visitor;
+    use crate::ast::{AstArena, Ident, NodeId};
+    use crate::lexer::Scanner;
+    use crate::parser::parse;
+    use crate::utils::SymbolTable;
+    use pretty_assertions::assert_eq;
+
+    const SOURCE: &str = "struct P { x: int }
+fn f(p: P) -> int { return p.x; }
+int g = 1;
+fn h() { let q = P { x: 2 }; while (q.x) { q.x -= 1; } }
+fn k() { x = @; }
+enum E { A, B(int) }
+";
+
+    /// Checks that every identifier's symbol has its name.
+    struct Symbols<'ast> {
+        arena: &'ast AstArena,
+        symbols: &'ast SymbolTable,
+    }
+
+    impl<'ast> Visitor<'ast> for Symbols<'ast> {
+        fn arena(&self) -> &'ast AstArena {
+            self.arena
+        }
+
+        fn visit_ident(&mut self, ident: &'ast Ident) {
+            assert_eq!(self.symbols.resolve(ident.symbol), ident.name);
+        }
+    }
+
+    fn id_and_name(item: &Item) -> (NodeId, &str) {
+        match item {
+            Item::Function(function) => (function.id, &function.name.name),
+            Item::Struct(decl) => (decl.id, &decl.name.name),
+            Item::Enum(decl) => (decl.id, &decl.name.name),
+            Item::Global(decl) => (decl.id, &decl.name.name),
+        }
+    }
+
+    /// Reparses `SOURCE` with `edit` replaced by `text`, checks the result
+    /// against a full parse of the new text, and returns the names of the
+    /// items that were kept rather than reparsed.
+    fn reparse(edit: Range<usize>, text: &str) -> Vec<String> {
+        let mut old_scanner = Scanner::new(SOURCE);
+        let old_tokens: Vec<_> = old_scanner.by_ref().collect();
+        let mut parser =
+            Parser::new(Scanner::new(SOURCE).with_symbol_table(old_scanner.into_symbols()));
+        let (old, old_diagnostics) = parser.parse_program();
+        let old_ids: Vec<_> = old.items.iter().map(|item| id_and_name(item).0).collect();
+
+        let mut new_src = SOURCE.to_string();
+        new_src.replace_range(edit.clone(), text);
+        let mut scanner = Scanner::new(&new_src).with_symbol_table(parser.symbols().clone());
+        let tokens = scanner.relex(edit.clone(), text, &old_tokens);
+        let mut parser =
+            Parser::new(Scanner::new(&new_src).with_symbol_table(scanner.into_symbols()));
+        let (program, diagnostics) =
+            parser.reparse_program(old, old_diagnostics, edit.clone(), text, &tokens);
+
+        let (expected, expected_diagnostics) = parse(&new_src);
+        let context = format!("edit {:?} -> {:?}", edit, text);
+        assert_eq!(
+            dump(&program, DumpFormat::Sexpr),
+            dump(&expected, DumpFormat::Sexpr),
+            "{}",
+            context
+        );
+        assert_eq!(diagnostics, expected_diagnostics, "{}", context);
+        Symbols {
+            arena: &program.arena,
+            symbols: parser.symbols(),
+        }
+        .visit_program(&program);
+        program
+            .items
+            .iter()
+            .map(id_and_name)
+            .filter(|(id, _)| old_ids.contains(id))
+            .map(|(_, name)| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_reparse() {
+        let at = |text: &str| SOURCE.find(text).unwrap();
+
+        // Only the edited function is parsed again.
+        let one = at("1; } }");
+        assert_eq!(reparse(one..one + 1, "10"), ["P", "f", "g", "k", "E"]);
+        let param = at("p: P");
+        assert_eq!(
+            reparse(param..param + 1, "point"),
+            ["P", "g", "h", "k", "E"]
+        );
+        // Fixing the error in `k` drops its diagnostic.
+        let error = at("@");
+        assert_eq!(reparse(error..error + 1, "1"), ["P", "f", "g", "h", "E"]);
+        // A new item between two others. The item before an insertion is
+        // parsed again too, in case the new text continues it.
+        let h = at("fn h");
+        assert_eq!(
+            reparse(h..h, "fn new(a: int) {}\n"),
+            ["P", "f", "h", "k", "E"]
+        );
+        // Edits to the first token of an item, and at either end.
+        assert_eq!(reparse(h..h + 2, "fx"), ["P", "f", "k", "E"]);
+        assert_eq!(reparse(0..0, "int z;\n"), ["P", "f", "g", "h", "k", "E"]);
+        assert_eq!(
+            reparse(SOURCE.len()..SOURCE.len(), "fn end() {}"),
+            ["P", "f", "g", "h", "k"]
+        );
+        // An unclosed function or comment takes in the items after it.
+        let close = at("} }\nfn k");
+        reparse(close..close + 3, "");
+        let comment = at("return");
+        assert_eq!(reparse(comment..comment, "/*"), ["P"]);
+    }
+}
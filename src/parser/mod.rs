@@ -0,0 +1,2102 @@
+//! Recursive-descent parser turning tokens into an [`ast::Program`].
+
+pub mod error;
+pub mod expr;
+pub mod incremental;
+pub mod pattern;
+pub mod stmt;
+
+pub use error::{Expected, ParseError};
+
+use crate::ast::dump::{dump, DumpFormat};
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    ArrayLen, AstArena, EnumDecl, Expr, ExprId, ExprKind, Field, Function, Ident, Item, NodeId,
+    Param, Program, Stmt, StmtId, StmtKind, StructDecl, Type, TypeKind, TypeParam, VarDecl,
+    Variant,
+};
+use crate::diagnostics::{codes, Diagnostic};
+use crate::lexer::{LiteralValue, Scanner, Token, TokenStream, TokenType};
+use crate::lowering;
+use crate::semantic::{self, typeck, TypeckOptions};
+use crate::source::Span;
+use crate::utils::SymbolTable;
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// Parses a token stream into a [`Program`]. After a syntax error the
+/// parser records a diagnostic, skips to the next statement boundary and
+/// carries on, so one mistake does not hide the rest.
+pub struct Parser<'a> {
+    tokens: TokenStream<'a>,
+    diagnostics: Vec<Diagnostic>,
+    /// What would have been accepted at `expected_at`, for the message if
+    /// the token there turns out to be an error.
+    expected: Vec<Expected>,
+    expected_at: Span,
+    /// Number of [`NodeId`]s handed out so far.
+    node_count: usize,
+    /// The expressions and statements parsed so far.
+    arena: AstArena,
+    /// How deep the expression, statement or type being parsed is: one
+    /// level per nested construct, and per operator, call, field access,
+    /// subscript or array suffix a loop wraps around what came before.
+    depth: usize,
+    max_depth: usize,
+    /// Whether `Name {` may start a struct literal. It may not in a `match`
+    /// scrutinee, where the `{` opens the arms, unless it is bracketed.
+    struct_literals: bool,
+    /// Where the expression being parsed starts, if its value is thrown
+    /// away, so that it may be a postfix `x++`.
+    discarded: Option<usize>,
+}
+
+impl<'a> Parser<'a> {
+    pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+    /// How many tokens [`Self::at_var_decl`] looks through for the `>`
+    /// closing a type argument list, which bounds the cost of telling
+    /// `Pair<int> p;` from a comparison such as `a < b;`.
+    const TYPE_ARGS_LOOKAHEAD: usize = 32;
+
+    pub fn new(scanner: Scanner<'a>) -> Self {
+        Self {
+            tokens: TokenStream::new(scanner),
+            diagnostics: Vec::new(),
+            expected: Vec::new(),
+            expected_at: Span::new(0, 0),
+            node_count: 0,
+            arena: AstArena::new(),
+            depth: 0,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            struct_literals: true,
+            discarded: None,
+        }
+    }
+
+    /// Limits how deeply expressions, statements and types may nest. Past
+    /// the limit the parser reports "too deeply nested" rather than
+    /// overflowing the stack.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Symbols of the identifiers parsed so far.
+    pub fn symbols(&self) -> &SymbolTable {
+        self.tokens.scanner().symbols()
+    }
+
+    /// Parses the whole input. The program holds every item that parsed;
+    /// the diagnostics describe the rest.
+    pub fn parse_program(&mut self) -> (Program, Vec<Diagnostic>) {
+        let start = self.tokens.peek().span;
+        let mut items = Vec::new();
+        while !self.tokens.is_at_end() {
+            let before = self.tokens.peek().span;
+            match self.item() {
+                Ok(item) => items.push(item),
+                Err(error) => self.recover(error, before),
+            }
+        }
+        let end = self.tokens.peek().span;
+        let program = Program {
+            items,
+            span: start.to(end),
+            node_count: self.node_count,
+            arena: std::mem::take(&mut self.arena),
+        };
+        (program, std::mem::take(&mut self.diagnostics))
+    }
+
+    /// Parses the whole input as one expression, for callers such as a REPL
+    /// that have no surrounding function. Anything after the expression is
+    /// an error. On success, returns the arena holding the expression with
+    /// the handle of its root.
+    pub fn parse_expression(&mut self) -> Result<(AstArena, ExprId), Vec<Diagnostic>> {
+        let result = self.expression().and_then(|expr| {
+            if self.tokens.is_at_end() {
+                Ok((std::mem::take(&mut self.arena), expr))
+            } else {
+                Err(self.unexpected(Expected::Token(TokenType::EndOfFile)))
+            }
+        });
+        result.map_err(|error| {
+            self.diagnostics.push(error.into());
+            std::mem::take(&mut self.diagnostics)
+        })
+    }
+
+    fn item(&mut self) -> ParseResult<Item> {
+        // Before `fn`, which may start a function type: `fn(int) f;`.
+        if self.at_var_decl() {
+            return self.var_decl().map(Item::Global);
+        }
+        if self.tokens.check(TokenType::Fn) {
+            return self.function().map(Item::Function);
+        }
+        if self.tokens.check(TokenType::Struct) {
+            return self.struct_decl().map(Item::Struct);
+        }
+        if self.tokens.check(TokenType::Enum) {
+            return self.enum_decl().map(Item::Enum);
+        }
+        Err(self.unexpected(Expected::Construct("item")))
+    }
+
+    /// `fn name(a: int, b: int) -> int { ... }`, or a generic function
+    /// such as `fn max<T>(a: T, b: T) -> T { ... }`.
+    fn function(&mut self) -> ParseResult<Function> {
+        let start = self.expect(TokenType::Fn)?.span;
+        let name = self.ident()?;
+        let type_params = self.type_params()?;
+        self.expect(TokenType::LParen)?;
+        let mut params = Vec::new();
+        if !self.check(TokenType::RParen) {
+            loop {
+                params.push(self.param()?);
+                if !self.eat(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenType::RParen)?;
+        let return_type = if self.eat(TokenType::Arrow) {
+            Some(self.ty()?)
+        } else {
+            None
+        };
+        let body = self.block()?;
+        Ok(Function {
+            id: self.next_id(),
+            name,
+            type_params,
+            params,
+            return_type,
+            span: start.to(body.span),
+            body,
+        })
+    }
+
+    /// `struct Name { field: type, ... }` or `struct Name<A, B> { ... }`,
+    /// with an optional trailing comma.
+    fn struct_decl(&mut self) -> ParseResult<StructDecl> {
+        let start = self.expect(TokenType::Struct)?.span;
+        let name = self.ident()?;
+        let type_params = self.type_params()?;
+        self.expect(TokenType::LBrace)?;
+        let mut fields: Vec<Field> = Vec::new();
+        while !self.tokens.check(TokenType::RBrace) {
+            let field_name = self.ident()?;
+            let unique = self.check_unique(&fields, |f| &f.name, &field_name);
+            self.expect(TokenType::Colon)?;
+            let ty = self.ty()?;
+            let field = Field {
+                id: self.next_id(),
+                span: field_name.span.to(ty.span),
+                name: field_name,
+                ty,
+            };
+            if unique {
+                fields.push(field);
+            }
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span;
+        Ok(StructDecl {
+            id: self.next_id(),
+            name,
+            type_params,
+            fields,
+            span: start.to(end),
+        })
+    }
+
+    /// `enum Name { A, B(int, string), ... }`, with an optional trailing
+    /// comma.
+    fn enum_decl(&mut self) -> ParseResult<EnumDecl> {
+        let start = self.expect(TokenType::Enum)?.span;
+        let name = self.ident()?;
+        self.expect(TokenType::LBrace)?;
+        let mut variants: Vec<Variant> = Vec::new();
+        while !self.tokens.check(TokenType::RBrace) {
+            let variant = self.ident()?;
+            // Reported, and then left out like a duplicate field.
+            let first = variants.iter().find(|v| v.name.name == variant.name);
+            let unique = first.is_none();
+            if let Some(first) = first {
+                let error = ParseError::DuplicateVariant {
+                    name: variant.name.clone(),
+                    span: variant.span,
+                    first: first.name.span,
+                };
+                self.diagnostics.push(error.into());
+            }
+            let fields = if self.check(TokenType::LParen) {
+                self.type_list()?
+            } else {
+                Vec::new()
+            };
+            let variant = Variant {
+                id: self.next_id(),
+                span: variant.span.to(self.tokens.prev_span()),
+                name: variant,
+                fields,
+            };
+            if unique {
+                variants.push(variant);
+            }
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span;
+        Ok(EnumDecl {
+            id: self.next_id(),
+            name,
+            variants,
+            span: start.to(end),
+        })
+    }
+
+    /// `<A, B>` after the name of a generic function or struct; nothing for
+    /// one that is not generic.
+    fn type_params(&mut self) -> ParseResult<Vec<TypeParam>> {
+        let mut params: Vec<TypeParam> = Vec::new();
+        if !self.eat(TokenType::Less) {
+            return Ok(params);
+        }
+        loop {
+            let name = self.ident()?;
+            if let Some(first) = params.iter().find(|p| p.name.name == name.name) {
+                return Err(ParseError::DuplicateTypeParam {
+                    name: name.name,
+                    span: name.span,
+                    first: first.span,
+                });
+            }
+            params.push(TypeParam {
+                id: self.next_id(),
+                span: name.span,
+                name,
+            });
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::Greater)?;
+        Ok(params)
+    }
+
+    fn param(&mut self) -> ParseResult<Param> {
+        let name = self.ident()?;
+        self.expect(TokenType::Colon)?;
+        let ty = self.ty()?;
+        Ok(Param {
+            id: self.next_id(),
+            span: name.span.to(ty.span),
+            name,
+            ty,
+        })
+    }
+
+    /// A type name followed by any number of `[len]` suffixes.
+    fn ty(&mut self) -> ParseResult<Type> {
+        self.chain(Self::ty_inner)
+    }
+
+    fn ty_inner(&mut self) -> ParseResult<Type> {
+        let mut ty = self.base_type()?;
+        while self.tokens.check(TokenType::LBracket) {
+            self.deepen("type")?;
+            self.tokens.next_token();
+            let len = self.array_len()?;
+            let end = self.expect(TokenType::RBracket)?.span;
+            ty = Type {
+                id: self.next_id(),
+                span: ty.span.to(end),
+                kind: TypeKind::Array {
+                    element: Box::new(ty),
+                    len,
+                },
+            };
+        }
+        Ok(ty)
+    }
+
+    fn base_type(&mut self) -> ParseResult<Type> {
+        let token_type = self.tokens.peek().token_type;
+        let kind = match token_type {
+            TokenType::Int => TypeKind::Int,
+            TokenType::Float => TypeKind::Float,
+            TokenType::Bool => TypeKind::Bool,
+            TokenType::Char => TypeKind::Char,
+            TokenType::String => TypeKind::String,
+            TokenType::Void => TypeKind::Void,
+            TokenType::Identifier if self.tokens.peek_nth(1).token_type == TokenType::Less => {
+                return self.nested("type", Self::generic_type);
+            }
+            TokenType::Identifier => {
+                let name = self.ident()?;
+                return Ok(Type {
+                    id: self.next_id(),
+                    span: name.span,
+                    kind: TypeKind::Named(name),
+                });
+            }
+            TokenType::Fn => return self.nested("type", Self::function_type),
+            _ => return Err(self.unexpected(Expected::Construct("type"))),
+        };
+        let span = self.tokens.next_token().span;
+        Ok(Type {
+            id: self.next_id(),
+            kind,
+            span,
+        })
+    }
+
+    /// `Pair<int, float>`. The closing `>` may be the first half of a `>>`,
+    /// as in `Pair<Pair<int, int>>`.
+    fn generic_type(&mut self) -> ParseResult<Type> {
+        let name = self.ident()?;
+        self.expect(TokenType::Less)?;
+        let mut args = Vec::new();
+        loop {
+            args.push(self.ty()?);
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        self.tokens.split_greater();
+        let end = self.expect(TokenType::Greater)?.span;
+        Ok(Type {
+            id: self.next_id(),
+            span: name.span.to(end),
+            kind: TypeKind::Generic { name, args },
+        })
+    }
+
+    /// `fn(int, float) -> int`. Array suffixes after the return type belong
+    /// to it, so `fn() -> int[2]` returns an array.
+    fn function_type(&mut self) -> ParseResult<Type> {
+        let start = self.expect(TokenType::Fn)?.span;
+        let params = self.type_list()?;
+        let return_type = if self.eat(TokenType::Arrow) {
+            Some(Box::new(self.ty()?))
+        } else {
+            None
+        };
+        Ok(Type {
+            id: self.next_id(),
+            span: start.to(self.tokens.prev_span()),
+            kind: TypeKind::Function {
+                params,
+                return_type,
+            },
+        })
+    }
+
+    /// `(type, ...)`, as in a function type or an enum variant.
+    fn type_list(&mut self) -> ParseResult<Vec<Type>> {
+        self.expect(TokenType::LParen)?;
+        let mut types = Vec::new();
+        if !self.check(TokenType::RParen) {
+            loop {
+                types.push(self.ty()?);
+                if !self.eat(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenType::RParen)?;
+        Ok(types)
+    }
+
+    /// Runs `parse` one nesting level deeper, failing instead if that is
+    /// past the limit.
+    fn nested<T>(
+        &mut self,
+        construct: &'static str,
+        parse: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        self.chain(|parser| {
+            parser.deepen(construct)?;
+            parse(parser)
+        })
+    }
+
+    /// Runs `parse`, which may [`deepen`](Self::deepen) the tree, and then
+    /// restores the depth.
+    fn chain<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Counts one more level of `construct`, failing instead if that is
+    /// past the limit. Loops that wrap each node in the next call this
+    /// per step, so a long chain such as `a.b.c…` or `1 + 1 + …` is as deep
+    /// as the tree it builds, which later phases walk recursively.
+    fn deepen(&mut self, construct: &'static str) -> ParseResult<()> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::TooDeep {
+                construct,
+                span: self.tokens.peek().span,
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Runs `parse` with struct literals allowed or not, restoring the
+    /// setting afterwards.
+    fn struct_literals<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        let outer = std::mem::replace(&mut self.struct_literals, allowed);
+        let result = parse(self);
+        self.struct_literals = outer;
+        result
+    }
+
+    /// The integer literal or constant name giving the length of an array
+    /// type.
+    fn array_len(&mut self) -> ParseResult<ArrayLen> {
+        if self.tokens.check(TokenType::Identifier) {
+            return Ok(ArrayLen::Const(self.ident()?));
+        }
+        if !self.tokens.check(TokenType::IntLiteral) {
+            return Err(self.unexpected(Expected::Construct("array length")));
+        }
+        match self.tokens.next_token().literal {
+            LiteralValue::Integer(len) => Ok(ArrayLen::Literal(len as u64)),
+            _ => unreachable!("integer literals carry an integer value"),
+        }
+    }
+
+    /// Whether the next tokens start a declaration: `let`, a type keyword,
+    /// `fn(` opening a function type, or a type name (with any type
+    /// arguments and `[len]` suffixes) followed by the variable name.
+    fn at_var_decl(&mut self) -> bool {
+        match self.tokens.peek().token_type {
+            TokenType::Let
+            | TokenType::Const
+            | TokenType::Int
+            | TokenType::Float
+            | TokenType::Bool
+            | TokenType::Char
+            | TokenType::String
+            | TokenType::Void => true,
+            TokenType::Fn => self.tokens.peek_nth(1).token_type == TokenType::LParen,
+            TokenType::Identifier => {
+                let mut n = 1;
+                if self.tokens.peek_nth(n).token_type == TokenType::Less {
+                    match self.skip_type_args(n) {
+                        Some(end) => n = end,
+                        None => return false,
+                    }
+                }
+                while self.tokens.peek_nth(n).token_type == TokenType::LBracket
+                    && matches!(
+                        self.tokens.peek_nth(n + 1).token_type,
+                        TokenType::IntLiteral | TokenType::Identifier
+                    )
+                    && self.tokens.peek_nth(n + 2).token_type == TokenType::RBracket
+                {
+                    n += 3;
+                }
+                self.tokens.peek_nth(n).token_type == TokenType::Identifier
+            }
+            _ => false,
+        }
+    }
+
+    /// If the tokens from the `n`th on form a type argument list `<...>`,
+    /// the position just after its `>`. Only tokens that can appear in a
+    /// type are allowed inside, so an expression such as `a < b + c` fails
+    /// at the `+`.
+    fn skip_type_args(&mut self, n: usize) -> Option<usize> {
+        let mut depth: usize = 0;
+        for i in n..n + Self::TYPE_ARGS_LOOKAHEAD {
+            match self.tokens.peek_nth(i).token_type {
+                TokenType::Less => depth += 1,
+                TokenType::Greater => depth -= 1,
+                TokenType::Shr => depth = depth.checked_sub(2)?,
+                TokenType::Identifier
+                | TokenType::Int
+                | TokenType::Float
+                | TokenType::Bool
+                | TokenType::Char
+                | TokenType::String
+                | TokenType::Void
+                | TokenType::Fn
+                | TokenType::LParen
+                | TokenType::RParen
+                | TokenType::Arrow
+                | TokenType::LBracket
+                | TokenType::IntLiteral
+                | TokenType::RBracket
+                | TokenType::Comma => {}
+                _ => return None,
+            }
+            if depth == 0 {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
+    /// `int x;`, `int x = init;`, `let x: int;` or `let x = init;`, or a
+    /// constant: `const int N = init;`, or `const N = init;` with or without
+    /// `: int`, as for `let`. A `let` without a type needs an initializer to
+    /// take the type from, and a constant needs one for its value.
+    fn var_decl(&mut self) -> ParseResult<VarDecl> {
+        let start = self.tokens.peek().span;
+        let constant = self.tokens.eat(TokenType::Const);
+        let untyped = if constant {
+            self.tokens.check(TokenType::Identifier)
+                && matches!(
+                    self.tokens.peek_nth(1).token_type,
+                    TokenType::Colon | TokenType::Equal
+                )
+        } else {
+            self.tokens.eat(TokenType::Let)
+        };
+        let (ty, name) = if untyped {
+            let name = self.ident()?;
+            let ty = if self.eat(TokenType::Colon) {
+                Some(self.ty()?)
+            } else {
+                None
+            };
+            (ty, name)
+        } else {
+            let ty = self.ty()?;
+            (Some(ty), self.ident()?)
+        };
+        let init = if constant || ty.is_none() || self.check(TokenType::Equal) {
+            self.expect(TokenType::Equal)?;
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        let end = self.expect_semicolon()?;
+        Ok(VarDecl {
+            id: self.next_id(),
+            constant,
+            ty,
+            name,
+            init,
+            span: start.to(end),
+        })
+    }
+
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId::new(self.node_count);
+        self.node_count += 1;
+        id
+    }
+
+    /// Adds an expression with the next [`NodeId`] to the arena.
+    fn alloc_expr(&mut self, kind: ExprKind, span: Span) -> ExprId {
+        let id = self.next_id();
+        self.arena.alloc_expr(Expr { id, kind, span })
+    }
+
+    /// Adds a statement with the next [`NodeId`] to the arena.
+    fn alloc_stmt(&mut self, kind: StmtKind, span: Span) -> StmtId {
+        let id = self.next_id();
+        self.arena.alloc_stmt(Stmt { id, kind, span })
+    }
+
+    fn ident(&mut self) -> ParseResult<Ident> {
+        let token = self.expect(TokenType::Identifier)?;
+        Ok(ident(token))
+    }
+
+    /// Consumes a token of type `token_type`, or fails without consuming.
+    fn expect(&mut self, token_type: TokenType) -> ParseResult<Token<'a>> {
+        match self.tokens.expect(token_type) {
+            Ok(token) => Ok(token),
+            Err(_) => Err(self.unexpected(Expected::Token(token_type))),
+        }
+    }
+
+    /// The `;` ending a statement or declaration, returning its span. A `;`
+    /// missing before a line break, a `}` or the start of another statement
+    /// is reported with a fix-it, and parsing carries on as if it were
+    /// there; the span is then that of the last token.
+    fn expect_semicolon(&mut self) -> ParseResult<Span> {
+        if self.tokens.check(TokenType::Semicolon) {
+            return Ok(self.tokens.next_token().span);
+        }
+        let prev = self.tokens.prev_span();
+        let next = self.tokens.peek();
+        let (next_type, next_start) = (next.token_type, next.span.start);
+        if next.error.is_some() {
+            return Err(self.unexpected(Expected::Token(TokenType::Semicolon)));
+        }
+        // Preprocessed tokens are not always in text order, and there is
+        // no line break between two that are not.
+        let between = self.tokens.scanner().source().get(prev.end..next_start);
+        let at_boundary = matches!(next_type, TokenType::RBrace | TokenType::EndOfFile)
+            || starts_statement(next_type)
+            || between.is_some_and(|between| between.contains('\n'));
+        if !at_boundary {
+            return Err(self.unexpected(Expected::Token(TokenType::Semicolon)));
+        }
+        let span = Span::new(prev.end, prev.end);
+        self.diagnostics
+            .push(ParseError::MissingSemicolon { span }.into());
+        Ok(prev)
+    }
+
+    /// Consumes a token of type `token_type` if it is next. If not, the
+    /// token type is listed as an alternative should the next token turn
+    /// out to be an error.
+    fn eat(&mut self, token_type: TokenType) -> bool {
+        self.check(token_type) && self.tokens.eat(token_type)
+    }
+
+    /// Whether the next token is of type `token_type`, listing it as an
+    /// alternative if not, like [`eat`](Self::eat).
+    fn check(&mut self, token_type: TokenType) -> bool {
+        let found = self.tokens.check(token_type);
+        if !found {
+            self.note_expected(Expected::Token(token_type));
+        }
+        found
+    }
+
+    fn note_expected(&mut self, expected: Expected) {
+        let at = self.tokens.peek().span;
+        if at != self.expected_at {
+            self.expected.clear();
+            self.expected_at = at;
+        }
+        if !self.expected.contains(&expected) {
+            self.expected.push(expected);
+        }
+    }
+
+    /// An error for a next token that is not `expected`, nor any alternative
+    /// tried at the same position.
+    fn unexpected(&mut self, expected: Expected) -> ParseError {
+        if let Some(err) = self.lexical_error() {
+            return err;
+        }
+        self.note_expected(expected);
+        let found = self.tokens.peek();
+        ParseError::Expected {
+            expected: self.expected.clone(),
+            found: found.token_type,
+            span: found.span,
+        }
+    }
+
+    /// Records `error` and skips to a point where parsing can resume: just
+    /// after a `;` or a `{ ... }` group, or before a `}` or a keyword that
+    /// starts a statement or item. `before` is where the failed construct
+    /// started; at least one token is skipped if nothing was consumed since,
+    /// so the caller's loop always makes progress.
+    fn recover(&mut self, error: ParseError, before: Span) {
+        self.diagnostics.push(error.into());
+        if self.tokens.peek().span == before {
+            self.skip_token();
+        }
+        let mut depth = 0usize;
+        while !self.tokens.is_at_end() {
+            let token_type = self.tokens.peek().token_type;
+            if depth == 0 && (token_type == TokenType::RBrace || starts_statement(token_type)) {
+                return;
+            }
+            self.skip_token();
+            match token_type {
+                TokenType::LBrace => depth += 1,
+                TokenType::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                TokenType::Semicolon if depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Skips the next token, reporting it if it is a lexical error that has
+    /// not been reported yet.
+    fn skip_token(&mut self) {
+        let token = self.tokens.next_token();
+        if let Some(error) = token.error {
+            if self.diagnostics.last().map(|d| d.primary_span) != Some(token.span) {
+                self.diagnostics.push(
+                    ParseError::Lexical {
+                        error,
+                        span: token.span,
+                    }
+                    .into(),
+                );
+            }
+        }
+    }
+
+    /// Whether `name` is not yet the name of one of `items`, reporting it
+    /// if it is. Parsing carries on either way; the caller leaves a
+    /// duplicate out of the tree, so the first one listed is the one later
+    /// phases see.
+    fn check_unique<T>(
+        &mut self,
+        items: &[T],
+        name_of: impl Fn(&T) -> &Ident,
+        name: &Ident,
+    ) -> bool {
+        let Some(first) = items
+            .iter()
+            .map(name_of)
+            .find(|other| other.name == name.name)
+        else {
+            return true;
+        };
+        let error = ParseError::DuplicateField {
+            name: name.name.clone(),
+            span: name.span,
+            first: first.span,
+        };
+        self.diagnostics.push(error.into());
+        false
+    }
+
+    /// The lexer's error, if the next token is an error token.
+    fn lexical_error(&mut self) -> Option<ParseError> {
+        let token = self.tokens.peek();
+        let error = token.error.clone()?;
+        Some(ParseError::Lexical {
+            error,
+            span: token.span,
+        })
+    }
+}
+
+/// Whether a token begins a statement or item, making it a safe place to
+/// resume after an error.
+fn starts_statement(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::If
+            | TokenType::While
+            | TokenType::For
+            | TokenType::Return
+            | TokenType::Break
+            | TokenType::Continue
+            | TokenType::Let
+            | TokenType::Const
+            | TokenType::Int
+            | TokenType::Float
+            | TokenType::Bool
+            | TokenType::Char
+            | TokenType::String
+            | TokenType::Void
+            | TokenType::Fn
+            | TokenType::Struct
+            | TokenType::Enum
+            | TokenType::Match
+    )
+}
+
+fn ident(token: Token<'_>) -> Ident {
+    Ident {
+        name: token.lexeme.into_owned(),
+        symbol: token.symbol.expect("identifiers are interned"),
+        span: token.span,
+    }
+}
+
+/// Whether the tree holds all of the program despite `diagnostic`, so that
+/// later phases can still check it. A field or variant listed twice is
+/// reported without stopping the parse; every other syntax error leaves part
+/// of the source out.
+pub fn keeps_tree(diagnostic: &Diagnostic) -> bool {
+    [codes::DUPLICATE_FIELD, codes::DUPLICATE_VARIANT].contains(&diagnostic.code)
+}
+
+/// Parses `source` with the default scanner options.
+pub fn parse(source: &str) -> (Program, Vec<Diagnostic>) {
+    Parser::new(Scanner::new(source)).parse_program()
+}
+
+/// Parses `source` as a single expression with the default scanner options.
+pub fn parse_expression(source: &str) -> Result<(AstArena, ExprId), Vec<Diagnostic>> {
+    Parser::new(Scanner::new(source)).parse_expression()
+}
+
+/// Parses arbitrary bytes as a program and as an expression, panicking if
+/// the result breaks an invariant of the tree or the diagnostics. Fuzz
+/// targets call this; a parser that hangs shows up there as a timeout.
+///
+/// The program is also dumped and, if it parsed as `check` would go on
+/// with, resolved, type checked and lowered, since those walk the tree
+/// recursively and would overflow the stack on one deeper than the parser
+/// allows.
+///
+/// Invalid UTF-8 is replaced with U+FFFD first, as a source file read in
+/// [`Utf8Mode::Lossy`](crate::input::Utf8Mode::Lossy) would be.
+pub fn fuzz_check(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let in_source = |span: Span| span.start <= span.end && span.end <= source.len();
+    let check_diagnostics = |diagnostics: &[Diagnostic]| {
+        // Recovery always moves on, so there is at most one error per
+        // token, and a token is at least a byte apart from the last one.
+        assert!(
+            diagnostics.len() <= source.len() + 1,
+            "too many diagnostics"
+        );
+        for diagnostic in diagnostics {
+            assert!(
+                in_source(diagnostic.primary_span),
+                "{:?} is out of bounds",
+                diagnostic
+            );
+            assert!(diagnostic.labels.iter().all(|label| in_source(label.span)));
+            assert!(diagnostic.fixits.iter().all(|fixit| in_source(fixit.span)));
+        }
+    };
+
+    let (program, diagnostics) = parse(&source);
+    check_diagnostics(&diagnostics);
+    assert!(in_source(program.span));
+    let mut checked = Checked {
+        arena: &program.arena,
+        node_count: program.node_count,
+        source_len: source.len(),
+    };
+    checked.visit_program(&program);
+
+    dump(&program, DumpFormat::Sexpr);
+    dump(&program, DumpFormat::Json);
+    if !diagnostics.iter().any(|d| d.is_error() && !keeps_tree(d)) {
+        let mut program = program;
+        let (resolution, mut errors) = semantic::resolve(&program);
+        let (consts, evaluation) = semantic::evaluate(&program, &resolution);
+        errors.extend(evaluation);
+        let options = TypeckOptions::default();
+        errors.extend(typeck::check(&mut program, &resolution, &consts, &options).1);
+        if errors.iter().all(|d| !d.is_error()) {
+            lowering::lower(&mut program);
+        }
+    }
+
+    match parse_expression(&source) {
+        Ok((arena, expr)) => {
+            let mut checked = Checked {
+                arena: &arena,
+                node_count: usize::MAX,
+                source_len: source.len(),
+            };
+            checked.visit_expr(&arena[expr]);
+        }
+        Err(diagnostics) => {
+            assert!(!diagnostics.is_empty());
+            check_diagnostics(&diagnostics);
+        }
+    }
+}
+
+/// Checks that every expression and statement reachable from the tree has
+/// an id below `node_count` and a span inside the source. Walking the tree
+/// also checks that every handle points into the arena.
+struct Checked<'ast> {
+    arena: &'ast AstArena,
+    node_count: usize,
+    source_len: usize,
+}
+
+impl Checked<'_> {
+    fn check(&self, id: NodeId, span: Span) {
+        assert!(
+            id.index() < self.node_count,
+            "{:?} was never handed out",
+            id
+        );
+        assert!(
+            span.start <= span.end && span.end <= self.source_len,
+            "{} is out of bounds",
+            span
+        );
+    }
+}
+
+impl<'ast> Visitor<'ast> for Checked<'ast> {
+    fn arena(&self) -> &'ast AstArena {
+        self.arena
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        self.check(stmt.id, stmt.span);
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        self.check(expr.id, expr.span);
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{ArmBody, BinaryOp, ExprKind, PatternKind, Stmt, StmtKind};
+    use crate::diagnostics::FixIt;
+    use crate::lexer::{LexicalError, LiteralValue};
+    use crate::source::Span;
+    use pretty_assertions::assert_eq;
+
+    /// Parses `source`, failing the test if there are any diagnostics.
+    fn parse_ok(source: &str) -> Program {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, [], "in {:?}", source);
+        program
+    }
+
+    /// The first diagnostic reported for `source`.
+    fn first_error(source: &str) -> Diagnostic {
+        let (_, mut diagnostics) = parse(source);
+        assert!(!diagnostics.is_empty(), "no errors in {:?}", source);
+        diagnostics.swap_remove(0)
+    }
+
+    fn parse_expr(source: &str) -> (AstArena, ExprId) {
+        let (arena, stmts) = body(&format!("{};", source));
+        match arena[stmts[0]].kind {
+            StmtKind::Expr(expr) => (arena, expr),
+            ref other => panic!("expected an expression statement, got {:?}", other),
+        }
+    }
+
+    /// The shape of `source` parsed as an expression.
+    fn expr_shape(source: &str) -> String {
+        let (arena, expr) = parse_expr(source);
+        shape(&arena, expr)
+    }
+
+    /// Fully parenthesized rendering of an expression, for checking shape.
+    fn shape(arena: &AstArena, expr: ExprId) -> String {
+        let shape = |expr: &ExprId| shape(arena, *expr);
+        match &arena[expr].kind {
+            ExprKind::Literal(LiteralValue::Integer(n)) => n.to_string(),
+            ExprKind::Literal(value) => format!("{:?}", value),
+            ExprKind::Variable(name) => name.name.clone(),
+            ExprKind::Binary { op, left, right } => {
+                format!("({} {} {})", shape(left), op, shape(right))
+            }
+            ExprKind::Assign { op, target, value } => {
+                let op = op.map_or(String::new(), |op| op.to_string());
+                format!("({} {}= {})", shape(target), op, shape(value))
+            }
+            // Parentheses show up in the rendering anyway.
+            ExprKind::Grouping(inner) => shape(inner),
+            ExprKind::IntToFloat(inner) => format!("float({})", shape(inner)),
+            ExprKind::Unary { op, operand } => format!("({}{})", op, shape(operand)),
+            ExprKind::Call { callee, args } => {
+                let args: Vec<String> = args.iter().map(shape).collect();
+                format!("{}({})", shape(callee), args.join(", "))
+            }
+            ExprKind::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(shape).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            ExprKind::Index { object, index } => format!("{}[{}]", shape(object), shape(index)),
+            ExprKind::StructLiteral { name, fields } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|f| format!("{}: {}", f.name.name, shape(&f.value)))
+                    .collect();
+                format!("{} {{ {} }}", name.name, fields.join(", "))
+            }
+            ExprKind::Field { object, field } => format!("{}.{}", shape(object), field.name),
+            ExprKind::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => format!(
+                "({} ? {} : {})",
+                shape(condition),
+                shape(then_expr),
+                shape(else_expr)
+            ),
+            ExprKind::Variant { enum_name, variant } => {
+                format!("{}::{}", enum_name.name, variant.name)
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                format!("match({}, {} arms)", shape(scrutinee), arms.len())
+            }
+        }
+    }
+
+    #[test]
+    fn test_function_and_globals() {
+        let program = parse_ok(
+            "int limit = 10;\nfn add(a: int, b: Point) -> int { return a; }\nfn main() {}",
+        );
+        assert_eq!(program.items.len(), 3);
+        let Item::Global(global) = &program.items[0] else {
+            panic!("expected a global");
+        };
+        assert_eq!(global.name.name, "limit");
+        assert_eq!(global.ty.as_ref().unwrap().kind, TypeKind::Int);
+        assert_eq!(global.span, Span::new(0, 15));
+
+        let Item::Function(add) = &program.items[1] else {
+            panic!("expected a function");
+        };
+        assert_eq!(add.name.name, "add");
+        assert_eq!(add.params.len(), 2);
+        assert_eq!(add.params[0].ty.kind, TypeKind::Int);
+        assert!(matches!(&add.params[1].ty.kind, TypeKind::Named(name) if name.name == "Point"));
+        assert_eq!(
+            add.return_type.as_ref().map(|t| &t.kind),
+            Some(&TypeKind::Int)
+        );
+        assert_eq!(add.span, Span::new(16, 61));
+
+        let Item::Function(main) = &program.items[2] else {
+            panic!("expected a function");
+        };
+        assert!(main.params.is_empty());
+        assert!(main.return_type.is_none());
+        assert!(main.body.stmts.is_empty());
+    }
+
+    #[test]
+    fn test_statements() {
+        let src = "fn main() { int x = 1; Point p; if (x < 2) x = 3; else { return; } while (x) x -= 1; return x; }";
+        let program = parse_ok(src);
+        let Item::Function(main) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let arena = &program.arena;
+        let stmts: Vec<&Stmt> = main.body.stmts.iter().map(|id| &arena[*id]).collect();
+        assert_eq!(stmts.len(), 5);
+        assert!(matches!(&stmts[0].kind, StmtKind::VarDecl(decl) if decl.init.is_some()));
+        assert!(matches!(&stmts[1].kind, StmtKind::VarDecl(decl) if decl.init.is_none()));
+        assert!(matches!(
+            &stmts[2].kind,
+            StmtKind::If { else_branch: Some(branch), .. } if matches!(arena[*branch].kind, StmtKind::Block(_))
+        ));
+        assert!(matches!(&stmts[3].kind, StmtKind::While { .. }));
+        assert!(matches!(&stmts[4].kind, StmtKind::Return(Some(_))));
+        assert_eq!(
+            &src[stmts[2].span.start..stmts[2].span.end],
+            "if (x < 2) x = 3; else { return; }"
+        );
+    }
+
+    /// The statements of a function body, with the arena holding them.
+    fn body(source: &str) -> (AstArena, Vec<StmtId>) {
+        let program = parse_ok(&format!("fn f() {{ {} }}", source));
+        let Item::Function(function) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        (program.arena, function.body.stmts.clone())
+    }
+
+    #[test]
+    fn test_var_decls() {
+        let src = "int x; int y = 5; let z = y + 1; let w: float;";
+        let (arena, stmts) = body(src);
+        let decls: Vec<&VarDecl> = stmts
+            .iter()
+            .map(|stmt| match &arena[*stmt].kind {
+                StmtKind::VarDecl(decl) => decl,
+                other => panic!("expected a declaration, got {:?}", other),
+            })
+            .collect();
+        let names: Vec<&str> = decls.iter().map(|d| d.name.name.as_str()).collect();
+        assert_eq!(names, ["x", "y", "z", "w"]);
+        assert_eq!(decls[0].ty.as_ref().map(|t| &t.kind), Some(&TypeKind::Int));
+        assert!(decls[0].init.is_none());
+        assert_eq!(
+            decls[1].init.map(|e| &arena[e].kind),
+            Some(&ExprKind::Literal(LiteralValue::Integer(5)))
+        );
+        assert!(decls[2].ty.is_none());
+        assert_eq!(shape(&arena, decls[2].init.unwrap()), "(y + 1)");
+        assert_eq!(
+            decls[3].ty.as_ref().map(|t| &t.kind),
+            Some(&TypeKind::Float)
+        );
+
+        // A constant needs an initializer.
+        let (arena, stmts) = body("const int a = 1; const b = 2; const c: float = 3.0;");
+        for (stmt, ty) in stmts
+            .iter()
+            .zip([Some(TypeKind::Int), None, Some(TypeKind::Float)])
+        {
+            let StmtKind::VarDecl(decl) = &arena[*stmt].kind else {
+                panic!("expected a declaration");
+            };
+            assert!(decl.constant && decl.init.is_some());
+            assert_eq!(decl.ty.as_ref().map(|t| &t.kind), ty.as_ref());
+        }
+        assert!(!decls[0].constant);
+        let err = first_error("const int n;");
+        assert_eq!(err.message, "expected '=', found ';'");
+
+        // Spans cover the whole declaration, from the type or `let` to `;`.
+        let offset = "fn f() { ".len();
+        let text = |decl: &VarDecl| &src[decl.span.start - offset..decl.span.end - offset];
+        assert_eq!(text(decls[1]), "int y = 5;");
+        assert_eq!(text(decls[2]), "let z = y + 1;");
+
+        // The same symbol is used for every mention of a name.
+        let program = parse_ok("let n = 1; fn f() { n = 2; }");
+        let Item::Global(global) = &program.items[0] else {
+            panic!("expected a global");
+        };
+        let Item::Function(f) = &program.items[1] else {
+            panic!("expected a function");
+        };
+        let arena = &program.arena;
+        let StmtKind::Expr(assign) = arena[f.body.stmts[0]].kind else {
+            panic!("expected an expression statement");
+        };
+        let ExprKind::Assign { target, .. } = arena[assign].kind else {
+            panic!("expected an assignment");
+        };
+        assert!(
+            matches!(&arena[target].kind, ExprKind::Variable(n) if n.symbol == global.name.symbol)
+        );
+
+        let err = first_error("fn f() { let x; }");
+        assert_eq!(err.message, "expected ':' or '=', found ';'");
+    }
+
+    #[test]
+    fn test_dangling_else() {
+        let (arena, stmts) = body("if (a) if (b) x = 1; else x = 2;");
+        let StmtKind::If {
+            then_branch,
+            else_branch: None,
+            ..
+        } = arena[stmts[0]].kind
+        else {
+            panic!("the outer if has no else");
+        };
+        assert!(matches!(
+            arena[then_branch].kind,
+            StmtKind::If {
+                else_branch: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_loops() {
+        let src = "for (int i = 0; i < 10; i += 1) { if (i == 5) break; continue; } for (;;) {} for (i = 0; ; ) x;";
+        let (arena, stmts) = body(src);
+        let StmtKind::For {
+            init: Some(init),
+            condition: Some(_),
+            step: Some(_),
+            body,
+        } = arena[stmts[0]].kind
+        else {
+            panic!("expected a full for header");
+        };
+        assert!(matches!(arena[init].kind, StmtKind::VarDecl(_)));
+        let StmtKind::Block(block) = &arena[body].kind else {
+            panic!("expected a block body");
+        };
+        assert!(matches!(arena[block.stmts[1]].kind, StmtKind::Continue));
+        assert!(matches!(
+            arena[stmts[1]].kind,
+            StmtKind::For {
+                init: None,
+                condition: None,
+                step: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            arena[stmts[2]].kind,
+            StmtKind::For { init: Some(init), condition: None, step: None, .. }
+                if matches!(arena[init].kind, StmtKind::Expr(_))
+        ));
+        assert_eq!(arena[stmts[1]].span.len(), "for (;;) {}".len());
+
+        let err = first_error("fn f() { for (i = 0; i < 1) x; }");
+        assert_eq!(err.message, "expected ';', found ')'");
+
+        // The C-style header, with a postfix increment as its step or init.
+        let (arena, stmts) = self::body("for (int i = 0; i < 10; i++) {} for (i--; ; --i) {}");
+        let StmtKind::For {
+            step: Some(step), ..
+        } = arena[stmts[0]].kind
+        else {
+            panic!("expected a step");
+        };
+        assert!(matches!(
+            arena[step].kind,
+            ExprKind::Assign {
+                op: Some(BinaryOp::Add),
+                ..
+            }
+        ));
+        assert_eq!(arena[step].span.len(), "i++".len());
+        let err = first_error("fn f() { for (;; a[i++]) {} }");
+        assert_eq!(err.code, codes::POSTFIX_INCREMENT_VALUE);
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(expr_shape("1 + 2 * 3 - 4"), "((1 + (2 * 3)) - 4)");
+        assert_eq!(expr_shape("(1 + 2) * 3"), "((1 + 2) * 3)");
+        assert_eq!(
+            expr_shape("a < b == c > d && e || f"),
+            "((((a < b) == (c > d)) && e) || f)"
+        );
+        assert_eq!(expr_shape("a = b += c"), "(a = (b += c))");
+        assert_eq!(expr_shape("x == true"), "(x == Boolean(true))");
+        assert_eq!(
+            expr_shape("a + b * c == d && e"),
+            "(((a + (b * c)) == d) && e)"
+        );
+        assert_eq!(
+            expr_shape("a | b ^ c & d << 1 + 2"),
+            "(a | (b ^ (c & (d << (1 + 2)))))"
+        );
+        assert_eq!(expr_shape("a - b - c"), "((a - b) - c)");
+        assert_eq!(expr_shape("x <<= y >>= 2"), "(x <<= (y >>= 2))");
+        assert_eq!(expr_shape("a ? b : c ? d : e"), "(a ? b : (c ? d : e))");
+        assert_eq!(
+            expr_shape("x = a || b ? c = 1 : d"),
+            "(x = ((a || b) ? (c = 1) : d))"
+        );
+        let (arena, expr) = parse_expr("a + b");
+        assert_eq!(arena[expr].span, Span::new(9, 14));
+    }
+
+    /// Every pair of rows of the operator table, in both orders, against
+    /// the precedence and associativity the table gives them.
+    #[test]
+    fn test_operator_precedence() {
+        use expr::{Infix, INFIX_OPERATORS};
+
+        let mut binary = Vec::new();
+        let mut assignments = Vec::new();
+        for row in INFIX_OPERATORS {
+            match row.infix {
+                Infix::Binary(op) => binary.push((row.precedence, op.as_str())),
+                Infix::Assign(op) => {
+                    assignments.push(format!("{}=", op.map_or("", |op| op.as_str())))
+                }
+                Infix::Conditional => {}
+            }
+        }
+        assert_eq!(binary.len(), 18);
+        assert_eq!(assignments.len(), 11);
+
+        for &(p, x) in &binary {
+            for &(q, y) in &binary {
+                // Operators on one level are all left-associative.
+                let expected = if p >= q {
+                    format!("((1 {x} 2) {y} 3)")
+                } else {
+                    format!("(1 {x} (2 {y} 3))")
+                };
+                assert_eq!(expr_shape(&format!("1 {x} 2 {y} 3")), expected);
+            }
+            assert_eq!(
+                expr_shape(&format!("-1 {x} !2")),
+                format!("((-1) {x} (!2))")
+            );
+            assert_eq!(
+                expr_shape(&format!("a = 1 {x} 2 ? 3 {x} 4 : 5 {x} 6")),
+                format!("(a = ((1 {x} 2) ? (3 {x} 4) : (5 {x} 6)))")
+            );
+        }
+        for x in &assignments {
+            for y in &assignments {
+                assert_eq!(
+                    expr_shape(&format!("a {x} b {y} c")),
+                    format!("(a {x} (b {y} c))")
+                );
+            }
+        }
+
+        assert_eq!(expr_shape("a ? b : c ? d : e"), "(a ? b : (c ? d : e))");
+        assert_eq!(expr_shape("a ? b ? c : d : e"), "(a ? (b ? c : d) : e)");
+        assert_eq!(expr_shape("a ? b = c : d"), "(a ? (b = c) : d)");
+        assert_eq!(expr_shape("a = b ? c : d"), "(a = (b ? c : d))");
+
+        // Calls, fields and subscripts bind tighter than prefix operators,
+        // `++` and `--` included, and postfix `++` and `--` apply to all of
+        // them.
+        assert_eq!(expr_shape("-a.b[0](c)"), "(-a.b[0](c))");
+        assert_eq!(expr_shape("++a.b[i]"), "(a.b[i] += 1)");
+        assert_eq!(expr_shape("!--a[i].b"), "(!(a[i].b -= 1))");
+        assert_eq!(expr_shape("- --a"), "(-(a -= 1))");
+        assert_eq!(expr_shape("++a * 2"), "((a += 1) * 2)");
+        assert_eq!(expr_shape("a.b[i]++"), "(a.b[i] += 1)");
+        assert_eq!(expr_shape("f(a)[i]--"), "(f(a)[i] -= 1)");
+        let err = first_error("fn f() { ++-a; }");
+        assert_eq!(err.code, codes::INVALID_ASSIGNMENT_TARGET);
+        assert_eq!(err.primary_span, Span::new(11, 13));
+        let err = first_error("fn f() { -a++; }");
+        assert_eq!(err.code, codes::POSTFIX_INCREMENT_VALUE);
+        assert_eq!(err.primary_span, Span::new(11, 13));
+    }
+
+    #[test]
+    fn test_structs() {
+        let program = parse_ok("struct Point { x: int, y: int, }\nfn main() {}");
+        let Item::Struct(point) = &program.items[0] else {
+            panic!("expected a struct");
+        };
+        assert_eq!(point.name.name, "Point");
+        assert_eq!(point.span, Span::new(0, 32));
+        let fields: Vec<_> = point
+            .fields
+            .iter()
+            .map(|f| (f.name.name.as_str(), &f.ty.kind))
+            .collect();
+        assert_eq!(fields, [("x", &TypeKind::Int), ("y", &TypeKind::Int)]);
+        assert_eq!(point.fields[1].span, Span::new(23, 29));
+        parse_ok("struct Empty {}");
+
+        assert_eq!(
+            expr_shape("Point { x: 1, y: a + 2 }"),
+            "Point { x: 1, y: (a + 2) }"
+        );
+        assert_eq!(expr_shape("Empty {}"), "Empty {  }");
+        assert_eq!(expr_shape("p.x = q.r.y"), "(p.x = q.r.y)");
+        assert_eq!(expr_shape("Point { x: 1 }.x + 1"), "(Point { x: 1 }.x + 1)");
+    }
+
+    #[test]
+    fn test_unary_and_grouping() {
+        assert_eq!(expr_shape("-a * b"), "((-a) * b)");
+        assert_eq!(expr_shape("!a && ~b"), "((!a) && (~b))");
+        assert_eq!(expr_shape("- -a"), "(-(-a))");
+        assert_eq!(expr_shape("-a[0].x"), "(-a[0].x)");
+        assert_eq!(expr_shape("-f(x)"), "(-f(x))");
+        assert_eq!(expr_shape("a - -1"), "(a - (-1))");
+        assert_eq!(expr_shape("-(a + b)"), "(-(a + b))");
+        assert_eq!(expr_shape("!(a < b) == c"), "((!(a < b)) == c)");
+
+        // The grouping keeps the span of its parentheses.
+        let (arena, group) = parse_expr("(a + b) * c");
+        let ExprKind::Binary { left, .. } = arena[group].kind else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(arena[left].span, Span::new(9, 16));
+        assert!(matches!(
+            arena.ungrouped(left).kind,
+            ExprKind::Binary { .. }
+        ));
+        assert_eq!(arena.ungrouped(left).span, Span::new(10, 15));
+        let (arena, negation) = parse_expr("-x");
+        assert_eq!(arena[negation].span, Span::new(9, 11));
+
+        assert_eq!(expr_shape("(x) = 1"), "(x = 1)");
+        let err = first_error("fn f() { -x = 1; }");
+        assert_eq!(err.primary_span, Span::new(9, 11));
+    }
+
+    #[test]
+    fn test_increments() {
+        assert_eq!(expr_shape("++x"), "(x += 1)");
+        assert_eq!(expr_shape("y = --a[i] * 2"), "(y = ((a[i] -= 1) * 2))");
+        assert_eq!(expr_shape("-++x"), "(-(x += 1))");
+        assert_eq!(expr_shape("p.x++"), "(p.x += 1)");
+        assert_eq!(expr_shape("(x)--"), "(x -= 1)");
+        let (arena, increment) = parse_expr("x++");
+        assert_eq!(arena[increment].span, Span::new(9, 12));
+
+        // A postfix increment's value would be the old one, which `x += 1`
+        // does not give, so it may only be thrown away.
+        for source in [
+            "fn f() { y = x++; }",
+            "fn f() { g(x++); }",
+            "fn f() { x++ + 1; }",
+            "fn f() { (x++); }",
+            "fn f() { int y = x--; }",
+        ] {
+            assert_eq!(first_error(source).code, codes::POSTFIX_INCREMENT_VALUE);
+        }
+        let err = first_error("fn f() { y = x++; }");
+        assert_eq!(err.message, "the value of a postfix '++' cannot be used");
+        assert_eq!(err.primary_span, Span::new(14, 16));
+
+        let err = first_error("fn f() { 1++; }");
+        assert_eq!(err.code, codes::INVALID_ASSIGNMENT_TARGET);
+        let err = first_error("fn f() { ++f(); }");
+        assert_eq!(err.primary_span, Span::new(11, 14));
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_eq!(expr_shape("foo(a, b + 1)"), "foo(a, (b + 1))");
+        assert_eq!(expr_shape("foo()"), "foo()");
+        assert_eq!(expr_shape("foo(a,)"), "foo(a)");
+        assert_eq!(expr_shape("f(x)(y) + p.m(1)"), "(f(x)(y) + p.m(1))");
+        assert_eq!(expr_shape("x = f(g(1), [2])"), "(x = f(g(1), [2]))");
+
+        let (arena, call) = parse_expr("foo(a, b + 1)");
+        let ExprKind::Call { callee, args } = &arena[call].kind else {
+            panic!("expected a call");
+        };
+        assert_eq!(arena[call].span, Span::new(9, 22));
+        assert_eq!(arena[*callee].span, Span::new(9, 12));
+        let spans: Vec<Span> = args.iter().map(|arg| arena[*arg].span).collect();
+        assert_eq!(spans, [Span::new(13, 14), Span::new(16, 21)]);
+
+        let err = first_error("fn f() { foo(a b); }");
+        assert_eq!(err.message, "expected ',' or ')', found identifier");
+        let err = first_error("fn f() { foo(,); }");
+        assert_eq!(err.message, "expected expression, found ','");
+    }
+
+    #[test]
+    fn test_arrays() {
+        let (arena, stmts) = body("int[10] xs; Point[2][3] grid = g; xs[1] = 2;");
+        let StmtKind::VarDecl(xs) = &arena[stmts[0]].kind else {
+            panic!("expected a declaration");
+        };
+        let xs_ty = xs.ty.as_ref().unwrap();
+        let TypeKind::Array { element, len } = &xs_ty.kind else {
+            panic!("expected an array type");
+        };
+        assert_eq!(
+            (&element.kind, len),
+            (&TypeKind::Int, &ArrayLen::Literal(10))
+        );
+        assert_eq!(xs_ty.span, Span::new(9, 16));
+
+        // `Point[2][3]` is an array of 3 arrays of 2 points.
+        let StmtKind::VarDecl(grid) = &arena[stmts[1]].kind else {
+            panic!("expected a declaration");
+        };
+        let TypeKind::Array {
+            element,
+            len: ArrayLen::Literal(3),
+        } = &grid.ty.as_ref().unwrap().kind
+        else {
+            panic!("expected an array type");
+        };
+        assert!(matches!(
+            element.kind,
+            TypeKind::Array {
+                len: ArrayLen::Literal(2),
+                ..
+            }
+        ));
+        assert!(matches!(arena[stmts[2]].kind, StmtKind::Expr(_)));
+
+        assert_eq!(expr_shape("[1, 2, 3,]"), "[1, 2, 3]");
+        assert_eq!(expr_shape("[]"), "[]");
+        assert_eq!(expr_shape("a[i + 1][j] = [x]"), "(a[(i + 1)][j] = [x])");
+        assert_eq!(expr_shape("ps[0].x"), "ps[0].x");
+        let (arena, index) = parse_expr("a[i]");
+        assert_eq!(arena[index].span, Span::new(9, 13));
+
+        // A length may name a constant.
+        let program = parse_ok("const int N = 2; int[N] xs;");
+        let Item::Global(xs) = &program.items[1] else {
+            panic!("expected a global");
+        };
+        let TypeKind::Array {
+            len: ArrayLen::Const(name),
+            ..
+        } = &xs.ty.as_ref().unwrap().kind
+        else {
+            panic!("expected an array type");
+        };
+        assert_eq!(name.name, "N");
+        let err = first_error("fn f() { int[1.5] xs; }");
+        assert_eq!(err.message, "expected array length, found float literal");
+    }
+
+    #[test]
+    fn test_function_types() {
+        let program = parse_ok("fn(int, float) -> bool g; fn f(h: fn()) -> fn(int) -> int[2] {}");
+        let Item::Global(g) = &program.items[0] else {
+            panic!("expected a global");
+        };
+        let g_ty = g.ty.as_ref().unwrap();
+        let TypeKind::Function {
+            params,
+            return_type: Some(return_type),
+        } = &g_ty.kind
+        else {
+            panic!("expected a function type");
+        };
+        let params: Vec<_> = params.iter().map(|p| &p.kind).collect();
+        assert_eq!(params, [&TypeKind::Int, &TypeKind::Float]);
+        assert_eq!(return_type.kind, TypeKind::Bool);
+        assert_eq!(g_ty.span, Span::new(0, 22));
+
+        let Item::Function(f) = &program.items[1] else {
+            panic!("expected a function");
+        };
+        assert!(matches!(
+            &f.params[0].ty.kind,
+            TypeKind::Function { params, return_type: None } if params.is_empty()
+        ));
+        // The array suffix belongs to the innermost return type.
+        let Some(TypeKind::Function {
+            return_type: Some(return_type),
+            ..
+        }) = f.return_type.as_ref().map(|t| &t.kind)
+        else {
+            panic!("expected a function type");
+        };
+        assert!(matches!(
+            return_type.kind,
+            TypeKind::Array {
+                len: ArrayLen::Literal(2),
+                ..
+            }
+        ));
+
+        let (arena, stmts) = body("fn(int) k = f; fn(P)[2] ks;");
+        assert!(stmts
+            .iter()
+            .all(|s| matches!(arena[*s].kind, StmtKind::VarDecl(_))));
+
+        let err = first_error("fn(int, ) -> int g;");
+        assert_eq!(err.message, "expected type, found ')'");
+    }
+
+    #[test]
+    fn test_generics() {
+        let program = parse_ok(
+            "fn max<T>(a: T, b: T) -> T {} struct Pair<A, B> { first: A, second: B }
+            Pair<int, Pair<float, bool>> p; fn f(ps: Pair<int, fn(T) -> T>[2]) {}",
+        );
+        let Item::Function(max) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let names: Vec<_> = max.type_params.iter().map(|p| &*p.name.name).collect();
+        assert_eq!(names, ["T"]);
+        assert_eq!(max.type_params[0].span, Span::new(7, 8));
+        let Item::Struct(pair) = &program.items[1] else {
+            panic!("expected a struct");
+        };
+        let names: Vec<_> = pair.type_params.iter().map(|p| &*p.name.name).collect();
+        assert_eq!(names, ["A", "B"]);
+
+        // `>>` closes two argument lists.
+        let Item::Global(p) = &program.items[2] else {
+            panic!("expected a global");
+        };
+        let p_ty = p.ty.as_ref().unwrap();
+        assert_eq!(p_ty.span, Span::new(84, 112));
+        let TypeKind::Generic { name, args } = &p_ty.kind else {
+            panic!("expected a generic type");
+        };
+        assert_eq!(name.name, "Pair");
+        assert!(matches!(&args[1].kind, TypeKind::Generic { args, .. } if args.len() == 2));
+        let Item::Function(f) = &program.items[3] else {
+            panic!("expected a function");
+        };
+        assert!(matches!(
+            f.params[0].ty.kind,
+            TypeKind::Array {
+                len: ArrayLen::Literal(2),
+                ..
+            }
+        ));
+
+        // A statement that reads as `Name<types> name` is a declaration;
+        // anything else starting `name <` is a comparison.
+        let (arena, stmts) = body("Map<string, int[3]> m; a < b; a < b > c; x < y >> z;");
+        assert!(matches!(&arena[stmts[0]].kind, StmtKind::VarDecl(d) if d.name.name == "m"));
+        assert!(matches!(&arena[stmts[1]].kind, StmtKind::Expr(_)));
+        assert!(matches!(&arena[stmts[2]].kind, StmtKind::VarDecl(d) if d.name.name == "c"));
+        assert!(matches!(&arena[stmts[3]].kind, StmtKind::Expr(_)));
+        let (arena, stmts) = body("let m: Box<Box<int>>= n;");
+        let StmtKind::VarDecl(decl) = &arena[stmts[0]].kind else {
+            panic!("expected a declaration");
+        };
+        assert!(decl.init.is_some());
+
+        let err = first_error("fn f<T, U, T>() {}");
+        assert_eq!(
+            err,
+            Diagnostic::error(
+                codes::DUPLICATE_TYPE_PARAM,
+                "type parameter 'T' is listed more than once",
+                Span::new(11, 12)
+            )
+            .with_label(Span::new(5, 6), "first listed here")
+        );
+        let err = first_error("struct S<> {}");
+        assert_eq!(err.message, "expected identifier, found '>'");
+    }
+
+    #[test]
+    fn test_enums() {
+        let program =
+            parse_ok("enum Shape { Circle(float), Rect(float, float), Empty, } enum E {}");
+        let Item::Enum(decl) = &program.items[0] else {
+            panic!("expected an enum");
+        };
+        let variants: Vec<_> = decl
+            .variants
+            .iter()
+            .map(|v| (&*v.name.name, v.fields.len()))
+            .collect();
+        assert_eq!(variants, [("Circle", 1), ("Rect", 2), ("Empty", 0)]);
+        assert_eq!(decl.variants[1].span, Span::new(28, 46));
+        assert!(matches!(&program.items[1], Item::Enum(e) if e.variants.is_empty()));
+
+        let err = first_error("enum E { A, B(int), A }");
+        assert_eq!(
+            err,
+            Diagnostic::error(
+                codes::DUPLICATE_VARIANT,
+                "variant 'A' is listed more than once",
+                Span::new(20, 21)
+            )
+            .with_label(Span::new(9, 10), "first listed here")
+        );
+
+        // The rest of the enum, and of the file, still parses.
+        let (program, diagnostics) = parse("enum E { A, A(int), B } fn f() { int x = ; }");
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, [codes::DUPLICATE_VARIANT, codes::UNEXPECTED_TOKEN]);
+        assert!(keeps_tree(&diagnostics[0]));
+        let Item::Enum(decl) = &program.items[0] else {
+            panic!("expected an enum");
+        };
+        let names: Vec<_> = decl.variants.iter().map(|v| &*v.name.name).collect();
+        assert_eq!(names, ["A", "B"]);
+        assert_eq!(program.items.len(), 2);
+
+        assert_eq!(
+            expr_shape("x = Shape::Circle(1.0) + Shape::Empty"),
+            "(x = (Shape::Circle(Float(1.0)) + Shape::Empty))"
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let (arena, stmts) = body(
+            "match s { Shape::Circle(r) => r * r, Shape::Rect(_, 0) => { return 0; } _ => 1 }
+            let n = match x { 1 => a, \"b\" => b, true => c, };",
+        );
+        let StmtKind::Expr(expr) = arena[stmts[0]].kind else {
+            panic!("expected an expression statement");
+        };
+        let ExprKind::Match { scrutinee, arms } = &arena[expr].kind else {
+            panic!("expected a match");
+        };
+        assert_eq!(shape(&arena, *scrutinee), "s");
+        assert_eq!(arms.len(), 3);
+        let PatternKind::Variant {
+            enum_name, fields, ..
+        } = &arms[1].pattern.kind
+        else {
+            panic!("expected a variant pattern");
+        };
+        assert_eq!(enum_name.name, "Shape");
+        assert_eq!(fields[0].kind, PatternKind::Wildcard);
+        assert_eq!(
+            fields[1].kind,
+            PatternKind::Literal(LiteralValue::Integer(0))
+        );
+        assert!(matches!(arms[1].body, ArmBody::Block(_)));
+        assert!(matches!(&arms[2].body, ArmBody::Expr(e) if shape(&arena, *e) == "1"));
+        assert_eq!(arms[0].span, Span::new(19, 44));
+        assert!(matches!(
+            &arms[0].pattern.kind,
+            PatternKind::Variant { fields, .. } if matches!(&fields[0].kind, PatternKind::Binding(r) if r.name == "r")
+        ));
+        let StmtKind::VarDecl(decl) = &arena[stmts[1]].kind else {
+            panic!("expected a declaration");
+        };
+        assert_eq!(shape(&arena, decl.init.unwrap()), "match(x, 3 arms)");
+
+        // The `{` after a scrutinee opens the arms, not a struct literal.
+        assert_eq!(expr_shape("x = match p {}"), "(x = match(p, 0 arms))");
+        assert_eq!(
+            expr_shape("x = match f(P {}) { _ => P {} }"),
+            "(x = match(f(P {  }), 1 arms))"
+        );
+
+        // Only a block body may leave out the comma before the next arm.
+        let err = first_error("fn f() { match x { 1 => a 2 => b } }");
+        assert_eq!(err.message, "expected ',' or '}', found integer literal");
+        let err = first_error("fn f() { match x { + => a } }");
+        assert_eq!(err.message, "expected pattern, found '+'");
+    }
+
+    #[test]
+    fn test_duplicate_fields() {
+        let err = first_error("struct P { x: int, x: float }");
+        assert_eq!(
+            err,
+            Diagnostic::error(
+                codes::DUPLICATE_FIELD,
+                "field 'x' is listed more than once",
+                Span::new(19, 20)
+            )
+            .with_label(Span::new(11, 12), "first listed here")
+        );
+
+        let err = first_error("fn main() { P { x: 1, y: 2, x: 3 }; }");
+        assert_eq!(err.primary_span, Span::new(28, 29));
+
+        // The rest of the item still parses, and later errors are found.
+        let (program, diagnostics) =
+            parse("struct P { x: int, x: float } fn main() { P { x: 1, x: 2 }; } int y = ;");
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            [
+                codes::DUPLICATE_FIELD,
+                codes::DUPLICATE_FIELD,
+                codes::UNEXPECTED_TOKEN
+            ]
+        );
+        assert_eq!(program.items.len(), 2);
+        let Item::Struct(decl) = &program.items[0] else {
+            panic!("expected a struct");
+        };
+        assert_eq!(decl.fields.len(), 1);
+        assert!(diagnostics[..2].iter().all(keeps_tree));
+    }
+
+    #[test]
+    fn test_node_ids() {
+        let program = parse_ok("fn f() { x = 1 + 2; }");
+        let Item::Function(f) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let arena = &program.arena;
+        let stmt = &arena[f.body.stmts[0]];
+        let StmtKind::Expr(assign) = stmt.kind else {
+            panic!("expected an expression statement");
+        };
+        let ExprKind::Assign { target, value, .. } = arena[assign].kind else {
+            panic!("expected an assignment");
+        };
+        let ExprKind::Binary { left, right, .. } = arena[value].kind else {
+            panic!("expected a binary expression");
+        };
+        // Numbered in the order the nodes are finished.
+        let ids: Vec<usize> = [target, left, right, value, assign]
+            .iter()
+            .map(|expr| arena[*expr].id.index())
+            .chain([stmt.id.index(), f.body.id.index(), f.id.index()])
+            .collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // Parsing the same text again gives the same ids.
+        assert_eq!(parse_ok("fn f() { x = 1 + 2; }"), program);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = first_error("fn main() { int x = 1 2; }");
+        assert_eq!(err.message, "expected ';', found integer literal");
+        assert_eq!(err.primary_span, Span::new(22, 23));
+
+        let err = first_error("fn main() { x = ; }");
+        assert_eq!(err.message, "expected expression, found ';'");
+
+        let err = first_error("fn main() { 1 = x; }");
+        assert_eq!(
+            err,
+            Diagnostic::error(
+                codes::INVALID_ASSIGNMENT_TARGET,
+                "invalid assignment target",
+                Span::new(12, 13)
+            )
+        );
+        let err = first_error("fn main() { a + b = c; }");
+        assert_eq!(err.primary_span, Span::new(12, 17));
+
+        let err = first_error("fn main() { x = @; }");
+        assert_eq!(
+            err,
+            ParseError::Lexical {
+                error: LexicalError::InvalidCharacter('@'),
+                span: Span::new(16, 17),
+            }
+            .into()
+        );
+        // A lexical error keeps the lexer's code, and its hint becomes a note.
+        let err = first_error("fn main() { x = 'a'; }");
+        assert_eq!(err.code, codes::INVALID_CHARACTER);
+        assert_eq!(err.notes, ["string literals use double quotes: \"...\""]);
+
+        let err = first_error("x;");
+        assert_eq!(err.message, "expected item, found identifier");
+        assert_eq!(err.code, codes::UNEXPECTED_TOKEN);
+    }
+
+    #[test]
+    fn test_expected_alternatives() {
+        let err = first_error("int x {");
+        assert_eq!(err.message, "expected '=' or ';', found '{'");
+        assert_eq!(err.primary_span, Span::new(6, 7));
+
+        let err = first_error("fn f(a: int b: int) {}");
+        assert_eq!(err.message, "expected ',' or ')', found identifier");
+        let err = first_error("fn f() int {}");
+        assert_eq!(err.message, "expected '->' or '{', found 'int'");
+        let err = first_error("struct S { x: int y: int }");
+        assert_eq!(err.message, "expected ',' or '}', found identifier");
+        let err = first_error("fn f() { P { x: 1 y: 2 }; }");
+        assert_eq!(err.message, "expected ',' or '}', found identifier");
+        let err = first_error("fn f() {");
+        assert_eq!(err.message, "expected '}', found end of file");
+    }
+
+    #[test]
+    fn test_recovery() {
+        let messages = |source| {
+            let (_, diagnostics) = parse(source);
+            diagnostics
+                .iter()
+                .map(|d| d.message.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Each bad statement is reported, and the good ones around it kept.
+        let src = "fn main() { int x = ; x = 1; y = * 2; return x; }";
+        let (program, diagnostics) = parse(src);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].primary_span, Span::new(20, 21));
+        assert_eq!(diagnostics[1].primary_span, Span::new(33, 34));
+        let Item::Function(main) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let stmts = &main.body.stmts;
+        assert!(matches!(program.arena[stmts[0]].kind, StmtKind::Expr(_)));
+        assert!(matches!(program.arena[stmts[1]].kind, StmtKind::Return(_)));
+
+        // A missing `;` before the next statement is one error, not two.
+        assert_eq!(
+            messages("fn f() { x = 1 return x; while (x y) { a; b; } z = 2 }"),
+            [
+                "missing ';'",
+                "expected ')', found identifier",
+                "missing ';'"
+            ]
+        );
+
+        // A broken function header skips the body, and later items still
+        // parse.
+        let (program, diagnostics) = parse("fn f( { a; } struct S { x: int } } fn g() {}");
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "expected ')' or identifier, found '{'",
+                "expected item, found '}'"
+            ]
+        );
+        assert_eq!(program.items.len(), 2);
+
+        // Lexical errors in skipped text are reported once each.
+        assert_eq!(
+            messages("fn f() { x = @ $; }"),
+            ["invalid character: '@'", "invalid character: '$'"]
+        );
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let (arena, expr) = parse_expression("a + b * f(2)").unwrap();
+        assert_eq!(shape(&arena, expr), "(a + (b * f(2)))");
+        assert_eq!(arena[expr].span, Span::new(0, 12));
+
+        let messages = |source| -> Vec<String> {
+            let diagnostics = parse_expression(source).unwrap_err();
+            diagnostics.into_iter().map(|d| d.message).collect()
+        };
+        assert_eq!(
+            messages("1 2"),
+            ["expected end of file, found integer literal"]
+        );
+        assert_eq!(messages(""), ["expected expression, found end of file"]);
+        assert_eq!(messages("x = 1;"), ["expected end of file, found ';'"]);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let nested = |depth| format!("fn f() {{ {}x{}; }}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse(&nested(100)).1, []);
+        let err = first_error(&nested(100_000));
+        assert_eq!(err.message, "expression too deeply nested");
+
+        let source = nested(10);
+        let mut parser = Parser::new(Scanner::new(&source)).with_max_depth(8);
+        let (_, diagnostics) = parser.parse_program();
+        // The statement and the outer expression take two levels.
+        assert_eq!(diagnostics[0].primary_span, Span::new(16, 17));
+    }
+
+    /// Long chains of operators, postfix operations and array suffixes
+    /// build trees as deep as they are long, so they count against the
+    /// limit as nesting does.
+    #[test]
+    fn test_depth_limit_counts_chains() {
+        let long = 5_000;
+        let sum = vec!["1"; long].join(" + ");
+        let sources = [
+            format!("fn f() {{ int x = 0; x = {}; }}", sum),
+            format!("const int X = {};", sum),
+            format!("fn f() {{ a{} = 1; }}", "[0]".repeat(long)),
+            format!("fn f() {{ a{} = 1; }}", ".b".repeat(long)),
+            format!("fn f() {{ f{}; }}", "()".repeat(long)),
+            format!("int{} x;", "[1]".repeat(long)),
+        ];
+        for source in &sources {
+            let message = &first_error(source).message;
+            assert!(message.ends_with("too deeply nested"), "in {:?}", source);
+            fuzz_check(source.as_bytes());
+        }
+
+        let sum = vec!["1"; 100].join(" + ");
+        let source = format!("fn f() {{ int x = 0; x = {}; }}", sum);
+        assert_eq!(parse(&source).1, []);
+        fuzz_check(source.as_bytes());
+    }
+
+    /// Random deep nestings of every recursive construct end in the depth
+    /// error, not a stack overflow.
+    #[test]
+    fn test_depth_limit_fuzz() {
+        let expressions = [
+            ("(", ")"),
+            ("- ", ""),
+            ("[", "]"),
+            ("g(", ")"),
+            ("(a = ", ")"),
+            ("c ? ", " : d"),
+            ("P { x: ", " }"),
+        ];
+        let statements = [("{ ", " }"), ("if (c) ", ""), ("while (c) ", "")];
+        let types = [("fn(", ")"), ("fn() -> ", "")];
+        // xorshift64, so the inputs are the same on every run.
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut random = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % n
+        };
+        for _ in 0..30 {
+            let depth = [50, 400, 5_000][random(3)];
+            let (mut open, mut close) = (String::new(), String::new());
+            let mut wrap = |(start, end): (&str, &str)| {
+                open.push_str(start);
+                close.insert_str(0, end);
+            };
+            let source = match random(3) {
+                0 => {
+                    (0..depth).for_each(|_| wrap(expressions[random(expressions.len())]));
+                    format!("fn f() {{ {}x{}; }}", open, close)
+                }
+                1 => {
+                    (0..depth).for_each(|_| wrap(statements[random(statements.len())]));
+                    format!("fn f() {{ {}x;{} }}", open, close)
+                }
+                _ => {
+                    (0..depth).for_each(|_| wrap(types[random(types.len())]));
+                    format!("{}int{} t;", open, close)
+                }
+            };
+            let (_, diagnostics) = parse(&source);
+            if depth < Parser::DEFAULT_MAX_DEPTH {
+                assert_eq!(diagnostics, [], "in {:?}", source);
+            } else {
+                // A statement's condition may be what crosses the limit.
+                let message = &diagnostics[0].message;
+                assert!(message.ends_with("too deeply nested"), "in {:?}", source);
+            }
+        }
+    }
+
+    /// Random byte edits of valid programs, and random bytes, all parse
+    /// without breaking the invariants `fuzz_check` asserts.
+    #[test]
+    fn test_fuzz_check() {
+        let seeds = [
+            "struct P<T> { x: T[2] } enum E { A(int), B }",
+            "fn f(a: int) -> int { for (let i = 0; i < a; i += 1) { if (i) break; } return a; }",
+            "fn g() { let m: Map<int, P<int>>= n; x = match e { E::A(v) => v, _ => { y; } }; }",
+            "int x = -(1 + 2) * f(3)[4].y; float z = a ? b : c;",
+        ];
+        let bytes: &[u8] = b"(){}[]<>;:,.=+-*/!?&|^~\"'0a_ \n\xff";
+        // xorshift64, so the inputs are the same on every run.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % n
+        };
+        for _ in 0..300 {
+            let mut data = seeds[random(seeds.len())].as_bytes().to_vec();
+            for _ in 0..1 + random(8) {
+                let at = random(data.len() + 1);
+                match random(3) {
+                    0 if at < data.len() => {
+                        data.remove(at);
+                    }
+                    1 => data.truncate(at),
+                    _ => data.insert(at, bytes[random(bytes.len())]),
+                }
+            }
+            fuzz_check(&data);
+        }
+        for _ in 0..100 {
+            let data: Vec<u8> = (0..random(64)).map(|_| random(256) as u8).collect();
+            fuzz_check(&data);
+        }
+    }
+
+    #[test]
+    fn test_missing_semicolon() {
+        let src = "fn f() {\n    int x = 1\n    x += 2\n    return x\n}\nint g = 3";
+        let (program, diagnostics) = parse(src);
+        let ends: Vec<usize> = diagnostics.iter().map(|d| d.primary_span.start).collect();
+        assert_eq!(ends, [22, 33, 46, 58]);
+        assert!(diagnostics.iter().all(|d| d.message == "missing ';'"));
+        assert_eq!(
+            diagnostics[0].fixits,
+            [FixIt {
+                span: Span::new(22, 22),
+                replacement: ";".to_string(),
+                message: "insert ';' here".to_string(),
+            }]
+        );
+        // Parsing carried on as if each `;` were there.
+        let Item::Function(f) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(f.body.stmts.len(), 3);
+        assert_eq!(program.arena[f.body.stmts[0]].span, Span::new(13, 22));
+        assert!(matches!(program.items[1], Item::Global(_)));
+
+        // Within a line, before something that cannot start a statement,
+        // it stays an ordinary syntax error.
+        let err = first_error("fn f() { x = 1 ) }");
+        assert_eq!(err.message, "expected ';', found ')'");
+        assert_eq!(err.fixits, []);
+    }
+}
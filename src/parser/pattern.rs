@@ -0,0 +1,61 @@
+//! Pattern parsing, for `match` arms.
+
+use super::{Expected, ParseResult, Parser};
+use crate::ast::{Pattern, PatternKind};
+use crate::lexer::TokenType;
+
+impl<'a> Parser<'a> {
+    pub(super) fn pattern(&mut self) -> ParseResult<Pattern> {
+        self.nested("pattern", Self::pattern_inner)
+    }
+
+    /// `_`, a literal, a name to bind, or `Enum::Variant` with an optional
+    /// `(pattern, ...)` for its payload.
+    fn pattern_inner(&mut self) -> ParseResult<Pattern> {
+        let next = self.tokens.peek();
+        let (start, token_type) = (next.span, next.token_type);
+        let kind = match token_type {
+            TokenType::IntLiteral
+            | TokenType::FloatLiteral
+            | TokenType::StringLiteral
+            | TokenType::BoolLiteral
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Null => PatternKind::Literal(self.tokens.next_token().literal),
+            TokenType::Identifier
+                if self.tokens.peek_nth(1).token_type == TokenType::ColonColon =>
+            {
+                let (enum_name, variant) = self.path()?;
+                let mut fields = Vec::new();
+                if self.tokens.eat(TokenType::LParen) {
+                    while !self.tokens.check(TokenType::RParen) {
+                        fields.push(self.pattern()?);
+                        if !self.eat(TokenType::Comma) {
+                            break;
+                        }
+                    }
+                    self.expect(TokenType::RParen)?;
+                }
+                PatternKind::Variant {
+                    enum_name,
+                    variant,
+                    fields,
+                }
+            }
+            TokenType::Identifier => {
+                let name = self.ident()?;
+                if name.name == "_" {
+                    PatternKind::Wildcard
+                } else {
+                    PatternKind::Binding(name)
+                }
+            }
+            _ => return Err(self.unexpected(Expected::Construct("pattern"))),
+        };
+        Ok(Pattern {
+            id: self.next_id(),
+            kind,
+            span: start.to(self.tokens.prev_span()),
+        })
+    }
+}
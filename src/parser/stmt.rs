@@ -0,0 +1,152 @@
+//! Statement parsing.
+
+use super::{ParseResult, Parser};
+use crate::ast::{Block, ExprId, StmtId, StmtKind};
+use crate::lexer::TokenType;
+
+impl<'a> Parser<'a> {
+    /// `{ stmts }`
+    pub(super) fn block(&mut self) -> ParseResult<Block> {
+        let start = self.expect(TokenType::LBrace)?.span;
+        let mut stmts = Vec::new();
+        while !self.tokens.check(TokenType::RBrace) && !self.tokens.is_at_end() {
+            let before = self.tokens.peek().span;
+            match self.statement() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(error) => self.recover(error, before),
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span;
+        Ok(Block {
+            id: self.next_id(),
+            stmts,
+            span: start.to(end),
+        })
+    }
+
+    pub(super) fn statement(&mut self) -> ParseResult<StmtId> {
+        self.nested("statement", Self::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> ParseResult<StmtId> {
+        let start = self.tokens.peek().span;
+        let token_type = self.tokens.peek().token_type;
+        let kind = match token_type {
+            TokenType::LBrace => StmtKind::Block(self.block()?),
+            TokenType::If => self.if_statement()?,
+            TokenType::While => {
+                self.tokens.next_token();
+                let condition = self.condition()?;
+                let body = self.statement()?;
+                StmtKind::While { condition, body }
+            }
+            TokenType::For => self.for_statement()?,
+            TokenType::Match => {
+                // Like a block, a `match` statement needs no `;` after it.
+                let expr = self.match_expr()?;
+                self.tokens.eat(TokenType::Semicolon);
+                StmtKind::Expr(expr)
+            }
+            TokenType::Return => {
+                self.tokens.next_token();
+                let value = if self.tokens.check(TokenType::Semicolon) {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.expect_semicolon()?;
+                StmtKind::Return(value)
+            }
+            TokenType::Break | TokenType::Continue => {
+                self.tokens.next_token();
+                self.expect_semicolon()?;
+                if token_type == TokenType::Break {
+                    StmtKind::Break
+                } else {
+                    StmtKind::Continue
+                }
+            }
+            _ if self.at_var_decl() => StmtKind::VarDecl(self.var_decl()?),
+            _ => {
+                let expr = self.discarded_expression()?;
+                self.expect_semicolon()?;
+                StmtKind::Expr(expr)
+            }
+        };
+        let span = start.to(self.tokens.prev_span());
+        Ok(self.alloc_stmt(kind, span))
+    }
+
+    /// `if (cond) stmt [else stmt]`. An `else` binds to the nearest `if`
+    /// without one: the inner `if` parses its `else` before returning, so
+    /// `if (a) if (b) x; else y;` attaches `else y;` to `if (b)`.
+    fn if_statement(&mut self) -> ParseResult<StmtKind> {
+        self.expect(TokenType::If)?;
+        let condition = self.condition()?;
+        let then_branch = self.statement()?;
+        let else_branch = if self.tokens.eat(TokenType::Else) {
+            Some(self.statement()?)
+        } else {
+            None
+        };
+        Ok(StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    /// `for (init; cond; step) body`, where each part may be empty and
+    /// `init` may declare a variable.
+    fn for_statement(&mut self) -> ParseResult<StmtKind> {
+        self.expect(TokenType::For)?;
+        self.expect(TokenType::LParen)?;
+        let init = if self.tokens.eat(TokenType::Semicolon) {
+            None
+        } else {
+            // A declaration or expression statement, including its `;`.
+            let start = self.tokens.peek().span;
+            let kind = if self.at_var_decl() {
+                StmtKind::VarDecl(self.var_decl()?)
+            } else {
+                let expr = self.discarded_expression()?;
+                self.expect_semicolon()?;
+                StmtKind::Expr(expr)
+            };
+            let span = start.to(self.tokens.prev_span());
+            Some(self.alloc_stmt(kind, span))
+        };
+        let condition = self.optional_expression(TokenType::Semicolon)?;
+        self.expect(TokenType::Semicolon)?;
+        let step = if self.tokens.check(TokenType::RParen) {
+            None
+        } else {
+            Some(self.discarded_expression()?)
+        };
+        self.expect(TokenType::RParen)?;
+        let body = self.statement()?;
+        Ok(StmtKind::For {
+            init,
+            condition,
+            step,
+            body,
+        })
+    }
+
+    /// An expression, or nothing if the next token is `terminator`.
+    fn optional_expression(&mut self, terminator: TokenType) -> ParseResult<Option<ExprId>> {
+        if self.tokens.check(terminator) {
+            Ok(None)
+        } else {
+            self.expression().map(Some)
+        }
+    }
+
+    /// `( expr )` after `if` or `while`.
+    fn condition(&mut self) -> ParseResult<ExprId> {
+        self.expect(TokenType::LParen)?;
+        let condition = self.expression()?;
+        self.expect(TokenType::RParen)?;
+        Ok(condition)
+    }
+}
@@ -0,0 +1,486 @@
+//! Textual inclusion of other source files, and object-like macros.
+//!
+//! A line of the form `#include "path"` is replaced by the tokens of the
+//! named file, resolved relative to the including file. `#` is not a token
+//! of the language, so directives are recognised from the lexer's output:
+//! an invalid `#` that starts a line, followed by the directive name on the
+//! same line.
+//!
+//! With macros enabled, `#define NAME tokens...` makes every later `NAME`
+//! expand to the rest of the directive's line. As in C, a macro is not
+//! expanded again inside its own expansion, so `#define X X + 1` terminates.
+//!
+//! The parser reads one text, so a [`Unit`] lays the files that took part
+//! out one after another and moves each token there.
+
+use crate::diagnostics::{codes, Code, Diagnostic};
+use crate::lexer::{LexicalError, LiteralValue, Scanner, ScannerOptions, Token, TokenType};
+use crate::source::{FileId, SourceManager, Span, Spanned};
+use crate::utils::SymbolTable;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum PreprocessError {
+    #[error("expected a file name in double quotes after #include")]
+    MalformedInclude,
+
+    #[error("cannot read included file '{path}': {message}")]
+    UnreadableInclude { path: PathBuf, message: String },
+
+    #[error("'{0}' includes itself")]
+    RecursiveInclude(PathBuf),
+
+    #[error("expected a macro name after #define")]
+    MalformedDefine,
+}
+
+impl PreprocessError {
+    pub fn code(&self) -> Code {
+        match self {
+            PreprocessError::MalformedInclude => codes::MALFORMED_INCLUDE,
+            PreprocessError::UnreadableInclude { .. } => codes::UNREADABLE_INCLUDE,
+            PreprocessError::RecursiveInclude(_) => codes::RECURSIVE_INCLUDE,
+            PreprocessError::MalformedDefine => codes::MALFORMED_DEFINE,
+        }
+    }
+}
+
+/// A token together with the file it was scanned from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceToken {
+    pub file: FileId,
+    pub token: Token<'static>,
+    /// For a token that came from a macro body (whose `file` and span point
+    /// at the `#define`), the macro use it replaced. Nested expansions
+    /// record the outermost use.
+    pub expanded_from: Option<ExpansionSite>,
+}
+
+/// Where a macro was used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpansionSite {
+    pub file: FileId,
+    pub span: Span,
+}
+
+/// Expands `#include` (and optionally `#define`) directives, producing one
+/// token stream for a file and everything it includes.
+pub struct Preprocessor<'m> {
+    sources: &'m mut SourceManager,
+    options: ScannerOptions,
+    /// Whether `#define` is handled; otherwise it is left to the lexer's
+    /// invalid-character errors.
+    macros_enabled: bool,
+    macros: HashMap<String, Vec<SourceToken>>,
+    /// Shared by every file, so the same name has the same symbol everywhere.
+    symbols: SymbolTable,
+    /// Files currently being expanded, innermost last.
+    stack: Vec<FileId>,
+    errors: Vec<(FileId, Spanned<PreprocessError>)>,
+}
+
+impl<'m> Preprocessor<'m> {
+    pub fn new(sources: &'m mut SourceManager, options: ScannerOptions) -> Self {
+        Self {
+            sources,
+            options,
+            macros_enabled: false,
+            macros: HashMap::new(),
+            symbols: SymbolTable::new(),
+            stack: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Enables `#define` and expansion of the macros it defines.
+    pub fn with_macros(mut self, enabled: bool) -> Self {
+        self.macros_enabled = enabled;
+        self
+    }
+
+    /// Tokens of `root` with every include expanded, ending with the
+    /// `EndOfFile` of `root`.
+    pub fn run(&mut self, root: FileId) -> Vec<SourceToken> {
+        let mut out = Vec::new();
+        self.expand(root, &mut out);
+        out
+    }
+
+    /// Problems with directives, each with the file it occurred in.
+    pub fn errors(&self) -> &[(FileId, Spanned<PreprocessError>)] {
+        &self.errors
+    }
+
+    pub fn into_symbols(self) -> SymbolTable {
+        self.symbols
+    }
+
+    fn expand(&mut self, file: FileId, out: &mut Vec<SourceToken>) {
+        self.stack.push(file);
+        let text = self.sources.text(file).to_string();
+        let mut scanner = Scanner::with_options(&text, self.options.clone())
+            .with_symbol_table(std::mem::take(&mut self.symbols));
+        let tokens: Vec<_> = scanner.by_ref().map(Token::into_owned).collect();
+        self.symbols = scanner.into_symbols();
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            if is_directive(&text, &tokens, i, "include") {
+                let hash = token.span;
+                match tokens.get(i + 2) {
+                    Some(name)
+                        if name.token_type == TokenType::StringLiteral
+                            && !text[hash.start..name.span.start].contains('\n') =>
+                    {
+                        self.include(file, name, hash.to(name.span), out);
+                        i += 3;
+                    }
+                    _ => {
+                        let span = hash.to(tokens[i + 1].span);
+                        self.error(file, PreprocessError::MalformedInclude, span);
+                        i += 2;
+                    }
+                }
+                continue;
+            }
+            if self.macros_enabled && is_directive(&text, &tokens, i, "define") {
+                i = self.define(file, &text, &tokens, i);
+                continue;
+            }
+            // Only the outermost file ends the stream.
+            if token.token_type == TokenType::EndOfFile && self.stack.len() > 1 {
+                break;
+            }
+            let token = SourceToken {
+                file,
+                token: token.clone(),
+                expanded_from: None,
+            };
+            self.substitute(token, &mut Vec::new(), out);
+            i += 1;
+        }
+        self.stack.pop();
+    }
+
+    /// Records the `#define` at `tokens[i]` and returns the index of the
+    /// first token after it.
+    fn define(&mut self, file: FileId, text: &str, tokens: &[Token<'static>], i: usize) -> usize {
+        let directive = tokens[i].span.to(tokens[i + 1].span);
+        let line_end = text[directive.end..]
+            .find('\n')
+            .map_or(text.len(), |n| directive.end + n);
+        let name = match tokens.get(i + 2) {
+            Some(name)
+                if name.token_type == TokenType::Identifier && name.span.start < line_end =>
+            {
+                name
+            }
+            _ => {
+                self.error(file, PreprocessError::MalformedDefine, directive);
+                return i + 2;
+            }
+        };
+        let body: Vec<_> = tokens[i + 3..]
+            .iter()
+            .take_while(|t| t.span.start < line_end && t.token_type != TokenType::EndOfFile)
+            .map(|t| SourceToken {
+                file,
+                token: t.clone(),
+                expanded_from: None,
+            })
+            .collect();
+        let next = i + 3 + body.len();
+        self.macros.insert(name.lexeme.to_string(), body);
+        next
+    }
+
+    /// Pushes `token`, or the expansion of the macro it names. `active` holds
+    /// the macros being expanded, which are not expanded again.
+    fn substitute(&self, token: SourceToken, active: &mut Vec<String>, out: &mut Vec<SourceToken>) {
+        let name = token.token.lexeme.as_ref();
+        let body = match token.token.token_type {
+            TokenType::Identifier if !active.iter().any(|m| m == name) => self.macros.get(name),
+            _ => None,
+        };
+        let Some(body) = body else {
+            out.push(token);
+            return;
+        };
+        let site = token.expanded_from.unwrap_or(ExpansionSite {
+            file: token.file,
+            span: token.token.span,
+        });
+        active.push(name.to_string());
+        for t in body {
+            let t = SourceToken {
+                expanded_from: Some(site),
+                ..t.clone()
+            };
+            self.substitute(t, active, out);
+        }
+        active.pop();
+    }
+
+    fn include(&mut self, from: FileId, name: &Token<'_>, span: Span, out: &mut Vec<SourceToken>) {
+        let LiteralValue::String(name) = &name.literal else {
+            return;
+        };
+        let path = match self.sources.path(from).parent() {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        };
+        match self.sources.load(&path) {
+            Ok(file) if self.stack.contains(&file) => {
+                self.error(from, PreprocessError::RecursiveInclude(path), span);
+            }
+            Ok(file) => self.expand(file, out),
+            Err(err) => {
+                let message = err.to_string();
+                self.error(
+                    from,
+                    PreprocessError::UnreadableInclude { path, message },
+                    span,
+                );
+            }
+        }
+    }
+
+    fn error(&mut self, file: FileId, error: PreprocessError, span: Span) {
+        self.errors.push((file, Spanned::new(error, span)));
+    }
+}
+
+/// The tokens of a preprocessed file, with the text of every file taking
+/// part laid out one after another, in [`FileId`] order, as a single text
+/// for the parser. Each token's span is moved to where its file landed; a
+/// token a macro expanded to takes the span of the macro use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub text: String,
+    pub tokens: Vec<Token<'static>>,
+    /// Where the text of each file starts in `text`, by file index.
+    starts: Vec<usize>,
+}
+
+impl Unit {
+    pub fn new(sources: &SourceManager, tokens: Vec<SourceToken>) -> Self {
+        let mut text = String::new();
+        let mut starts = Vec::new();
+        for file in sources.files() {
+            starts.push(text.len());
+            text.push_str(sources.text(file));
+            // Keeps the end of one file apart from the start of the next.
+            text.push('\n');
+        }
+        let tokens = tokens
+            .into_iter()
+            .map(|t| {
+                let (file, span) = t
+                    .expanded_from
+                    .map_or((t.file, t.token.span), |site| (site.file, site.span));
+                let mut token = t.token;
+                token.span = span.shift(starts[file.index()] as isize);
+                token
+            })
+            .collect();
+        Self {
+            text,
+            tokens,
+            starts,
+        }
+    }
+
+    /// `span` of `file` moved into the unit's text.
+    pub fn span(&self, file: FileId, span: Span) -> Span {
+        span.shift(self.starts[file.index()] as isize)
+    }
+
+    /// The file the unit's text at `offset` comes from, and the offset in
+    /// that file.
+    pub fn locate(&self, offset: usize) -> (FileId, usize) {
+        let index = self.starts.partition_point(|&start| start <= offset) - 1;
+        (FileId::new(index), offset - self.starts[index])
+    }
+
+    /// `diagnostic` about the unit moved into the file its primary span is
+    /// in. Labels and fix-its in other files are dropped, as they cannot be
+    /// shown with it.
+    pub fn locate_diagnostic(&self, mut diagnostic: Diagnostic) -> (FileId, Diagnostic) {
+        let (file, _) = self.locate(diagnostic.primary_span.start);
+        let start = self.starts[file.index()];
+        let end = self
+            .starts
+            .get(file.index() + 1)
+            .map_or(self.text.len(), |&next| next);
+        let local = |span: Span| {
+            (start <= span.start && span.end < end).then(|| span.shift(-(start as isize)))
+        };
+        diagnostic.primary_span = diagnostic.primary_span.shift(-(start as isize));
+        diagnostic
+            .labels
+            .retain_mut(|label| local(label.span).map(|span| label.span = span).is_some());
+        diagnostic
+            .fixits
+            .retain_mut(|fixit| local(fixit.span).map(|span| fixit.span = span).is_some());
+        (file, diagnostic)
+    }
+}
+
+/// Whether `tokens[i]` is a `#` at the start of a line, followed by the
+/// identifier `name` on the same line.
+fn is_directive(text: &str, tokens: &[Token<'_>], i: usize, name: &str) -> bool {
+    let hash = &tokens[i];
+    if hash.error != Some(LexicalError::InvalidCharacter('#')) {
+        return false;
+    }
+    let before = text[..hash.span.start].trim_end_matches([' ', '\t']);
+    (before.is_empty() || before.ends_with('\n'))
+        && tokens.get(i + 1).is_some_and(|t| {
+            t.token_type == TokenType::Identifier
+                && t.lexeme == name
+                && !text[hash.span.end..t.span.start].contains('\n')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn lexemes(tokens: &[SourceToken]) -> Vec<(usize, &str)> {
+        tokens
+            .iter()
+            .map(|t| (t.file.index(), t.token.lexeme.as_ref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_include() {
+        let mut sources = SourceManager::new();
+        let main = sources.add("tests/main.mc", "#include \"lib/consts.mc\"\nint x = ONE;");
+        let consts = sources.add("tests/lib/consts.mc", "int ONE = 1;\n");
+        let mut pp = Preprocessor::new(&mut sources, ScannerOptions::new());
+        let tokens = pp.run(main);
+        assert!(pp.errors().is_empty());
+        assert_eq!(
+            lexemes(&tokens),
+            vec![
+                (1, "int"),
+                (1, "ONE"),
+                (1, "="),
+                (1, "1"),
+                (1, ";"),
+                (0, "int"),
+                (0, "x"),
+                (0, "="),
+                (0, "ONE"),
+                (0, ";"),
+                (0, ""),
+            ]
+        );
+        assert_eq!(tokens[0].file, consts);
+        // Both files agree on the symbol for `ONE`.
+        assert_eq!(tokens[1].token.symbol, tokens[8].token.symbol);
+    }
+
+    #[test]
+    fn test_define() {
+        let mut sources = SourceManager::new();
+        let main = sources.add(
+            "main.mc",
+            "#define SIZE 4 * UNIT\n#define UNIT 8\n#define LOOP LOOP + 1\n#define\nSIZE; LOOP;",
+        );
+        let mut pp = Preprocessor::new(&mut sources, ScannerOptions::new()).with_macros(true);
+        let tokens = pp.run(main);
+        assert!(matches!(
+            pp.errors(),
+            [(
+                _,
+                Spanned {
+                    value: PreprocessError::MalformedDefine,
+                    ..
+                }
+            )]
+        ));
+        assert_eq!(
+            lexemes(&tokens)
+                .iter()
+                .map(|&(_, lexeme)| lexeme)
+                .collect::<Vec<_>>(),
+            vec!["4", "*", "8", ";", "LOOP", "+", "1", ";", ""]
+        );
+        // `8` comes from the body of UNIT, but was expanded from the use of
+        // SIZE on the last line.
+        assert_eq!(tokens[2].token.span, Span::new(35, 36));
+        let site = tokens[2].expanded_from.unwrap();
+        assert_eq!(site.span, Span::new(67, 71));
+        assert_eq!(tokens[3].expanded_from, None);
+
+        // Without macros, `#define` lines are left alone.
+        let mut pp = Preprocessor::new(&mut sources, ScannerOptions::new());
+        assert_eq!(pp.run(main)[0].token.lexeme, "#");
+    }
+
+    #[test]
+    fn test_unit() {
+        let mut sources = SourceManager::new();
+        let main = sources.add("main.mc", "#include \"lib.mc\"\n#define ONE 1\nint y = ONE");
+        let lib = sources.add("lib.mc", "int x = 1;\n");
+        let mut pp = Preprocessor::new(&mut sources, ScannerOptions::new()).with_macros(true);
+        let tokens = pp.run(main);
+        let unit = Unit::new(&sources, tokens);
+
+        // `lib.mc` comes after `main.mc` and the line break that ends it.
+        let lib_start = sources.text(main).len() + 1;
+        assert_eq!(&unit.text[lib_start..], "int x = 1;\n\n");
+        assert_eq!(unit.tokens[0].span, Span::new(lib_start, lib_start + 3));
+        assert_eq!(unit.locate(lib_start + 4), (lib, 4));
+        // `1` takes the span of the use of ONE.
+        assert_eq!(unit.tokens[8].span, Span::new(40, 43));
+
+        let scanner = Scanner::new(&unit.text).with_tokens(unit.tokens.clone());
+        let (program, diagnostics) = crate::parser::Parser::new(scanner).parse_program();
+        assert_eq!(program.items.len(), 2);
+        let (file, diagnostic) = unit.locate_diagnostic(diagnostics[0].clone());
+        assert_eq!(file, main);
+        assert_eq!(diagnostic.message, "missing ';'");
+        assert_eq!(diagnostic.primary_span, Span::new(43, 43));
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let mut sources = SourceManager::new();
+        let main = sources.add(
+            "main.mc",
+            "#include \"self.mc\"\n#include missing\nx # include \"a\";\n#include \"nope.mc\"",
+        );
+        sources.add("self.mc", "#include \"main.mc\"\ny");
+        let mut pp = Preprocessor::new(&mut sources, ScannerOptions::new());
+        let tokens = pp.run(main);
+        let errors: Vec<_> = pp
+            .errors()
+            .iter()
+            .map(|(file, e)| (file.index(), &e.value))
+            .collect();
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                (1, PreprocessError::RecursiveInclude(_)),
+                (0, PreprocessError::MalformedInclude),
+                (0, PreprocessError::UnreadableInclude { .. }),
+            ]
+        ));
+        // A `#` in the middle of a line is left alone.
+        assert_eq!(
+            tokens[3].token.error,
+            Some(LexicalError::InvalidCharacter('#'))
+        );
+        assert_eq!(
+            lexemes(&tokens)[..4],
+            [(1, "y"), (0, "missing"), (0, "x"), (0, "#")]
+        );
+    }
+}
@@ -0,0 +1,686 @@
+//! Demand-driven, memoized compilation queries.
+//!
+//! Inputs (source texts) are set on the [`Database`]; derived queries are
+//! computed on first use and cached. They form a pipeline, each query
+//! depending on the one before it:
+//!
+//! [`tokens`](Database::tokens) → [`parse`](Database::parse) →
+//! [`resolve`](Database::resolve) → [`typeck`](Database::typeck) →
+//! [`ir`](Database::ir).
+//!
+//! Every input change bumps the database revision, and a memo is only
+//! recomputed when one of its inputs changed after the memo was last
+//! verified. If a recomputed value is equal to the old one, the memo keeps
+//! its old `changed_at` revision so queries further down the pipeline do not
+//! need to rerun ("early cutoff").
+//!
+//! Tokens count as equal when only their positions differ, as after an edit
+//! to a comment or to spaces, as long as no two of them came to touch or
+//! stopped touching and no line break appeared or disappeared between two
+//! of them (the parser looks for those where a `;` is missing). The results after [`tokens`](Database::tokens) are then kept
+//! with their spans moved along with the tokens, so none of those queries
+//! runs again.
+//!
+//! Each query is a pure function of its inputs. Whether to go on to the next
+//! phase after errors, as the command-line driver decides after a syntax
+//! error, is up to the caller.
+
+use crate::analysis::{Cfg, Dominators, Ssa};
+use crate::ast::visit_mut::map_spans;
+use crate::ast::{Item, Program};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Scanner, ScannerOptions, Token, Trivia};
+use crate::lowering;
+use crate::parser::Parser;
+use crate::semantic::{self, typeck, ConstValues, Resolution, TypeckOptions};
+pub use crate::source::FileId;
+use crate::source::Span;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub type Revision = u64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Lookups answered from a memo without running the query.
+    pub hits: usize,
+    /// Query executions (first computation or recomputation).
+    pub executions: usize,
+}
+
+/// The tree of a file and its syntax errors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parsed {
+    pub program: Program,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// What the names of a parsed file refer to.
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub resolution: Resolution,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A type-checked file.
+#[derive(Debug, Clone)]
+pub struct Typed {
+    /// The parsed program with the conversions the checker inserted.
+    pub program: Program,
+    pub consts: ConstValues,
+    /// Errors from constant evaluation and type checking, then lint
+    /// warnings.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The lowered form of a type-checked file, with a control-flow graph in
+/// SSA form for each function.
+#[derive(Debug, Clone)]
+pub struct Ir {
+    pub program: Program,
+    /// The names of the lowered program, which lowering adds to.
+    pub resolution: Resolution,
+    pub functions: Vec<FunctionIr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionIr {
+    /// Index of the function in `program.items`.
+    pub item: usize,
+    pub cfg: Cfg,
+    pub ssa: Ssa,
+}
+
+struct Input {
+    path: PathBuf,
+    text: Rc<str>,
+    /// Tokens the text is not scanned for, such as a preprocessor's output.
+    tokens: Option<Rc<[Token<'static>]>>,
+    changed_at: Revision,
+}
+
+struct Memo<T: ?Sized> {
+    value: Rc<T>,
+    /// Last revision at which the memo was known to be up to date.
+    verified_at: Revision,
+    /// Last revision at which the memoized value actually changed.
+    changed_at: Revision,
+}
+
+type Memos<T> = HashMap<FileId, Memo<T>>;
+
+#[derive(Default)]
+pub struct Database {
+    revision: Revision,
+    scanner: ScannerOptions,
+    typeck: TypeckOptions,
+    inputs: Vec<Input>,
+    file_ids: HashMap<PathBuf, FileId>,
+    tokens: Memos<[Token<'static>]>,
+    /// The text the memoized tokens of each file were scanned from.
+    scanned: HashMap<FileId, Rc<str>>,
+    parsed: Memos<Parsed>,
+    resolved: Memos<Resolved>,
+    typed: Memos<Typed>,
+    ir: Memos<Ir>,
+    stats: QueryStats,
+}
+
+impl Database {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A database scanning with `scanner` and type checking with `typeck`.
+    /// Queries on it use these throughout; other options need another
+    /// database.
+    pub fn with_options(scanner: ScannerOptions, typeck: TypeckOptions) -> Self {
+        Self {
+            scanner,
+            typeck,
+            ..Self::default()
+        }
+    }
+
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    pub fn stats(&self) -> QueryStats {
+        self.stats
+    }
+
+    /// Sets (or replaces) the source text for `path` and returns its file id.
+    /// Setting identical text does not start a new revision.
+    pub fn set_source_text(&mut self, path: impl AsRef<Path>, text: impl Into<String>) -> FileId {
+        self.set_input(path.as_ref(), text.into(), None)
+    }
+
+    /// Sets the text for `path` together with its `tokens`, which queries
+    /// use instead of scanning it. A [`Unit`](crate::preprocess::Unit) is
+    /// set this way.
+    pub fn set_source_tokens(
+        &mut self,
+        path: impl AsRef<Path>,
+        text: impl Into<String>,
+        tokens: Vec<Token<'static>>,
+    ) -> FileId {
+        self.set_input(path.as_ref(), text.into(), Some(tokens.into()))
+    }
+
+    fn set_input(
+        &mut self,
+        path: &Path,
+        text: String,
+        tokens: Option<Rc<[Token<'static>]>>,
+    ) -> FileId {
+        if let Some(&file) = self.file_ids.get(path) {
+            let input = &mut self.inputs[file.index()];
+            if *input.text != *text || input.tokens != tokens {
+                self.revision += 1;
+                input.text = text.into();
+                input.tokens = tokens;
+                input.changed_at = self.revision;
+            }
+            return file;
+        }
+
+        self.revision += 1;
+        let file = FileId::new(self.inputs.len());
+        self.inputs.push(Input {
+            path: path.to_path_buf(),
+            text: text.into(),
+            tokens,
+            changed_at: self.revision,
+        });
+        self.file_ids.insert(path.to_path_buf(), file);
+        file
+    }
+
+    pub fn file_id(&self, path: impl AsRef<Path>) -> Option<FileId> {
+        self.file_ids.get(path.as_ref()).copied()
+    }
+
+    pub fn path(&self, file: FileId) -> &Path {
+        &self.inputs[file.index()].path
+    }
+
+    pub fn source_text(&self, file: FileId) -> Rc<str> {
+        Rc::clone(&self.inputs[file.index()].text)
+    }
+
+    /// Tokens of `file`, including the trailing `EndOfFile` token.
+    pub fn tokens(&mut self, file: FileId) -> Rc<[Token<'static>]> {
+        let input = &self.inputs[file.index()];
+        let input_changed_at = input.changed_at;
+        let given = input.tokens.clone();
+        let text = self.source_text(file);
+        let options = self.scanner.clone();
+        let compute = || match given {
+            Some(tokens) => tokens,
+            // Memoized tokens outlive any borrow of the source text.
+            None => Scanner::with_options(&text, options)
+                .map(Token::into_owned)
+                .collect(),
+        };
+        let old = self
+            .tokens
+            .get(&file)
+            .map(|memo| (Rc::clone(&memo.value), memo.changed_at));
+        // Compared below, where the texts are at hand.
+        let tokens = self.memoized(
+            Self::tokens_memos,
+            file,
+            input_changed_at,
+            compute,
+            |_, _| false,
+        );
+        if old
+            .as_ref()
+            .is_some_and(|(old, _)| Rc::ptr_eq(old, &tokens))
+        {
+            return tokens;
+        }
+        let old_text = self.scanned.insert(file, Rc::clone(&text));
+        if let (Some((old, changed_at)), Some(old_text)) = (old, old_text) {
+            if let Some(moved) = Moved::new(&old_text, old, &text, Rc::clone(&tokens)) {
+                self.tokens
+                    .get_mut(&file)
+                    .expect("just memoized")
+                    .changed_at = changed_at;
+                if !moved.is_unmoved() {
+                    self.relocate(file, &moved);
+                }
+            }
+        }
+        tokens
+    }
+
+    /// Moves the spans of the results computed from the tokens of `file`
+    /// before they `moved`.
+    fn relocate(&mut self, file: FileId, moved: &Moved) {
+        let span = |span| moved.span(span);
+        let diagnostics = |diagnostics: &[Diagnostic]| -> Vec<Diagnostic> {
+            diagnostics
+                .iter()
+                .map(|d| d.clone().map_spans(span))
+                .collect()
+        };
+        if let Some(memo) = self.parsed.get_mut(&file) {
+            let mut program = memo.value.program.clone();
+            map_spans(&mut program, span);
+            memo.value = Rc::new(Parsed {
+                program,
+                diagnostics: diagnostics(&memo.value.diagnostics),
+            });
+        }
+        if let Some(memo) = self.resolved.get_mut(&file) {
+            let mut resolution = memo.value.resolution.clone();
+            resolution.symbols.map_spans(span);
+            memo.value = Rc::new(Resolved {
+                resolution,
+                diagnostics: diagnostics(&memo.value.diagnostics),
+            });
+        }
+        if let Some(memo) = self.typed.get_mut(&file) {
+            let mut program = memo.value.program.clone();
+            map_spans(&mut program, span);
+            memo.value = Rc::new(Typed {
+                program,
+                consts: memo.value.consts.clone(),
+                diagnostics: diagnostics(&memo.value.diagnostics),
+            });
+        }
+        if let Some(memo) = self.ir.get_mut(&file) {
+            let mut ir = Ir::clone(&memo.value);
+            map_spans(&mut ir.program, span);
+            ir.resolution.symbols.map_spans(span);
+            memo.value = Rc::new(ir);
+        }
+    }
+
+    /// Revision at which the tokens of `file` last changed, if they have been
+    /// computed. Downstream queries compare against this to decide whether
+    /// they need to rerun.
+    pub fn tokens_changed_at(&self, file: FileId) -> Option<Revision> {
+        self.tokens.get(&file).map(|memo| memo.changed_at)
+    }
+
+    /// The tree of `file`, which depends only on its tokens.
+    pub fn parse(&mut self, file: FileId) -> Rc<Parsed> {
+        let tokens = self.tokens(file);
+        let deps_changed_at = self.tokens[&file].changed_at;
+        let text = self.source_text(file);
+        let options = self.scanner.clone();
+        let compute = || {
+            let scanner = Scanner::with_options(&text, options).with_tokens(tokens.iter().cloned());
+            let (program, diagnostics) = Parser::new(scanner).parse_program();
+            Rc::new(Parsed {
+                program,
+                diagnostics,
+            })
+        };
+        self.memoized(
+            Self::parsed_memos,
+            file,
+            deps_changed_at,
+            compute,
+            |a, b| a == b,
+        )
+    }
+
+    /// The names of `file` resolved.
+    pub fn resolve(&mut self, file: FileId) -> Rc<Resolved> {
+        let parsed = self.parse(file);
+        let deps_changed_at = self.parsed[&file].changed_at;
+        let compute = || {
+            let (resolution, diagnostics) = semantic::resolve(&parsed.program);
+            Rc::new(Resolved {
+                resolution,
+                diagnostics,
+            })
+        };
+        self.memoized(
+            Self::resolved_memos,
+            file,
+            deps_changed_at,
+            compute,
+            |_, _| false,
+        )
+    }
+
+    /// `file` with its constants evaluated, type checked and linted.
+    pub fn typeck(&mut self, file: FileId) -> Rc<Typed> {
+        let parsed = self.parse(file);
+        let resolved = self.resolve(file);
+        let deps_changed_at = self.parsed[&file]
+            .changed_at
+            .max(self.resolved[&file].changed_at);
+        let options = self.typeck.clone();
+        let compute = || {
+            let mut program = parsed.program.clone();
+            let resolution = &resolved.resolution;
+            let (consts, mut diagnostics) = semantic::evaluate(&program, resolution);
+            let (_, errors) = typeck::check(&mut program, resolution, &consts, &options);
+            diagnostics.extend(errors);
+            diagnostics.extend(semantic::lint(&program, resolution));
+            Rc::new(Typed {
+                program,
+                consts,
+                diagnostics,
+            })
+        };
+        self.memoized(Self::typed_memos, file, deps_changed_at, compute, |_, _| {
+            false
+        })
+    }
+
+    /// The IR of `file`: its type-checked program lowered, and the graph of
+    /// each function in SSA form.
+    pub fn ir(&mut self, file: FileId) -> Rc<Ir> {
+        let typed = self.typeck(file);
+        let deps_changed_at = self.typed[&file].changed_at;
+        let compute = || {
+            let mut program = typed.program.clone();
+            lowering::lower(&mut program);
+            let (resolution, _) = semantic::resolve(&program);
+            let functions = program
+                .items
+                .iter()
+                .enumerate()
+                .filter_map(|(item, function)| match function {
+                    Item::Function(function) => Some((item, function)),
+                    _ => None,
+                })
+                .map(|(item, function)| {
+                    let cfg = Cfg::build(&program.arena, function);
+                    let dominators = Dominators::compute(&cfg);
+                    let ssa = Ssa::build(&program.arena, function, &cfg, &dominators, &resolution);
+                    FunctionIr { item, cfg, ssa }
+                })
+                .collect();
+            Rc::new(Ir {
+                program,
+                resolution,
+                functions,
+            })
+        };
+        self.memoized(Self::ir_memos, file, deps_changed_at, compute, |_, _| false)
+    }
+
+    fn tokens_memos(&mut self) -> &mut Memos<[Token<'static>]> {
+        &mut self.tokens
+    }
+
+    fn parsed_memos(&mut self) -> &mut Memos<Parsed> {
+        &mut self.parsed
+    }
+
+    fn resolved_memos(&mut self) -> &mut Memos<Resolved> {
+        &mut self.resolved
+    }
+
+    fn typed_memos(&mut self) -> &mut Memos<Typed> {
+        &mut self.typed
+    }
+
+    fn ir_memos(&mut self) -> &mut Memos<Ir> {
+        &mut self.ir
+    }
+
+    /// The memo of a query for `file` in the table `memos` returns, computing
+    /// it again with `compute` if what it depends on changed after
+    /// `deps_changed_at`. A recomputed value that `same` finds equal to the
+    /// old one keeps the old `changed_at`.
+    fn memoized<T: ?Sized>(
+        &mut self,
+        memos: fn(&mut Self) -> &mut Memos<T>,
+        file: FileId,
+        deps_changed_at: Revision,
+        compute: impl FnOnce() -> Rc<T>,
+        same: impl FnOnce(&T, &T) -> bool,
+    ) -> Rc<T> {
+        let revision = self.revision;
+        if let Some(memo) = memos(self).get_mut(&file) {
+            if memo.verified_at >= deps_changed_at {
+                memo.verified_at = revision;
+                let value = Rc::clone(&memo.value);
+                self.stats.hits += 1;
+                return value;
+            }
+        }
+
+        self.stats.executions += 1;
+        let value = compute();
+        let memo = match memos(self).remove(&file) {
+            Some(old) if same(&old.value, &value) => Memo {
+                value: old.value,
+                verified_at: revision,
+                changed_at: old.changed_at,
+            },
+            _ => Memo {
+                value,
+                verified_at: revision,
+                changed_at: revision,
+            },
+        };
+        let result = Rc::clone(&memo.value);
+        memos(self).insert(file, memo);
+        result
+    }
+}
+
+/// Tokens of a file before and after an edit that only moved them.
+struct Moved {
+    old: Rc<[Token<'static>]>,
+    new: Rc<[Token<'static>]>,
+}
+
+impl Moved {
+    /// `old` and `new`, scanned from `old_text` and `new_text`, if they are
+    /// the same tokens in the same order, apart from where they are. Two
+    /// consecutive tokens must touch in both texts or in neither, so that an
+    /// offset where one ends and the other starts moves with both, and have
+    /// a line break between them in both or in neither.
+    fn new(
+        old_text: &str,
+        old: Rc<[Token<'static>]>,
+        new_text: &str,
+        new: Rc<[Token<'static>]>,
+    ) -> Option<Moved> {
+        let gap = |text: &str, tokens: &[Token<'_>], i: usize| {
+            let start = tokens[i.checked_sub(1)?].span.end;
+            text.get(start..tokens[i].span.start)
+                .map(|between| (between.is_empty(), between.contains('\n')))
+        };
+        let trivia_eq = |a: &[Trivia<'_>], b: &[Trivia<'_>]| {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|(a, b)| a.kind == b.kind && a.text == b.text)
+        };
+        let same = old.len() == new.len()
+            && old.iter().zip(new.iter()).enumerate().all(|(i, (a, b))| {
+                a.token_type == b.token_type
+                    && a.lexeme == b.lexeme
+                    && a.literal == b.literal
+                    && a.error == b.error
+                    && a.suffix == b.suffix
+                    && a.symbol == b.symbol
+                    && trivia_eq(&a.leading_trivia, &b.leading_trivia)
+                    && trivia_eq(&a.trailing_trivia, &b.trailing_trivia)
+                    && gap(old_text, &old, i) == gap(new_text, &new, i)
+            });
+        // Offsets can only be mapped through tokens in text order, which
+        // preprocessed ones need not be.
+        let in_order = |tokens: &[Token<'_>]| {
+            tokens
+                .windows(2)
+                .all(|pair| pair[0].span.end <= pair[1].span.start)
+        };
+        let moved = Moved { old, new };
+        let mappable = moved.is_unmoved() || (in_order(&moved.old) && in_order(&moved.new));
+        (same && mappable).then_some(moved)
+    }
+
+    fn is_unmoved(&self) -> bool {
+        self.old
+            .iter()
+            .zip(self.new.iter())
+            .all(|(a, b)| a.span == b.span)
+    }
+
+    /// Where `offset` in the old text is in the new one. An offset in a
+    /// token stays at the same place in it; one between two tokens keeps
+    /// its distance from the end of the first, up to the start of the
+    /// second.
+    fn offset(&self, offset: usize) -> usize {
+        let i = self.old.partition_point(|t| t.span.start <= offset);
+        let Some(before) = i.checked_sub(1) else {
+            return self
+                .new
+                .first()
+                .map_or(offset, |t| offset.min(t.span.start));
+        };
+        let (old, new) = (self.old[before].span, self.new[before].span);
+        if offset <= old.end {
+            return new.start + (offset - old.start);
+        }
+        let moved = new.end + (offset - old.end);
+        self.new
+            .get(i)
+            .map_or(moved, |next| moved.min(next.span.start))
+    }
+
+    fn span(&self, span: Span) -> Span {
+        Span::new(self.offset(span.start), self.offset(span.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::TokenType;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tokens_are_memoized() {
+        let mut db = Database::new();
+        let file = db.set_source_text("main.src", "int x = 1;");
+
+        let first = db.tokens(file);
+        let second = db.tokens(file);
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(
+            db.stats(),
+            QueryStats {
+                hits: 1,
+                executions: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_edit_invalidates_only_edited_file() {
+        let mut db = Database::new();
+        let a = db.set_source_text("a.src", "int x;");
+        let b = db.set_source_text("b.src", "int y;");
+        db.tokens(a);
+        db.tokens(b);
+
+        db.set_source_text("a.src", "float x;");
+        assert_eq!(db.tokens(a)[0].token_type, TokenType::Float);
+        db.tokens(b);
+        assert_eq!(db.stats().executions, 3);
+        assert_eq!(db.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_typeck_is_memoized() {
+        let mut db = Database::new();
+        let file = db.set_source_text("main.src", "int x = true;");
+
+        let first = db.typeck(file);
+        let executions = db.stats().executions;
+        assert_eq!(executions, 4);
+        assert_eq!(first.diagnostics.len(), 1);
+        assert!(Rc::ptr_eq(&first, &db.typeck(file)));
+        assert_eq!(db.stats().executions, executions);
+
+        db.set_source_text("main.src", "int x = 1;");
+        assert!(db.typeck(file).diagnostics.is_empty());
+        assert_eq!(db.stats().executions, 2 * executions);
+    }
+
+    #[test]
+    fn test_ir_has_a_graph_per_function() {
+        let mut db = Database::new();
+        let file = db.set_source_text(
+            "main.src",
+            "int g = 1;\nfn f() -> int { return g; }\nfn h() { for (int i = 0; i < 3; i++) {} }",
+        );
+
+        let ir = db.ir(file);
+        let items: Vec<usize> = ir.functions.iter().map(|f| f.item).collect();
+        assert_eq!(items, vec![1, 2]);
+        assert!(ir.functions[1].cfg.len() > 1);
+    }
+
+    #[test]
+    fn test_comment_edit_moves_results() {
+        let before = "int x = true; // a\nfn f() { y = 1 }\n";
+        let after = "/* new */ int x = true; // a longer comment\nfn f() { y = 1 }\n";
+        let mut db = Database::new();
+        let file = db.set_source_text("main.src", before);
+        db.ir(file);
+        let executions = db.stats().executions;
+
+        db.set_source_text("main.src", after);
+        let typed = db.typeck(file);
+        let ir = db.ir(file);
+        // Only the tokens were scanned again.
+        assert_eq!(db.stats().executions, executions + 1);
+
+        // The results are those of the edited text.
+        let mut fresh = Database::new();
+        let fresh_file = fresh.set_source_text("main.src", after);
+        assert_eq!(db.parse(file), fresh.parse(fresh_file));
+        let fresh_typed = fresh.typeck(fresh_file);
+        assert_eq!(typed.program, fresh_typed.program);
+        assert_eq!(typed.diagnostics, fresh_typed.diagnostics);
+        let spans = |resolution: &Resolution| -> Vec<Span> {
+            resolution
+                .symbols
+                .definitions()
+                .map(|(_, def)| def.span)
+                .collect()
+        };
+        let resolved = db.resolve(file);
+        let fresh_resolved = fresh.resolve(fresh_file);
+        assert_eq!(resolved.diagnostics, fresh_resolved.diagnostics);
+        assert_eq!(
+            spans(&resolved.resolution),
+            spans(&fresh_resolved.resolution)
+        );
+        let fresh_ir = fresh.ir(fresh_file);
+        assert_eq!(ir.program, fresh_ir.program);
+        assert_eq!(spans(&ir.resolution), spans(&fresh_ir.resolution));
+
+        // A line break where the `;` is missing changes the diagnostic, so
+        // the file is parsed again.
+        let executions = db.stats().executions;
+        db.set_source_text("main.src", after.replace("1 }", "1 // c\n}"));
+        db.parse(file);
+        assert_eq!(db.stats().executions, executions + 2);
+    }
+
+    #[test]
+    fn test_identical_text_keeps_revision() {
+        let mut db = Database::new();
+        let file = db.set_source_text("main.src", "int x;");
+        let revision = db.revision();
+        assert_eq!(db.set_source_text("main.src", "int x;"), file);
+        assert_eq!(db.revision(), revision);
+    }
+}
@@ -0,0 +1,595 @@
+//! Compile-time evaluation of constant expressions.
+//!
+//! The initializer of every global and every `const` declaration must be a
+//! constant expression: literals, operators, `?:`, array literals and
+//! indexing, and names of other constants. [`evaluate`] works each one out
+//! on a resolved program, before type checking, so the checker can use a
+//! constant as an array length and later phases can use the values instead
+//! of running the initializers.
+//!
+//! Values are computed as the types suggest without checking them: an
+//! operation on operands of the wrong types simply has no value, and the
+//! type checker reports it. An `int` operand meeting a `float` one is
+//! converted, as the checker does by default. `int` is 32 bits wide, so
+//! integer arithmetic whose result does not fit in an `i32` is an overflow.
+
+use super::resolve::Resolution;
+use super::scope::{DefId, ScopeId, SymbolKind};
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    ArrayLen, AstArena, BinaryOp, ExprId, ExprKind, Ident, NodeId, Program, Type, TypeKind,
+    UnaryOp, VarDecl,
+};
+use crate::diagnostics::{codes, Code, Diagnostic};
+use crate::lexer::LiteralValue;
+use crate::source::Span;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// The value of a constant expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(Rc<str>),
+    Null,
+    Array(Vec<ConstValue>),
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Int(value) => write!(f, "{}", value),
+            ConstValue::Float(value) => write!(f, "{:?}", value),
+            ConstValue::Bool(value) => write!(f, "{}", value),
+            ConstValue::String(value) => write!(f, "{:?}", value),
+            ConstValue::Null => f.write_str("null"),
+            ConstValue::Array(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+/// The values [`evaluate`] worked out.
+#[derive(Debug, Clone, Default)]
+pub struct ConstValues {
+    /// The value of every global and constant whose initializer could be
+    /// evaluated.
+    pub values: HashMap<DefId, ConstValue>,
+    /// The length of every array type whose length names a constant, keyed
+    /// by the id of the array type.
+    pub array_lens: HashMap<NodeId, u64>,
+}
+
+impl ConstValues {
+    pub fn value(&self, def: DefId) -> Option<&ConstValue> {
+        self.values.get(&def)
+    }
+
+    pub fn array_len(&self, ty: NodeId) -> Option<u64> {
+        self.array_lens.get(&ty).copied()
+    }
+}
+
+/// Evaluates the initializers of the globals and constants in `program`,
+/// whose names `resolution` binds, and the array lengths naming constants.
+pub fn evaluate(program: &Program, resolution: &Resolution) -> (ConstValues, Vec<Diagnostic>) {
+    let mut collector = Collector {
+        arena: &program.arena,
+        resolution,
+        decls: Vec::new(),
+        lens: Vec::new(),
+    };
+    collector.visit_program(program);
+    let mut evaluator = Evaluator {
+        arena: &program.arena,
+        resolution,
+        decls: collector.decls.iter().copied().collect(),
+        state: HashMap::new(),
+        results: ConstValues::default(),
+        diagnostics: Vec::new(),
+    };
+    for &(def, _) in &collector.decls {
+        evaluator.decl(def);
+    }
+    for (ty, name) in collector.lens {
+        if let Some(len) = evaluator.array_len(ty, name) {
+            evaluator.results.array_lens.insert(ty, len);
+        }
+    }
+    (evaluator.results, evaluator.diagnostics)
+}
+
+/// Finds the declarations to evaluate and the array lengths naming
+/// constants.
+struct Collector<'ast> {
+    arena: &'ast AstArena,
+    resolution: &'ast Resolution,
+    /// Globals and constants in source order.
+    decls: Vec<(DefId, &'ast VarDecl)>,
+    /// Array types whose length names a constant, with that name.
+    lens: Vec<(NodeId, &'ast Ident)>,
+}
+
+impl<'ast> Visitor<'ast> for Collector<'ast> {
+    fn arena(&self) -> &'ast AstArena {
+        self.arena
+    }
+
+    fn visit_var_decl(&mut self, decl: &'ast VarDecl) {
+        if let Some(def) = self.resolution.declaration(decl.id) {
+            if decl.constant || self.resolution.symbols[def].scope == ScopeId::GLOBAL {
+                self.decls.push((def, decl));
+            }
+        }
+        visit::walk_var_decl(self, decl);
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type) {
+        if let TypeKind::Array {
+            len: ArrayLen::Const(name),
+            ..
+        } = &ty.kind
+        {
+            self.lens.push((ty.id, name));
+        }
+        visit::walk_type(self, ty);
+    }
+}
+
+enum State {
+    InProgress,
+    Done,
+}
+
+struct Evaluator<'ast> {
+    arena: &'ast AstArena,
+    resolution: &'ast Resolution,
+    decls: HashMap<DefId, &'ast VarDecl>,
+    state: HashMap<DefId, State>,
+    results: ConstValues,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'ast> Evaluator<'ast> {
+    /// The value of global or constant `def`, evaluating it the first time.
+    fn decl(&mut self, def: DefId) -> Option<ConstValue> {
+        match self.state.get(&def) {
+            Some(State::Done) => return self.results.values.get(&def).cloned(),
+            Some(State::InProgress) => {
+                let decl = self.decls[&def];
+                self.diagnostics.push(Diagnostic::error(
+                    codes::CYCLIC_CONSTANT,
+                    format!("the value of '{}' depends on itself", decl.name.name),
+                    decl.name.span,
+                ));
+                return None;
+            }
+            None => {}
+        }
+        let decl = self.decls[&def];
+        self.state.insert(def, State::InProgress);
+        let value = decl.init.and_then(|init| self.expr(init, decl));
+        self.state.insert(def, State::Done);
+        if let Some(value) = &value {
+            self.results.values.insert(def, value.clone());
+        }
+        value
+    }
+
+    /// The value of the constant `name`, the length of array type `ty`.
+    fn array_len(&mut self, ty: NodeId, name: &Ident) -> Option<u64> {
+        let def = self.resolution.use_of(ty)?;
+        if !self.decls.get(&def).is_some_and(|decl| decl.constant) {
+            let kind = self.resolution.symbols[def].kind.describe();
+            self.diagnostics.push(Diagnostic::error(
+                codes::ARRAY_LENGTH_NOT_CONSTANT,
+                format!(
+                    "array length must be a constant, but '{}' is a {}",
+                    name.name, kind
+                ),
+                name.span,
+            ));
+            return None;
+        }
+        match self.decl(def)? {
+            ConstValue::Int(len) if len >= 0 => Some(len as u64),
+            value => {
+                self.diagnostics.push(Diagnostic::error(
+                    codes::INVALID_ARRAY_LENGTH,
+                    format!(
+                        "array length must be a non-negative int, but '{}' is {}",
+                        name.name, value
+                    ),
+                    name.span,
+                ));
+                None
+            }
+        }
+    }
+
+    /// Reports `message` at `span` as a problem with the initializer of
+    /// `decl`.
+    fn error(&mut self, code: Code, message: String, span: Span, decl: &VarDecl) {
+        let what = if decl.constant { "constant" } else { "global" };
+        self.diagnostics
+            .push(Diagnostic::error(code, message, span).with_label(
+                decl.name.span,
+                format!(
+                    "the initializer of {} '{}' must be constant",
+                    what, decl.name.name
+                ),
+            ));
+    }
+
+    /// The value of `id`, part of the initializer of `decl`.
+    fn expr(&mut self, id: ExprId, decl: &VarDecl) -> Option<ConstValue> {
+        let expr = &self.arena[id];
+        let not_constant = match &expr.kind {
+            ExprKind::Literal(value) => return literal(value),
+            ExprKind::Grouping(inner) => return self.expr(*inner, decl),
+            ExprKind::IntToFloat(inner) => {
+                return match self.expr(*inner, decl)? {
+                    ConstValue::Int(value) => Some(ConstValue::Float(value as f64)),
+                    _ => None,
+                }
+            }
+            ExprKind::Variable(name) => {
+                let def = self.resolution.use_of(expr.id)?;
+                if self.decls.get(&def).is_some_and(|decl| decl.constant) {
+                    return self.decl(def);
+                }
+                let kind = self.resolution.symbols[def].kind;
+                let kind = match kind {
+                    SymbolKind::Variable if self.decls.contains_key(&def) => "global",
+                    _ => kind.describe(),
+                };
+                format!("{} '{}' is not a constant", kind, name.name)
+            }
+            ExprKind::Unary { op, operand } => {
+                let operand = self.expr(*operand, decl)?;
+                return match (op, operand) {
+                    (UnaryOp::Neg, ConstValue::Int(value)) => match int(value.checked_neg()) {
+                        Ok(value) => Some(value),
+                        Err(_) => {
+                            let (code, message) = OVERFLOW;
+                            self.error(code, message.into(), expr.span, decl);
+                            None
+                        }
+                    },
+                    (UnaryOp::Neg, ConstValue::Float(value)) => Some(ConstValue::Float(-value)),
+                    (UnaryOp::Not, ConstValue::Bool(value)) => Some(ConstValue::Bool(!value)),
+                    (UnaryOp::BitNot, ConstValue::Int(value)) => Some(ConstValue::Int(!value)),
+                    _ => None,
+                };
+            }
+            ExprKind::Binary { op, left, right } => {
+                let left_value = self.expr(*left, decl)?;
+                // `&&` and `||` only evaluate the right operand when needed.
+                match (op, &left_value) {
+                    (BinaryOp::And, ConstValue::Bool(false)) => {
+                        return Some(ConstValue::Bool(false))
+                    }
+                    (BinaryOp::Or, ConstValue::Bool(true)) => return Some(ConstValue::Bool(true)),
+                    _ => {}
+                }
+                let right_value = self.expr(*right, decl)?;
+                return match binary(*op, left_value, right_value) {
+                    Ok(value) => value,
+                    Err((code, message)) => {
+                        self.error(code, message.into(), expr.span, decl);
+                        None
+                    }
+                };
+            }
+            ExprKind::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                return match self.expr(*condition, decl)? {
+                    ConstValue::Bool(true) => self.expr(*then_expr, decl),
+                    ConstValue::Bool(false) => self.expr(*else_expr, decl),
+                    _ => None,
+                }
+            }
+            ExprKind::Array(elements) => {
+                let mut values = Vec::new();
+                for &element in elements {
+                    values.push(self.expr(element, decl));
+                }
+                return values
+                    .into_iter()
+                    .collect::<Option<_>>()
+                    .map(ConstValue::Array);
+            }
+            ExprKind::Index { object, index } => {
+                let object = self.expr(*object, decl);
+                let index = self.expr(*index, decl);
+                return match (object?, index?) {
+                    (ConstValue::Array(elements), ConstValue::Int(index)) => {
+                        let element = usize::try_from(index)
+                            .ok()
+                            .and_then(|index| elements.get(index));
+                        if element.is_none() {
+                            self.error(
+                                codes::INDEX_OUT_OF_BOUNDS,
+                                format!(
+                                    "index {} is out of bounds for an array of length {}",
+                                    index,
+                                    elements.len()
+                                ),
+                                expr.span,
+                                decl,
+                            );
+                        }
+                        element.cloned()
+                    }
+                    _ => None,
+                };
+            }
+            ExprKind::Call { .. } => "a function call cannot be evaluated at compile time".into(),
+            ExprKind::Assign { .. } => "an assignment cannot be evaluated at compile time".into(),
+            ExprKind::StructLiteral { .. } => {
+                "a struct literal cannot be evaluated at compile time".into()
+            }
+            ExprKind::Field { .. } => "a field access cannot be evaluated at compile time".into(),
+            ExprKind::Variant { .. } => {
+                "an enum variant cannot be evaluated at compile time".into()
+            }
+            ExprKind::Match { .. } => "a match cannot be evaluated at compile time".into(),
+        };
+        self.error(codes::NOT_CONSTANT, not_constant, expr.span, decl);
+        None
+    }
+}
+
+fn literal(value: &LiteralValue) -> Option<ConstValue> {
+    match value {
+        LiteralValue::Integer(value) => Some(ConstValue::Int(*value)),
+        LiteralValue::Float(value) => Some(ConstValue::Float(*value)),
+        LiteralValue::String(value) => Some(ConstValue::String(value.as_str().into())),
+        LiteralValue::Boolean(value) => Some(ConstValue::Bool(*value)),
+        LiteralValue::Null => Some(ConstValue::Null),
+        LiteralValue::None => None,
+    }
+}
+
+const OVERFLOW: (Code, &str) = (
+    codes::CONSTANT_OVERFLOW,
+    "overflow in a constant expression",
+);
+
+/// `value` as an `int`, or an overflow if it does not fit in 32 bits.
+fn int(value: Option<i64>) -> Result<ConstValue, (Code, &'static str)> {
+    match value {
+        Some(value) if i32::try_from(value).is_ok() => Ok(ConstValue::Int(value)),
+        _ => Err(OVERFLOW),
+    }
+}
+
+/// The value of `left op right`: `Ok(None)` if the operator does not apply
+/// to the operands, an error if it applies but fails.
+fn binary(
+    op: BinaryOp,
+    left: ConstValue,
+    right: ConstValue,
+) -> Result<Option<ConstValue>, (Code, &'static str)> {
+    use BinaryOp::*;
+    use ConstValue::*;
+    let value = match (left, right) {
+        (Int(a), Int(b)) => {
+            let checked = match op {
+                Add => a.checked_add(b),
+                Sub => a.checked_sub(b),
+                Mul => a.checked_mul(b),
+                Div | Rem if b == 0 => {
+                    return Err((
+                        codes::DIVISION_BY_ZERO,
+                        "division by zero in a constant expression",
+                    ))
+                }
+                Div => a.checked_div(b),
+                Rem => a.checked_rem(b),
+                BitAnd => Some(a & b),
+                BitOr => Some(a | b),
+                BitXor => Some(a ^ b),
+                Shl | Shr if !(0..32).contains(&b) => return Err(OVERFLOW),
+                Shl => a.checked_mul(1 << b),
+                Shr => Some(a >> b),
+                _ => return Ok(compare(op, a.cmp(&b))),
+            };
+            int(checked)?
+        }
+        (Int(a), Float(b)) => return binary(op, Float(a as f64), Float(b)),
+        (Float(a), Int(b)) => return binary(op, Float(a), Float(b as f64)),
+        (Float(a), Float(b)) => match op {
+            Add => Float(a + b),
+            Sub => Float(a - b),
+            Mul => Float(a * b),
+            Div => Float(a / b),
+            Rem => Float(a % b),
+            // Unordered NaNs are unequal.
+            Eq => Bool(a == b),
+            Ne => Bool(a != b),
+            _ => return Ok(a.partial_cmp(&b).and_then(|ordering| compare(op, ordering))),
+        },
+        (String(a), String(b)) => match op {
+            Add => String(format!("{}{}", a, b).into()),
+            _ => return Ok(compare(op, a.cmp(&b))),
+        },
+        (Bool(a), Bool(b)) => match op {
+            And => Bool(a && b),
+            Or => Bool(a || b),
+            Eq => Bool(a == b),
+            Ne => Bool(a != b),
+            _ => return Ok(None),
+        },
+        (left, right) => match op {
+            Eq => Bool(left == right),
+            Ne => Bool(left != right),
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some(value))
+}
+
+/// The value of comparison `op` between operands ordered as `ordering`.
+fn compare(op: BinaryOp, ordering: std::cmp::Ordering) -> Option<ConstValue> {
+    let result = match op {
+        BinaryOp::Eq => ordering.is_eq(),
+        BinaryOp::Ne => ordering.is_ne(),
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Le => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::Ge => ordering.is_ge(),
+        _ => return None,
+    };
+    Some(ConstValue::Bool(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::semantic::resolve;
+    use pretty_assertions::assert_eq;
+
+    /// Each global and constant with its value, or `-` without one, and
+    /// each error with its labels.
+    fn evaluate_source(source: &str) -> (Vec<String>, Vec<String>) {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, diagnostics) = resolve(&program);
+        assert_eq!(diagnostics, []);
+        let (consts, diagnostics) = evaluate(&program, &resolution);
+        let values = resolution
+            .symbols
+            .definitions()
+            .filter(|(_, def)| def.kind == SymbolKind::Variable)
+            .map(|(id, def)| {
+                let name = &source[def.span.start..def.span.end];
+                match consts.value(id) {
+                    Some(value) => format!("{} = {}", name, value),
+                    None => format!("{} -", name),
+                }
+            })
+            .collect();
+        let errors = diagnostics
+            .iter()
+            .map(|d| {
+                let mut line = format!("{} at {}", d.message, d.primary_span);
+                for label in &d.labels {
+                    line += &format!("; {} at {}", label.message, label.span);
+                }
+                line
+            })
+            .collect();
+        (values, errors)
+    }
+
+    #[test]
+    fn test_values() {
+        let source = "const int N = 2 + 3 * 4;\nint a = N << 2;\nfloat b = N / 2.0;\nconst S = \"ab\" + \"c\";\nbool c = N > 10 && S == \"abc\";\nconst int[3] xs = [N, -N, ~N];\nint d = true ? xs[1] : 1 / 0;\nfn f() { const int M = N - 1; int x = M; }";
+        let (values, errors) = evaluate_source(source);
+        assert_eq!(errors, [] as [String; 0]);
+        assert_eq!(
+            values,
+            [
+                "N = 14",
+                "a = 56",
+                "b = 7.0",
+                "S = \"abc\"",
+                "c = true",
+                "xs = [14, -14, -15]",
+                "d = -14",
+                "M = 13",
+                "x -",
+            ]
+        );
+
+        // Array lengths may name constants.
+        let source = "const int N = 4;\nconst int M = 2;\nint[N][M] grid;";
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, _) = resolve(&program);
+        let (consts, diagnostics) = evaluate(&program, &resolution);
+        assert_eq!(diagnostics, []);
+        let mut lens: Vec<u64> = consts.array_lens.values().copied().collect();
+        lens.sort();
+        assert_eq!(lens, [2, 4]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let source = "fn f() -> int { return 1; }\nint g = 1;\nint a = f() + 1;\nint b = g;\nconst int C = D;\nconst int D = C;\nint e = 2147483647 * 2147483647 * 4;\nint h = 1 % 0;\nint i = [1, 2][2];\nfn k(p: int) { const int q = p; int[p] r; }\nconst float F = 1.5;\nint[F] s;";
+        let (_, errors) = evaluate_source(source);
+        assert_eq!(
+            errors,
+            [
+                "a function call cannot be evaluated at compile time at 47..50; the initializer of global 'a' must be constant at 43..44",
+                "global 'g' is not a constant at 64..65; the initializer of global 'b' must be constant at 60..61",
+                "the value of 'C' depends on itself at 77..78",
+                "overflow in a constant expression at 109..132; the initializer of global 'e' must be constant at 105..106",
+                "division by zero in a constant expression at 146..151; the initializer of global 'h' must be constant at 142..143",
+                "index 2 is out of bounds for an array of length 2 at 161..170; the initializer of global 'i' must be constant at 157..158",
+                "parameter 'p' is not a constant at 201..202; the initializer of constant 'q' must be constant at 197..198",
+                "array length must be a constant, but 'p' is a parameter at 208..209",
+                "array length must be a non-negative int, but 'F' is 1.5 at 241..242",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_int_overflow() {
+        // `int` is 32 bits: results up to its limits are fine.
+        let source = "const A = 2147483646 + 1;\nconst B = -2147483647 - 1;\nconst C = 1 << 30;\nconst D = -1 << 31;\nconst E = B % -1;\nconst F = ~2147483647;";
+        let (values, errors) = evaluate_source(source);
+        assert_eq!(errors, [] as [String; 0]);
+        assert_eq!(
+            values,
+            [
+                "A = 2147483647",
+                "B = -2147483648",
+                "C = 1073741824",
+                "D = -2147483648",
+                "E = 0",
+                "F = -2147483648",
+            ]
+        );
+
+        let source = "const A = 2147483647 + 1;\nconst B = -2147483647 - 2;\nconst C = 65536 * 32768;\nconst D = 1 << 40;\nconst E = 1 << 31;\nconst F = 1 >> 32;\nconst G = (-2147483647 - 1) / -1;\nconst H = -(-2147483647 - 1);";
+        let (values, errors) = evaluate_source(source);
+        assert_eq!(
+            values,
+            ["A -", "B -", "C -", "D -", "E -", "F -", "G -", "H -"]
+        );
+        let spans: Vec<_> = errors
+            .iter()
+            .map(|error| error.split(';').next().unwrap())
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                "overflow in a constant expression at 10..24",
+                "overflow in a constant expression at 36..51",
+                "overflow in a constant expression at 63..76",
+                "overflow in a constant expression at 88..95",
+                "overflow in a constant expression at 107..114",
+                "overflow in a constant expression at 126..133",
+                "overflow in a constant expression at 145..167",
+                "overflow in a constant expression at 179..197",
+            ]
+        );
+    }
+}
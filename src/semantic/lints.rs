@@ -0,0 +1,180 @@
+//! Warnings about code that is legal but probably not what was meant: a
+//! variable that is never used, and a statement that can never run.
+//!
+//! Both are lints, so a [`LintStore`](crate::diagnostics::LintStore) can
+//! silence them or make them errors.
+
+use super::resolve::Resolution;
+use super::scope::DefId;
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    AstArena, Block, Ident, Item, NodeId, Param, Pattern, PatternKind, Program, StmtKind, VarDecl,
+};
+use crate::diagnostics::{codes, Diagnostic};
+use std::collections::HashSet;
+
+/// Lints `program`, whose names `resolution` binds.
+pub fn lint(program: &Program, resolution: &Resolution) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        arena: &program.arena,
+        resolution,
+        used: resolution.uses.values().copied().collect(),
+        in_function: false,
+        diagnostics: Vec::new(),
+    };
+    linter.visit_program(program);
+    linter.diagnostics
+}
+
+struct Linter<'ast> {
+    arena: &'ast AstArena,
+    resolution: &'ast Resolution,
+    /// Every definition some name refers to.
+    used: HashSet<DefId>,
+    /// Whether the walk is inside a function, where a variable is local.
+    in_function: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'ast> Linter<'ast> {
+    /// Warns about `name`, declared by `node`, if nothing uses it. A name
+    /// starting with `_` is unused on purpose.
+    fn check_used(&mut self, node: NodeId, name: &Ident, what: &str) {
+        let Some(def) = self.resolution.declaration(node) else {
+            return;
+        };
+        if self.used.contains(&def) || name.name.starts_with('_') {
+            return;
+        }
+        self.diagnostics.push(
+            Diagnostic::warning(
+                codes::UNUSED_VARIABLE,
+                format!("unused {} '{}'", what, name.name),
+                name.span,
+            )
+            .with_fixit(
+                name.span,
+                format!("_{}", name.name),
+                "if this is intentional, prefix it with an underscore",
+            ),
+        );
+    }
+}
+
+impl<'ast> Visitor<'ast> for Linter<'ast> {
+    fn arena(&self) -> &'ast AstArena {
+        self.arena
+    }
+
+    fn visit_item(&mut self, item: &'ast Item) {
+        // Globals can be used from anywhere, so only locals are checked.
+        self.in_function = matches!(item, Item::Function(_));
+        visit::walk_item(self, item);
+        self.in_function = false;
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        self.check_used(param.id, &param.name, "parameter");
+        visit::walk_param(self, param);
+    }
+
+    fn visit_var_decl(&mut self, decl: &'ast VarDecl) {
+        if self.in_function {
+            let what = if decl.constant {
+                "constant"
+            } else {
+                "variable"
+            };
+            self.check_used(decl.id, &decl.name, what);
+        }
+        visit::walk_var_decl(self, decl);
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        if let PatternKind::Binding(name) = &pattern.kind {
+            self.check_used(pattern.id, name, "variable");
+        }
+        visit::walk_pattern(self, pattern);
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        // Only the first unreachable statement is reported; the ones after
+        // it are unreachable for the same reason.
+        let exit = block.stmts.iter().position(|&stmt| {
+            matches!(
+                self.arena[stmt].kind,
+                StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue
+            )
+        });
+        if let Some(exit) = exit {
+            let rest = &block.stmts[exit + 1..];
+            if let (Some(&first), Some(&last)) = (rest.first(), rest.last()) {
+                let span = self.arena[first].span.to(self.arena[last].span);
+                self.diagnostics.push(
+                    Diagnostic::warning(codes::UNREACHABLE_CODE, "unreachable statement", span)
+                        .with_label(
+                            self.arena[block.stmts[exit]].span,
+                            "any code following this is unreachable",
+                        ),
+                );
+            }
+        }
+        visit::walk_block(self, block);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::semantic::resolve;
+    use pretty_assertions::assert_eq;
+
+    /// Each lint with its labels and fixits, one line each.
+    fn lints(source: &str) -> Vec<String> {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, diagnostics) = resolve(&program);
+        assert_eq!(diagnostics, []);
+        lint(&program, &resolution)
+            .iter()
+            .map(|d| {
+                let mut line = format!("{} {} at {}", d.code, d.message, d.primary_span);
+                for label in &d.labels {
+                    line += &format!("; {} at {}", label.message, label.span);
+                }
+                for fixit in &d.fixits {
+                    line += &format!("; '{}' at {}", fixit.replacement, fixit.span);
+                }
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unused() {
+        let source = "int g = 1;\nfn f(a: int, _b: int, c: int) -> int {\n    let x = c;\n    const N = 2;\n    int y = 0;\n    y = 1;\n    return match a { n => 0 };\n}";
+        assert_eq!(
+            lints(source),
+            [
+                "W0601 unused variable 'x' at 58..59; '_x' at 58..59",
+                "W0601 unused constant 'N' at 75..76; '_N' at 75..76",
+                "W0601 unused variable 'n' at 129..130; '_n' at 129..130",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let source = "fn f(x: int) {\n    while (x > 0) {\n        break;\n        x = x - 1;\n        x = 0;\n    }\n    return;\n}";
+        assert_eq!(
+            lints(source),
+            ["W0602 unreachable statement at 58..83; any code following this is unreachable at 43..49"]
+        );
+        // Code after the loop is reachable, and so is an `if` that returns.
+        assert_eq!(
+            lints("fn f(x: int) -> int { if (x > 0) return 1; return 0; }"),
+            Vec::<String>::new()
+        );
+    }
+}
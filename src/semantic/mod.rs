@@ -0,0 +1,22 @@
+//! Semantic analysis: what the names in a parsed program refer to, and
+//! whether its types agree.
+//!
+//! [`SymbolTable`] tracks the declarations visible at each point of a walk
+//! over the AST, scope by scope, and [`resolve`] uses it to bind every name
+//! to its declaration. [`typeck::check`] then gives every expression a
+//! [`Ty`], and [`lint`] warns about code that is legal but likely a
+//! mistake.
+
+pub mod consteval;
+pub mod lints;
+pub mod resolve;
+pub mod scope;
+pub mod ty;
+pub mod typeck;
+
+pub use consteval::{evaluate, ConstValue, ConstValues};
+pub use lints::lint;
+pub use resolve::{resolve, Resolution};
+pub use scope::{DefId, Definition, Namespace, ScopeId, SymbolKind, SymbolTable};
+pub use ty::Ty;
+pub use typeck::{ConversionPolicy, TypeckOptions, TypeckResults};
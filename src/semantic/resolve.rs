@@ -0,0 +1,479 @@
+//! Name resolution: binding every use of a name to its declaration.
+//!
+//! [`resolve`] first declares every item in the global scope, so functions
+//! and types can be used before the item that declares them. It then walks
+//! the items in order. A function's type parameters and parameters share
+//! one scope with the top level of its body; every other block, each `for`
+//! loop and each `match` arm opens a scope of its own. A local variable is
+//! declared once its initializer has been resolved, so in `let x = x;` the
+//! initializer refers to an outer `x`.
+//!
+//! A name with no visible declaration is reported as, for example,
+//! `use of undeclared variable 'x'`, with labels pointing at declarations
+//! of similar names. A name declared twice in the same scope is reported at
+//! the second declaration, with a label at the first; uses bind to the
+//! first.
+
+use super::scope::{DefId, Namespace, SymbolKind, SymbolTable};
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    ArrayLen, AstArena, Block, EnumDecl, Expr, ExprKind, Function, Ident, Item, MatchArm, NodeId,
+    Pattern, PatternKind, Program, Stmt, StmtKind, StructDecl, Type, TypeKind, VarDecl,
+};
+use crate::diagnostics::{codes, Diagnostic};
+use crate::utils::{self, Symbol};
+use std::collections::HashMap;
+
+/// What the names in a program refer to.
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    pub symbols: SymbolTable,
+    /// The definition each name use refers to, keyed by the node using it:
+    /// a variable expression, a named type, an array type whose length names
+    /// a constant, a struct literal, or a variant expression or pattern
+    /// (which refer to their enum).
+    pub uses: HashMap<NodeId, DefId>,
+    /// The definition each declaring node introduced.
+    pub declarations: HashMap<NodeId, DefId>,
+}
+
+impl Resolution {
+    /// The definition the name used by `node` refers to.
+    pub fn use_of(&self, node: NodeId) -> Option<DefId> {
+        self.uses.get(&node).copied()
+    }
+
+    /// The definition `node` declares.
+    pub fn declaration(&self, node: NodeId) -> Option<DefId> {
+        self.declarations.get(&node).copied()
+    }
+}
+
+/// Resolves every name in `program`.
+pub fn resolve(program: &Program) -> (Resolution, Vec<Diagnostic>) {
+    let mut resolver = Resolver {
+        arena: &program.arena,
+        resolution: Resolution::default(),
+        spellings: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    for item in &program.items {
+        match item {
+            Item::Function(function) => {
+                resolver.define(&function.name, SymbolKind::Function, function.id)
+            }
+            Item::Struct(decl) => resolver.define(&decl.name, SymbolKind::Struct, decl.id),
+            Item::Enum(decl) => resolver.define(&decl.name, SymbolKind::Enum, decl.id),
+            Item::Global(decl) => resolver.define(&decl.name, SymbolKind::Variable, decl.id),
+        }
+    }
+    resolver.visit_program(program);
+    (resolver.resolution, resolver.diagnostics)
+}
+
+struct Resolver<'ast> {
+    arena: &'ast AstArena,
+    resolution: Resolution,
+    /// How each defined name is written, for suggestions.
+    spellings: HashMap<Symbol, &'ast str>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'ast> Resolver<'ast> {
+    fn define(&mut self, name: &'ast Ident, kind: SymbolKind, node: NodeId) {
+        self.spellings.insert(name.symbol, &name.name);
+        let symbols = &mut self.resolution.symbols;
+        match symbols.define(name.symbol, kind, node, name.span) {
+            Ok(def) => {
+                self.resolution.declarations.insert(node, def);
+            }
+            Err(previous) => {
+                let previous = symbols[previous].span;
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        codes::ALREADY_DEFINED,
+                        format!("{} '{}' is already defined", kind.describe(), name.name),
+                        name.span,
+                    )
+                    .with_label(previous, "previously defined here"),
+                );
+            }
+        }
+    }
+
+    /// Binds the use of `name` by `node`, or reports it as undeclared.
+    fn use_name(&mut self, name: &Ident, namespace: Namespace, node: NodeId) {
+        if let Some(def) = self.resolution.symbols.lookup(name.symbol, namespace) {
+            self.resolution.uses.insert(node, def);
+            return;
+        }
+        let what = match namespace {
+            Namespace::Value => "variable",
+            Namespace::Type => "type",
+        };
+        let mut diagnostic = Diagnostic::error(
+            codes::UNDECLARED_NAME,
+            format!("use of undeclared {} '{}'", what, name.name),
+            name.span,
+        );
+        let candidates = self.similar(&name.name, namespace);
+        if let Some(&(_, best)) = candidates.first() {
+            diagnostic = diagnostic.with_fixit(name.span, best, format!("replace with '{}'", best));
+        }
+        for (def, spelling) in candidates {
+            let def = &self.resolution.symbols[def];
+            diagnostic = diagnostic.with_label(
+                def.span,
+                format!(
+                    "a {} with a similar name, '{}', is declared here",
+                    def.kind.describe(),
+                    spelling
+                ),
+            );
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Up to three visible names closest to `name`, closest and innermost
+    /// first, among those within a third of its length in edits.
+    fn similar(&self, name: &str, namespace: Namespace) -> Vec<(DefId, &'ast str)> {
+        let mut candidates: Vec<(usize, DefId, &'ast str)> = self
+            .resolution
+            .symbols
+            .visible(namespace)
+            .into_iter()
+            .filter_map(|def| {
+                let spelling = self.spellings[&self.resolution.symbols[def].name];
+                let distance = utils::suggestion_distance(name, spelling)?;
+                Some((distance, def, spelling))
+            })
+            .collect();
+        // Stable, so equally close names stay innermost first.
+        candidates.sort_by_key(|&(distance, _, _)| distance);
+        candidates
+            .into_iter()
+            .take(3)
+            .map(|(_, def, spelling)| (def, spelling))
+            .collect()
+    }
+
+    fn scoped(&mut self, walk: impl FnOnce(&mut Self)) {
+        self.resolution.symbols.push_scope();
+        walk(self);
+        self.resolution.symbols.pop_scope();
+    }
+}
+
+impl<'ast> Visitor<'ast> for Resolver<'ast> {
+    fn arena(&self) -> &'ast AstArena {
+        self.arena
+    }
+
+    fn visit_item(&mut self, item: &'ast Item) {
+        match item {
+            // Declared up front with the other items.
+            Item::Global(decl) => {
+                if let Some(ty) = &decl.ty {
+                    self.visit_type(ty);
+                }
+                if let Some(init) = decl.init {
+                    self.visit_expr(&self.arena[init]);
+                }
+            }
+            _ => visit::walk_item(self, item),
+        }
+    }
+
+    fn visit_function(&mut self, function: &'ast Function) {
+        self.scoped(|r| {
+            for param in &function.type_params {
+                r.define(&param.name, SymbolKind::TypeParam, param.id);
+            }
+            for param in &function.params {
+                r.visit_type(&param.ty);
+                r.define(&param.name, SymbolKind::Parameter, param.id);
+            }
+            if let Some(return_type) = &function.return_type {
+                r.visit_type(return_type);
+            }
+            visit::walk_block(r, &function.body);
+        });
+    }
+
+    fn visit_struct(&mut self, decl: &'ast StructDecl) {
+        self.scoped(|r| {
+            for param in &decl.type_params {
+                r.define(&param.name, SymbolKind::TypeParam, param.id);
+            }
+            for field in &decl.fields {
+                r.visit_type(&field.ty);
+            }
+        });
+    }
+
+    fn visit_enum(&mut self, decl: &'ast EnumDecl) {
+        for variant in &decl.variants {
+            for ty in &variant.fields {
+                self.visit_type(ty);
+            }
+        }
+    }
+
+    fn visit_var_decl(&mut self, decl: &'ast VarDecl) {
+        if let Some(ty) = &decl.ty {
+            self.visit_type(ty);
+        }
+        if let Some(init) = decl.init {
+            self.visit_expr(&self.arena[init]);
+        }
+        self.define(&decl.name, SymbolKind::Variable, decl.id);
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        self.scoped(|r| visit::walk_block(r, block));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        match stmt.kind {
+            StmtKind::For { .. } => self.scoped(|r| visit::walk_stmt(r, stmt)),
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.use_name(name, Namespace::Value, expr.id),
+            ExprKind::StructLiteral { name, fields } => {
+                self.use_name(name, Namespace::Type, expr.id);
+                for field in fields {
+                    self.visit_expr(&self.arena[field.value]);
+                }
+            }
+            ExprKind::Variant { enum_name, .. } => {
+                self.use_name(enum_name, Namespace::Type, expr.id)
+            }
+            // Field names depend on the object's type, which is not known
+            // until type checking.
+            ExprKind::Field { object, .. } => self.visit_expr(&self.arena[*object]),
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_arm(&mut self, arm: &'ast MatchArm) {
+        self.scoped(|r| visit::walk_arm(r, arm));
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding(name) => self.define(name, SymbolKind::Variable, pattern.id),
+            PatternKind::Variant {
+                enum_name, fields, ..
+            } => {
+                self.use_name(enum_name, Namespace::Type, pattern.id);
+                for field in fields {
+                    self.visit_pattern(field);
+                }
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) => {}
+        }
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type) {
+        match &ty.kind {
+            TypeKind::Named(name) => self.use_name(name, Namespace::Type, ty.id),
+            TypeKind::Generic { name, args } => {
+                self.use_name(name, Namespace::Type, ty.id);
+                for arg in args {
+                    self.visit_type(arg);
+                }
+            }
+            TypeKind::Array {
+                element,
+                len: ArrayLen::Const(name),
+            } => {
+                self.visit_type(element);
+                self.use_name(name, Namespace::Value, ty.id);
+            }
+            _ => visit::walk_type(self, ty),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::source::Span;
+    use pretty_assertions::assert_eq;
+
+    /// Each use of a name as `use -> declaration`, by source text and
+    /// offset.
+    fn bindings(source: &str) -> Vec<String> {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, diagnostics) = resolve(&program);
+        assert_eq!(diagnostics, []);
+        let mut uses: Vec<(Span, DefId)> = Vec::new();
+        let mut spans = Spans {
+            arena: &program.arena,
+            spans: HashMap::new(),
+        };
+        spans.visit_program(&program);
+        for (node, &def) in &resolution.uses {
+            uses.push((spans.spans[node], def));
+        }
+        uses.sort_by_key(|&(span, _)| span.start);
+        uses.into_iter()
+            .map(|(span, def)| {
+                let decl = resolution.symbols[def].span;
+                format!(
+                    "{}@{} -> {}@{}",
+                    &source[span.start..span.end],
+                    span.start,
+                    &source[decl.start..decl.end],
+                    decl.start
+                )
+            })
+            .collect()
+    }
+
+    /// The name span of every node that can use a name.
+    struct Spans<'ast> {
+        arena: &'ast AstArena,
+        spans: HashMap<NodeId, Span>,
+    }
+
+    impl<'ast> Visitor<'ast> for Spans<'ast> {
+        fn arena(&self) -> &'ast AstArena {
+            self.arena
+        }
+
+        fn visit_expr(&mut self, expr: &'ast Expr) {
+            let name = match &expr.kind {
+                ExprKind::Variable(name) => Some(name),
+                ExprKind::StructLiteral { name, .. } => Some(name),
+                ExprKind::Variant { enum_name, .. } => Some(enum_name),
+                _ => None,
+            };
+            if let Some(name) = name {
+                self.spans.insert(expr.id, name.span);
+            }
+            visit::walk_expr(self, expr);
+        }
+
+        fn visit_type(&mut self, ty: &'ast Type) {
+            if let TypeKind::Named(name) | TypeKind::Generic { name, .. } = &ty.kind {
+                self.spans.insert(ty.id, name.span);
+            }
+            visit::walk_type(self, ty);
+        }
+
+        fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+            if let PatternKind::Variant { enum_name, .. } = &pattern.kind {
+                self.spans.insert(pattern.id, enum_name.span);
+            }
+            visit::walk_pattern(self, pattern);
+        }
+    }
+
+    #[test]
+    fn test_bindings() {
+        assert_eq!(
+            bindings("fn f(x: int) -> int { return g(x); }\nfn g(y: int) -> int { return y; }"),
+            ["g@29 -> g@40", "x@31 -> x@5", "y@66 -> y@42",]
+        );
+        // Blocks, `for` loops and arms scope their declarations, and a
+        // variable's initializer cannot see the variable itself.
+        let source = "int x = 1;\nfn f() {\n    let x = x;\n    { let x = 2; x; }\n    x;\n    for (let i = 0; i < x; i = i + 1) {}\n    match x { y => y, _ => x }\n}";
+        assert_eq!(
+            bindings(source),
+            [
+                "x@32 -> x@4",
+                "x@52 -> x@45",
+                "x@61 -> x@28",
+                "i@84 -> i@77",
+                "x@88 -> x@28",
+                "i@91 -> i@77",
+                "i@95 -> i@77",
+                "x@115 -> x@28",
+                "y@124 -> y@119",
+                "x@132 -> x@28",
+            ]
+        );
+        // Types and values are looked up separately, and items may be used
+        // before they are declared.
+        let source = "fn f<T>(a: T, P: P) -> Shape { return Shape::Dot; }\nenum Shape { Dot }\nstruct P { s: Shape }\nfn g(p: P) { match p { Shape::Dot => p, _ => P { s: p.s } }; }";
+        assert_eq!(
+            bindings(source),
+            [
+                "T@11 -> T@5",
+                "P@17 -> P@78",
+                "Shape@23 -> Shape@57",
+                "Shape@38 -> Shape@57",
+                "Shape@85 -> Shape@57",
+                "P@101 -> P@78",
+                "p@112 -> p@98",
+                "Shape@116 -> Shape@57",
+                "p@130 -> p@98",
+                "P@138 -> P@78",
+                "p@145 -> p@98",
+            ]
+        );
+    }
+
+    /// Each resolution error with its labels and fix-its, one line each.
+    fn errors(source: &str) -> Vec<String> {
+        let (program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (_, diagnostics) = resolve(&program);
+        diagnostics
+            .iter()
+            .map(|d| {
+                let mut line = format!("{} at {}", d.message, d.primary_span);
+                for label in &d.labels {
+                    line += &format!("; {} at {}", label.message, label.span);
+                }
+                for fixit in &d.fixits {
+                    line += &format!("; {}", fixit.message);
+                }
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_undeclared() {
+        let source = "struct Point { x: int }\nfn f(count: int) -> Pont {\n    let counter = cont;\n    return zzz;\n}";
+        assert_eq!(
+            errors(source),
+            [
+                "use of undeclared type 'Pont' at 44..48; a struct with a similar name, 'Point', is declared here at 7..12; replace with 'Point'",
+                "use of undeclared variable 'cont' at 69..73; a parameter with a similar name, 'count', is declared here at 29..34; replace with 'count'",
+                "use of undeclared variable 'zzz' at 86..89",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let source = "fn f(a: int, a: int) {\n    let b = 1;\n    let b = a;\n    { let b = 2; }\n}\nstruct f { x: int }\nfn f() {}\nfn g(x: int) { let x = 1; }";
+        assert_eq!(
+            errors(source),
+            [
+                "function 'f' is already defined at 97..98; previously defined here at 3..4",
+                "parameter 'a' is already defined at 13..14; previously defined here at 5..6",
+                "variable 'b' is already defined at 46..47; previously defined here at 31..32",
+                "variable 'x' is already defined at 123..124; previously defined here at 109..110",
+            ]
+        );
+        // Later uses see the first definition.
+        let (program, _) = parse("fn f() { let b = 1; let b = 2.0; b; }");
+        let (resolution, _) = resolve(&program);
+        let (first, _) = resolution
+            .symbols
+            .definitions()
+            .find(|(_, def)| def.kind == SymbolKind::Variable)
+            .unwrap();
+        assert_eq!(resolution.uses.values().collect::<Vec<_>>(), [&first]);
+    }
+}
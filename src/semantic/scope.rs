@@ -0,0 +1,328 @@
+//! Nested lexical scopes.
+//!
+//! A [`SymbolTable`] starts with the global scope open. A pass pushes a
+//! scope on entering a function or block and pops it on leaving, defining
+//! each declaration in the innermost open scope as it goes. Lookups search
+//! from the innermost open scope outwards, so an inner declaration shadows
+//! an outer one of the same name, while two in the same scope conflict.
+//!
+//! Types and values live in separate [`Namespace`]s: a struct and a variable
+//! may share a name without clashing.
+
+use crate::ast::NodeId;
+use crate::source::Span;
+use crate::utils::Symbol;
+use std::collections::HashMap;
+use std::ops::Index;
+
+/// Identifies one definition in a [`SymbolTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefId(u32);
+
+impl DefId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Identifies one scope in a [`SymbolTable`]. Scopes outlive being popped,
+/// so ids stay valid after the walk that created them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ScopeId(u32);
+
+impl ScopeId {
+    /// The global scope every table starts with.
+    pub const GLOBAL: ScopeId = ScopeId(0);
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// What a name was declared as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Struct,
+    Enum,
+    TypeParam,
+}
+
+impl SymbolKind {
+    pub fn namespace(self) -> Namespace {
+        match self {
+            SymbolKind::Variable | SymbolKind::Parameter | SymbolKind::Function => Namespace::Value,
+            SymbolKind::Struct | SymbolKind::Enum | SymbolKind::TypeParam => Namespace::Type,
+        }
+    }
+
+    /// The kind as a word for diagnostics, e.g. `parameter`.
+    pub fn describe(self) -> &'static str {
+        match self {
+            SymbolKind::Variable => "variable",
+            SymbolKind::Parameter => "parameter",
+            SymbolKind::Function => "function",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::TypeParam => "type parameter",
+        }
+    }
+}
+
+/// The set of names a lookup searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    /// Variables, parameters and functions.
+    Value,
+    /// Structs, enums and type parameters.
+    Type,
+}
+
+/// One declared name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: Symbol,
+    pub kind: SymbolKind,
+    /// The declaring node: a function, param, type param, struct, enum,
+    /// var decl or binding pattern.
+    pub node: NodeId,
+    /// Span of the declared name.
+    pub span: Span,
+    pub scope: ScopeId,
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    parent: Option<ScopeId>,
+    names: HashMap<(Namespace, Symbol), DefId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    definitions: Vec<Definition>,
+    scopes: Vec<Scope>,
+    /// The innermost open scope.
+    current: ScopeId,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            definitions: Vec::new(),
+            scopes: vec![Scope {
+                parent: None,
+                names: HashMap::new(),
+            }],
+            current: ScopeId::GLOBAL,
+        }
+    }
+
+    /// The innermost open scope.
+    pub fn current_scope(&self) -> ScopeId {
+        self.current
+    }
+
+    /// Opens a scope inside the current one.
+    pub fn push_scope(&mut self) -> ScopeId {
+        let scope = ScopeId(self.scopes.len() as u32);
+        self.scopes.push(Scope {
+            parent: Some(self.current),
+            names: HashMap::new(),
+        });
+        self.current = scope;
+        scope
+    }
+
+    /// Closes the current scope, returning to its parent.
+    ///
+    /// Panics if only the global scope is open.
+    pub fn pop_scope(&mut self) {
+        self.current = self.scopes[self.current.index()]
+            .parent
+            .expect("the global scope is never popped");
+    }
+
+    /// The scope enclosing `scope`; `None` for the global scope.
+    pub fn parent(&self, scope: ScopeId) -> Option<ScopeId> {
+        self.scopes[scope.index()].parent
+    }
+
+    /// Declares `name` in the current scope.
+    ///
+    /// A name already declared in the same scope and namespace is left as it
+    /// is and its definition returned as the error. One declared in an
+    /// enclosing scope is shadowed.
+    pub fn define(
+        &mut self,
+        name: Symbol,
+        kind: SymbolKind,
+        node: NodeId,
+        span: Span,
+    ) -> Result<DefId, DefId> {
+        let key = (kind.namespace(), name);
+        if let Some(&previous) = self.scopes[self.current.index()].names.get(&key) {
+            return Err(previous);
+        }
+        let def = DefId(self.definitions.len() as u32);
+        self.definitions.push(Definition {
+            name,
+            kind,
+            node,
+            span,
+            scope: self.current,
+        });
+        self.scopes[self.current.index()].names.insert(key, def);
+        Ok(def)
+    }
+
+    /// The innermost visible definition of `name`.
+    pub fn lookup(&self, name: Symbol, namespace: Namespace) -> Option<DefId> {
+        self.lookup_from(self.current, name, namespace)
+    }
+
+    /// The innermost definition of `name` visible from `scope`, which need
+    /// not be open.
+    pub fn lookup_from(&self, scope: ScopeId, name: Symbol, namespace: Namespace) -> Option<DefId> {
+        let mut scope = Some(scope);
+        while let Some(id) = scope {
+            let s = &self.scopes[id.index()];
+            if let Some(&def) = s.names.get(&(namespace, name)) {
+                return Some(def);
+            }
+            scope = s.parent;
+        }
+        None
+    }
+
+    /// The definition of `name` in the current scope itself, ignoring
+    /// enclosing ones.
+    pub fn lookup_local(&self, name: Symbol, namespace: Namespace) -> Option<DefId> {
+        self.scopes[self.current.index()]
+            .names
+            .get(&(namespace, name))
+            .copied()
+    }
+
+    /// The definitions visible from the current scope in `namespace`,
+    /// innermost first, leaving out shadowed ones.
+    pub fn visible(&self, namespace: Namespace) -> Vec<DefId> {
+        let mut visible: Vec<DefId> = Vec::new();
+        let mut scope = Some(self.current);
+        while let Some(id) = scope {
+            let s = &self.scopes[id.index()];
+            let mut defs: Vec<DefId> = s
+                .names
+                .iter()
+                .filter(|((ns, name), _)| {
+                    *ns == namespace && !visible.iter().any(|&def| self[def].name == *name)
+                })
+                .map(|(_, &def)| def)
+                .collect();
+            defs.sort();
+            visible.extend(defs);
+            scope = s.parent;
+        }
+        visible
+    }
+
+    /// Every definition, in the order they were made.
+    pub fn definitions(&self) -> impl Iterator<Item = (DefId, &Definition)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .map(|(index, def)| (DefId(index as u32), def))
+    }
+
+    /// Applies `map` to the span of every definition.
+    pub fn map_spans(&mut self, map: impl Fn(Span) -> Span) {
+        for def in &mut self.definitions {
+            def.span = map(def.span);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+}
+
+impl Index<DefId> for SymbolTable {
+    type Output = Definition;
+
+    fn index(&self, def: DefId) -> &Definition {
+        &self.definitions[def.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_scopes() {
+        let mut names = utils::SymbolTable::new();
+        let [x, f, p] = ["x", "f", "P"].map(|name| names.intern(name));
+        let span = Span::default();
+        let mut table = SymbolTable::new();
+
+        let global_x = table
+            .define(x, SymbolKind::Variable, NodeId::new(0), span)
+            .unwrap();
+        let func = table
+            .define(f, SymbolKind::Function, NodeId::new(1), span)
+            .unwrap();
+        // A type does not clash with a value of the same name.
+        let ty = table
+            .define(x, SymbolKind::Struct, NodeId::new(2), span)
+            .unwrap();
+        assert_eq!(table.lookup(x, Namespace::Type), Some(ty));
+        // Redeclaring in the same scope keeps the first definition.
+        assert_eq!(
+            table.define(x, SymbolKind::Variable, NodeId::new(3), span),
+            Err(global_x)
+        );
+
+        let body = table.push_scope();
+        let param = table
+            .define(x, SymbolKind::Parameter, NodeId::new(4), span)
+            .unwrap();
+        assert_eq!(table.lookup(x, Namespace::Value), Some(param));
+        assert_eq!(table.lookup(f, Namespace::Value), Some(func));
+        assert_eq!(table.lookup_local(f, Namespace::Value), None);
+        assert_eq!(table.lookup(p, Namespace::Type), None);
+
+        table.push_scope();
+        let local = table
+            .define(x, SymbolKind::Variable, NodeId::new(5), span)
+            .unwrap();
+        assert_eq!(table.lookup(x, Namespace::Value), Some(local));
+        assert_eq!(table.visible(Namespace::Value), [local, func]);
+        table.pop_scope();
+
+        assert_eq!(table.current_scope(), body);
+        assert_eq!(table.lookup(x, Namespace::Value), Some(param));
+        table.pop_scope();
+        assert_eq!(table.current_scope(), ScopeId::GLOBAL);
+        assert_eq!(table.lookup(x, Namespace::Value), Some(global_x));
+        // Closed scopes can still be searched.
+        assert_eq!(table.lookup_from(body, x, Namespace::Value), Some(param));
+
+        assert_eq!(table[local].kind, SymbolKind::Variable);
+        assert_eq!(table[param].scope, body);
+        assert_eq!(table.parent(body), Some(ScopeId::GLOBAL));
+        assert_eq!(table.len(), 5);
+    }
+}
@@ -0,0 +1,141 @@
+//! Types as the checker sees them.
+
+use super::scope::DefId;
+use std::fmt;
+use std::rc::Rc;
+
+/// The type of an expression or declaration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Int,
+    Float,
+    Bool,
+    Char,
+    String,
+    Void,
+    /// The type of `null`, which any struct, string, array or function
+    /// value may be.
+    Null,
+    Struct {
+        def: DefId,
+        name: Rc<str>,
+        /// Type arguments of a generic struct.
+        args: Vec<Ty>,
+    },
+    Enum {
+        def: DefId,
+        name: Rc<str>,
+    },
+    /// A type parameter of a generic function or struct. Nothing is known
+    /// about it, so it is compatible with every type.
+    Param {
+        def: DefId,
+        name: Rc<str>,
+    },
+    Array {
+        element: Box<Ty>,
+        len: u64,
+    },
+    Function {
+        params: Vec<Ty>,
+        ret: Box<Ty>,
+    },
+    /// The type of something already reported as an error, or not known.
+    /// It is compatible with every type, so one mistake is reported once.
+    Error,
+}
+
+impl Ty {
+    pub fn is_error(&self) -> bool {
+        matches!(self, Ty::Error)
+    }
+
+    /// Whether `null` is a value of this type.
+    pub fn is_nullable(&self) -> bool {
+        matches!(
+            self,
+            Ty::String | Ty::Struct { .. } | Ty::Array { .. } | Ty::Function { .. }
+        )
+    }
+
+    /// Whether a value of type `found` may be used where `self` is
+    /// expected.
+    pub fn accepts(&self, found: &Ty) -> bool {
+        match (self, found) {
+            (Ty::Error | Ty::Param { .. }, _) | (_, Ty::Error | Ty::Param { .. }) => true,
+            (expected, Ty::Null) => expected.is_nullable() || *expected == Ty::Null,
+            (
+                Ty::Struct { def, args, .. },
+                Ty::Struct {
+                    def: found_def,
+                    args: found_args,
+                    ..
+                },
+            ) => {
+                def == found_def
+                    && args.len() == found_args.len()
+                    && args.iter().zip(found_args).all(|(a, b)| a.accepts(b))
+            }
+            (
+                Ty::Array { element, len },
+                Ty::Array {
+                    element: found_element,
+                    len: found_len,
+                },
+            ) => len == found_len && element.accepts(found_element),
+            (
+                Ty::Function { params, ret },
+                Ty::Function {
+                    params: found_params,
+                    ret: found_ret,
+                },
+            ) => {
+                params.len() == found_params.len()
+                    && params.iter().zip(found_params).all(|(a, b)| a.accepts(b))
+                    && ret.accepts(found_ret)
+            }
+            (expected, found) => expected == found,
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Int => f.write_str("int"),
+            Ty::Float => f.write_str("float"),
+            Ty::Bool => f.write_str("bool"),
+            Ty::Char => f.write_str("char"),
+            Ty::String => f.write_str("string"),
+            Ty::Void => f.write_str("void"),
+            Ty::Null => f.write_str("null"),
+            Ty::Struct { name, args, .. } => {
+                f.write_str(name)?;
+                if !args.is_empty() {
+                    f.write_str("<")?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            f.write_str(", ")?;
+                        }
+                        write!(f, "{}", arg)?;
+                    }
+                    f.write_str(">")?;
+                }
+                Ok(())
+            }
+            Ty::Enum { name, .. } | Ty::Param { name, .. } => f.write_str(name),
+            Ty::Array { element, len } => write!(f, "{}[{}]", element, len),
+            Ty::Function { params, ret } => {
+                f.write_str("fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+            Ty::Error => f.write_str("{unknown}"),
+        }
+    }
+}
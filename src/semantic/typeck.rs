@@ -0,0 +1,1349 @@
+//! Type checking: a type for every expression, and errors where types
+//! disagree.
+//!
+//! [`check`] works on a resolved program. It first works out the type of
+//! every item, so functions and globals can be used before their
+//! declarations, then checks each item in order. A mistake is reported once:
+//! an expression whose type could not be worked out gets [`Ty::Error`],
+//! which every other type accepts.
+//!
+//! Where [`TypeckOptions::int_to_float`] allows an `int` to be used as a
+//! `float`, the checker wraps the `int` operand in an
+//! [`ExprKind::IntToFloat`] node, so later phases see every conversion
+//! spelled out.
+
+use super::consteval::ConstValues;
+use super::resolve::Resolution;
+use super::scope::{DefId, SymbolKind};
+use super::ty::Ty;
+use crate::ast::{
+    ArmBody, ArrayLen, AstArena, BinaryOp, Block, Expr, ExprId, ExprKind, Ident, Item, NodeId,
+    Pattern, PatternKind, Program, StmtId, StmtKind, Type, TypeKind, UnaryOp, VarDecl,
+};
+use crate::diagnostics::{codes, Diagnostic};
+use crate::lexer::LiteralValue;
+use crate::source::Span;
+use crate::utils;
+use std::collections::HashMap;
+
+/// What the checker does with an `int` where a `float` is expected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum ConversionPolicy {
+    /// Convert it silently.
+    #[default]
+    Implicit,
+    /// Convert it, with a warning at each conversion.
+    Warn,
+    /// Report a type mismatch, as between any two other types.
+    Error,
+}
+
+/// Configuration for [`check`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeckOptions {
+    /// Whether an `int` may be used as a `float`: as an initializer,
+    /// assigned value, argument, return value or field value of type
+    /// `float`, as the other operand of arithmetic or a comparison with a
+    /// `float`, or as the other branch of a `?:` or `match`.
+    pub int_to_float: ConversionPolicy,
+}
+
+impl TypeckOptions {
+    /// The default options; start of a chain of `with_*` calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_int_to_float(mut self, policy: ConversionPolicy) -> Self {
+        self.int_to_float = policy;
+        self
+    }
+}
+
+/// The types [`check`] worked out.
+#[derive(Debug, Clone, Default)]
+pub struct TypeckResults {
+    /// The type of every expression, keyed by its id.
+    pub exprs: HashMap<NodeId, Ty>,
+    /// The type of every variable, parameter and function.
+    pub defs: HashMap<DefId, Ty>,
+}
+
+impl TypeckResults {
+    pub fn expr_type(&self, expr: NodeId) -> Option<&Ty> {
+        self.exprs.get(&expr)
+    }
+
+    pub fn def_type(&self, def: DefId) -> Option<&Ty> {
+        self.defs.get(&def)
+    }
+}
+
+/// Type checks `program`, whose names `resolution` binds and whose
+/// constants have the values `consts`, inserting the conversions `options`
+/// allow.
+pub fn check(
+    program: &mut Program,
+    resolution: &Resolution,
+    consts: &ConstValues,
+    options: &TypeckOptions,
+) -> (TypeckResults, Vec<Diagnostic>) {
+    let mut checker = Checker {
+        arena: &program.arena,
+        resolution,
+        consts,
+        options,
+        results: TypeckResults::default(),
+        structs: HashMap::new(),
+        enums: HashMap::new(),
+        ret: None,
+        function: None,
+        loops: 0,
+        conversions: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for item in &program.items {
+        checker.declare(item);
+    }
+    // Globals first, so every function sees the types inferred for them.
+    let (globals, rest): (Vec<_>, Vec<_>) = program
+        .items
+        .iter()
+        .partition(|item| matches!(item, Item::Global(_)));
+    for item in globals.into_iter().chain(rest) {
+        checker.item(item);
+    }
+    let Checker {
+        mut results,
+        conversions,
+        diagnostics,
+        ..
+    } = checker;
+    // The converted expression moves to a new slot and the conversion takes
+    // its place, so whatever held the old handle now holds the conversion.
+    for id in conversions {
+        let converted = program.arena[id].clone();
+        let span = converted.span;
+        let inner = program.arena.alloc_expr(converted);
+        let node = NodeId::new(program.node_count);
+        program.node_count += 1;
+        program.arena[id] = Expr {
+            id: node,
+            kind: ExprKind::IntToFloat(inner),
+            span,
+        };
+        results.exprs.insert(node, Ty::Float);
+    }
+    (results, diagnostics)
+}
+
+struct StructInfo<'ast> {
+    /// Type parameters, which field types may mention.
+    params: Vec<Option<DefId>>,
+    /// Each field's name, type and the span of its name.
+    fields: Vec<(&'ast str, Ty, Span)>,
+}
+
+struct Checker<'ast> {
+    arena: &'ast AstArena,
+    resolution: &'ast Resolution,
+    consts: &'ast ConstValues,
+    options: &'ast TypeckOptions,
+    results: TypeckResults,
+    structs: HashMap<DefId, StructInfo<'ast>>,
+    /// Each enum's variants with their payload types.
+    enums: HashMap<DefId, Vec<(&'ast str, Vec<Ty>)>>,
+    /// The return type of the function being checked, with the span of its
+    /// annotation if it has one.
+    ret: Option<(Ty, Option<Span>)>,
+    /// The name of the function being checked.
+    function: Option<&'ast Ident>,
+    /// How many loops enclose the statement being checked.
+    loops: usize,
+    /// Expressions to wrap in an `IntToFloat` once checking is done.
+    conversions: Vec<ExprId>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// `mismatched types: expected int, found bool`
+fn mismatch(expected: &Ty, found: &Ty, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        codes::MISMATCHED_TYPES,
+        format!("mismatched types: expected {}, found {}", expected, found),
+        span,
+    )
+}
+
+impl<'ast> Checker<'ast> {
+    /// Records the type of every definition `item` makes, before any body
+    /// is checked.
+    fn declare(&mut self, item: &'ast Item) {
+        let Some(def) = self.resolution.declaration(item_id(item)) else {
+            // A duplicate, already reported by the resolver.
+            return;
+        };
+        match item {
+            Item::Function(function) => {
+                let mut params = Vec::new();
+                for param in &function.params {
+                    let ty = self.lower(&param.ty);
+                    if let Some(def) = self.resolution.declaration(param.id) {
+                        self.results.defs.insert(def, ty.clone());
+                    }
+                    params.push(ty);
+                }
+                let ret = match &function.return_type {
+                    Some(ty) => self.lower(ty),
+                    None => Ty::Void,
+                };
+                let ty = Ty::Function {
+                    params,
+                    ret: Box::new(ret),
+                };
+                self.results.defs.insert(def, ty);
+            }
+            Item::Struct(decl) => {
+                let info = StructInfo {
+                    params: decl
+                        .type_params
+                        .iter()
+                        .map(|param| self.resolution.declaration(param.id))
+                        .collect(),
+                    fields: decl
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            (
+                                field.name.name.as_str(),
+                                self.lower(&field.ty),
+                                field.name.span,
+                            )
+                        })
+                        .collect(),
+                };
+                self.structs.insert(def, info);
+            }
+            Item::Enum(decl) => {
+                let variants = decl
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let payload = variant.fields.iter().map(|ty| self.lower(ty)).collect();
+                        (variant.name.name.as_str(), payload)
+                    })
+                    .collect();
+                self.enums.insert(def, variants);
+            }
+            Item::Global(decl) => {
+                let ty = decl.ty.as_ref().map_or(Ty::Error, |ty| self.lower(ty));
+                self.results.defs.insert(def, ty);
+            }
+        }
+    }
+
+    fn item(&mut self, item: &'ast Item) {
+        match item {
+            Item::Function(function) => {
+                self.ret = Some(match &function.return_type {
+                    Some(ty) => (self.lower(ty), Some(ty.span)),
+                    None => (Ty::Void, None),
+                });
+                self.function = Some(&function.name);
+                self.block(&function.body);
+                self.function = None;
+                self.ret = None;
+            }
+            Item::Global(decl) => {
+                let declared = decl.ty.as_ref().map(|ty| self.lower(ty));
+                let ty = self.initializer(decl, declared);
+                if let Some(def) = self.resolution.declaration(decl.id) {
+                    self.results.defs.insert(def, ty);
+                }
+            }
+            Item::Struct(_) | Item::Enum(_) => {}
+        }
+    }
+
+    /// The type an annotation names.
+    fn lower(&self, ty: &Type) -> Ty {
+        match &ty.kind {
+            TypeKind::Int => Ty::Int,
+            TypeKind::Float => Ty::Float,
+            TypeKind::Bool => Ty::Bool,
+            TypeKind::Char => Ty::Char,
+            TypeKind::String => Ty::String,
+            TypeKind::Void => Ty::Void,
+            TypeKind::Named(name) => self.named(ty.id, &name.name, Vec::new()),
+            TypeKind::Generic { name, args } => {
+                let args = args.iter().map(|arg| self.lower(arg)).collect();
+                self.named(ty.id, &name.name, args)
+            }
+            TypeKind::Array { element, len } => {
+                let len = match len {
+                    ArrayLen::Literal(len) => *len,
+                    // Already reported if the constant has no usable value.
+                    ArrayLen::Const(_) => match self.consts.array_len(ty.id) {
+                        Some(len) => len,
+                        None => return Ty::Error,
+                    },
+                };
+                Ty::Array {
+                    element: Box::new(self.lower(element)),
+                    len,
+                }
+            }
+            TypeKind::Function {
+                params,
+                return_type,
+            } => Ty::Function {
+                params: params.iter().map(|param| self.lower(param)).collect(),
+                ret: Box::new(return_type.as_ref().map_or(Ty::Void, |ty| self.lower(ty))),
+            },
+        }
+    }
+
+    /// The type a name used by `node` stands for.
+    fn named(&self, node: NodeId, name: &str, args: Vec<Ty>) -> Ty {
+        let Some(def) = self.resolution.use_of(node) else {
+            return Ty::Error;
+        };
+        let name = name.into();
+        match self.resolution.symbols[def].kind {
+            SymbolKind::Struct => Ty::Struct { def, name, args },
+            SymbolKind::Enum => Ty::Enum { def, name },
+            SymbolKind::TypeParam => Ty::Param { def, name },
+            _ => Ty::Error,
+        }
+    }
+
+    /// The type of field `name` of a value of type `ty`, with the struct's
+    /// type arguments filled in, reporting a field `ty` does not have.
+    fn field(&mut self, ty: &Ty, name: &Ident) -> Ty {
+        let (info, args) = match ty {
+            Ty::Struct { def, args, .. } => (self.structs.get(def), args.as_slice()),
+            Ty::Error | Ty::Param { .. } => return Ty::Error,
+            _ => (None, &[][..]),
+        };
+        let Some(info) = info else {
+            self.diagnostics.push(Diagnostic::error(
+                codes::NO_FIELD,
+                format!("no field '{}' on type {}", name.name, ty),
+                name.span,
+            ));
+            return Ty::Error;
+        };
+        if let Some((_, field, _)) = info.fields.iter().find(|(field, ..)| *field == name.name) {
+            return substitute(field, &info.params, args);
+        }
+        let mut diagnostic = Diagnostic::error(
+            codes::NO_FIELD,
+            format!("no field '{}' on type {}", name.name, ty),
+            name.span,
+        );
+        let closest = info
+            .fields
+            .iter()
+            .filter_map(|&(field, _, span)| {
+                let distance = utils::suggestion_distance(&name.name, field)?;
+                Some((distance, field, span))
+            })
+            .min_by_key(|&(distance, ..)| distance);
+        if let Some((_, field, span)) = closest {
+            diagnostic = diagnostic
+                .with_label(
+                    span,
+                    format!("a field with a similar name, '{}', is declared here", field),
+                )
+                .with_fixit(name.span, field, format!("replace with '{}'", field));
+        }
+        self.diagnostics.push(diagnostic);
+        Ty::Error
+    }
+
+    /// Whether `id`, of type `found`, is converted to `expected` rather than
+    /// being a mismatch.
+    fn coerce(&mut self, id: ExprId, found: &Ty, expected: &Ty) -> bool {
+        if !(*found == Ty::Int && *expected == Ty::Float) {
+            return false;
+        }
+        match self.options.int_to_float {
+            ConversionPolicy::Error => return false,
+            ConversionPolicy::Warn => self.diagnostics.push(Diagnostic::warning(
+                codes::IMPLICIT_CONVERSION,
+                "implicit conversion from int to float",
+                self.arena[id].span,
+            )),
+            ConversionPolicy::Implicit => {}
+        }
+        self.conversions.push(id);
+        true
+    }
+
+    fn block(&mut self, block: &'ast Block) {
+        for &stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, id: StmtId) {
+        let stmt = &self.arena[id];
+        match &stmt.kind {
+            StmtKind::VarDecl(decl) => {
+                let declared = decl.ty.as_ref().map(|ty| self.lower(ty));
+                let ty = self.initializer(decl, declared);
+                if let Some(def) = self.resolution.declaration(decl.id) {
+                    self.results.defs.insert(def, ty);
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(*expr);
+            }
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.condition(*condition);
+                self.stmt(*then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(*else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.condition(*condition);
+                self.loop_body(*body);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.stmt(*init);
+                }
+                if let Some(condition) = condition {
+                    self.condition(*condition);
+                }
+                if let Some(step) = step {
+                    self.expr(*step);
+                }
+                self.loop_body(*body);
+            }
+            StmtKind::Return(value) => self.ret(stmt.span, *value),
+            StmtKind::Break | StmtKind::Continue if self.loops == 0 => {
+                let keyword = match stmt.kind {
+                    StmtKind::Break => "break",
+                    _ => "continue",
+                };
+                let mut diagnostic = Diagnostic::error(
+                    codes::LOOP_CONTROL_OUTSIDE_LOOP,
+                    format!("'{}' outside of a loop", keyword),
+                    stmt.span,
+                );
+                if let Some(function) = self.function {
+                    diagnostic = diagnostic.with_label(
+                        function.span,
+                        format!("enclosing function '{}'", function.name),
+                    );
+                }
+                self.diagnostics.push(diagnostic);
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    fn loop_body(&mut self, body: StmtId) {
+        self.loops += 1;
+        self.stmt(body);
+        self.loops -= 1;
+    }
+
+    /// Checks a declaration's initializer against its annotation, returning
+    /// the variable's type. Without an annotation the type is the
+    /// initializer's.
+    fn initializer(&mut self, decl: &VarDecl, declared: Option<Ty>) -> Ty {
+        let Some(init) = decl.init else {
+            if declared.is_none() {
+                self.diagnostics.push(Diagnostic::error(
+                    codes::TYPE_ANNOTATIONS_NEEDED,
+                    format!("type annotations needed for '{}'", decl.name.name),
+                    decl.name.span,
+                ));
+            }
+            return declared.unwrap_or(Ty::Error);
+        };
+        let found = self.expr(init);
+        let Some(declared) = declared else {
+            if matches!(found, Ty::Null | Ty::Void) {
+                let diagnostic = Diagnostic::error(
+                    codes::TYPE_ANNOTATIONS_NEEDED,
+                    format!("type annotations needed for '{}'", decl.name.name),
+                    decl.name.span,
+                )
+                .with_label(
+                    self.arena[init].span,
+                    format!("a {} initializer does not say which type", found),
+                );
+                self.diagnostics.push(diagnostic);
+                return Ty::Error;
+            }
+            return found;
+        };
+        if !declared.accepts(&found) && !self.coerce(init, &found, &declared) {
+            let annotation = decl.ty.as_ref().expect("declared comes from it").span;
+            let diagnostic = mismatch(&declared, &found, self.arena[init].span)
+                .with_label(annotation, "expected because of this type");
+            self.diagnostics.push(diagnostic);
+        }
+        declared
+    }
+
+    /// Checks a call's arguments against the callee's parameters, returning
+    /// the type of the call.
+    fn call(&mut self, id: ExprId, callee: ExprId, args: &[ExprId]) -> Ty {
+        let callee_ty = self.expr(callee);
+        let found: Vec<Ty> = args.iter().map(|&arg| self.expr(arg)).collect();
+        let (params, ret) = match callee_ty {
+            Ty::Function { params, ret } => (params, ret),
+            Ty::Error | Ty::Param { .. } => return Ty::Error,
+            ty => {
+                self.diagnostics.push(Diagnostic::error(
+                    codes::NOT_CALLABLE,
+                    format!("cannot call a value of type {}", ty),
+                    self.arena[callee].span,
+                ));
+                return Ty::Error;
+            }
+        };
+        if params.len() != found.len() {
+            let label = format!("this takes ({})", join_types(&params));
+            self.diagnostics.push(
+                Diagnostic::error(
+                    codes::WRONG_ARGUMENT_COUNT,
+                    format!(
+                        "expected {} argument{}, found {}",
+                        params.len(),
+                        if params.len() == 1 { "" } else { "s" },
+                        found.len()
+                    ),
+                    self.arena[id].span,
+                )
+                .with_label(self.arena[callee].span, label),
+            );
+        }
+        for ((expected, found), &arg) in params.iter().zip(&found).zip(args) {
+            if !expected.accepts(found) && !self.coerce(arg, found, expected) {
+                let diagnostic = mismatch(expected, found, self.arena[arg].span);
+                self.diagnostics.push(diagnostic);
+            }
+        }
+        *ret
+    }
+
+    /// Checks `return value;`, or `return;` without one, against the
+    /// enclosing function's return type.
+    fn ret(&mut self, span: Span, value: Option<ExprId>) {
+        let found = value.map(|value| self.expr(value));
+        let Some((expected, annotation)) = self.ret.clone() else {
+            return;
+        };
+        let diagnostic = match (found, value) {
+            (Some(found), Some(value))
+                if !expected.accepts(&found) && !self.coerce(value, &found, &expected) =>
+            {
+                let span = self.arena[value].span;
+                if expected == Ty::Void {
+                    Diagnostic::error(
+                        codes::RETURN_FROM_VOID,
+                        format!(
+                            "cannot return a value of type {} from a void function",
+                            found
+                        ),
+                        span,
+                    )
+                } else {
+                    mismatch(&expected, &found, span)
+                }
+            }
+            (None, _) if expected != Ty::Void && !expected.is_error() => Diagnostic::error(
+                codes::MISSING_RETURN_VALUE,
+                format!("'return;' in a function returning {}", expected),
+                span,
+            ),
+            _ => return,
+        };
+        let diagnostic = match annotation {
+            Some(annotation) => {
+                diagnostic.with_label(annotation, "expected because of this return type")
+            }
+            None => diagnostic,
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Checks that `id` is a `bool`, as the condition of an `if`, a loop or
+    /// `?:` must be.
+    fn condition(&mut self, id: ExprId) {
+        let ty = self.expr(id);
+        if !Ty::Bool.accepts(&ty) {
+            let diagnostic = mismatch(&Ty::Bool, &ty, self.arena[id].span);
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn expr(&mut self, id: ExprId) -> Ty {
+        let expr = &self.arena[id];
+        let ty = match &expr.kind {
+            ExprKind::Literal(value) => literal(value),
+            ExprKind::Variable(_) => self
+                .resolution
+                .use_of(expr.id)
+                .and_then(|def| self.results.defs.get(&def).cloned())
+                .unwrap_or(Ty::Error),
+            ExprKind::Grouping(inner) => self.expr(*inner),
+            // Only the checker inserts these, after checking.
+            ExprKind::IntToFloat(inner) => {
+                self.expr(*inner);
+                Ty::Float
+            }
+            ExprKind::Unary { op, operand } => {
+                let ty = self.expr(*operand);
+                match unary_type(*op, &ty) {
+                    Some(result) => result,
+                    None => {
+                        let span = self.arena[*operand].span;
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                codes::INVALID_OPERANDS,
+                                format!("cannot apply '{}' to {}", op, ty),
+                                expr.span,
+                            )
+                            .with_label(span, ty.to_string()),
+                        );
+                        Ty::Error
+                    }
+                }
+            }
+            ExprKind::Binary { op, left, right } => {
+                let left_ty = self.expr(*left);
+                let right_ty = self.expr(*right);
+                self.binary(*op, id, (*left, &left_ty), (*right, &right_ty))
+            }
+            ExprKind::Assign { op, target, value } => {
+                let target_ty = self.expr(*target);
+                let value_ty = self.expr(*value);
+                let result = match op {
+                    Some(op) => self.binary(*op, id, (*target, &target_ty), (*value, &value_ty)),
+                    None if self.coerce(*value, &value_ty, &target_ty) => Ty::Float,
+                    None => value_ty,
+                };
+                if !target_ty.accepts(&result) {
+                    let diagnostic = mismatch(&target_ty, &result, self.arena[*value].span)
+                        .with_label(self.arena[*target].span, target_ty.to_string());
+                    self.diagnostics.push(diagnostic);
+                }
+                target_ty
+            }
+            ExprKind::Call { callee, args } => self.call(id, *callee, args),
+            ExprKind::Array(elements) => {
+                let mut element_ty: Option<(Ty, Span)> = None;
+                for &element in elements {
+                    let ty = self.expr(element);
+                    let span = self.arena[element].span;
+                    match &element_ty {
+                        None => element_ty = Some((ty, span)),
+                        Some((first, first_span)) => {
+                            if !first.accepts(&ty) {
+                                let diagnostic = mismatch(first, &ty, span)
+                                    .with_label(*first_span, first.to_string());
+                                self.diagnostics.push(diagnostic);
+                            }
+                        }
+                    }
+                }
+                Ty::Array {
+                    element: Box::new(element_ty.map_or(Ty::Error, |(ty, _)| ty)),
+                    len: elements.len() as u64,
+                }
+            }
+            ExprKind::Index { object, index } => {
+                let object_ty = self.expr(*object);
+                let index_ty = self.expr(*index);
+                if !Ty::Int.accepts(&index_ty) {
+                    let diagnostic = mismatch(&Ty::Int, &index_ty, self.arena[*index].span);
+                    self.diagnostics.push(diagnostic);
+                }
+                match object_ty {
+                    Ty::Array { element, .. } => *element,
+                    Ty::String => Ty::Char,
+                    Ty::Error | Ty::Param { .. } => Ty::Error,
+                    ty => {
+                        self.diagnostics.push(Diagnostic::error(
+                            codes::NOT_INDEXABLE,
+                            format!("cannot index into a value of type {}", ty),
+                            self.arena[*object].span,
+                        ));
+                        Ty::Error
+                    }
+                }
+            }
+            ExprKind::StructLiteral { name, fields } => {
+                let ty = match self.named(expr.id, &name.name, Vec::new()) {
+                    Ty::Struct { def, name, .. } => {
+                        let params = self.structs.get(&def).map_or(0, |info| info.params.len());
+                        Ty::Struct {
+                            def,
+                            name,
+                            args: vec![Ty::Error; params],
+                        }
+                    }
+                    Ty::Error => Ty::Error,
+                    _ => {
+                        let def = self.resolution.use_of(expr.id).expect("named found it");
+                        let kind = self.resolution.symbols[def].kind.describe();
+                        self.diagnostics.push(Diagnostic::error(
+                            codes::NOT_A_STRUCT,
+                            format!("expected a struct, found {} '{}'", kind, name.name),
+                            name.span,
+                        ));
+                        Ty::Error
+                    }
+                };
+                for field in fields {
+                    let found = self.expr(field.value);
+                    let expected = self.field(&ty, &field.name);
+                    if !expected.accepts(&found) && !self.coerce(field.value, &found, &expected) {
+                        let diagnostic = mismatch(&expected, &found, self.arena[field.value].span);
+                        self.diagnostics.push(diagnostic);
+                    }
+                }
+                if let Ty::Struct { def, .. } = &ty {
+                    let missing: Vec<String> = self.structs[def]
+                        .fields
+                        .iter()
+                        .filter(|(name, ..)| !fields.iter().any(|field| field.name.name == *name))
+                        .map(|(name, ..)| format!("'{}'", name))
+                        .collect();
+                    if !missing.is_empty() {
+                        self.diagnostics.push(Diagnostic::error(
+                            codes::MISSING_FIELDS,
+                            format!(
+                                "missing field{} {} in initializer of {}",
+                                if missing.len() == 1 { "" } else { "s" },
+                                missing.join(", "),
+                                ty
+                            ),
+                            expr.span,
+                        ));
+                    }
+                }
+                ty
+            }
+            ExprKind::Field { object, field } => {
+                let object_ty = self.expr(*object);
+                self.field(&object_ty, field)
+            }
+            ExprKind::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.condition(*condition);
+                let then_ty = self.expr(*then_expr);
+                let else_ty = self.expr(*else_expr);
+                self.join(then_ty, *then_expr, else_ty, *else_expr)
+            }
+            ExprKind::Variant { enum_name, variant } => {
+                match self.named(expr.id, &enum_name.name, Vec::new()) {
+                    ty @ Ty::Enum { .. } => match self.payload(&ty, &variant.name) {
+                        Some(payload) if payload.is_empty() => ty,
+                        Some(payload) => Ty::Function {
+                            params: payload,
+                            ret: Box::new(ty),
+                        },
+                        None => {
+                            self.no_variant(&ty, &variant.name, variant.span);
+                            Ty::Error
+                        }
+                    },
+                    _ => Ty::Error,
+                }
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                let scrutinee_ty = self.expr(*scrutinee);
+                let mut result: Option<(Ty, ExprId)> = None;
+                for arm in arms {
+                    self.pattern(&arm.pattern, &scrutinee_ty);
+                    match &arm.body {
+                        ArmBody::Expr(body) => {
+                            let ty = self.expr(*body);
+                            result = Some(match result {
+                                None => (ty, *body),
+                                Some((first, first_id)) => {
+                                    (self.join(first, first_id, ty, *body), first_id)
+                                }
+                            });
+                        }
+                        ArmBody::Block(block) => self.block(block),
+                    }
+                }
+                result.map_or(Ty::Void, |(ty, _)| ty)
+            }
+        };
+        self.results.exprs.insert(expr.id, ty.clone());
+        ty
+    }
+
+    /// The type of `left op right` in the binary or compound assignment
+    /// expression `id`, reporting operands the operator does not apply to.
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        id: ExprId,
+        left: (ExprId, &Ty),
+        right: (ExprId, &Ty),
+    ) -> Ty {
+        let (left, left_ty) = left;
+        let (right, right_ty) = right;
+        if let Some(ty) = binary_type(op, left_ty, right_ty) {
+            return ty;
+        }
+        let expr = &self.arena[id];
+        let assign = matches!(expr.kind, ExprKind::Assign { .. });
+        // Mixed `int` and `float` operands meet at `float`, but the target
+        // of a compound assignment keeps its type.
+        if binary_type(op, &Ty::Float, &Ty::Float).is_some()
+            && (self.coerce(right, right_ty, left_ty)
+                || (!assign && self.coerce(left, left_ty, right_ty)))
+        {
+            return binary_type(op, &Ty::Float, &Ty::Float).expect("checked above");
+        }
+        let assign = if assign { "=" } else { "" };
+        let diagnostic = Diagnostic::error(
+            codes::INVALID_OPERANDS,
+            format!(
+                "cannot apply '{}{}' to {} and {}",
+                op, assign, left_ty, right_ty
+            ),
+            expr.span,
+        )
+        .with_label(self.arena[left].span, left_ty.to_string())
+        .with_label(self.arena[right].span, right_ty.to_string());
+        self.diagnostics.push(diagnostic);
+        Ty::Error
+    }
+
+    /// The type of two branches that must agree, such as those of `?:`.
+    fn join(&mut self, first: Ty, first_id: ExprId, second: Ty, second_id: ExprId) -> Ty {
+        if first.accepts(&second) {
+            if first == Ty::Null {
+                return second;
+            }
+            return first;
+        }
+        if second.accepts(&first) {
+            return second;
+        }
+        if self.coerce(second_id, &second, &first) || self.coerce(first_id, &first, &second) {
+            return Ty::Float;
+        }
+        let diagnostic = mismatch(&first, &second, self.arena[second_id].span)
+            .with_label(self.arena[first_id].span, first.to_string());
+        self.diagnostics.push(diagnostic);
+        first
+    }
+
+    /// The payload types of variant `name` of the enum type `ty`.
+    fn payload(&self, ty: &Ty, name: &str) -> Option<Vec<Ty>> {
+        let Ty::Enum { def, .. } = ty else {
+            return None;
+        };
+        let variants = self.enums.get(def)?;
+        let (_, payload) = variants.iter().find(|(variant, _)| *variant == name)?;
+        Some(payload.clone())
+    }
+
+    fn no_variant(&mut self, ty: &Ty, name: &str, span: Span) {
+        self.diagnostics.push(Diagnostic::error(
+            codes::NO_VARIANT,
+            format!("no variant '{}' in enum '{}'", name, ty),
+            span,
+        ));
+    }
+
+    /// Checks that `pattern` can match a value of type `scrutinee`, giving
+    /// the names it binds their types.
+    fn pattern(&mut self, pattern: &Pattern, scrutinee: &Ty) {
+        match &pattern.kind {
+            PatternKind::Wildcard => {}
+            PatternKind::Literal(value) => {
+                let ty = literal(value);
+                if !scrutinee.accepts(&ty) {
+                    let diagnostic = mismatch(scrutinee, &ty, pattern.span);
+                    self.diagnostics.push(diagnostic);
+                }
+            }
+            PatternKind::Binding(_) => {
+                if let Some(def) = self.resolution.declaration(pattern.id) {
+                    self.results.defs.insert(def, scrutinee.clone());
+                }
+            }
+            PatternKind::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let ty = self.named(pattern.id, &enum_name.name, Vec::new());
+                let payload = match &ty {
+                    Ty::Enum { .. } => {
+                        if !scrutinee.accepts(&ty) {
+                            let diagnostic = mismatch(scrutinee, &ty, pattern.span);
+                            self.diagnostics.push(diagnostic);
+                        }
+                        let payload = self.payload(&ty, &variant.name);
+                        if payload.is_none() {
+                            self.no_variant(&ty, &variant.name, variant.span);
+                        }
+                        payload
+                    }
+                    _ => None,
+                };
+                if let Some(payload) = &payload {
+                    if payload.len() != fields.len() {
+                        self.diagnostics.push(Diagnostic::error(
+                            codes::PATTERN_FIELD_COUNT,
+                            format!(
+                                "variant '{}' has {} field{}, but the pattern has {}",
+                                variant.name,
+                                payload.len(),
+                                if payload.len() == 1 { "" } else { "s" },
+                                fields.len()
+                            ),
+                            pattern.span,
+                        ));
+                    }
+                }
+                for (i, field) in fields.iter().enumerate() {
+                    let ty = payload
+                        .as_ref()
+                        .and_then(|payload| payload.get(i).cloned())
+                        .unwrap_or(Ty::Error);
+                    self.pattern(field, &ty);
+                }
+            }
+        }
+    }
+}
+
+fn item_id(item: &Item) -> NodeId {
+    match item {
+        Item::Function(function) => function.id,
+        Item::Struct(decl) => decl.id,
+        Item::Enum(decl) => decl.id,
+        Item::Global(decl) => decl.id,
+    }
+}
+
+fn literal(value: &LiteralValue) -> Ty {
+    match value {
+        LiteralValue::Integer(_) => Ty::Int,
+        LiteralValue::Float(_) => Ty::Float,
+        LiteralValue::String(_) => Ty::String,
+        LiteralValue::Boolean(_) => Ty::Bool,
+        LiteralValue::Null => Ty::Null,
+        LiteralValue::None => Ty::Error,
+    }
+}
+
+/// The type of `op operand`, if the operator applies to it.
+fn unary_type(op: UnaryOp, operand: &Ty) -> Option<Ty> {
+    match (op, operand) {
+        (_, Ty::Error | Ty::Param { .. }) => Some(Ty::Error),
+        (UnaryOp::Neg, Ty::Int | Ty::Float) | (UnaryOp::BitNot, Ty::Int) => Some(operand.clone()),
+        (UnaryOp::Not, Ty::Bool) => Some(Ty::Bool),
+        _ => None,
+    }
+}
+
+/// The type of `left op right`, if the operator applies to them.
+fn binary_type(op: BinaryOp, left: &Ty, right: &Ty) -> Option<Ty> {
+    use BinaryOp::*;
+    let unknown = |ty: &Ty| matches!(ty, Ty::Error | Ty::Param { .. });
+    if unknown(left) || unknown(right) {
+        return Some(match op {
+            Eq | Ne | Lt | Le | Gt | Ge | And | Or => Ty::Bool,
+            _ => Ty::Error,
+        });
+    }
+    match (op, left, right) {
+        (Add, Ty::String, Ty::String) => Some(Ty::String),
+        (Add | Sub | Mul | Div | Rem, Ty::Int, Ty::Int)
+        | (Add | Sub | Mul | Div | Rem, Ty::Float, Ty::Float)
+        | (BitAnd | BitOr | BitXor | Shl | Shr, Ty::Int, Ty::Int) => Some(left.clone()),
+        (Lt | Le | Gt | Ge, Ty::Int, Ty::Int)
+        | (Lt | Le | Gt | Ge, Ty::Float, Ty::Float)
+        | (Lt | Le | Gt | Ge, Ty::Char, Ty::Char)
+        | (Lt | Le | Gt | Ge, Ty::String, Ty::String)
+        | (And | Or, Ty::Bool, Ty::Bool) => Some(Ty::Bool),
+        (Eq | Ne, _, _) if left.accepts(right) || right.accepts(left) => Some(Ty::Bool),
+        _ => None,
+    }
+}
+
+/// `int, bool`
+fn join_types(types: &[Ty]) -> String {
+    types
+        .iter()
+        .map(|ty| ty.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `ty` with the type parameters `params` replaced by `args`.
+fn substitute(ty: &Ty, params: &[Option<DefId>], args: &[Ty]) -> Ty {
+    match ty {
+        Ty::Param { def, .. } => params
+            .iter()
+            .position(|param| *param == Some(*def))
+            .and_then(|i| args.get(i).cloned())
+            .unwrap_or_else(|| ty.clone()),
+        Ty::Struct {
+            def,
+            name,
+            args: inner,
+        } => Ty::Struct {
+            def: *def,
+            name: name.clone(),
+            args: inner
+                .iter()
+                .map(|arg| substitute(arg, params, args))
+                .collect(),
+        },
+        Ty::Array { element, len } => Ty::Array {
+            element: Box::new(substitute(element, params, args)),
+            len: *len,
+        },
+        Ty::Function { params: inner, ret } => Ty::Function {
+            params: inner
+                .iter()
+                .map(|param| substitute(param, params, args))
+                .collect(),
+            ret: Box::new(substitute(ret, params, args)),
+        },
+        _ => ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::visit::{self, Visitor};
+    use crate::parser::parse;
+    use crate::semantic::{evaluate, resolve};
+    use pretty_assertions::assert_eq;
+
+    fn check_source(source: &str) -> (Program, TypeckResults, Vec<Diagnostic>) {
+        check_with(source, &TypeckOptions::default())
+    }
+
+    fn check_with(
+        source: &str,
+        options: &TypeckOptions,
+    ) -> (Program, TypeckResults, Vec<Diagnostic>) {
+        let (mut program, diagnostics) = parse(source);
+        assert_eq!(diagnostics, []);
+        let (resolution, diagnostics) = resolve(&program);
+        assert_eq!(diagnostics, []);
+        let (consts, _) = evaluate(&program, &resolution);
+        let (results, diagnostics) = check(&mut program, &resolution, &consts, options);
+        (program, results, diagnostics)
+    }
+
+    /// Each type error with its labels, one line each.
+    fn errors(source: &str) -> Vec<String> {
+        let (_, _, diagnostics) = check_source(source);
+        diagnostics
+            .iter()
+            .map(|d| {
+                let mut line = format!("{} at {}", d.message, d.primary_span);
+                for label in &d.labels {
+                    line += &format!("; {} at {}", label.message, label.span);
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// The type of the expression statement in `fn f(...) { <expr>; }`,
+    /// with the given declarations in scope.
+    fn type_of(decls: &str, expr: &str) -> String {
+        let source = format!("{}\nfn test() {{ {}; }}", decls, expr);
+        let (program, results, diagnostics) = check_source(&source);
+        assert_eq!(diagnostics, []);
+        let Some(Item::Function(test)) = program.items.last() else {
+            panic!("expected the test function");
+        };
+        let StmtKind::Expr(id) = program.arena[*test.body.stmts.last().unwrap()].kind else {
+            panic!("expected an expression statement");
+        };
+        results.exprs[&program.arena[id].id].to_string()
+    }
+
+    #[test]
+    fn test_types() {
+        let decls = "struct Box<T> { value: T }\nenum Shape { Circle(float), Dot }\nfn f(n: int) -> float { return 1.0; }\nint[3] xs;\nBox<int> b;\nstring s;\nconst int N = 1 + 2;\nint[N] ys;";
+        let cases = [
+            ("1 + 2 * 3", "int"),
+            ("1.5 / 2.0", "float"),
+            ("1 < 2 && !(3 == 4)", "bool"),
+            ("\"a\" + \"b\"", "string"),
+            ("s[0]", "char"),
+            ("xs", "int[3]"),
+            ("ys", "int[3]"),
+            ("xs[1] << 2", "int"),
+            ("[1.0, 2.0]", "float[2]"),
+            ("f", "fn(int) -> float"),
+            ("f(2)", "float"),
+            ("b", "Box<int>"),
+            ("b.value", "int"),
+            ("Box { value: 1 }", "Box<{unknown}>"),
+            ("Shape::Dot", "Shape"),
+            ("Shape::Circle", "fn(float) -> Shape"),
+            ("Shape::Circle(1.0)", "Shape"),
+            ("true ? s : null", "string"),
+            ("xs[0] = 4", "int"),
+            (
+                "match Shape::Dot { Shape::Circle(r) => r, _ => 0.0 }",
+                "float",
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(type_of(decls, expr), expected, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let source = "fn f(b: bool) {\n    int x = b;\n    x = 1 + true;\n    if (5) {}\n    while (x) {}\n    -b;\n    x[0];\n    [1, 2.0];\n    b ? 1 : \"one\";\n    x += 1.0;\n}";
+        assert_eq!(
+            errors(source),
+            [
+                "mismatched types: expected int, found bool at 28..29; expected because of this type at 20..23",
+                "cannot apply '+' to int and bool at 39..47; int at 39..40; bool at 43..47",
+                "mismatched types: expected bool, found int at 57..58",
+                "mismatched types: expected bool, found int at 74..75",
+                "cannot apply '-' to bool at 84..86; bool at 85..86",
+                "cannot index into a value of type int at 92..93",
+                "mismatched types: expected int, found float at 106..109; int at 103..104",
+                "mismatched types: expected int, found string at 124..129; int at 120..121",
+                "cannot apply '+=' to int and float at 135..143; int at 135..136; float at 140..143",
+            ]
+        );
+        // An error is reported once, not again by every expression around it.
+        assert_eq!(errors("fn f() { int x = (1 + true) * 2 - 3; }").len(), 1);
+    }
+
+    #[test]
+    fn test_patterns() {
+        let source = "enum E { A(int), B }\nfn f(e: E) -> int {\n    return match e { E::A(n) => n, E::A(1, 2) => 0, E::C => 0, 1 => 0, _ => 0 };\n}";
+        assert_eq!(
+            errors(source),
+            [
+                "variant 'A' has 1 field, but the pattern has 2 at 76..86",
+                "no variant 'C' in enum 'E' at 96..97",
+                "mismatched types: expected E, found int at 104..105",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calls() {
+        let source = "fn add(a: int, b: int) -> int { return a + b; }\nfn f() {\n    add(1);\n    add(1, true);\n    add(1, 2)(3);\n    add(1, 2, 3);\n}";
+        assert_eq!(
+            errors(source),
+            [
+                "expected 2 arguments, found 1 at 61..67; this takes (int, int) at 61..64",
+                "mismatched types: expected int, found bool at 80..84",
+                "cannot call a value of type int at 91..100",
+                "expected 2 arguments, found 3 at 109..121; this takes (int, int) at 109..112",
+            ]
+        );
+        // Generic parameters and variant payloads are checked like any other.
+        let source = "enum E { A(int) }\nfn id<T>(x: T) -> T { return x; }\nfn f() { id(1); id(true); E::A(1.0); }";
+        assert_eq!(
+            errors(source),
+            ["mismatched types: expected int, found float at 83..86"]
+        );
+    }
+
+    #[test]
+    fn test_inference() {
+        let decls = "let g = 2.5;\nconst N = 3;\nfn f() -> bool { return true; }";
+        let cases = [
+            ("g", "float"),
+            ("N", "int"),
+            ("let x = f(); x", "bool"),
+            ("let xs = [N, 1]; xs", "int[2]"),
+            ("let s = \"a\"; s[0]", "char"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(type_of(decls, expr), expected, "{}", expr);
+        }
+        let source = "fn v() {}\nfn f() {\n    let a = null;\n    let b = v();\n}";
+        assert_eq!(
+            errors(source),
+            [
+                "type annotations needed for 'a' at 27..28; a null initializer does not say which type at 31..35",
+                "type annotations needed for 'b' at 45..46; a void initializer does not say which type at 49..52",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop_control() {
+        let source = "fn f(x: int) {\n    while (x > 0) { if (x == 1) break; continue; }\n    for (;;) { match x { _ => { break; } } }\n    break;\n    { continue; }\n}";
+        assert_eq!(
+            errors(source),
+            [
+                "'break' outside of a loop at 115..121; enclosing function 'f' at 3..4",
+                "'continue' outside of a loop at 128..137; enclosing function 'f' at 3..4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_returns() {
+        let source = "fn f() -> int {\n    return true;\n}\nfn g() -> int {\n    return;\n}\nfn h() {\n    return 1;\n}\nfn k() { return; }\nfn m() -> int { return k(); }";
+        assert_eq!(
+            errors(source),
+            [
+                "mismatched types: expected int, found bool at 27..31; expected because of this return type at 10..13",
+                "'return;' in a function returning int at 55..62; expected because of this return type at 45..48",
+                "cannot return a value of type int from a void function at 85..86",
+                "mismatched types: expected int, found void at 132..135; expected because of this return type at 119..122",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fields() {
+        let source = "struct Point { x: int, y: int, label: string }\nfn f(p: Point) {\n    p.x;\n    p.lable;\n    p.z;\n    p.x.y;\n    Point { x: 1, y: 2, label: \"\" };\n    Point { x: 1, lable: \"\" };\n    Point { x: 1.0, y: 2, label: \"\" };\n}";
+        let (_, _, diagnostics) = check_source(source);
+        let fixits: Vec<_> = diagnostics
+            .iter()
+            .flat_map(|d| &d.fixits)
+            .map(|fixit| (fixit.span, fixit.replacement.as_str()))
+            .collect();
+        assert_eq!(
+            fixits,
+            [(Span::new(79, 84), "label"), (Span::new(161, 166), "label")]
+        );
+        assert_eq!(
+            errors(source),
+            [
+                "no field 'lable' on type Point at 79..84; a field with a similar name, 'label', is declared here at 31..36",
+                "no field 'z' on type Point at 92..93",
+                "no field 'y' on type int at 103..104",
+                "no field 'lable' on type Point at 161..166; a field with a similar name, 'label', is declared here at 31..36",
+                "missing fields 'y', 'label' in initializer of Point at 147..172",
+                "mismatched types: expected int, found float at 189..192",
+            ]
+        );
+        // Field types of a generic struct follow its type arguments.
+        let decls = "struct Pair<A, B> { first: A, second: B }\nPair<int, string> p;";
+        assert_eq!(type_of(decls, "p.second"), "string");
+    }
+
+    /// The source text of every conversion in the program, in order.
+    struct Conversions<'ast> {
+        arena: &'ast AstArena,
+        spans: Vec<Span>,
+    }
+
+    impl<'ast> Visitor<'ast> for Conversions<'ast> {
+        fn arena(&self) -> &'ast AstArena {
+            self.arena
+        }
+
+        fn visit_expr(&mut self, expr: &'ast Expr) {
+            if let ExprKind::IntToFloat(_) = expr.kind {
+                self.spans.push(expr.span);
+            }
+            visit::walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        let source = "struct P { v: float }\nfn g(a: float, b: int) {}\nfn f(n: int) -> float {\n    float x = n;\n    x = 2;\n    x += n;\n    n += 1.0;\n    n * 1.5 < n;\n    g(n, 1);\n    P { v: 1 };\n    return true ? n : x;\n}\nfn h() -> float { return 1; }";
+        let (program, results, diagnostics) = check_source(source);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>(),
+            ["error[T0203]: cannot apply '+=' to int and float"]
+        );
+        let mut conversions = Conversions {
+            arena: &program.arena,
+            spans: Vec::new(),
+        };
+        conversions.visit_program(&program);
+        let converted: Vec<_> = conversions
+            .spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect();
+        assert_eq!(converted, ["n", "2", "n", "n", "n", "n", "1", "n", "1"]);
+        // The converted expression keeps its type; the conversion is a float.
+        let Some(Item::Function(h)) = program.items.last() else {
+            panic!("expected h");
+        };
+        let StmtKind::Return(Some(value)) = program.arena[h.body.stmts[0]].kind else {
+            panic!("expected a return");
+        };
+        let ExprKind::IntToFloat(inner) = program.arena[value].kind else {
+            panic!("expected a conversion");
+        };
+        assert_eq!(results.exprs[&program.arena[value].id], Ty::Float);
+        assert_eq!(results.exprs[&program.arena[inner].id], Ty::Int);
+
+        let options = TypeckOptions::new().with_int_to_float(ConversionPolicy::Warn);
+        let (_, _, diagnostics) = check_with(source, &options);
+        let warnings = diagnostics.iter().filter(|d| !d.is_error()).count();
+        assert_eq!(warnings, 9);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "warning[T0215]: implicit conversion from int to float"
+        );
+
+        let options = TypeckOptions::new().with_int_to_float(ConversionPolicy::Error);
+        let (program, _, diagnostics) = check_with(source, &options);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "error[T0201]: mismatched types: expected float, found int",
+                "error[T0201]: mismatched types: expected float, found int",
+                "error[T0203]: cannot apply '+=' to float and int",
+                "error[T0203]: cannot apply '+=' to int and float",
+                "error[T0203]: cannot apply '*' to int and float",
+                "error[T0201]: mismatched types: expected float, found int",
+                "error[T0201]: mismatched types: expected float, found int",
+                "error[T0201]: mismatched types: expected int, found float",
+                "error[T0201]: mismatched types: expected float, found int",
+                "error[T0201]: mismatched types: expected float, found int",
+            ]
+        );
+        let mut conversions = Conversions {
+            arena: &program.arena,
+            spans: Vec::new(),
+        };
+        conversions.visit_program(&program);
+        assert_eq!(conversions.spans, []);
+    }
+}
@@ -0,0 +1,117 @@
+use super::SourceFile;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Identifies one file known to a [`SourceManager`] (or to a query
+/// [`Database`](crate::query::Database)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+impl FileId {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    text: String,
+}
+
+/// Owns the text of every file taking part in a compilation, so that
+/// positions from several files (e.g. after `#include`) can be told apart
+/// by their [`FileId`].
+#[derive(Default)]
+pub struct SourceManager {
+    files: Vec<Entry>,
+    ids: HashMap<PathBuf, FileId>,
+}
+
+impl SourceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `text` as the contents of `path`, replacing any earlier
+    /// text for the same path.
+    pub fn add(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) -> FileId {
+        let path = path.into();
+        let text = text.into();
+        if let Some(&file) = self.ids.get(&path) {
+            self.files[file.index()].text = text;
+            return file;
+        }
+        let file = FileId::new(self.files.len());
+        self.ids.insert(path.clone(), file);
+        self.files.push(Entry { path, text });
+        file
+    }
+
+    /// The id of `path`, reading the file from disk the first time.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<FileId> {
+        let path = path.as_ref();
+        match self.file_id(path) {
+            Some(file) => Ok(file),
+            None => Ok(self.add(path, fs::read_to_string(path)?)),
+        }
+    }
+
+    pub fn file_id(&self, path: impl AsRef<Path>) -> Option<FileId> {
+        self.ids.get(path.as_ref()).copied()
+    }
+
+    pub fn path(&self, file: FileId) -> &Path {
+        &self.files[file.index()].path
+    }
+
+    pub fn text(&self, file: FileId) -> &str {
+        &self.files[file.index()].text
+    }
+
+    /// Line index of `file`, for turning its byte offsets into positions.
+    pub fn source_file(&self, file: FileId) -> SourceFile<'_> {
+        SourceFile::new(self.text(file))
+    }
+
+    /// Every file registered, in the order they were added.
+    pub fn files(&self) -> impl Iterator<Item = FileId> {
+        (0..self.files.len()).map(FileId::new)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_add_and_lookup() {
+        let mut sources = SourceManager::new();
+        let main = sources.add("main.mc", "int x;");
+        let lib = sources.add("lib/util.mc", "int y;");
+        assert_ne!(main, lib);
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources.file_id("lib/util.mc"), Some(lib));
+        assert_eq!(sources.path(lib), Path::new("lib/util.mc"));
+
+        // Loading a registered path does not touch the file system.
+        assert_eq!(sources.load("main.mc").unwrap(), main);
+        assert_eq!(sources.add("main.mc", "int z;"), main);
+        assert_eq!(sources.text(main), "int z;");
+        assert!(sources.load("does/not/exist.mc").is_err());
+    }
+}
@@ -0,0 +1,294 @@
+pub mod manager;
+
+pub use manager::{FileId, SourceManager};
+
+use std::cell::Cell;
+use std::fmt;
+
+/// Half-open byte range `start..end` into a source file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The same span moved by `delta` bytes, e.g. to follow an edit earlier in
+    /// the file.
+    pub fn shift(self, delta: isize) -> Span {
+        Span::new(
+            self.start.wrapping_add_signed(delta),
+            self.end.wrapping_add_signed(delta),
+        )
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// A value together with the source range it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Self { value, span }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// Unit in which [`SourceFile::line_col`] counts columns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum ColumnEncoding {
+    /// UTF-8 bytes.
+    Utf8Bytes,
+    /// Unicode scalar values.
+    #[default]
+    Chars,
+    /// UTF-16 code units, as in Language Server Protocol positions (which
+    /// are 0-based: subtract one from the column).
+    Utf16,
+}
+
+impl ColumnEncoding {
+    /// Columns taken up by the UTF-8 byte `b`, counted on the first byte of
+    /// each character.
+    fn width(self, b: u8) -> usize {
+        let leading = b & 0xC0 != 0x80;
+        match self {
+            ColumnEncoding::Utf8Bytes => 1,
+            ColumnEncoding::Chars => usize::from(leading),
+            // Characters outside the BMP (4-byte sequences) are surrogate pairs.
+            ColumnEncoding::Utf16 if b >= 0xF0 => 2,
+            ColumnEncoding::Utf16 => usize::from(leading),
+        }
+    }
+}
+
+/// 1-based line and column. Columns count Unicode scalar values unless the
+/// file was given another [`ColumnEncoding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LineCol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Source text together with the byte offsets at which its lines start.
+///
+/// The index is built once per file with a single scan for `\n`, and turns
+/// byte offsets into line/column pairs with a binary search. The column of
+/// the last lookup is remembered, so walking a line with increasing offsets
+/// (as when printing every token) counts each byte once.
+pub struct SourceFile<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+    tab_width: usize,
+    columns: ColumnEncoding,
+    /// Offset and 0-based column of the last [`line_col`](Self::line_col).
+    last: Cell<(usize, usize)>,
+}
+
+impl<'a> SourceFile<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            text.bytes()
+                .enumerate()
+                .filter(|&(_, b)| b == b'\n')
+                .map(|(i, _)| i + 1),
+        );
+        Self {
+            text,
+            line_starts,
+            tab_width: 1,
+            columns: ColumnEncoding::Chars,
+            last: Cell::new((0, 0)),
+        }
+    }
+
+    /// Makes a tab advance the column to the next multiple of `width` (plus
+    /// one) instead of counting as a single column. A width of 0 is taken
+    /// as 1.
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width.max(1);
+        self
+    }
+
+    /// Counts columns in `encoding` units instead of characters.
+    pub fn with_column_encoding(mut self, encoding: ColumnEncoding) -> Self {
+        self.columns = encoding;
+        self
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Line and column of the byte at `offset`. Offsets past the end of the
+    /// text are clamped to the end.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        // Continue from the last lookup when it is earlier on the same line.
+        let (from, col) = match self.last.get() {
+            (last, col) if line_start <= last && last <= offset => (last, col),
+            _ => (line_start, 0),
+        };
+        let col = self.advance(col, &self.text.as_bytes()[from..offset]);
+        self.last.set((offset, col));
+        LineCol {
+            line: line + 1,
+            column: col + 1,
+        }
+    }
+
+    /// The 0-based column `col` moved past `bytes`, which contain no `\n`.
+    fn advance(&self, col: usize, bytes: &[u8]) -> usize {
+        match (self.columns, self.tab_width) {
+            (ColumnEncoding::Utf8Bytes, 1) => col + bytes.len(),
+            // Count scalar values by skipping UTF-8 continuation bytes.
+            (ColumnEncoding::Chars, 1) => col + bytes.iter().filter(|&&b| b & 0xC0 != 0x80).count(),
+            (columns, tab_width) => bytes.iter().fold(col, |col, &b| match b {
+                b'\t' => (col / tab_width + 1) * tab_width,
+                _ => col + columns.width(b),
+            }),
+        }
+    }
+
+    /// Byte offset at which the 1-based `line` starts.
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line - 1]
+    }
+
+    /// Text of the 1-based `line`, without its line terminator.
+    pub fn line_text(&self, line: usize) -> &'a str {
+        let start = self.line_start(line);
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.text.len(), |&next| next - 1);
+        self.text[start..end].trim_end_matches('\r')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_line_col() {
+        let file = SourceFile::new("ab\nc\u{e9}d\n\nx");
+        assert_eq!(file.line_count(), 4);
+        assert_eq!(file.line_col(0), LineCol { line: 1, column: 1 });
+        assert_eq!(file.line_col(2), LineCol { line: 1, column: 3 });
+        assert_eq!(file.line_col(3), LineCol { line: 2, column: 1 });
+        // 'd' follows a two-byte character.
+        assert_eq!(file.line_col(6), LineCol { line: 2, column: 3 });
+        assert_eq!(file.line_col(8), LineCol { line: 3, column: 1 });
+        assert_eq!(file.line_col(100), LineCol { line: 4, column: 2 });
+    }
+
+    #[test]
+    fn test_tab_width() {
+        let text = "\tx\n a\tb\n\t\ty";
+        let file = SourceFile::new(text);
+        assert_eq!(file.line_col(1).column, 2);
+
+        let file = SourceFile::new(text).with_tab_width(4);
+        assert_eq!(file.line_col(1).column, 5);
+        // A tab after two columns only advances to the next stop.
+        assert_eq!(file.line_col(6).column, 5);
+        assert_eq!(file.line_col(10).column, 9);
+    }
+
+    #[test]
+    fn test_column_encoding() {
+        // 'é' is 2 bytes and 1 UTF-16 unit; '😀' is 4 bytes and 2 units.
+        let text = "\u{e9}\u{1f600}x\n\ty";
+        let columns = |encoding| {
+            let file = SourceFile::new(text).with_column_encoding(encoding);
+            (file.line_col(6).column, file.line_col(9).column)
+        };
+        assert_eq!(columns(ColumnEncoding::Utf8Bytes), (7, 2));
+        assert_eq!(columns(ColumnEncoding::Chars), (3, 2));
+        assert_eq!(columns(ColumnEncoding::Utf16), (4, 2));
+
+        let file = SourceFile::new(text)
+            .with_column_encoding(ColumnEncoding::Utf16)
+            .with_tab_width(4);
+        assert_eq!(file.line_col(9).column, 5);
+    }
+
+    #[test]
+    fn test_line_col_any_order() {
+        // The cached column must not leak into lookups behind it or on
+        // other lines.
+        let text = "a\t\u{e9}b\u{1f600}c\n\txy\nz";
+        let offsets = [0, 3, 8, 2, 9, 13, 12, 14, 1, 15, 11, 4];
+        for encoding in [
+            ColumnEncoding::Utf8Bytes,
+            ColumnEncoding::Chars,
+            ColumnEncoding::Utf16,
+        ] {
+            for tab_width in [1, 4] {
+                let file = SourceFile::new(text)
+                    .with_column_encoding(encoding)
+                    .with_tab_width(tab_width);
+                for offset in offsets {
+                    let fresh = SourceFile::new(text)
+                        .with_column_encoding(encoding)
+                        .with_tab_width(tab_width);
+                    assert_eq!(file.line_col(offset), fresh.line_col(offset), "{offset}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_text() {
+        let file = SourceFile::new("first\r\nsecond\n");
+        assert_eq!(file.line_text(1), "first");
+        assert_eq!(file.line_text(2), "second");
+        assert_eq!(file.line_text(3), "");
+    }
+}
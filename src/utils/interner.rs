@@ -0,0 +1,94 @@
+use super::Fnv1a;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::BuildHasherDefault;
+use std::rc::Rc;
+
+/// An interned string: a small integer that stands for one distinct name.
+///
+/// Symbols from the same [`SymbolTable`] are equal exactly when their
+/// strings are, so later phases can compare names without touching text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Position of the symbol in its table, in order of first interning.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// A symbol no [`SymbolTable`] hands out, for a pass that needs a name
+    /// unequal to every written one. Different `n`s give different symbols.
+    pub(crate) fn synthetic(n: usize) -> Self {
+        Self(u32::MAX - n as u32)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Maps strings to [`Symbol`]s and back.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    ids: HashMap<Rc<str>, Symbol, BuildHasherDefault<Fnv1a>>,
+    names: Vec<Rc<str>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the symbol for `name`, adding it on first use.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.ids.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.names.push(name.clone());
+        self.ids.insert(name, symbol);
+        symbol
+    }
+
+    /// The symbol for `name`, if it has been interned.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.ids.get(name).copied()
+    }
+
+    /// The string `symbol` stands for.
+    ///
+    /// Panics if `symbol` comes from a different table.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_interning_is_idempotent() {
+        let mut table = SymbolTable::new();
+        let i = table.intern("i");
+        let j = table.intern("j");
+        assert_eq!(table.intern("i"), i);
+        assert_ne!(i, j);
+        assert_eq!(table.resolve(j), "j");
+        assert_eq!(table.get("k"), None);
+        assert_eq!(table.len(), 2);
+    }
+}